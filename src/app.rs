@@ -1,18 +1,28 @@
 use crate::db::{
-    Circuit, Clock, DB, Gate, GateKind, InstanceId, InstanceKind, Label, LabelId, Lamp,
-    ModuleDefId, Pin, Power, Wire,
+    Circuit, Clock, DB, FlipFlop, FlipFlopKind, Gate, GateKind, InstanceId, InstanceKind, Label,
+    LabelId, Lamp, MAX_ADDRESS_WIDTH, Memory, MemoryKind, ModuleDefId, Pin, Power, Splitter,
+    SplitterKind, TUNNEL_SIZE, Tunnel, Wire,
 };
 use std::collections::HashSet;
 use std::fmt::Write as _;
 
 use egui::{
-    Align, Button, Color32, CornerRadius, Image, Layout, Pos2, Rect, Response, Sense, Stroke,
-    StrokeKind, Ui, Vec2, Widget as _, pos2, vec2,
+    Align, Button, Color32, CornerRadius, Image, LayerId, Layout, Pos2, Rect, Response, Sense,
+    Stroke, StrokeKind, Ui, UiBuilder, Vec2, Widget as _, emath::TSTransform, pos2, vec2,
 };
 
 use crate::assets::PinKind;
 use crate::drag::CanvasDrag;
-use crate::simulator::{SimulationStatus, Simulator, Value, lamp_input, wire_start};
+use crate::drc::DrcView;
+use crate::equivalence::EquivalenceView;
+use crate::history::{History, REDO, UNDO};
+use crate::minimization::ExpressionView;
+use crate::simulator::{
+    MAX_BUS_WIDTH, SimulationStatus, Simulator, lamp_input, memory_address, tunnel_pin, wire_start,
+};
+use crate::synthesis::SynthesisView;
+use crate::truth_table::TruthTable;
+use crate::waveform::WaveformView;
 use crate::{
     assets::{self},
     config::CanvasConfig,
@@ -39,6 +49,10 @@ pub const COLOR_WIRE_HOVER: Color32 = Color32::GRAY;
 pub const COLOR_HOVER_INSTANCE_OUTLINE: Color32 = Color32::GRAY;
 pub const COLOR_HOVER_PIN_TO_WIRE: Color32 = Color32::GRAY;
 pub const COLOR_HOVER_PIN_DETACH: Color32 = Color32::RED;
+pub const COLOR_CONFLICT: Color32 = Color32::RED;
+pub const CONFLICT_MARKER_RADIUS: f32 = 12.0;
+pub const COLOR_OSCILLATING: Color32 = Color32::ORANGE;
+pub const OSCILLATING_MARKER_RADIUS: f32 = 8.0;
 pub const PIN_HOVER_THRESHOLD: f32 = 10.0;
 
 pub const INSTANEC_OUTLINE_EXPAND: f32 = 6.0;
@@ -58,6 +72,8 @@ pub const COLOR_SELECTION_HIGHLIGHT: Color32 = Color32::GRAY;
 pub const COLOR_SELECTION_BOX: Color32 = Color32::LIGHT_BLUE;
 
 pub const MIN_WIRE_SIZE: f32 = 40.0;
+/// Grid lines closer than this many screen pixels are skipped
+pub const MIN_GRID_SPACING: f32 = 8.0;
 
 #[derive(serde::Deserialize, serde::Serialize, Eq, PartialEq, Hash, Copy, Debug, Clone)]
 pub enum Hover {
@@ -77,41 +93,57 @@ impl Hover {
 // Items with their offset compared to a middle point in the rectangle
 #[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
 pub enum ClipBoardItem {
-    Gate(GateKind, Vec2),
-    Power(Vec2),
-    Wire(Vec2, Vec2),
-    Lamp(Vec2),
-    Clock(Vec2),
+    Gate(Gate, Vec2),
+    Power(Power, Vec2),
+    Wire(Wire, Vec2),
+    Lamp(Lamp, Vec2),
+    Clock(Clock, Vec2),
     // Index to definition
     Module(ModuleDefId, Vec2),
     Label(String, Vec2),
+    Splitter(Splitter, Vec2),
+    FlipFlop(FlipFlop, Vec2),
+    Memory(Memory, Vec2),
+    Tunnel(Tunnel, Vec2),
 }
 
 pub fn default_true() -> bool {
     true
 }
 
+pub fn default_zoom() -> f32 {
+    1.0
+}
+
+pub fn default_canvas_rect() -> Rect {
+    Rect::NOTHING
+}
+
 #[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ClockState {
     Stopped,
     Running,
 }
 
+/// Drives simulated time forward while the clock is running. Each clock derives its own output
+/// from simulated time.
 #[derive(Debug, Clone)]
 pub struct ClockController {
-    pub voltage: bool,
     pub state: ClockState,
+    // fraction of a time unit not yet simulated
     pub tick_accumulator: f32,
-    pub tick_interval: f32, // seconds between ticks
+    pub speed: f32, // simulated time units per second
+    // time the simulator should advance to on the next frame
+    pub advance_to: Option<u64>,
 }
 
 impl Default for ClockController {
     fn default() -> Self {
         Self {
-            voltage: false,
             state: ClockState::Running,
             tick_accumulator: 0.0,
-            tick_interval: 0.5, // 0.5 seconds = 2 Hz
+            speed: 20.0, // one period of a default clock per second
+            advance_to: None,
         }
     }
 }
@@ -134,6 +166,12 @@ pub struct App {
     // Where are we in the world
     #[serde(skip)]
     pub viewport_offset: Vec2,
+    // Screen pixels per world unit
+    #[serde(skip, default = "default_zoom")]
+    pub zoom: f32,
+    // Where the canvas is on the screen
+    #[serde(skip, default = "default_canvas_rect")]
+    pub canvas_rect: Rect,
     #[serde(skip)]
     pub panning: bool,
 
@@ -147,6 +185,9 @@ pub struct App {
 
     #[serde(skip)]
     pub drag: Option<Drag>,
+    /// Components a wire drawn with `Drag::Route` goes around, found when the drag starts
+    #[serde(skip)]
+    pub obstacles: Vec<Rect>,
     #[serde(skip)]
     pub hovered: Option<Hover>,
     // selection set and move preview
@@ -158,10 +199,29 @@ pub struct App {
 
     #[serde(skip)]
     pub show_debug: bool,
+    #[serde(skip)]
+    pub show_memory_viewer: bool,
+    #[serde(skip)]
+    pub show_waveform: bool,
+    #[serde(skip)]
+    pub waveform_view: WaveformView,
+    #[serde(skip)]
+    pub truth_table: Option<TruthTable>,
+    #[serde(skip)]
+    pub expressions: Option<ExpressionView>,
+    #[serde(skip)]
+    pub synthesis: Option<SynthesisView>,
+    #[serde(skip)]
+    pub equivalence: Option<EquivalenceView>,
+    #[serde(skip)]
+    pub drc: Option<DrcView>,
 
     // For web load functionality - stores pending JSON to load
     #[serde(skip)]
     pub pending_load_json: Option<String>,
+    // For web memory loading - the memory waiting for its hex file
+    #[serde(skip)]
+    pub pending_memory_load: Option<InstanceId>,
 
     #[serde(skip)]
     pub editing_label: Option<LabelId>,
@@ -177,6 +237,11 @@ pub struct App {
 
     #[serde(skip)]
     pub viewing_module: Option<ViewModule>,
+
+    #[serde(skip)]
+    pub history: History,
+    #[serde(skip)]
+    pub show_history: bool,
 }
 
 impl Default for App {
@@ -188,15 +253,27 @@ impl Default for App {
             db,
             canvas_config,
             drag: Default::default(),
+            obstacles: Vec::new(),
             hovered: Default::default(),
             connection_manager: c,
             potential_connections: Default::default(),
             current_dirty: true,
             show_debug: true,
+            show_memory_viewer: false,
+            show_waveform: false,
+            waveform_view: WaveformView::default(),
+            truth_table: None,
+            expressions: None,
+            synthesis: None,
+            equivalence: None,
+            drc: None,
             selected: Default::default(),
             clipboard: Default::default(),
             pending_load_json: None,
+            pending_memory_load: None,
             viewport_offset: Vec2::ZERO,
+            zoom: 1.0,
+            canvas_rect: default_canvas_rect(),
             panning: false,
             editing_label: None,
             label_edit_buffer: String::new(),
@@ -206,6 +283,8 @@ impl Default for App {
             simulator: Simulator::default(),
             clock_controller: ClockController::default(),
             viewing_module: None,
+            history: History::default(),
+            show_history: false,
         }
     }
 }
@@ -231,6 +310,28 @@ impl eframe::App for App {
                     {
                         log::error!("Failed to load circuit: {e}");
                     }
+                    ui.separator();
+                    if ui
+                        .add_enabled(
+                            !self.simulator.recorder.traces.is_empty(),
+                            Button::new("Export VCD"),
+                        )
+                        .on_disabled_hover_text("Probe a pin or wire first")
+                        .clicked()
+                        && let Err(e) = self.export_vcd()
+                    {
+                        log::error!("Failed to export waveforms: {e}");
+                    }
+                    if ui.button("Export Verilog").clicked()
+                        && let Err(e) = self.export_verilog()
+                    {
+                        log::error!("Failed to export Verilog: {e}");
+                    }
+                    if ui.button("Import Verilog").clicked()
+                        && let Err(e) = self.import_verilog()
+                    {
+                        log::error!("Failed to import Verilog: {e}");
+                    }
                     if !is_web {
                         ui.separator();
                         if ui.button("Quit").clicked() {
@@ -240,8 +341,56 @@ impl eframe::App for App {
                 });
                 ui.add_space(16.0);
 
+                ui.menu_button("Edit", |ui| {
+                    let undo = match self.history.undo_label() {
+                        Some(label) => format!("Undo {label}"),
+                        None => "Undo".to_owned(),
+                    };
+                    if ui
+                        .add_enabled(
+                            self.history.can_undo(),
+                            Button::new(undo).shortcut_text(ctx.format_shortcut(&UNDO)),
+                        )
+                        .clicked()
+                    {
+                        self.undo();
+                    }
+                    let redo = match self.history.redo_label() {
+                        Some(label) => format!("Redo {label}"),
+                        None => "Redo".to_owned(),
+                    };
+                    if ui
+                        .add_enabled(
+                            self.history.can_redo(),
+                            Button::new(redo).shortcut_text(ctx.format_shortcut(&REDO)),
+                        )
+                        .clicked()
+                    {
+                        self.redo();
+                    }
+                    ui.separator();
+                    ui.checkbox(&mut self.show_history, "History");
+                });
+                ui.add_space(16.0);
+
                 ui.menu_button("View", |ui| {
                     ui.checkbox(&mut self.show_debug, "World Debug");
+                    ui.checkbox(&mut self.show_memory_viewer, "Memory Viewer");
+                    ui.checkbox(&mut self.show_waveform, "Waveforms");
+                    ui.separator();
+                    if ui.button("Zoom to fit").clicked() {
+                        self.zoom_to_fit();
+                    }
+                    if ui
+                        .add_enabled(!self.selected.is_empty(), Button::new("Zoom to selection"))
+                        .clicked()
+                    {
+                        self.zoom_to_selection();
+                    }
+                    let reset = format!("Reset zoom ({:.0}%)", self.zoom * 100.0);
+                    if ui.button(reset).clicked() {
+                        self.reset_zoom();
+                    }
                 });
                 ui.add_space(16.0);
 
@@ -249,6 +398,64 @@ impl eframe::App for App {
                     if ui.button("Create module").clicked() {
                         self.create_module();
                     }
+                    if ui
+                        .add_enabled(
+                            !self.selected.is_empty(),
+                            Button::new("Generate truth table"),
+                        )
+                        .on_disabled_hover_text("Select the part of the circuit to tabulate")
+                        .clicked()
+                    {
+                        self.truth_table_of_selection();
+                    }
+                    if ui
+                        .add_enabled(
+                            !self.selected.is_empty(),
+                            Button::new("Boolean expressions"),
+                        )
+                        .on_disabled_hover_text("Select a combinational part of the circuit")
+                        .clicked()
+                    {
+                        self.expressions_of_selection();
+                    }
+                    ui.add_enabled_ui(!self.db.module_definitions.is_empty(), |ui| {
+                        ui.menu_button("Truth table of module", |ui| {
+                            let definitions: Vec<(ModuleDefId, String)> = self
+                                .db
+                                .module_definitions
+                                .iter()
+                                .map(|(id, definition)| (id, definition.name.clone()))
+                                .collect();
+                            for (id, name) in definitions {
+                                if ui.button(name).clicked() {
+                                    self.truth_table_of_definition(id);
+                                }
+                            }
+                        });
+                    });
+                    if ui.button("Synthesize…").clicked() {
+                        self.synthesis.get_or_insert_with(SynthesisView::default);
+                    }
+                    if ui.button("Check equivalence…").clicked() {
+                        self.equivalence
+                            .get_or_insert_with(EquivalenceView::default);
+                    }
+                    if ui.button("Design rule check…").clicked() {
+                        self.open_drc();
+                    }
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.label("Event budget:");
+                        if ui
+                            .add(
+                                egui::DragValue::new(&mut self.simulator.event_budget)
+                                    .range(100..=10_000_000),
+                            )
+                            .changed()
+                        {
+                            self.current_dirty = true;
+                        }
+                    });
                 });
                 ui.add_space(16.0);
 
@@ -256,10 +463,17 @@ impl eframe::App for App {
                 if ui.button("⏹ Stop").clicked() {
                     self.clock_controller.state = ClockState::Stopped;
                 }
-                if ui.button("⏭ Step").clicked() {
+                if ui
+                    .button("⏭ Step")
+                    .on_hover_text("Run until the next clock edge")
+                    .clicked()
+                {
                     self.clock_controller.state = ClockState::Stopped;
-                    self.clock_controller.voltage = !self.clock_controller.voltage;
-                    self.current_dirty = true;
+                    let next = self
+                        .simulator
+                        .next_clock_edge(&self.db.circuit)
+                        .unwrap_or(self.simulator.time + 1);
+                    self.clock_controller.advance_to = Some(next);
                 }
                 if ui.button("▶ Start").clicked() {
                     self.clock_controller.state = ClockState::Running;
@@ -269,15 +483,25 @@ impl eframe::App for App {
 
                 // Clock speed slider
                 ui.label("Speed:");
-                let mut speed_hz = 1.0 / self.clock_controller.tick_interval;
-                if ui
-                    .add(egui::Slider::new(&mut speed_hz, 0.5..=5.0).text("Hz"))
-                    .changed()
-                {
-                    self.clock_controller.tick_interval = 1.0 / speed_hz;
-                }
+                ui.add(
+                    egui::Slider::new(&mut self.clock_controller.speed, 1.0..=1000.0)
+                        .logarithmic(true)
+                        .text("units/s"),
+                );
+                ui.add_space(16.0);
+
+                ui.label(format!("t = {}", self.simulator.time));
                 ui.add_space(16.0);
 
+                if let Some(reason) = self.simulator.instability() {
+                    ui.colored_label(COLOR_OSCILLATING, format!("⚠ Unstable: {reason}"))
+                        .on_hover_text(
+                            "The simulation used its event budget without settling. \
+                             Outlined instances form the loop, circled pins kept changing.",
+                        );
+                    ui.add_space(16.0);
+                }
+
                 ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
                     egui::widgets::global_theme_preference_buttons(ui);
 
@@ -287,23 +511,32 @@ impl eframe::App for App {
         });
 
         let dt = ctx.input(|i| i.stable_dt);
-        let should_tick = match self.clock_controller.state {
-            ClockState::Running => {
-                self.clock_controller.tick_accumulator += dt;
-                if self.clock_controller.tick_accumulator >= self.clock_controller.tick_interval {
-                    self.clock_controller.tick_accumulator -= self.clock_controller.tick_interval;
-                    true
-                } else {
-                    false
-                }
+        if self.clock_controller.state == ClockState::Running {
+            self.clock_controller.tick_accumulator += dt * self.clock_controller.speed;
+            let units = self.clock_controller.tick_accumulator.floor();
+            if units >= 1.0 {
+                self.clock_controller.tick_accumulator -= units;
+                let from = self
+                    .clock_controller
+                    .advance_to
+                    .unwrap_or(self.simulator.time);
+                self.clock_controller.advance_to = Some(from + units as u64);
             }
-            ClockState::Stopped => false,
-        };
+        }
 
-        if should_tick {
-            self.clock_controller.voltage = !self.clock_controller.voltage;
-            self.current_dirty = true;
+        if self.show_memory_viewer {
+            self.draw_memory_viewer(ctx);
+        }
+        if self.show_waveform {
+            self.draw_waveform_panel(ctx);
         }
+        self.draw_truth_table(ctx);
+        self.draw_expressions(ctx);
+        self.draw_synthesis(ctx);
+        self.draw_equivalence(ctx);
+        self.draw_drc(ctx);
+        self.draw_history(ctx);
+        self.handle_history_keys(ctx);
 
         egui::CentralPanel::default().show(ctx, |ui| {
             self.draw_main(ui);
@@ -330,16 +563,13 @@ impl App {
     }
 
     fn is_on(&self, pin: Pin) -> bool {
-        let pin = pin.is_passthrough(&self.db).unwrap_or(pin);
-        let Some(v) = self.simulator.current.get(&pin) else {
-            return false;
-        };
-
-        *v == Value::One
+        self.simulator.pin_value(self.circuit(), pin).any_one()
     }
 
     pub fn draw_main(&mut self, ui: &mut Ui) {
         self.process_pending_load();
+        self.process_pending_memory_load();
+        self.process_pending_verilog_import();
 
         if self.show_debug {
             egui::Window::new("Debug logs").show(ui.ctx(), |ui| {
@@ -411,6 +641,8 @@ impl App {
             }
         }
 
+        self.draw_properties(ui.ctx());
+
         ui.with_layout(Layout::left_to_right(Align::Min), |ui| {
             self.canvas_config = CanvasConfig::default();
             if self.show_debug {
@@ -433,7 +665,10 @@ impl App {
                 ui.heading("Canvas");
                 ui.label("press backspace/d to remove object");
                 ui.label("right click on powers to toggle");
+                ui.label("press r to rotate, f to flip");
                 ui.label("right click on canvas to drag");
+                ui.label("scroll or pinch to zoom");
+                ui.label("double click on a wire to add or remove a bend");
                 self.draw_canvas(ui);
             });
         });
@@ -450,10 +685,21 @@ impl App {
                 self.draw_panel_button(ui, InstanceKind::Gate(GateKind::Xor));
                 self.draw_panel_button(ui, InstanceKind::Gate(GateKind::Xnor));
                 self.draw_panel_button(ui, InstanceKind::Gate(GateKind::Not));
+                self.draw_panel_button(ui, InstanceKind::Gate(GateKind::TriState));
                 self.draw_panel_button(ui, InstanceKind::Power);
                 self.draw_panel_button(ui, InstanceKind::Lamp);
                 self.draw_panel_button(ui, InstanceKind::Clock);
+                self.draw_panel_button(ui, InstanceKind::FlipFlop(FlipFlopKind::SrLatch));
+                self.draw_panel_button(ui, InstanceKind::FlipFlop(FlipFlopKind::DLatch));
+                self.draw_panel_button(ui, InstanceKind::FlipFlop(FlipFlopKind::D));
+                self.draw_panel_button(ui, InstanceKind::FlipFlop(FlipFlopKind::Jk));
+                self.draw_panel_button(ui, InstanceKind::FlipFlop(FlipFlopKind::T));
                 self.draw_panel_button(ui, InstanceKind::Wire);
+                self.draw_panel_button(ui, InstanceKind::Tunnel);
+                self.draw_panel_button(ui, InstanceKind::Splitter(SplitterKind::Split));
+                self.draw_panel_button(ui, InstanceKind::Splitter(SplitterKind::Join));
+                self.draw_panel_button(ui, InstanceKind::Memory(MemoryKind::Rom));
+                self.draw_panel_button(ui, InstanceKind::Memory(MemoryKind::Ram));
 
                 ui.add_space(8.0);
                 self.draw_label_button(ui);
@@ -474,6 +720,7 @@ impl App {
                     .ui(ui)
                     .clicked()
                 {
+                    self.checkpoint("Clear");
                     self.db.circuit = Circuit::default();
                     self.hovered = None;
                     self.selected.clear();
@@ -482,6 +729,7 @@ impl App {
                         ConnectionManager::new(self.circuit(), &self.canvas_config, &self.db);
                     self.simulator = Simulator::new();
                     self.viewport_offset = Vec2::ZERO;
+                    self.zoom = 1.0;
                 }
             });
     }
@@ -494,26 +742,17 @@ impl App {
                 ui.add(egui::Button::image(s).sense(Sense::click_and_drag()))
             }
             InstanceKind::Power => {
-                let s = get_icon(
-                    ui,
-                    Power {
-                        pos: Pos2::ZERO,
-                        on: true,
-                    }
-                    .graphics()
-                    .svg
-                    .clone(),
-                )
-                .fit_to_exact_size(vec2(PANEL_BUTTON_MAX_HEIGHT, PANEL_BUTTON_MAX_HEIGHT));
+                let s = get_icon(ui, Power::new(Pos2::ZERO, true).graphics().svg.clone())
+                    .fit_to_exact_size(vec2(PANEL_BUTTON_MAX_HEIGHT, PANEL_BUTTON_MAX_HEIGHT));
                 ui.add(egui::Button::image(s).sense(Sense::click_and_drag()))
             }
             InstanceKind::Lamp => {
-                let s = get_icon(ui, Lamp { pos: Pos2::ZERO }.graphics().svg.clone())
+                let s = get_icon(ui, Lamp::new(Pos2::ZERO).graphics().svg.clone())
                     .fit_to_exact_size(vec2(PANEL_BUTTON_MAX_HEIGHT, PANEL_BUTTON_MAX_HEIGHT));
                 ui.add(egui::Button::image(s).sense(Sense::click_and_drag()))
             }
             InstanceKind::Clock => {
-                let s = get_icon(ui, Clock { pos: Pos2::ZERO }.graphics().svg.clone())
+                let s = get_icon(ui, Clock::new(Pos2::ZERO).graphics().svg.clone())
                     .fit_to_exact_size(vec2(PANEL_BUTTON_MAX_HEIGHT, PANEL_BUTTON_MAX_HEIGHT));
                 ui.add(egui::Button::image(s).sense(Sense::click_and_drag()))
             }
@@ -527,19 +766,52 @@ impl App {
                     .sense(Sense::click_and_drag())
                     .min_size(vec2(PANEL_BUTTON_MAX_HEIGHT, 30.0)),
             ),
+            InstanceKind::FlipFlop(kind) => {
+                let s = get_icon(ui, kind.graphics().svg.clone())
+                    .fit_to_exact_size(vec2(PANEL_BUTTON_MAX_HEIGHT, PANEL_BUTTON_MAX_HEIGHT));
+                ui.add(egui::Button::image(s).sense(Sense::click_and_drag()))
+                    .on_hover_text(format!("{kind:?}"))
+            }
+            InstanceKind::Splitter(kind) => ui.add(
+                Button::new(format!("{kind:?}"))
+                    .sense(Sense::click_and_drag())
+                    .min_size(vec2(PANEL_BUTTON_MAX_HEIGHT, 30.0)),
+            ),
+            InstanceKind::Memory(kind) => ui.add(
+                Button::new(format!("{kind:?}").to_uppercase())
+                    .sense(Sense::click_and_drag())
+                    .min_size(vec2(PANEL_BUTTON_MAX_HEIGHT, 30.0)),
+            ),
+            InstanceKind::Tunnel => ui
+                .add(
+                    Button::new("Tunnel")
+                        .sense(Sense::click_and_drag())
+                        .min_size(vec2(PANEL_BUTTON_MAX_HEIGHT, 30.0)),
+                )
+                .on_hover_text("Tunnels with the same name are connected"),
         };
         let mouse_pos_world = self.mouse_pos_world(ui);
 
         if resp.drag_started()
             && let Some(pos) = mouse_pos_world
         {
+            self.history
+                .begin(format!("Add {}", kind_name(kind)), &self.db);
             let id = match kind {
-                InstanceKind::Gate(kind) => self.db.circuit.new_gate(Gate { pos, kind }),
-                InstanceKind::Power => self.db.circuit.new_power(Power { pos, on: true }),
+                InstanceKind::Gate(kind) => self.db.circuit.new_gate(Gate::new(pos, kind)),
+                InstanceKind::Power => self.db.circuit.new_power(Power::new(pos, true)),
                 InstanceKind::Wire => self.db.circuit.new_wire(Wire::new_at(pos)),
-                InstanceKind::Lamp => self.db.circuit.new_lamp(Lamp { pos }),
-                InstanceKind::Clock => self.db.circuit.new_clock(Clock { pos }),
+                InstanceKind::Lamp => self.db.circuit.new_lamp(Lamp::new(pos)),
+                InstanceKind::Clock => self.db.circuit.new_clock(Clock::new(pos)),
                 InstanceKind::Module(c) => self.db.new_module(c, pos),
+                InstanceKind::Splitter(kind) => {
+                    self.db.circuit.new_splitter(Splitter::new(pos, kind))
+                }
+                InstanceKind::FlipFlop(kind) => {
+                    self.db.circuit.new_flip_flop(FlipFlop::new(pos, kind))
+                }
+                InstanceKind::Memory(kind) => self.db.circuit.new_memory(Memory::new(pos, kind)),
+                InstanceKind::Tunnel => self.db.circuit.new_tunnel(Tunnel::new(pos, "net")),
             };
             self.set_drag(Drag::Canvas(crate::drag::CanvasDrag::Single {
                 id,
@@ -552,6 +824,7 @@ impl App {
             && d_pressed
             && let InstanceKind::Module(i) = kind
         {
+            self.checkpoint("Delete module");
             let mut ids = Vec::new();
             for (id, m) in &self.circuit().modules {
                 if m.definition_id == i {
@@ -580,6 +853,7 @@ impl App {
         if resp.drag_started()
             && let Some(pos) = mouse
         {
+            self.history.begin("Add label", &self.db);
             let id = self.db.circuit.new_label(Label::new(pos));
             self.set_drag(Drag::Label {
                 id,
@@ -619,6 +893,7 @@ impl App {
             && !self.clipboard.is_empty()
             && let Some(mouse) = mouse_pos_world
         {
+            self.checkpoint("Paste");
             self.paste_from_clipboard(mouse);
             self.current_dirty = true;
         }
@@ -632,7 +907,8 @@ impl App {
         let bs_pressed = ui.input(|i| i.key_pressed(egui::Key::Backspace));
         let d_pressed = ui.input(|i| i.key_pressed(egui::Key::D));
 
-        if bs_pressed || d_pressed {
+        if (bs_pressed || d_pressed) && (self.hovered.is_some() || !self.selected.is_empty()) {
+            self.checkpoint("Delete");
             if let Some(id) = self.hovered.take() {
                 match id {
                     Hover::Pin(pin) => self.delete_instance(pin.ins),
@@ -647,6 +923,57 @@ impl App {
         }
     }
 
+    /// P toggles the probe on the hovered pin or wire
+    fn handle_probing(&mut self, ui: &Ui) {
+        if ui.input(|i| i.key_pressed(egui::Key::P))
+            && let Some(pin) = self.hovered_probe_pin()
+        {
+            if self.simulator.recorder.toggle(pin) {
+                self.show_waveform = true;
+            }
+            self.current_dirty = true;
+        }
+    }
+
+    /// R turns the hovered instance, or else the selection, a quarter turn clockwise and F
+    /// mirrors it
+    fn handle_orientation(&mut self, ui: &Ui) {
+        if ui.ctx().wants_keyboard_input() {
+            return;
+        }
+        let rotate = ui.input(|i| i.key_pressed(egui::Key::R));
+        let flip = ui.input(|i| i.key_pressed(egui::Key::F));
+        if !rotate && !flip {
+            return;
+        }
+        let targets: Vec<InstanceId> = match self.hovered {
+            Some(hovered) => vec![hovered.instance()],
+            None => self.selected.iter().copied().collect(),
+        };
+        let targets: Vec<InstanceId> = targets
+            .into_iter()
+            .filter(|&id| !self.db.is_hidden(id) && self.db.circuit.orientation(id).is_some())
+            .collect();
+        if targets.is_empty() {
+            return;
+        }
+
+        self.checkpoint(if rotate { "Rotate" } else { "Flip" });
+        for &id in &targets {
+            if let Some(orientation) = self.db.circuit.orientation(id) {
+                let orientation = if rotate {
+                    orientation.rotated()
+                } else {
+                    orientation.flipped()
+                };
+                self.db.circuit.set_orientation(id, orientation);
+            }
+        }
+        self.connection_manager.mark_instances_dirty(&targets);
+        self.connection_manager.update_connections(&mut self.db);
+        self.current_dirty = true;
+    }
+
     pub fn delete_instance(&mut self, id: InstanceId) {
         self.hovered.take();
         self.drag.take();
@@ -679,7 +1006,7 @@ impl App {
 
         ui.set_clip_rect(canvas_rect);
 
-        Self::draw_grid(ui, canvas_rect, view_module.viewport_offset);
+        Self::draw_grid(ui, canvas_rect, view_module.viewport_offset, 1.0);
 
         let center = canvas_rect.center();
 
@@ -716,11 +1043,27 @@ impl App {
                     self.draw_instance_graphics(ui, graphics, pos, id, true);
                 }
                 InstanceKind::Wire => {
-                    let wire = *self.circuit().get_wire(id);
-                    let start = center + wire.start.to_vec2();
-                    let end = center + wire.end.to_vec2();
-                    let has_current = self.is_on(wire_start(id));
-                    self.draw_wire_with_pos(ui, id, start, end);
+                    let points: Vec<Pos2> = (self.circuit().get_wire(id).points().iter())
+                        .map(|p| center + p.to_vec2())
+                        .collect();
+                    self.draw_wire_with_pos(ui, id, &points);
+                }
+                InstanceKind::Splitter(_) => {
+                    let pos = center + self.circuit().get_splitter(id).pos.to_vec2();
+                    self.paint_splitter(ui, id, pos);
+                }
+                InstanceKind::FlipFlop(kind) => {
+                    let pos = center + self.circuit().get_flip_flop(id).pos.to_vec2();
+                    self.draw_instance_graphics(ui, kind.graphics(), pos, id, true);
+                    self.paint_flip_flop_labels(ui, id, pos);
+                }
+                InstanceKind::Memory(_) => {
+                    let pos = center + self.circuit().get_memory(id).pos.to_vec2();
+                    self.paint_memory(ui, id, pos);
+                }
+                InstanceKind::Tunnel => {
+                    let pos = center + self.circuit().get_tunnel(id).pos.to_vec2();
+                    self.paint_tunnel(ui, id, pos);
                 }
                 InstanceKind::Module(_) => {
                     let (pos, definition_id) = {
@@ -734,20 +1077,20 @@ impl App {
                         let pins = self.circuit().get_module(id).pins();
                         let pin_offsets: Vec<Vec2> = pins
                             .iter()
-                            .map(|pin| {
-                                definition.calculate_pin_offset(
-                                    &self.db,
-                                    &pins,
-                                    pin,
-                                    &self.canvas_config,
-                                )
+                            .map(|&pin| {
+                                self.circuit()
+                                    .pin_offset(pin, &self.canvas_config, &self.db)
                             })
                             .collect();
 
                         (name, pins, pin_offsets)
                     };
 
-                    let rect = Rect::from_center_size(pos, self.canvas_config.base_gate_size);
+                    let orientation = self.circuit().get_module(id).orientation;
+                    let rect = Rect::from_center_size(
+                        pos,
+                        orientation.size(self.canvas_config.base_gate_size),
+                    );
                     ui.painter().rect_filled(
                         rect,
                         CornerRadius::default(),
@@ -790,13 +1133,28 @@ impl App {
     }
 
     fn draw_canvas(&mut self, ui: &mut Ui) {
+        let (rect, _) = ui.allocate_exact_size(ui.available_size(), Sense::hover());
+        self.canvas_rect = rect;
+
+        // Everything on the canvas goes on a layer scaled by the zoom
+        let layer = LayerId::new(ui.layer_id().order, ui.id().with("canvas"));
+        ui.ctx().set_sublayer(ui.layer_id(), layer);
+        ui.ctx()
+            .set_transform_layer(layer, TSTransform::from_scaling(self.zoom));
+        let rect = TSTransform::from_scaling(1.0 / self.zoom) * rect;
+        self.connection_manager.set_zoom(self.zoom);
+        let mut ui = ui.new_child(UiBuilder::new().layer_id(layer).max_rect(rect));
+        self.draw_canvas_layer(&mut ui);
+    }
+
+    fn draw_canvas_layer(&mut self, ui: &mut Ui) {
         let (resp, _painter) = ui.allocate_painter(ui.available_size(), Sense::click_and_drag());
         let canvas_rect = resp.rect;
 
         // Set clip rectangle to prevent canvas objects from drawing outside canvas bounds
         ui.set_clip_rect(canvas_rect);
 
-        Self::draw_grid(ui, canvas_rect, self.viewport_offset);
+        Self::draw_grid(ui, canvas_rect, self.viewport_offset, self.zoom);
 
         let mouse_clicked_canvas = resp.clicked();
         let mouse_dragging_canvas = resp.dragged_by(egui::PointerButton::Primary);
@@ -826,11 +1184,16 @@ impl App {
                 self.panning = false;
             }
             if self.panning {
-                self.viewport_offset += ui.input(|i| i.pointer.delta());
+                self.viewport_offset += ui.input(|i| i.pointer.delta()) / self.zoom;
+            }
+            if mouse_is_visible {
+                self.handle_zoom(ui);
             }
 
             self.handle_copy_pasting(ui, mouse_pos_world);
             self.handle_deletion(ui);
+            self.handle_probing(ui);
+            self.handle_orientation(ui);
 
             if let Some(editing_id) = self.editing_label {
                 let label = self.db.circuit.get_label_mut(editing_id);
@@ -838,6 +1201,7 @@ impl App {
                 if mouse_up || enter_pressed || esc_pressed {
                     self.editing_label = None;
                     self.label_edit_buffer.clear();
+                    self.history.finish(&self.db);
                 }
             }
 
@@ -845,6 +1209,7 @@ impl App {
                 && self.hovered.is_none()
                 && let Some(mouse) = mouse_pos_world
             {
+                self.history.begin("Add label", &self.db);
                 let id = self.db.circuit.new_label(Label::new(mouse));
                 self.editing_label = Some(id);
                 self.label_edit_buffer = String::from("Label");
@@ -866,6 +1231,7 @@ impl App {
                     if self.connection_manager.update_connections(&mut self.db) {
                         self.current_dirty = true;
                     }
+                    self.history.finish(&self.db);
                 }
             }
         }
@@ -899,9 +1265,13 @@ impl App {
             }
         }
 
-        if self.current_dirty {
-            self.simulator.clocks_on = self.clock_controller.voltage;
-            self.simulator.compute(&self.db, &self.db.circuit);
+        let advance_to = self.clock_controller.advance_to.take();
+        if self.current_dirty || advance_to.is_some() {
+            match advance_to {
+                Some(until) => self.simulator.advance_to(&self.db, &self.db.circuit, until),
+                None => self.simulator.compute(&self.db, &self.db.circuit),
+            };
+            self.simulator.store_state(&mut self.db.circuit);
             self.current_dirty = false;
         }
 
@@ -912,6 +1282,9 @@ impl App {
             .filter(|id| self.db.is_hidden(*id))
             .collect();
         self.draw_circuit_components(ui, |id| !hidden_instances.contains(&id));
+        self.draw_conflicts(ui);
+        self.draw_oscillation(ui);
+        self.draw_probes(ui);
 
         for c in &self.potential_connections {
             // Highlight the pin that it's going to attach. The stable pin.
@@ -921,7 +1294,7 @@ impl App {
                 .pin_position(pin_to_highlight, &self.canvas_config, &self.db);
             ui.painter().circle_filled(
                 p - self.viewport_offset,
-                SNAP_THRESHOLD,
+                self.connection_manager.snap_distance(),
                 COLOR_POTENTIAL_CONN_HIGHLIGHT,
             );
         }
@@ -964,6 +1337,26 @@ impl App {
                 self.draw_module(ui, id);
             }
         }
+        for id in self.db.circuit.splitter_ids() {
+            if filter(id) {
+                self.draw_splitter(ui, id);
+            }
+        }
+        for id in self.db.circuit.flip_flop_ids() {
+            if filter(id) {
+                self.draw_flip_flop(ui, id);
+            }
+        }
+        for id in self.db.circuit.memory_ids() {
+            if filter(id) {
+                self.draw_memory(ui, id);
+            }
+        }
+        for id in self.db.circuit.tunnel_ids() {
+            if filter(id) {
+                self.draw_tunnel(ui, id);
+            }
+        }
         for id in self.db.circuit.wire_ids() {
             if filter(id) {
                 self.draw_wire(
@@ -982,41 +1375,114 @@ impl App {
         }
     }
 
-    fn draw_grid(ui: &Ui, canvas_rect: Rect, viewport_offset: Vec2) {
+    /// Mark pins where drivers disagree
+    fn draw_conflicts(&self, ui: &Ui) {
+        for &pin in &self.simulator.conflicts {
+            if !self.db.circuit.types.contains_key(pin.ins) || self.db.is_hidden(pin.ins) {
+                continue;
+            }
+            let pos = self.adjusted_pos(self.circuit().pin_position(
+                pin,
+                &self.canvas_config,
+                &self.db,
+            ));
+            ui.painter().circle_stroke(
+                pos,
+                CONFLICT_MARKER_RADIUS,
+                Stroke::new(2.0, COLOR_CONFLICT),
+            );
+            ui.painter().text(
+                pos + vec2(0.0, -CONFLICT_MARKER_RADIUS - 2.0),
+                egui::Align2::CENTER_BOTTOM,
+                "!",
+                egui::FontId::default(),
+                COLOR_CONFLICT,
+            );
+        }
+    }
+
+    /// Outline the loops that kept the last compute from settling and mark the pins that were
+    /// still toggling when it gave up
+    fn draw_oscillation(&self, ui: &Ui) {
+        let visible =
+            |pin: &&Pin| self.db.circuit.types.contains_key(pin.ins) && !self.db.is_hidden(pin.ins);
+        let stroke = Stroke::new(INSTANEC_OUTLINE_THICKNESS, COLOR_OSCILLATING);
+        for found in &self.simulator.loops {
+            let active = found
+                .instances
+                .iter()
+                .any(|&id| self.simulator.oscillating.iter().any(|pin| pin.ins == id));
+            if !active {
+                continue;
+            }
+            for &id in &found.instances {
+                if !self.db.circuit.types.contains_key(id) || self.db.is_hidden(id) {
+                    continue;
+                }
+                let rect = self
+                    .instance_bounds(id)
+                    .translate(-self.viewport_offset)
+                    .expand(INSTANEC_OUTLINE_EXPAND);
+                ui.painter().rect_stroke(
+                    rect,
+                    CornerRadius::default(),
+                    stroke,
+                    StrokeKind::Outside,
+                );
+            }
+        }
+        for pin in self.simulator.oscillating.iter().filter(visible) {
+            let pos = self.adjusted_pos(self.circuit().pin_position(
+                *pin,
+                &self.canvas_config,
+                &self.db,
+            ));
+            ui.painter()
+                .circle_stroke(pos, OSCILLATING_MARKER_RADIUS, stroke);
+        }
+    }
+
+    fn draw_grid(ui: &Ui, canvas_rect: Rect, viewport_offset: Vec2, zoom: f32) {
         let grid_color = if ui.visuals().dark_mode {
             COLOR_GRID_DARK
         } else {
             COLOR_GRID_LIGHT
         };
+        // Zoomed out, every other line is skipped until they are far enough apart
+        let mut step = GRID_SIZE;
+        while step * zoom < MIN_GRID_SPACING {
+            step *= 2.0;
+        }
+        let stroke = Stroke::new(1.0 / zoom, grid_color);
 
         let painter = ui.painter();
 
         // Draw vertical lines
         let start_x =
-            (canvas_rect.left() / GRID_SIZE).floor() * GRID_SIZE - viewport_offset.x % GRID_SIZE;
+            (canvas_rect.left() / step).floor() * step - viewport_offset.x.rem_euclid(step);
         let mut x = start_x;
         while x <= canvas_rect.right() {
             if x >= canvas_rect.left() {
                 painter.line_segment(
                     [pos2(x, canvas_rect.top()), pos2(x, canvas_rect.bottom())],
-                    Stroke::new(1.0, grid_color),
+                    stroke,
                 );
             }
-            x += GRID_SIZE;
+            x += step;
         }
 
         // Draw horizontal lines
         let start_y =
-            (canvas_rect.top() / GRID_SIZE).floor() * GRID_SIZE - viewport_offset.y % GRID_SIZE;
+            (canvas_rect.top() / step).floor() * step - viewport_offset.y.rem_euclid(step);
         let mut y = start_y;
         while y <= canvas_rect.bottom() {
             if y >= canvas_rect.top() {
                 painter.line_segment(
                     [pos2(canvas_rect.left(), y), pos2(canvas_rect.right(), y)],
-                    Stroke::new(1.0, grid_color),
+                    stroke,
                 );
             }
-            y += GRID_SIZE;
+            y += step;
         }
     }
 
@@ -1028,17 +1494,30 @@ impl App {
         id: InstanceId,
         readonly: bool,
     ) -> Rect {
-        let rect = Rect::from_center_size(pos, self.canvas_config.base_gate_size);
+        let orientation = self.db.circuit.orientation(id).unwrap_or_default();
+        let size = self.canvas_config.base_gate_size;
         let sense = if readonly {
             Sense::hover()
         } else {
             Sense::click_and_drag()
         };
-        let image = get_icon(ui, graphics.svg)
-            .fit_to_exact_size(rect.size())
-            .sense(sense);
+        let rect = Rect::from_center_size(pos, orientation.size(size));
+        let response = ui.allocate_rect(rect.expand(INSTANEC_OUTLINE_EXPAND), sense);
+        if ui.visuals().dark_mode {
+            ui.painter()
+                .rect_filled(rect, CornerRadius::default(), Color32::WHITE);
+        }
+        // The image is turned around its center, so it is painted into the unturned box
+        let uv = if orientation.mirrored {
+            Rect::from_min_max(pos2(1.0, 0.0), pos2(0.0, 1.0))
+        } else {
+            Rect::from_min_max(Pos2::ZERO, pos2(1.0, 1.0))
+        };
+        egui::Image::new(graphics.svg)
+            .uv(uv)
+            .rotate(orientation.angle(), Vec2::splat(0.5))
+            .paint_at(ui, Rect::from_center_size(pos, size));
         let rect = rect.expand(INSTANEC_OUTLINE_EXPAND);
-        let response = ui.put(rect, image);
 
         if !readonly {
             if response.clicked() {
@@ -1046,7 +1525,7 @@ impl App {
                 self.selected.insert(id);
             }
             if response.dragged()
-                && let Some(mouse) = ui.ctx().pointer_interact_pos()
+                && let Some(mouse) = self.mouse_pos_canvas(ui)
             {
                 // Only clear selection if dragging an unselected item
                 if !self.selected.contains(&id) {
@@ -1061,9 +1540,18 @@ impl App {
         if response.hovered() {
             self.hovered = Some(Hover::Instance(id));
         }
+        if let Some(name) = self.db.circuit.name(id) {
+            ui.painter().text(
+                rect.center_top(),
+                egui::Align2::CENTER_BOTTOM,
+                name,
+                egui::FontId::proportional(12.0),
+                ui.visuals().text_color(),
+            );
+        }
 
         for (i, pin) in graphics.pins.iter().enumerate() {
-            let pin_pos = pos + pin.offset;
+            let pin_pos = pos + orientation.apply(pin.offset);
             let color = match pin.kind {
                 assets::PinKind::Input => self.canvas_config.base_input_pin_color,
                 assets::PinKind::Output => self.canvas_config.base_output_pin_color,
@@ -1071,7 +1559,7 @@ impl App {
 
             let rect = Rect::from_center_size(
                 pin_pos,
-                Vec2::splat(self.canvas_config.base_pin_size + PIN_HOVER_THRESHOLD),
+                Vec2::splat(self.canvas_config.base_pin_size + PIN_HOVER_THRESHOLD / self.zoom),
             );
             let pin_sense = if readonly {
                 Sense::hover()
@@ -1121,9 +1609,9 @@ impl App {
 
     fn draw_lamp(&mut self, ui: &mut Ui, id: InstanceId) {
         let has_current = self.is_on(lamp_input(id));
-        let (pos, graphics) = {
+        let (pos, graphics, orientation) = {
             let lamp = self.db.circuit.get_lamp(id);
-            (lamp.pos, lamp.graphics())
+            (lamp.pos, lamp.graphics(), lamp.orientation)
         };
         let pos = self.adjusted_pos(pos);
 
@@ -1135,7 +1623,7 @@ impl App {
                 let radius = glow_radius * (1.0 - t);
                 let alpha = (255.0 * (1.0 - t) * 0.4) as u8;
                 ui.painter().circle_filled(
-                    pos + vec2(0.0, -25.0),
+                    pos + orientation.apply(vec2(0.0, -25.0)),
                     radius,
                     Color32::from_rgba_unmultiplied(255, 255, 0, alpha),
                 );
@@ -1167,15 +1655,21 @@ impl App {
             let pins = self.db.circuit.get_module(id).pins();
             let pin_offsets: Vec<Vec2> = pins
                 .iter()
-                .map(|pin| {
-                    definition.calculate_pin_offset(&self.db, &pins, pin, &self.canvas_config)
+                .map(|&pin| {
+                    self.db
+                        .circuit
+                        .pin_offset(pin, &self.canvas_config, &self.db)
                 })
                 .collect();
 
             (name, pins, pin_offsets)
         };
 
-        let rect = Rect::from_center_size(screen_center, self.canvas_config.base_gate_size);
+        let orientation = self.db.circuit.get_module(id).orientation;
+        let rect = Rect::from_center_size(
+            screen_center,
+            orientation.size(self.canvas_config.base_gate_size),
+        );
         ui.painter()
             .rect_filled(rect, CornerRadius::default(), egui::Color32::DARK_BLUE);
 
@@ -1197,7 +1691,7 @@ impl App {
             self.hovered = Some(Hover::Instance(id));
         }
         if response.dragged()
-            && let Some(mouse) = ui.ctx().pointer_interact_pos()
+            && let Some(mouse) = self.mouse_pos_canvas(ui)
         {
             // Only clear selection if dragging an unselected item
             if !self.selected.contains(&id) {
@@ -1233,7 +1727,7 @@ impl App {
 
             let pin_rect = Rect::from_center_size(
                 pin_screen_pos,
-                Vec2::splat(self.canvas_config.base_pin_size + PIN_HOVER_THRESHOLD),
+                Vec2::splat(self.canvas_config.base_pin_size + PIN_HOVER_THRESHOLD / self.zoom),
             );
             let pin_resp = ui.allocate_rect(pin_rect, Sense::drag());
             if pin_resp.hovered() {
@@ -1246,23 +1740,282 @@ impl App {
         }
     }
 
-    pub fn draw_wire_with_pos(&mut self, ui: &mut Ui, id: InstanceId, start: Pos2, end: Pos2) {
+    fn draw_flip_flop(&mut self, ui: &mut Ui, id: InstanceId) {
+        let (pos, kind) = {
+            let flip_flop = self.db.circuit.get_flip_flop(id);
+            (self.adjusted_pos(flip_flop.pos), flip_flop.kind)
+        };
+        self.draw_instance_graphics(ui, kind.graphics(), pos, id, false);
+        self.paint_flip_flop_labels(ui, id, pos);
+    }
+
+    /// Pin names inside the body and the stored state in the middle
+    fn paint_flip_flop_labels(&self, ui: &Ui, id: InstanceId, screen_pos: Pos2) {
+        let flip_flop = self.db.circuit.get_flip_flop(id);
+        let graphics = flip_flop.kind.graphics();
+        let font = egui::FontId::proportional(9.0);
+        let color = if ui.visuals().dark_mode {
+            Color32::BLACK
+        } else {
+            Color32::DARK_GRAY
+        };
+        for (role, pin) in flip_flop.kind.pins().iter().zip(graphics.pins) {
+            let (inset, align) = if pin.offset.x < 0.0 {
+                (vec2(18.0, 0.0), egui::Align2::LEFT_CENTER)
+            } else if pin.offset.x > 0.0 {
+                (vec2(-18.0, 0.0), egui::Align2::RIGHT_CENTER)
+            } else if pin.offset.y < 0.0 {
+                (vec2(0.0, 12.0), egui::Align2::CENTER_TOP)
+            } else {
+                (vec2(0.0, -12.0), egui::Align2::CENTER_BOTTOM)
+            };
+            ui.painter().text(
+                screen_pos + pin.offset + inset,
+                align,
+                role.label(),
+                font.clone(),
+                color,
+            );
+        }
+        ui.painter().text(
+            screen_pos,
+            egui::Align2::CENTER_CENTER,
+            flip_flop.state.q.as_char().to_string(),
+            egui::FontId::proportional(14.0),
+            color,
+        );
+    }
+
+    /// Paint the body and pins of a splitter centered at `screen_pos`
+    fn paint_splitter(&self, ui: &Ui, id: InstanceId, screen_pos: Pos2) -> Rect {
+        let splitter = *self.db.circuit.get_splitter(id);
+        let rect = Rect::from_center_size(screen_pos, splitter.size());
+        ui.painter()
+            .rect_filled(rect, CornerRadius::default(), Color32::DARK_GRAY);
+        ui.painter().text(
+            rect.center(),
+            egui::Align2::CENTER_CENTER,
+            splitter.width.to_string(),
+            egui::FontId::default(),
+            Color32::WHITE,
+        );
+
+        for pin in splitter.pins(id) {
+            let pin_pos = screen_pos + splitter.pin_offset(pin.index);
+            let pin_color = match pin.kind {
+                PinKind::Input => Color32::LIGHT_RED,
+                PinKind::Output => Color32::LIGHT_GREEN,
+            };
+            ui.painter()
+                .circle_filled(pin_pos, self.canvas_config.base_pin_size, pin_color);
+            if self.is_on(pin) {
+                ui.painter().circle_stroke(
+                    pin_pos,
+                    self.canvas_config.base_pin_size + 3.0,
+                    Stroke::new(2.0, COLOR_PIN_POWERED_OUTLINE),
+                );
+            }
+        }
+        rect
+    }
+
+    fn draw_splitter(&mut self, ui: &mut Ui, id: InstanceId) {
+        let splitter = *self.db.circuit.get_splitter(id);
+        let screen_center = self.adjusted_pos(splitter.pos);
+        let rect = self.paint_splitter(ui, id, screen_center);
+        let pins: Vec<(Pin, Vec2)> = splitter
+            .pins(id)
+            .into_iter()
+            .map(|pin| (pin, splitter.pin_offset(pin.index)))
+            .collect();
+        self.interact_box(ui, id, rect, screen_center, &pins);
+    }
+
+    /// Paint the body, pins and pin names of a memory centered at `screen_pos`
+    fn paint_memory(&self, ui: &Ui, id: InstanceId, screen_pos: Pos2) -> Rect {
+        let memory = self.db.circuit.get_memory(id);
+        let rect = Rect::from_center_size(screen_pos, memory.size());
+        ui.painter()
+            .rect_filled(rect, CornerRadius::default(), Color32::DARK_GRAY);
+        ui.painter().text(
+            rect.center_top() + vec2(0.0, 4.0),
+            egui::Align2::CENTER_TOP,
+            format!("{:?}", memory.kind).to_uppercase(),
+            egui::FontId::default(),
+            Color32::WHITE,
+        );
+        ui.painter().text(
+            rect.center_bottom() - vec2(0.0, 4.0),
+            egui::Align2::CENTER_BOTTOM,
+            format!("{}x{}", memory.contents.len(), memory.data_width),
+            egui::FontId::proportional(9.0),
+            Color32::WHITE,
+        );
+
+        for pin in memory.pins(id) {
+            let pin_pos = screen_pos + memory.pin_offset(pin.index);
+            let (pin_color, inset, align) = match pin.kind {
+                PinKind::Input => (
+                    Color32::LIGHT_RED,
+                    vec2(8.0, 0.0),
+                    egui::Align2::LEFT_CENTER,
+                ),
+                PinKind::Output => (
+                    Color32::LIGHT_GREEN,
+                    vec2(-8.0, 0.0),
+                    egui::Align2::RIGHT_CENTER,
+                ),
+            };
+            ui.painter().text(
+                pin_pos + inset,
+                align,
+                memory.pin_label(pin.index),
+                egui::FontId::proportional(9.0),
+                Color32::WHITE,
+            );
+            ui.painter()
+                .circle_filled(pin_pos, self.canvas_config.base_pin_size, pin_color);
+            if self.is_on(pin) {
+                ui.painter().circle_stroke(
+                    pin_pos,
+                    self.canvas_config.base_pin_size + 3.0,
+                    Stroke::new(2.0, COLOR_PIN_POWERED_OUTLINE),
+                );
+            }
+        }
+        rect
+    }
+
+    fn draw_memory(&mut self, ui: &mut Ui, id: InstanceId) {
+        let memory = self.db.circuit.get_memory(id);
+        let screen_center = self.adjusted_pos(memory.pos);
+        let pins: Vec<(Pin, Vec2)> = memory
+            .pins(id)
+            .into_iter()
+            .map(|pin| (pin, memory.pin_offset(pin.index)))
+            .collect();
+        let rect = self.paint_memory(ui, id, screen_center);
+        self.interact_box(ui, id, rect, screen_center, &pins);
+    }
+
+    /// Paint the flag, name and pin of a tunnel centered at `screen_pos`
+    fn paint_tunnel(&self, ui: &Ui, id: InstanceId, screen_pos: Pos2) -> Rect {
+        let tunnel = self.db.circuit.get_tunnel(id);
+        let points = tunnel
+            .outline()
+            .into_iter()
+            .map(|corner| screen_pos + corner)
+            .collect();
+        ui.painter().add(egui::Shape::convex_polygon(
+            points,
+            Color32::DARK_GRAY,
+            Stroke::NONE,
+        ));
+        // Keep the name clear of the tip
+        ui.painter().text(
+            screen_pos - tunnel.pin_offset() * 0.15,
+            egui::Align2::CENTER_CENTER,
+            &tunnel.name,
+            egui::FontId::proportional(12.0),
+            Color32::WHITE,
+        );
+
+        let pin = tunnel_pin(id);
+        let pin_pos = screen_pos + tunnel.pin_offset();
+        ui.painter().circle_filled(
+            pin_pos,
+            self.canvas_config.base_pin_size,
+            Color32::LIGHT_GREEN,
+        );
+        if self.is_on(pin) {
+            ui.painter().circle_stroke(
+                pin_pos,
+                self.canvas_config.base_pin_size + 3.0,
+                Stroke::new(2.0, COLOR_PIN_POWERED_OUTLINE),
+            );
+        }
+        Rect::from_center_size(screen_pos, tunnel.orientation.size(TUNNEL_SIZE))
+    }
+
+    fn draw_tunnel(&mut self, ui: &mut Ui, id: InstanceId) {
+        let tunnel = self.db.circuit.get_tunnel(id);
+        let screen_center = self.adjusted_pos(tunnel.pos);
+        let pins = [(tunnel_pin(id), tunnel.pin_offset())];
+        let rect = self.paint_tunnel(ui, id, screen_center);
+        self.interact_box(ui, id, rect, screen_center, &pins);
+    }
+
+    /// Selection, dragging and pin interaction for instances drawn as a plain box
+    fn interact_box(
+        &mut self,
+        ui: &mut Ui,
+        id: InstanceId,
+        rect: Rect,
+        screen_center: Pos2,
+        pins: &[(Pin, Vec2)],
+    ) {
+        let response = ui.allocate_rect(rect, Sense::click_and_drag());
+        if response.clicked() {
+            self.selected.clear();
+            self.selected.insert(id);
+        }
+        if response.hovered() {
+            self.hovered = Some(Hover::Instance(id));
+        }
+        if response.dragged()
+            && let Some(mouse) = self.mouse_pos_canvas(ui)
+        {
+            if !self.selected.contains(&id) {
+                self.selected.clear();
+            }
+            self.set_drag(Drag::Canvas(CanvasDrag::Single {
+                id,
+                offset: screen_center - mouse,
+            }));
+        }
+
+        for &(pin, offset) in pins {
+            let pin_rect = Rect::from_center_size(
+                screen_center + offset,
+                Vec2::splat(self.canvas_config.base_pin_size + PIN_HOVER_THRESHOLD / self.zoom),
+            );
+            let pin_resp = ui.allocate_rect(pin_rect, Sense::drag());
+            if pin_resp.hovered() {
+                self.hovered = Some(Hover::Pin(pin));
+            }
+            if pin_resp.dragged() {
+                self.selected.clear();
+                self.set_drag(Drag::PinToWire { source_pin: pin });
+            }
+        }
+    }
+
+    /// Buses are drawn thicker than single bit wires
+    fn wire_stroke_width(&self, id: InstanceId) -> f32 {
+        if self.db.circuit.get_wire(id).width > 1 {
+            self.canvas_config.wire_thickness * 2.0
+        } else {
+            self.canvas_config.wire_thickness
+        }
+    }
+
+    pub fn draw_wire_with_pos(&mut self, ui: &mut Ui, id: InstanceId, points: &[Pos2]) {
         let has_current = self.is_on(wire_start(id));
         let color = if has_current {
             COLOR_WIRE_POWERED
         } else {
             COLOR_WIRE_IDLE
         };
-        for (i, pin_pos) in [start, end].iter().enumerate() {
+        for pin_pos in [points[0], points[points.len() - 1]] {
             let pin_color = color;
             ui.painter()
-                .circle(*pin_pos, PIN_HOVER_THRESHOLD / 2.0, pin_color, Stroke::NONE);
+                .circle(pin_pos, PIN_HOVER_THRESHOLD / 2.0, pin_color, Stroke::NONE);
         }
 
-        ui.painter().line_segment(
-            [start, end],
-            Stroke::new(self.canvas_config.wire_thickness, color),
-        );
+        ui.painter().add(egui::Shape::line(
+            points.to_vec(),
+            Stroke::new(self.wire_stroke_width(id), color),
+        ));
     }
 
     pub fn draw_wire(&mut self, ui: &mut Ui, id: InstanceId, hovered: bool, readonly: bool) {
@@ -1275,7 +2028,7 @@ impl App {
         if hovered {
             color = COLOR_WIRE_HOVER;
         }
-        let wire = *self.db.circuit.get_wire(id);
+        let wire = self.db.circuit.get_wire(id).clone();
         let mut pin_interact = false;
 
         let pin_sense = if readonly {
@@ -1294,7 +2047,7 @@ impl App {
             let pin = Pin::new(id, i as u32, kind);
             let rect = Rect::from_center_size(
                 pin_pos,
-                Vec2::splat(self.canvas_config.base_pin_size + PIN_HOVER_THRESHOLD),
+                Vec2::splat(self.canvas_config.base_pin_size + PIN_HOVER_THRESHOLD / self.zoom),
             );
             let pin_resp = ui.allocate_rect(rect, pin_sense);
             if pin_resp.hovered() {
@@ -1338,14 +2091,17 @@ impl App {
                 .circle(pin_pos, PIN_HOVER_THRESHOLD / 2.0, pin_color, Stroke::NONE);
         }
 
-        let start = self.adjusted_pos(wire.start);
-        let end = self.adjusted_pos(wire.end);
+        let points: Vec<Pos2> = wire
+            .points()
+            .iter()
+            .map(|&p| self.adjusted_pos(p))
+            .collect();
 
         let hit_wire = if pin_interact {
             false
         } else if let Some(mouse_world) = self.mouse_pos_world(ui) {
-            let dist = wire.dist_to_closest_point_on_line(mouse_world);
-            dist < WIRE_HIT_DISTANCE
+            let dist = wire.distance_to(mouse_world);
+            dist < WIRE_HIT_DISTANCE / self.zoom
         } else {
             false
         };
@@ -1389,20 +2145,26 @@ impl App {
                         start_mouse_pos: mouse,
                     });
                 } else {
-                    let wire_center = pos2(
-                        (wire.start.x + wire.end.x) * 0.5,
-                        (wire.start.y + wire.end.y) * 0.5,
-                    );
-                    let offset = wire_center - mouse;
+                    let offset = wire.center() - mouse;
                     self.set_drag(Drag::Canvas(CanvasDrag::Single { id, offset }));
                 }
             }
+
+            if !readonly
+                && ui.input(|i| {
+                    i.pointer
+                        .button_double_clicked(egui::PointerButton::Primary)
+                })
+                && let Some(mouse) = self.mouse_pos_world(ui)
+            {
+                self.toggle_bend(id, mouse);
+            }
         }
 
-        ui.painter().line_segment(
-            [start, end],
-            Stroke::new(self.canvas_config.wire_thickness, color),
-        );
+        ui.painter().add(egui::Shape::line(
+            points,
+            Stroke::new(self.wire_stroke_width(id), color),
+        ));
     }
     fn draw_label(&mut self, ui: &mut Ui, id: LabelId) {
         let (pos, text) = {
@@ -1460,10 +2222,12 @@ impl App {
             );
 
             if response.double_clicked() {
+                self.history.begin("Edit label", &self.db);
                 self.editing_label = Some(id);
                 self.label_edit_buffer = text;
             }
             if response.hovered() && ui.input(|i| i.key_pressed(egui::Key::D)) {
+                self.checkpoint("Delete label");
                 self.delete_label(id);
             }
         }
@@ -1500,7 +2264,7 @@ impl App {
                     let gate = self.db.circuit.get_gate(hovered);
                     let outer = Rect::from_center_size(
                         gate.pos - self.viewport_offset,
-                        self.canvas_config.base_gate_size + INSTANEC_OUTLINE,
+                        gate.orientation.size(self.canvas_config.base_gate_size) + INSTANEC_OUTLINE,
                     );
                     ui.painter().rect_stroke(
                         outer,
@@ -1513,7 +2277,8 @@ impl App {
                     let power = self.db.circuit.get_power(hovered);
                     let outer = Rect::from_center_size(
                         power.pos - self.viewport_offset,
-                        self.canvas_config.base_gate_size + INSTANEC_OUTLINE,
+                        power.orientation.size(self.canvas_config.base_gate_size)
+                            + INSTANEC_OUTLINE,
                     );
                     ui.painter().rect_stroke(
                         outer,
@@ -1526,7 +2291,7 @@ impl App {
                     let lamp = self.db.circuit.get_lamp(hovered);
                     let outer = Rect::from_center_size(
                         lamp.pos - self.viewport_offset,
-                        self.canvas_config.base_gate_size + INSTANEC_OUTLINE,
+                        lamp.orientation.size(self.canvas_config.base_gate_size) + INSTANEC_OUTLINE,
                     );
                     ui.painter().rect_stroke(
                         outer,
@@ -1539,7 +2304,8 @@ impl App {
                     let clock = self.db.circuit.get_clock(hovered);
                     let outer = Rect::from_center_size(
                         clock.pos - self.viewport_offset,
-                        self.canvas_config.base_gate_size + INSTANEC_OUTLINE,
+                        clock.orientation.size(self.canvas_config.base_gate_size)
+                            + INSTANEC_OUTLINE,
                     );
                     ui.painter().rect_stroke(
                         outer,
@@ -1550,11 +2316,63 @@ impl App {
                 }
                 // Wire is highlighted when drawing
                 InstanceKind::Wire => {}
+                InstanceKind::FlipFlop(_) => {
+                    let f = self.db.circuit.get_flip_flop(hovered);
+                    let outer = Rect::from_center_size(
+                        f.pos - self.viewport_offset,
+                        self.canvas_config.base_gate_size + INSTANEC_OUTLINE,
+                    );
+                    ui.painter().rect_stroke(
+                        outer,
+                        CornerRadius::default(),
+                        Stroke::new(INSTANEC_OUTLINE_THICKNESS, COLOR_HOVER_INSTANCE_OUTLINE),
+                        StrokeKind::Middle,
+                    );
+                }
+                InstanceKind::Splitter(_) => {
+                    let s = self.db.circuit.get_splitter(hovered);
+                    let outer = Rect::from_center_size(
+                        s.pos - self.viewport_offset,
+                        s.size() + INSTANEC_OUTLINE,
+                    );
+                    ui.painter().rect_stroke(
+                        outer,
+                        CornerRadius::default(),
+                        Stroke::new(INSTANEC_OUTLINE_THICKNESS, COLOR_HOVER_INSTANCE_OUTLINE),
+                        StrokeKind::Middle,
+                    );
+                }
+                InstanceKind::Tunnel => {
+                    let t = self.db.circuit.get_tunnel(hovered);
+                    let r = Rect::from_center_size(
+                        t.pos - self.viewport_offset,
+                        t.orientation.size(TUNNEL_SIZE) + INSTANEC_OUTLINE,
+                    );
+                    ui.painter().rect_stroke(
+                        r,
+                        CornerRadius::default(),
+                        Stroke::new(INSTANEC_OUTLINE_THICKNESS, COLOR_HOVER_INSTANCE_OUTLINE),
+                        StrokeKind::Middle,
+                    );
+                }
+                InstanceKind::Memory(_) => {
+                    let m = self.db.circuit.get_memory(hovered);
+                    let outer = Rect::from_center_size(
+                        m.pos - self.viewport_offset,
+                        m.size() + INSTANEC_OUTLINE,
+                    );
+                    ui.painter().rect_stroke(
+                        outer,
+                        CornerRadius::default(),
+                        Stroke::new(INSTANEC_OUTLINE_THICKNESS, COLOR_HOVER_INSTANCE_OUTLINE),
+                        StrokeKind::Middle,
+                    );
+                }
                 InstanceKind::Module(_) => {
                     let cc = self.db.circuit.get_module(hovered);
                     let outer = Rect::from_center_size(
                         cc.pos - self.viewport_offset,
-                        self.canvas_config.base_gate_size + INSTANEC_OUTLINE,
+                        cc.orientation.size(self.canvas_config.base_gate_size) + INSTANEC_OUTLINE,
                     );
                     ui.painter().rect_stroke(
                         outer,
@@ -1578,7 +2396,7 @@ impl App {
                     let g = self.db.circuit.get_gate(id);
                     let r = Rect::from_center_size(
                         g.pos - self.viewport_offset,
-                        self.canvas_config.base_gate_size + INSTANEC_OUTLINE,
+                        g.orientation.size(self.canvas_config.base_gate_size) + INSTANEC_OUTLINE,
                     );
                     ui.painter().rect_stroke(
                         r,
@@ -1591,7 +2409,7 @@ impl App {
                     let p = self.db.circuit.get_power(id);
                     let r = Rect::from_center_size(
                         p.pos - self.viewport_offset,
-                        self.canvas_config.base_gate_size + INSTANEC_OUTLINE,
+                        p.orientation.size(self.canvas_config.base_gate_size) + INSTANEC_OUTLINE,
                     );
                     ui.painter().rect_stroke(
                         r,
@@ -1604,7 +2422,7 @@ impl App {
                     let l = self.db.circuit.get_lamp(id);
                     let r = Rect::from_center_size(
                         l.pos - self.viewport_offset,
-                        self.canvas_config.base_gate_size + INSTANEC_OUTLINE,
+                        l.orientation.size(self.canvas_config.base_gate_size) + INSTANEC_OUTLINE,
                     );
                     ui.painter().rect_stroke(
                         r,
@@ -1617,7 +2435,7 @@ impl App {
                     let c = self.db.circuit.get_clock(id);
                     let r = Rect::from_center_size(
                         c.pos - self.viewport_offset,
-                        self.canvas_config.base_gate_size + INSTANEC_OUTLINE,
+                        c.orientation.size(self.canvas_config.base_gate_size) + INSTANEC_OUTLINE,
                     );
                     ui.painter().rect_stroke(
                         r,
@@ -1633,7 +2451,7 @@ impl App {
                             .pin_position(pin, &self.canvas_config, &self.db);
                         ui.painter().circle_filled(
                             pos - self.viewport_offset,
-                            PIN_MOVE_HINT_D,
+                            PIN_MOVE_HINT_D / self.zoom,
                             PIN_MOVE_HINT_COLOR,
                         );
                     }
@@ -1642,6 +2460,19 @@ impl App {
                     let cc = self.db.circuit.get_module(id);
                     let r = Rect::from_center_size(
                         cc.pos - self.viewport_offset,
+                        cc.orientation.size(self.canvas_config.base_gate_size) + INSTANEC_OUTLINE,
+                    );
+                    ui.painter().rect_stroke(
+                        r,
+                        CornerRadius::default(),
+                        Stroke::new(INSTANEC_OUTLINE_THICKNESS, COLOR_SELECTION_HIGHLIGHT),
+                        StrokeKind::Outside,
+                    );
+                }
+                InstanceKind::FlipFlop(_) => {
+                    let f = self.db.circuit.get_flip_flop(id);
+                    let r = Rect::from_center_size(
+                        f.pos - self.viewport_offset,
                         self.canvas_config.base_gate_size + INSTANEC_OUTLINE,
                     );
                     ui.painter().rect_stroke(
@@ -1651,6 +2482,45 @@ impl App {
                         StrokeKind::Outside,
                     );
                 }
+                InstanceKind::Splitter(_) => {
+                    let s = self.db.circuit.get_splitter(id);
+                    let r = Rect::from_center_size(
+                        s.pos - self.viewport_offset,
+                        s.size() + INSTANEC_OUTLINE,
+                    );
+                    ui.painter().rect_stroke(
+                        r,
+                        CornerRadius::default(),
+                        Stroke::new(INSTANEC_OUTLINE_THICKNESS, COLOR_SELECTION_HIGHLIGHT),
+                        StrokeKind::Outside,
+                    );
+                }
+                InstanceKind::Tunnel => {
+                    let t = self.db.circuit.get_tunnel(id);
+                    let r = Rect::from_center_size(
+                        t.pos - self.viewport_offset,
+                        t.orientation.size(TUNNEL_SIZE) + INSTANEC_OUTLINE,
+                    );
+                    ui.painter().rect_stroke(
+                        r,
+                        CornerRadius::default(),
+                        Stroke::new(INSTANEC_OUTLINE_THICKNESS, COLOR_SELECTION_HIGHLIGHT),
+                        StrokeKind::Outside,
+                    );
+                }
+                InstanceKind::Memory(_) => {
+                    let m = self.db.circuit.get_memory(id);
+                    let r = Rect::from_center_size(
+                        m.pos - self.viewport_offset,
+                        m.size() + INSTANEC_OUTLINE,
+                    );
+                    ui.painter().rect_stroke(
+                        r,
+                        CornerRadius::default(),
+                        Stroke::new(INSTANEC_OUTLINE_THICKNESS, COLOR_SELECTION_HIGHLIGHT),
+                        StrokeKind::Outside,
+                    );
+                }
             }
         }
     }
@@ -1674,13 +2544,16 @@ impl App {
         writeln!(out, "  SIMULATION").ok();
         writeln!(out, "======================================").ok();
         match self.simulator.status {
-            SimulationStatus::Stable { iterations } => {
-                writeln!(out, "Status: STABLE ({iterations} iters)").ok();
+            SimulationStatus::Stable { events } => {
+                writeln!(out, "Status: STABLE ({events} events)").ok();
             }
             SimulationStatus::Unstable { max_reached } => {
                 if max_reached {
-                    let iters = self.simulator.last_iterations;
-                    writeln!(out, "Status: UNSTABLE (max: {iters})").ok();
+                    let events = self.simulator.last_events;
+                    writeln!(out, "Status: UNSTABLE (budget: {events} events)").ok();
+                    if let Some(reason) = self.simulator.instability() {
+                        writeln!(out, "Reason: {reason}").ok();
+                    }
                 } else {
                     writeln!(out, "Status: UNSTABLE").ok();
                 }
@@ -1689,10 +2562,13 @@ impl App {
                 writeln!(out, "Status: RUNNING...").ok();
             }
         }
+        writeln!(out, "Time: {}", self.simulator.time).ok();
+        writeln!(out, "Nets: {}", self.simulator.nets.nets.len()).ok();
+        writeln!(out, "Conflicts: {}", self.simulator.conflicts.len()).ok();
         writeln!(
             out,
-            "Clock: {:?}, interval: {:.2}s",
-            self.clock_controller.state, self.clock_controller.tick_interval
+            "Clock: {:?}, speed: {:.1} units/s",
+            self.clock_controller.state, self.clock_controller.speed
         )
         .ok();
 
@@ -1752,6 +2628,22 @@ impl App {
                     let cc = self.db.circuit.get_module(id);
                     points.push(cc.pos);
                 }
+                InstanceKind::Splitter(_) => {
+                    let s = self.db.circuit.get_splitter(id);
+                    points.push(s.pos);
+                }
+                InstanceKind::FlipFlop(_) => {
+                    let f = self.db.circuit.get_flip_flop(id);
+                    points.push(f.pos);
+                }
+                InstanceKind::Memory(_) => {
+                    let m = self.db.circuit.get_memory(id);
+                    points.push(m.pos);
+                }
+                InstanceKind::Tunnel => {
+                    let t = self.db.circuit.get_tunnel(id);
+                    points.push(t.pos);
+                }
             }
         }
         let rect = Rect::from_points(&points);
@@ -1764,28 +2656,44 @@ impl App {
             match ty {
                 InstanceKind::Gate(kind) => {
                     let g = self.db.circuit.get_gate(id);
-                    object_pos.push(ClipBoardItem::Gate(kind, center - g.pos));
+                    object_pos.push(ClipBoardItem::Gate(*g, center - g.pos));
                 }
                 InstanceKind::Power => {
                     let p = self.db.circuit.get_power(id);
-                    object_pos.push(ClipBoardItem::Power(center - p.pos));
+                    object_pos.push(ClipBoardItem::Power(*p, center - p.pos));
                 }
                 InstanceKind::Wire => {
                     let w = self.db.circuit.get_wire(id);
-                    object_pos.push(ClipBoardItem::Wire(center - w.start, center - w.end));
+                    object_pos.push(ClipBoardItem::Wire(w.clone(), center - w.start));
                 }
                 InstanceKind::Lamp => {
                     let l = self.db.circuit.get_lamp(id);
-                    object_pos.push(ClipBoardItem::Lamp(center - l.pos));
+                    object_pos.push(ClipBoardItem::Lamp(*l, center - l.pos));
                 }
                 InstanceKind::Clock => {
                     let c = self.db.circuit.get_clock(id);
-                    object_pos.push(ClipBoardItem::Clock(center - c.pos));
+                    object_pos.push(ClipBoardItem::Clock(*c, center - c.pos));
                 }
                 InstanceKind::Module(_) => {
                     let cc = self.db.circuit.get_module(id);
                     object_pos.push(ClipBoardItem::Module(cc.definition_id, center - cc.pos));
                 }
+                InstanceKind::Splitter(_) => {
+                    let s = self.db.circuit.get_splitter(id);
+                    object_pos.push(ClipBoardItem::Splitter(*s, center - s.pos));
+                }
+                InstanceKind::FlipFlop(_) => {
+                    let f = self.db.circuit.get_flip_flop(id);
+                    object_pos.push(ClipBoardItem::FlipFlop(*f, center - f.pos));
+                }
+                InstanceKind::Memory(_) => {
+                    let m = self.db.circuit.get_memory(id);
+                    object_pos.push(ClipBoardItem::Memory(m.clone(), center - m.pos));
+                }
+                InstanceKind::Tunnel => {
+                    let t = self.db.circuit.get_tunnel(id);
+                    object_pos.push(ClipBoardItem::Tunnel(t.clone(), center - t.pos));
+                }
             }
         }
 
@@ -1813,39 +2721,75 @@ impl App {
         self.selected.clear();
         for to_paste in self.clipboard.clone() {
             match to_paste {
-                ClipBoardItem::Gate(gate_kind, offset) => {
+                ClipBoardItem::Gate(gate, offset) => {
                     let id = self.db.circuit.new_gate(Gate {
-                        kind: gate_kind,
                         pos: mouse - offset,
+                        ..gate
                     });
                     self.connection_manager.mark_instance_dirty(id);
                     self.selected.insert(id);
                 }
-                ClipBoardItem::Power(offset) => {
+                ClipBoardItem::Power(power, offset) => {
                     let id = self.db.circuit.new_power(Power {
                         pos: mouse - offset,
                         on: false,
+                        ..power
+                    });
+                    self.connection_manager.mark_instance_dirty(id);
+                    self.selected.insert(id);
+                }
+                ClipBoardItem::Splitter(splitter, offset) => {
+                    let id = self.db.circuit.new_splitter(Splitter {
+                        pos: mouse - offset,
+                        ..splitter
+                    });
+                    self.connection_manager.mark_instance_dirty(id);
+                    self.selected.insert(id);
+                }
+                ClipBoardItem::FlipFlop(flip_flop, offset) => {
+                    let id = self.db.circuit.new_flip_flop(FlipFlop {
+                        pos: mouse - offset,
+                        ..flip_flop
+                    });
+                    self.connection_manager.mark_instance_dirty(id);
+                    self.selected.insert(id);
+                }
+                ClipBoardItem::Memory(memory, offset) => {
+                    let id = self.db.circuit.new_memory(Memory {
+                        pos: mouse - offset,
+                        ..memory
                     });
                     self.connection_manager.mark_instance_dirty(id);
                     self.selected.insert(id);
                 }
-                ClipBoardItem::Wire(s, e) => {
-                    let id = self.db.circuit.new_wire(Wire::new(mouse - s, mouse - e));
+                ClipBoardItem::Tunnel(tunnel, offset) => {
+                    let id = self.db.circuit.new_tunnel(Tunnel {
+                        pos: mouse - offset,
+                        ..tunnel
+                    });
+                    self.connection_manager.mark_instance_dirty(id);
+                    self.selected.insert(id);
+                }
+                ClipBoardItem::Wire(mut wire, offset) => {
+                    wire.translate(mouse - offset - wire.start);
+                    let id = self.db.circuit.new_wire(wire);
                     self.connection_manager.mark_instance_dirty(id);
                     self.selected.insert(id);
                 }
                 ClipBoardItem::Module(def_index, offset) => {
                     // TODO: Modules
                 }
-                ClipBoardItem::Lamp(offset) => {
+                ClipBoardItem::Lamp(lamp, offset) => {
                     let id = self.db.circuit.new_lamp(Lamp {
                         pos: mouse - offset,
+                        ..lamp
                     });
                     self.selected.insert(id);
                 }
-                ClipBoardItem::Clock(offset) => {
+                ClipBoardItem::Clock(clock, offset) => {
                     let id = self.db.circuit.new_clock(Clock {
                         pos: mouse - offset,
+                        ..clock
                     });
                     self.selected.insert(id);
                 }
@@ -1876,13 +2820,13 @@ impl App {
                         .pin_position(pin, &self.canvas_config, &self.db);
                     ui.painter().circle_filled(
                         pos - self.viewport_offset,
-                        PIN_MOVE_HINT_D,
+                        PIN_MOVE_HINT_D / self.zoom,
                         PIN_MOVE_HINT_COLOR,
                     );
 
                     if let Some(mouse) = mouse
                         && mouse_down
-                        && mouse.distance(pos) < PIN_MOVE_HINT_D
+                        && mouse.distance(pos) < PIN_MOVE_HINT_D / self.zoom
                     {
                         self.set_drag(Drag::Resize {
                             id: selected,
@@ -1890,23 +2834,51 @@ impl App {
                         });
                     }
                 }
+                let bends = self.circuit().get_wire(selected).bends.clone();
+                for (index, bend) in bends.into_iter().enumerate() {
+                    ui.painter().circle_stroke(
+                        self.adjusted_pos(bend),
+                        PIN_MOVE_HINT_D / self.zoom,
+                        Stroke::new(1.0 / self.zoom, PIN_MOVE_HINT_COLOR),
+                    );
+                    if let Some(mouse) = mouse
+                        && mouse_down
+                        && mouse.distance(bend) < PIN_MOVE_HINT_D / self.zoom
+                    {
+                        self.set_drag(Drag::Bend {
+                            id: selected,
+                            index,
+                        });
+                    }
+                }
             }
             InstanceKind::Gate(_)
             | InstanceKind::Power
             | InstanceKind::Lamp
             | InstanceKind::Clock
-            | InstanceKind::Module(_) => {}
+            | InstanceKind::Module(_)
+            | InstanceKind::Splitter(_)
+            | InstanceKind::FlipFlop(_)
+            | InstanceKind::Memory(_)
+            | InstanceKind::Tunnel => {}
         }
     }
 
     pub fn split_wire_at_point(&mut self, wire_id: InstanceId, split_point: Pos2) {
-        let original_wire = *self.db.circuit.get_wire(wire_id);
-
-        let new_wire = Wire::new(split_point, original_wire.end);
+        let original_wire = self.db.circuit.get_wire(wire_id);
+        let (segment, _) = original_wire.closest_point(split_point);
+
+        // Bends past the split point go to the new wire
+        let new_wire = Wire {
+            width: original_wire.width,
+            bends: original_wire.bends[segment..].to_vec(),
+            ..Wire::new(split_point, original_wire.end)
+        };
         let new_wire_id = self.db.circuit.new_wire(new_wire);
 
         let original_wire_mut = self.db.circuit.get_wire_mut(wire_id);
         original_wire_mut.end = split_point;
+        original_wire_mut.bends.truncate(segment);
 
         self.connection_manager
             .mark_instances_dirty(&[wire_id, new_wire_id]);
@@ -1925,11 +2897,11 @@ impl App {
         }
         let wire = self.db.circuit.get_wire(instance_id);
 
-        if wire.dist_to_closest_point_on_line(mouse) > NEW_PIN_ON_WIRE_THRESHOLD {
+        if wire.distance_to(mouse) > NEW_PIN_ON_WIRE_THRESHOLD / self.zoom {
             return None;
         }
 
-        let split_point = wire.closest_point_on_line(mouse);
+        let (_, split_point) = wire.closest_point(mouse);
         if (split_point - wire.start).length() < MIN_WIRE_SIZE
             || (split_point - wire.end).length() < MIN_WIRE_SIZE
         {
@@ -1939,6 +2911,309 @@ impl App {
         Some(split_point)
     }
 
+    /// Window to edit the properties of the single selected instance
+    fn draw_properties(&mut self, ctx: &egui::Context) {
+        if self.selected.len() != 1 {
+            return;
+        }
+        let Some(&id) = self.selected.iter().next() else {
+            return;
+        };
+        if self.db.is_hidden(id) {
+            return;
+        }
+
+        egui::Window::new("Properties")
+            .resizable(false)
+            .anchor(egui::Align2::RIGHT_TOP, [-8.0, 8.0])
+            .show(ctx, |ui| match self.db.circuit.ty(id) {
+                InstanceKind::Gate(kind) => {
+                    ui.label(format!("{kind:?} gate"));
+                    let gate = self.db.circuit.get_gate(id);
+                    let mut overridden = gate.delay.is_some();
+                    let mut delay = gate.delay();
+                    let mut changed = false;
+                    ui.horizontal(|ui| {
+                        changed |= ui.checkbox(&mut overridden, "Delay").changed();
+                        changed |= ui
+                            .add_enabled(
+                                overridden,
+                                egui::DragValue::new(&mut delay).range(0..=1_000),
+                            )
+                            .changed();
+                    });
+                    let mut width = gate.width;
+                    if changed {
+                        self.checkpoint_property(id);
+                        self.db.circuit.get_gate_mut(id).delay = overridden.then_some(delay);
+                        self.current_dirty = true;
+                    }
+                    if Self::width_property(ui, &mut width) {
+                        self.checkpoint_property(id);
+                        self.db.circuit.get_gate_mut(id).width = width;
+                        self.width_changed(id);
+                    }
+                }
+                InstanceKind::Wire => {
+                    ui.label("Wire");
+                    let mut width = self.db.circuit.get_wire(id).width;
+                    if Self::width_property(ui, &mut width) {
+                        self.checkpoint_property(id);
+                        self.db.circuit.get_wire_mut(id).width = width;
+                        self.width_changed(id);
+                    }
+                }
+                InstanceKind::Splitter(kind) => {
+                    ui.label(format!("{kind:?}"));
+                    let mut width = self.db.circuit.get_splitter(id).width;
+                    if Self::width_property(ui, &mut width) {
+                        self.checkpoint_property(id);
+                        self.db.circuit.get_splitter_mut(id).width = width;
+                        self.width_changed(id);
+                    }
+                }
+                InstanceKind::FlipFlop(kind) => {
+                    ui.label(format!("{kind:?}"));
+                    let q = self.db.circuit.get_flip_flop(id).state.q;
+                    ui.label(format!("Q = {}", q.as_char()));
+                }
+                InstanceKind::Memory(kind) => {
+                    ui.label(format!("{kind:?}").to_uppercase());
+                    let memory = self.db.circuit.get_memory(id);
+                    let mut address_width = memory.address_width;
+                    let mut data_width = memory.data_width;
+                    let mut changed = false;
+                    ui.horizontal(|ui| {
+                        ui.label("Address width");
+                        changed |= ui
+                            .add(
+                                egui::DragValue::new(&mut address_width)
+                                    .range(1..=MAX_ADDRESS_WIDTH),
+                            )
+                            .changed();
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Data width");
+                        changed |= ui
+                            .add(egui::DragValue::new(&mut data_width).range(1..=MAX_BUS_WIDTH))
+                            .changed();
+                    });
+                    if changed {
+                        self.checkpoint_property(id);
+                        self.db
+                            .circuit
+                            .get_memory_mut(id)
+                            .resize(address_width, data_width);
+                        self.simulator.memories.remove(&id);
+                        self.width_changed(id);
+                    }
+                    if ui.button("Open in memory viewer").clicked() {
+                        self.show_memory_viewer = true;
+                    }
+                }
+                InstanceKind::Clock => {
+                    ui.label("Clock");
+                    let mut clock = *self.db.circuit.get_clock(id);
+                    let mut changed = false;
+                    egui::Grid::new("clock_properties").show(ui, |ui| {
+                        ui.label("Period");
+                        changed |= ui
+                            .add(egui::DragValue::new(&mut clock.period).range(2..=1_000_000))
+                            .changed();
+                        ui.end_row();
+                        ui.label("Phase");
+                        changed |= ui
+                            .add(egui::DragValue::new(&mut clock.phase).range(0..=1_000_000))
+                            .changed();
+                        ui.end_row();
+                        ui.label("Duty cycle");
+                        changed |= ui
+                            .add(
+                                egui::DragValue::new(&mut clock.duty_cycle)
+                                    .range(1..=99)
+                                    .suffix("%"),
+                            )
+                            .changed();
+                        ui.end_row();
+                    });
+                    ui.label(format!(
+                        "High for {} of {}",
+                        clock.high_time(),
+                        clock.period
+                    ));
+                    if changed {
+                        self.checkpoint_property(id);
+                        *self.db.circuit.get_clock_mut(id) = clock;
+                        self.current_dirty = true;
+                    }
+                }
+                InstanceKind::Power | InstanceKind::Lamp => {
+                    let label = if matches!(self.db.circuit.ty(id), InstanceKind::Power) {
+                        "Input"
+                    } else {
+                        "Output"
+                    };
+                    ui.label(label);
+                    self.name_property(ui, id);
+                }
+                InstanceKind::Tunnel => {
+                    ui.label("Tunnel");
+                    let tunnel = self.db.circuit.get_tunnel(id);
+                    let mut name = tunnel.name.clone();
+                    let mut width = tunnel.width;
+                    let renamed = ui
+                        .horizontal(|ui| {
+                            ui.label("Net");
+                            ui.text_edit_singleline(&mut name).changed()
+                        })
+                        .inner;
+                    if renamed {
+                        self.checkpoint_property(id);
+                        self.db.circuit.get_tunnel_mut(id).name = name;
+                        self.current_dirty = true;
+                    }
+                    if Self::width_property(ui, &mut width) {
+                        self.checkpoint_property(id);
+                        self.db.circuit.get_tunnel_mut(id).width = width;
+                        self.width_changed(id);
+                    }
+                    let others = self.db.circuit.same_tunnels(id).len() - 1;
+                    ui.label(format!("Connected to {others} other tunnels"));
+                }
+                InstanceKind::Module(_) => {
+                    ui.label("No editable properties");
+                }
+            });
+    }
+
+    /// Side panel listing the words of the selected memory. Edits go to the stored contents, the
+    /// simulator then starts again from them.
+    fn draw_memory_viewer(&mut self, ctx: &egui::Context) {
+        egui::SidePanel::right("memory_viewer")
+            .resizable(true)
+            .default_width(360.0)
+            .show(ctx, |ui| {
+                ui.heading("Memory Viewer");
+                let memory_id = self.selected.iter().copied().find(|&id| {
+                    self.selected.len() == 1
+                        && !self.db.is_hidden(id)
+                        && matches!(self.db.circuit.ty(id), InstanceKind::Memory(_))
+                });
+                let Some(id) = memory_id else {
+                    ui.label("Select a ROM or RAM to see its contents");
+                    return;
+                };
+
+                let memory = self.db.circuit.get_memory(id);
+                ui.label(format!(
+                    "{} words of {} bits",
+                    memory.contents.len(),
+                    memory.data_width
+                ));
+                let address_digits = usize::from(memory.address_width.div_ceil(4));
+                let data_digits = usize::from(memory.data_width.div_ceil(4));
+                let mask = memory.data_mask();
+                let address = self
+                    .simulator
+                    .pin_value(self.circuit(), memory_address(id))
+                    .to_u64();
+                match address {
+                    Some(a) => ui.label(format!("Address: {a:0address_digits$X}")),
+                    None => ui.label("Address: unknown"),
+                };
+
+                ui.horizontal(|ui| {
+                    if ui.button("Load hex…").clicked()
+                        && let Err(e) = self.load_memory_from_file(id)
+                    {
+                        log::error!("Failed to load memory image: {e}");
+                    }
+                    if ui.button("Clear").clicked() {
+                        self.db.circuit.get_memory_mut(id).load(&[]);
+                        self.memory_edited(id);
+                    }
+                });
+                ui.separator();
+
+                const WORDS_PER_ROW: usize = 8;
+                let rows = self
+                    .db
+                    .circuit
+                    .get_memory(id)
+                    .contents
+                    .len()
+                    .div_ceil(WORDS_PER_ROW);
+                let row_height = ui.spacing().interact_size.y;
+                let mut edited = false;
+                egui::ScrollArea::both().show_rows(ui, row_height, rows, |ui, row_range| {
+                    for row in row_range {
+                        ui.horizontal(|ui| {
+                            let start = row * WORDS_PER_ROW;
+                            ui.monospace(format!("{start:0address_digits$X}:"));
+                            let contents = &mut self.db.circuit.get_memory_mut(id).contents;
+                            let end = (start + WORDS_PER_ROW).min(contents.len());
+                            for (offset, word) in contents[start..end].iter_mut().enumerate() {
+                                let mut response = ui.add(
+                                    egui::DragValue::new(word)
+                                        .hexadecimal(data_digits, false, true)
+                                        .range(0..=mask)
+                                        .speed(0.0),
+                                );
+                                if address == Some((start + offset) as u64) {
+                                    response = response.highlight();
+                                }
+                                edited |= response.changed();
+                            }
+                        });
+                    }
+                });
+                if edited {
+                    self.memory_edited(id);
+                }
+            });
+    }
+
+    /// Stored contents of a memory changed outside the simulation
+    pub fn memory_edited(&mut self, id: InstanceId) {
+        self.simulator.memories.remove(&id);
+        self.current_dirty = true;
+    }
+
+    /// Name editor, names identify inputs and outputs for the command line tester
+    fn name_property(&mut self, ui: &mut Ui, id: InstanceId) {
+        let mut name = self.db.circuit.name(id).unwrap_or_default().to_owned();
+        ui.horizontal(|ui| {
+            ui.label("Name");
+            if ui.text_edit_singleline(&mut name).changed() {
+                self.checkpoint_property(id);
+                self.db.circuit.set_name(id, &name);
+            }
+        });
+        if let Some(name) = self.db.circuit.name(id)
+            && self.db.circuit.find_by_name(name).is_err()
+        {
+            ui.colored_label(ui.visuals().warn_fg_color, "Name is used more than once");
+        }
+    }
+
+    /// Bus width editor, returns true when the width changed
+    fn width_property(ui: &mut Ui, width: &mut u8) -> bool {
+        ui.horizontal(|ui| {
+            ui.label("Width");
+            ui.add(egui::DragValue::new(width).range(1..=MAX_BUS_WIDTH))
+                .changed()
+        })
+        .inner
+    }
+
+    /// Connections of an instance are checked again after its width changes
+    fn width_changed(&mut self, id: InstanceId) {
+        self.connection_manager
+            .rebuild_spatial_index(&self.db.circuit, &self.db);
+        self.connection_manager.mark_instance_dirty(id);
+        self.current_dirty = true;
+    }
+
     fn create_module(&mut self) {
         self.creating_module = true;
         self.module_name_buffer = format!("module {}", self.db.module_definitions.len() + 1);
@@ -1952,8 +3227,11 @@ impl App {
             return;
         }
 
+        self.history.finish(&self.db);
+        let before = self.db.clone();
         match self.create_module_definition(name.clone(), &self.selected.clone()) {
             Ok(()) => {
+                self.history.push("Create module", before);
                 self.selected.clear();
                 self.creating_module = false;
                 self.module_name_buffer.clear();
@@ -1972,9 +3250,28 @@ impl App {
     }
 
     pub fn mouse_pos_world(&self, ui: &Ui) -> Option<Pos2> {
-        ui.ctx()
-            .pointer_interact_pos()
-            .map(|p| p + self.viewport_offset)
+        ui.ctx().pointer_interact_pos().map(|p| self.to_world(p))
+    }
+
+    // Mouse position on the canvas layer, the world position adjusted to the screen
+    fn mouse_pos_canvas(&self, ui: &Ui) -> Option<Pos2> {
+        self.mouse_pos_world(ui).map(|p| self.adjusted_pos(p))
+    }
+}
+
+/// Name of an instance kind in history labels
+fn kind_name(kind: InstanceKind) -> String {
+    match kind {
+        InstanceKind::Gate(kind) => format!("{kind:?} gate"),
+        InstanceKind::Power => "input".to_owned(),
+        InstanceKind::Lamp => "output".to_owned(),
+        InstanceKind::Clock => "clock".to_owned(),
+        InstanceKind::Wire => "wire".to_owned(),
+        InstanceKind::Module(_) => "module".to_owned(),
+        InstanceKind::Splitter(kind) => format!("{kind:?}"),
+        InstanceKind::FlipFlop(kind) => format!("{kind:?}"),
+        InstanceKind::Memory(kind) => format!("{kind:?}").to_uppercase(),
+        InstanceKind::Tunnel => "tunnel".to_owned(),
     }
 }
 
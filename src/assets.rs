@@ -155,6 +155,25 @@ pub static NOT_GRAPHICS: InstanceGraphics = InstanceGraphics {
     ],
 };
 
+/// Data input on the left, enable input below the body.
+pub static TRISTATE_GRAPHICS: InstanceGraphics = InstanceGraphics {
+    svg: include_image!("../assets/tristate.svg"),
+    pins: &[
+        PinGraphics {
+            kind: PinKind::Input,
+            offset: Vec2::new(-40.0, 0.0),
+        },
+        PinGraphics {
+            kind: PinKind::Input,
+            offset: Vec2::new(-3.0, 30.0),
+        },
+        PinGraphics {
+            kind: PinKind::Output,
+            offset: Vec2::new(40.0, 0.0),
+        },
+    ],
+};
+
 pub static POWER_ON_GRAPHICS: InstanceGraphics = InstanceGraphics {
     svg: include_image!("../assets/switch-on.svg"),
     pins: &[PinGraphics {
@@ -186,3 +205,146 @@ pub static CLOCK_GRAPHICS: InstanceGraphics = InstanceGraphics {
         offset: Vec2::new(40.0, 0.0),
     }],
 };
+
+/// S, R inputs on the left, Q and not Q on the right.
+pub static SR_LATCH_GRAPHICS: InstanceGraphics = InstanceGraphics {
+    svg: include_image!("../assets/sr-latch.svg"),
+    pins: &[
+        PinGraphics {
+            kind: PinKind::Input,
+            offset: Vec2::new(-40.0, -15.0),
+        },
+        PinGraphics {
+            kind: PinKind::Input,
+            offset: Vec2::new(-40.0, 15.0),
+        },
+        PinGraphics {
+            kind: PinKind::Output,
+            offset: Vec2::new(40.0, -15.0),
+        },
+        PinGraphics {
+            kind: PinKind::Output,
+            offset: Vec2::new(40.0, 15.0),
+        },
+    ],
+};
+
+/// D and enable inputs on the left, Q and not Q on the right.
+pub static D_LATCH_GRAPHICS: InstanceGraphics = InstanceGraphics {
+    svg: include_image!("../assets/d-latch.svg"),
+    pins: &[
+        PinGraphics {
+            kind: PinKind::Input,
+            offset: Vec2::new(-40.0, -15.0),
+        },
+        PinGraphics {
+            kind: PinKind::Input,
+            offset: Vec2::new(-40.0, 15.0),
+        },
+        PinGraphics {
+            kind: PinKind::Output,
+            offset: Vec2::new(40.0, -15.0),
+        },
+        PinGraphics {
+            kind: PinKind::Output,
+            offset: Vec2::new(40.0, 15.0),
+        },
+    ],
+};
+
+/// Async set on top, D and clock on the left, async reset at the bottom.
+pub static D_FLIP_FLOP_GRAPHICS: InstanceGraphics = InstanceGraphics {
+    svg: include_image!("../assets/d-flip-flop.svg"),
+    pins: &[
+        PinGraphics {
+            kind: PinKind::Input,
+            offset: Vec2::new(0.0, -40.0),
+        },
+        PinGraphics {
+            kind: PinKind::Input,
+            offset: Vec2::new(-40.0, -15.0),
+        },
+        PinGraphics {
+            kind: PinKind::Input,
+            offset: Vec2::new(-40.0, 15.0),
+        },
+        PinGraphics {
+            kind: PinKind::Input,
+            offset: Vec2::new(0.0, 40.0),
+        },
+        PinGraphics {
+            kind: PinKind::Output,
+            offset: Vec2::new(40.0, -15.0),
+        },
+        PinGraphics {
+            kind: PinKind::Output,
+            offset: Vec2::new(40.0, 15.0),
+        },
+    ],
+};
+
+/// Async set on top, J, clock and K on the left, async reset at the bottom.
+pub static JK_FLIP_FLOP_GRAPHICS: InstanceGraphics = InstanceGraphics {
+    svg: include_image!("../assets/jk-flip-flop.svg"),
+    pins: &[
+        PinGraphics {
+            kind: PinKind::Input,
+            offset: Vec2::new(0.0, -40.0),
+        },
+        PinGraphics {
+            kind: PinKind::Input,
+            offset: Vec2::new(-40.0, -20.0),
+        },
+        PinGraphics {
+            kind: PinKind::Input,
+            offset: Vec2::new(-40.0, 0.0),
+        },
+        PinGraphics {
+            kind: PinKind::Input,
+            offset: Vec2::new(-40.0, 20.0),
+        },
+        PinGraphics {
+            kind: PinKind::Input,
+            offset: Vec2::new(0.0, 40.0),
+        },
+        PinGraphics {
+            kind: PinKind::Output,
+            offset: Vec2::new(40.0, -15.0),
+        },
+        PinGraphics {
+            kind: PinKind::Output,
+            offset: Vec2::new(40.0, 15.0),
+        },
+    ],
+};
+
+/// Async set on top, T and clock on the left, async reset at the bottom.
+pub static T_FLIP_FLOP_GRAPHICS: InstanceGraphics = InstanceGraphics {
+    svg: include_image!("../assets/t-flip-flop.svg"),
+    pins: &[
+        PinGraphics {
+            kind: PinKind::Input,
+            offset: Vec2::new(0.0, -40.0),
+        },
+        PinGraphics {
+            kind: PinKind::Input,
+            offset: Vec2::new(-40.0, -15.0),
+        },
+        PinGraphics {
+            kind: PinKind::Input,
+            offset: Vec2::new(-40.0, 15.0),
+        },
+        PinGraphics {
+            kind: PinKind::Input,
+            offset: Vec2::new(0.0, 40.0),
+        },
+        PinGraphics {
+            kind: PinKind::Output,
+            offset: Vec2::new(40.0, -15.0),
+        },
+        PinGraphics {
+            kind: PinKind::Output,
+            offset: Vec2::new(40.0, 15.0),
+        },
+    ],
+};
@@ -0,0 +1,44 @@
+//! Run a saved circuit against a file of test vectors.
+//!
+//! Usage: `simu-check <circuit.json> <vectors.csv>`
+//!
+//! Exits with 0 when every vector passes, 1 when some vector fails and 2 when the files could
+//! not be read. See [`simu::test_vectors`] for the vector format.
+
+use std::process::ExitCode;
+
+use simu::{builder::Simulation, save_load::db_from_json, test_vectors};
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().collect();
+    let [_, circuit, vectors] = args.as_slice() else {
+        eprintln!("usage: simu-check <circuit.json> <vectors.csv>");
+        return ExitCode::from(2);
+    };
+
+    let report = std::fs::read_to_string(circuit)
+        .map_err(|e| format!("{circuit}: {e}"))
+        .and_then(|json| db_from_json(&json).map_err(|e| format!("{circuit}: {e}")))
+        .and_then(|db| {
+            let text = std::fs::read_to_string(vectors).map_err(|e| format!("{vectors}: {e}"))?;
+            test_vectors::run(&mut Simulation::new(db), &text)
+                .map_err(|e| format!("{vectors}: {e}"))
+        });
+    let report = match report {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let failures = report.failures();
+    if failures == 0 {
+        println!("all {} vectors passed", report.vectors.len());
+        ExitCode::SUCCESS
+    } else {
+        print!("{}", report.diff_table());
+        println!("{failures} of {} vectors failed", report.vectors.len());
+        ExitCode::FAILURE
+    }
+}
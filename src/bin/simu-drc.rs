@@ -0,0 +1,50 @@
+//! Run the design rule checks on a saved circuit.
+//!
+//! Usage: `simu-drc [--deny-warnings] <circuit.json>`
+//!
+//! Exits with 0 when there are no errors, 1 when some rule reports an error, or a warning with
+//! `--deny-warnings`, and 2 when the file could not be read. See [`simu::drc`] for the rules.
+
+use std::process::ExitCode;
+
+use simu::{
+    drc::{self, Severity},
+    save_load::db_from_json,
+};
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (deny_warnings, circuit) = match args.as_slice() {
+        [circuit] => (false, circuit),
+        [flag, circuit] if flag == "--deny-warnings" => (true, circuit),
+        _ => {
+            eprintln!("usage: simu-drc [--deny-warnings] <circuit.json>");
+            return ExitCode::from(2);
+        }
+    };
+
+    let db = match std::fs::read_to_string(circuit)
+        .map_err(|e| e.to_string())
+        .and_then(|json| db_from_json(&json))
+    {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("error: {circuit}: {e}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let violations = drc::check(&db);
+    print!("{}", drc::format_report(&violations));
+    let errors = violations
+        .iter()
+        .filter(|v| v.rule.severity() == Severity::Error)
+        .count();
+    let warnings = violations.len() - errors;
+    println!("{errors} errors, {warnings} warnings");
+    if errors > 0 || (deny_warnings && warnings > 0) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,582 @@
+//! Boolean expressions and functions given as truth tables.
+//!
+//! Expressions are written with `!` or `~` for NOT (or a `'` after an operand), `&` or `*` for
+//! AND, `^` for XOR and `|` or `+` for OR, binding in that order, plus parentheses and the
+//! constants `0` and `1`. Names start with a letter or `_`.
+//!
+//! Truth tables list rows in counting order with the first input as the most significant bit,
+//! the same order as the generated truth tables. They are minimized to a sum of products with the
+//! Quine-McCluskey method.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Largest number of inputs of a function
+pub const MAX_INPUTS: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Const(bool),
+    Var(String),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Xor(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+/// Product of literals, `(input, true)` is an input and `(input, false)` its complement
+pub type Term = Vec<(usize, bool)>;
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            chars: text.chars().collect(),
+            pos: 0,
+        };
+        let expr = parser.or()?;
+        parser.skip_space();
+        if let Some(c) = parser.peek() {
+            return Err(format!("unexpected `{c}` at column {}", parser.pos + 1));
+        }
+        Ok(expr)
+    }
+
+    /// Names used in the expression, sorted
+    pub fn variables(&self) -> BTreeSet<String> {
+        let mut names = BTreeSet::new();
+        self.collect_variables(&mut names);
+        names
+    }
+
+    fn collect_variables(&self, names: &mut BTreeSet<String>) {
+        match self {
+            Self::Const(_) => {}
+            Self::Var(name) => {
+                names.insert(name.clone());
+            }
+            Self::Not(a) => a.collect_variables(names),
+            Self::And(a, b) | Self::Xor(a, b) | Self::Or(a, b) => {
+                a.collect_variables(names);
+                b.collect_variables(names);
+            }
+        }
+    }
+
+    /// Value with the inputs named in `inputs` set from the bits of `row`, the first input being
+    /// the most significant bit
+    pub fn eval(&self, inputs: &[String], row: usize) -> bool {
+        match self {
+            Self::Const(value) => *value,
+            Self::Var(name) => inputs
+                .iter()
+                .position(|input| input == name)
+                .is_some_and(|i| row >> (inputs.len() - 1 - i) & 1 == 1),
+            Self::Not(a) => !a.eval(inputs, row),
+            Self::And(a, b) => a.eval(inputs, row) && b.eval(inputs, row),
+            Self::Xor(a, b) => a.eval(inputs, row) != b.eval(inputs, row),
+            Self::Or(a, b) => a.eval(inputs, row) || b.eval(inputs, row),
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Self::Or(..) => 0,
+            Self::Xor(..) => 1,
+            Self::And(..) => 2,
+            Self::Not(_) | Self::Const(_) | Self::Var(_) => 3,
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operand = |f: &mut fmt::Formatter<'_>, e: &Self, min: u8| {
+            if e.precedence() < min {
+                write!(f, "({e})")
+            } else {
+                write!(f, "{e}")
+            }
+        };
+        match self {
+            Self::Const(value) => write!(f, "{}", u8::from(*value)),
+            Self::Var(name) => write!(f, "{name}"),
+            Self::Not(a) => {
+                write!(f, "!")?;
+                operand(f, a, 3)
+            }
+            Self::And(a, b) | Self::Xor(a, b) | Self::Or(a, b) => {
+                let (symbol, level) = match self {
+                    Self::And(..) => (" & ", 2),
+                    Self::Xor(..) => (" ^ ", 1),
+                    _ => (" | ", 0),
+                };
+                operand(f, a, level)?;
+                write!(f, "{symbol}")?;
+                operand(f, b, level + 1)
+            }
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_space(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// Consume the next character when it is one of `symbols`
+    fn eat(&mut self, symbols: &[char]) -> bool {
+        self.skip_space();
+        let found = self.peek().is_some_and(|c| symbols.contains(&c));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.xor()?;
+        while self.eat(&['|', '+']) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.xor()?));
+        }
+        Ok(expr)
+    }
+
+    fn xor(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.eat(&['^']) {
+            expr = Expr::Xor(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while self.eat(&['&', '*', '·']) {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat(&['!', '~', '¬']) {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        let mut expr = self.primary()?;
+        while self.eat(&['\'']) {
+            expr = Expr::Not(Box::new(expr));
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        self.skip_space();
+        let column = self.pos + 1;
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let expr = self.or()?;
+                if !self.eat(&[')']) {
+                    return Err(format!("missing `)` for the `(` at column {column}"));
+                }
+                Ok(expr)
+            }
+            Some('0') => {
+                self.pos += 1;
+                Ok(Expr::Const(false))
+            }
+            Some('1') => {
+                self.pos += 1;
+                Ok(Expr::Const(true))
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                let mut name = String::new();
+                while let Some(c) = self
+                    .peek()
+                    .filter(|&c| c.is_ascii_alphanumeric() || c == '_')
+                {
+                    name.push(c);
+                    self.pos += 1;
+                }
+                Ok(Expr::Var(name))
+            }
+            Some(c) => Err(format!("unexpected `{c}` at column {column}")),
+            None => Err("expression ends too early".to_owned()),
+        }
+    }
+}
+
+/// One output column of a truth table
+#[derive(Debug, Clone)]
+pub struct Output {
+    pub name: String,
+    /// Value of every row, `None` where the value does not matter
+    pub values: Vec<Option<bool>>,
+}
+
+/// Outputs as functions of the inputs
+#[derive(Debug, Clone)]
+pub struct TruthFunction {
+    pub inputs: Vec<String>,
+    pub outputs: Vec<Output>,
+}
+
+impl TruthFunction {
+    /// Function of lines like `y = a & b`, a line without a name is output `y`, `y1`...
+    pub fn from_expressions(text: &str) -> Result<Self, String> {
+        let mut parsed = Vec::new();
+        for (line, text) in text.lines().enumerate() {
+            if text.trim().is_empty() {
+                continue;
+            }
+            let (name, expr) = match text.split_once('=') {
+                Some((name, expr)) => (name.trim().to_owned(), expr),
+                None if parsed.is_empty() => ("y".to_owned(), text),
+                None => (format!("y{}", parsed.len()), text),
+            };
+            let expr = Expr::parse(expr).map_err(|e| format!("line {}: {e}", line + 1))?;
+            parsed.push((name, expr));
+        }
+        if parsed.is_empty() {
+            return Err("type an expression like y = (a & b) | !c".to_owned());
+        }
+
+        let mut inputs = BTreeSet::new();
+        for (_, expr) in &parsed {
+            inputs.extend(expr.variables());
+        }
+        let inputs: Vec<String> = inputs.into_iter().collect();
+        if inputs.len() > MAX_INPUTS {
+            return Err(format!(
+                "{} inputs are too many, at most {MAX_INPUTS} are supported",
+                inputs.len()
+            ));
+        }
+        let outputs = parsed
+            .into_iter()
+            .map(|(name, expr)| Output {
+                values: (0..1 << inputs.len())
+                    .map(|row| Some(expr.eval(&inputs, row)))
+                    .collect(),
+                name,
+            })
+            .collect();
+        let function = Self { inputs, outputs };
+        function.check_names()?;
+        Ok(function)
+    }
+
+    pub fn rows(&self) -> usize {
+        1 << self.inputs.len()
+    }
+
+    /// Value of input `input` in `row`
+    pub fn input_bit(&self, row: usize, input: usize) -> bool {
+        row >> (self.inputs.len() - 1 - input) & 1 == 1
+    }
+
+    /// Every name must be a plain identifier and used once
+    pub fn check_names(&self) -> Result<(), String> {
+        let mut seen = BTreeSet::new();
+        for name in self
+            .inputs
+            .iter()
+            .chain(self.outputs.iter().map(|o| &o.name))
+        {
+            let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid {
+                return Err(format!("`{name}` is not a valid name"));
+            }
+            if !seen.insert(name) {
+                return Err(format!("`{name}` is used more than once"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether `term` over `inputs` inputs is true in `row`
+pub fn covers(term: &Term, inputs: usize, row: usize) -> bool {
+    term.iter()
+        .all(|&(input, value)| (row >> (inputs - 1 - input) & 1 == 1) == value)
+}
+
+/// OR of the terms, each the AND of its literals
+pub fn sum_of_products(inputs: &[String], terms: &[Term]) -> Expr {
+    let product = |term: &Term| {
+        term.iter()
+            .map(|&(input, value)| {
+                let var = Expr::Var(inputs[input].clone());
+                if value { var } else { Expr::Not(Box::new(var)) }
+            })
+            .reduce(|a, b| Expr::And(Box::new(a), Box::new(b)))
+            .unwrap_or(Expr::Const(true))
+    };
+    terms
+        .iter()
+        .map(product)
+        .reduce(|a, b| Expr::Or(Box::new(a), Box::new(b)))
+        .unwrap_or(Expr::Const(false))
+}
+
+/// Rows sharing `value` on the bits outside `free`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct Implicant {
+    value: usize,
+    free: usize,
+}
+
+impl Implicant {
+    fn covers(self, row: usize) -> bool {
+        row & !self.free == self.value
+    }
+
+    fn literals(self, inputs: usize) -> usize {
+        inputs - self.free.count_ones() as usize
+    }
+
+    fn term(self, inputs: usize) -> Term {
+        (0..inputs)
+            .filter_map(|input| {
+                let bit = 1 << (inputs - 1 - input);
+                (self.free & bit == 0).then_some((input, self.value & bit != 0))
+            })
+            .collect()
+    }
+}
+
+/// Smallest sum of products for `values` over `inputs` inputs, with the Quine-McCluskey method
+///
+/// Rows without a value may be covered or not. The cover of the prime implicants is exact unless
+/// the search gives up on very large functions and keeps the best cover found.
+pub fn minimize(values: &[Option<bool>], inputs: usize) -> Vec<Term> {
+    let minterms: Vec<usize> = (0..values.len())
+        .filter(|&row| values[row] == Some(true))
+        .collect();
+    let primes = prime_implicants(values, inputs);
+    // Minterms by the index they have in `minterms`
+    let covered_by: Vec<Vec<usize>> = primes
+        .iter()
+        .map(|prime| {
+            (0..minterms.len())
+                .filter(|&m| prime.covers(minterms[m]))
+                .collect()
+        })
+        .collect();
+    let mut covering = vec![Vec::new(); minterms.len()];
+    for (p, minterms) in covered_by.iter().enumerate() {
+        for &m in minterms {
+            covering[m].push(p);
+        }
+    }
+
+    // Primes that are the only cover of a minterm are always part of the result
+    let mut chosen: Vec<usize> = covering
+        .iter()
+        .filter_map(|primes| match primes[..] {
+            [p] => Some(p),
+            _ => None,
+        })
+        .collect();
+    chosen.sort_unstable();
+    chosen.dedup();
+    let mut done = vec![false; minterms.len()];
+    for &p in &chosen {
+        for &m in &covered_by[p] {
+            done[m] = true;
+        }
+    }
+    let left: Vec<usize> = (0..minterms.len()).filter(|&m| !done[m]).collect();
+
+    let mut search = CoverSearch {
+        primes: &primes,
+        inputs,
+        covered_by: &covered_by,
+        covering: &covering,
+        best: greedy_cover(&covered_by, &primes, inputs, done),
+        budget: 10_000,
+    };
+    search.run(&mut Vec::new(), &left);
+    chosen.extend(search.best);
+
+    let mut chosen: Vec<Implicant> = chosen.into_iter().map(|p| primes[p]).collect();
+    chosen.sort_by(|a, b| b.value.cmp(&a.value).then(a.free.cmp(&b.free)));
+    chosen.iter().map(|prime| prime.term(inputs)).collect()
+}
+
+fn prime_implicants(values: &[Option<bool>], inputs: usize) -> Vec<Implicant> {
+    let mut current: BTreeSet<Implicant> = (0..values.len())
+        .filter(|&row| values[row] != Some(false))
+        .map(|value| Implicant { value, free: 0 })
+        .collect();
+    let mut primes = Vec::new();
+    while !current.is_empty() {
+        let mut combined = BTreeSet::new();
+        let mut next = BTreeSet::new();
+        for &implicant in &current {
+            for bit in (0..inputs).map(|i| 1 << i) {
+                if implicant.free & bit != 0 || implicant.value & bit != 0 {
+                    continue;
+                }
+                let partner = Implicant {
+                    value: implicant.value | bit,
+                    free: implicant.free,
+                };
+                if current.contains(&partner) {
+                    combined.insert(implicant);
+                    combined.insert(partner);
+                    next.insert(Implicant {
+                        value: implicant.value,
+                        free: implicant.free | bit,
+                    });
+                }
+            }
+        }
+        primes.extend(current.difference(&combined));
+        current = next;
+    }
+    primes
+}
+
+/// Cover of the minterms not `done`, taking the prime that covers most of them each time
+fn greedy_cover(
+    covered_by: &[Vec<usize>],
+    primes: &[Implicant],
+    inputs: usize,
+    mut done: Vec<bool>,
+) -> Vec<usize> {
+    let mut cover = Vec::new();
+    loop {
+        let Some((best, count)) = covered_by
+            .iter()
+            .map(|minterms| minterms.iter().filter(|&&m| !done[m]).count())
+            .enumerate()
+            .max_by_key(|&(p, count)| (count, std::cmp::Reverse(primes[p].literals(inputs))))
+        else {
+            break;
+        };
+        if count == 0 {
+            break;
+        }
+        cover.push(best);
+        for &m in &covered_by[best] {
+            done[m] = true;
+        }
+    }
+    cover
+}
+
+/// Branch and bound for the cover with the fewest terms, then the fewest literals
+struct CoverSearch<'a> {
+    primes: &'a [Implicant],
+    inputs: usize,
+    /// Minterms covered by each prime
+    covered_by: &'a [Vec<usize>],
+    /// Primes covering each minterm
+    covering: &'a [Vec<usize>],
+    best: Vec<usize>,
+    budget: usize,
+}
+
+impl CoverSearch<'_> {
+    fn cost(&self, cover: &[usize]) -> (usize, usize) {
+        let literals = cover
+            .iter()
+            .map(|&p| self.primes[p].literals(self.inputs))
+            .sum();
+        (cover.len(), literals)
+    }
+
+    fn run(&mut self, chosen: &mut Vec<usize>, left: &[usize]) {
+        if self.budget == 0 {
+            return;
+        }
+        self.budget -= 1;
+        if left.is_empty() {
+            if self.cost(chosen) < self.cost(&self.best) {
+                self.best = chosen.clone();
+            }
+            return;
+        }
+        if chosen.len() + 1 > self.best.len() {
+            return;
+        }
+        // Branch on the minterm with the fewest primes covering it
+        let Some(&m) = left.iter().min_by_key(|&&m| self.covering[m].len()) else {
+            return;
+        };
+        for &p in &self.covering[m] {
+            chosen.push(p);
+            let rest: Vec<usize> = left
+                .iter()
+                .copied()
+                .filter(|m| !self.covered_by[p].contains(m))
+                .collect();
+            self.run(chosen, &rest);
+            chosen.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Expr, TruthFunction, covers, minimize, sum_of_products};
+
+    #[test]
+    fn parse_and_print() -> Result<(), String> {
+        let expr = Expr::parse("(a & b) | !c ^ a'")?;
+        assert_eq!(expr.to_string(), "a & b | !c ^ !a");
+        assert_eq!(Expr::parse("!(a | b) & c")?.to_string(), "!(a | b) & c");
+        assert!(Expr::parse("a & (b | c").is_err());
+        assert!(Expr::parse("a b").is_err());
+
+        let function = TruthFunction::from_expressions("y = (a & b) | !c")?;
+        assert_eq!(function.inputs, ["a", "b", "c"]);
+        let ones: Vec<bool> = function.outputs[0]
+            .values
+            .iter()
+            .map(|v| *v == Some(true))
+            .collect();
+        assert_eq!(ones, [true, false, true, false, true, false, true, true]);
+        Ok(())
+    }
+
+    #[test]
+    fn minimize_with_dont_cares() {
+        // Sum of the minterms 4, 8, 10, 11, 12 and 15, with 9 and 14 not mattering
+        let mut values = vec![Some(false); 16];
+        for row in [4, 8, 10, 11, 12, 15] {
+            values[row] = Some(true);
+        }
+        values[9] = None;
+        values[14] = None;
+        let terms = minimize(&values, 4);
+        assert_eq!(terms.len(), 3);
+        assert_eq!(terms.iter().map(Vec::len).sum::<usize>(), 7);
+        for (row, value) in values.iter().enumerate() {
+            if let Some(value) = value {
+                assert_eq!(terms.iter().any(|t| covers(t, 4, row)), *value);
+            }
+        }
+        let inputs = ["a", "b", "c", "d"].map(str::to_owned);
+        assert_eq!(
+            sum_of_products(&inputs, &minimize(&[Some(true); 16], 4)).to_string(),
+            "1"
+        );
+        assert_eq!(
+            sum_of_products(&inputs, &minimize(&[Some(false); 16], 4)).to_string(),
+            "0"
+        );
+    }
+}
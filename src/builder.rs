@@ -0,0 +1,415 @@
+//! Build and simulate circuits from plain Rust, without the editor.
+//!
+//! Instances get a unique name, stored in [`Circuit::names`] like the names given in the editor,
+//! and pins are referred to as `instance.pin`. Connections are made
+//! explicitly between pins, nothing is placed on the canvas or snapped together. The pin part can
+//! be left out when the instance has a single pin of the needed direction, so `a` means the
+//! output of the input `a` when it is the source of a connection.
+//!
+//! ```
+//! use simu::builder::CircuitBuilder;
+//! use simu::db::GateKind;
+//! use simu::simulator::Value;
+//!
+//! let mut b = CircuitBuilder::new();
+//! b.input("a")?;
+//! b.input("b")?;
+//! b.gate("sum", GateKind::Xor)?;
+//! b.gate("carry", GateKind::And)?;
+//! b.output("s")?;
+//! b.output("c")?;
+//! b.connect("a", "sum.a")?;
+//! b.connect("b", "sum.b")?;
+//! b.connect("a", "carry.a")?;
+//! b.connect("b", "carry.b")?;
+//! b.connect("sum", "s")?;
+//! b.connect("carry", "c")?;
+//!
+//! let mut sim = b.build();
+//! sim.set("a", true)?;
+//! sim.set("b", true)?;
+//! sim.settle()?;
+//! assert_eq!(sim.value("s")?, Value::Zero);
+//! assert_eq!(sim.value("c")?, Value::One);
+//! # Ok::<(), String>(())
+//! ```
+//!
+//! Pin names by instance:
+//!
+//! - Gates: `a`, `b` and `out`. NOT has `in` and `out`, tri-state has `in`, `en` and `out`.
+//! - Inputs have `out`, outputs have `in` and clocks have `out`.
+//! - Flip flops use their pin labels in lower case: `s`, `r`, `d`, `j`, `k`, `t`, `clk`, `e`,
+//!   `q` and `nq`.
+//! - Memories: `a` and `d` for a ROM, `a`, `din`, `we`, `clk` and `dout` for a RAM.
+//! - Splitters have `in` and `out0`, `out1`... Joiners have `in0`, `in1`... and `out`.
+
+use egui::pos2;
+
+use crate::{
+    assets::PinKind,
+    connection_manager::Connection,
+    db::{
+        Circuit, Clock, DB, FlipFlop, FlipFlopKind, Gate, GateKind, InstanceId, InstanceKind, Lamp,
+        Memory, MemoryKind, Pin, Power, Splitter, SplitterKind,
+    },
+    simulator::{BusValue, SimulationStatus, Simulator, Value},
+};
+
+/// Instances are laid out on a grid so a built circuit is still readable in the editor
+const GRID_COLUMNS: usize = 10;
+const GRID_SPACING: f32 = 120.0;
+
+#[derive(Default)]
+pub struct CircuitBuilder {
+    db: DB,
+}
+
+impl CircuitBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A switch that starts off. Change it with [`Simulation::set`].
+    pub fn input(&mut self, name: &str) -> Result<InstanceId, String> {
+        self.add(name, |circuit, pos| {
+            circuit.new_power(Power::new(pos, false))
+        })
+    }
+
+    /// A lamp, read it with [`Simulation::value`].
+    pub fn output(&mut self, name: &str) -> Result<InstanceId, String> {
+        self.add(name, |circuit, pos| circuit.new_lamp(Lamp::new(pos)))
+    }
+
+    pub fn gate(&mut self, name: &str, kind: GateKind) -> Result<InstanceId, String> {
+        self.add(name, |circuit, pos| circuit.new_gate(Gate::new(pos, kind)))
+    }
+
+    /// A gate working bitwise on buses of `width` bits
+    pub fn bus_gate(
+        &mut self,
+        name: &str,
+        kind: GateKind,
+        width: u8,
+    ) -> Result<InstanceId, String> {
+        self.add(name, |circuit, pos| {
+            circuit.new_gate(Gate {
+                width,
+                ..Gate::new(pos, kind)
+            })
+        })
+    }
+
+    /// A clock rising at `phase` and then every `period` time units, with a 50% duty cycle.
+    /// Other duty cycles can be set through [`CircuitBuilder::circuit_mut`].
+    pub fn clock(&mut self, name: &str, period: u64, phase: u64) -> Result<InstanceId, String> {
+        self.add(name, |circuit, pos| {
+            circuit.new_clock(Clock {
+                period,
+                phase,
+                ..Clock::new(pos)
+            })
+        })
+    }
+
+    pub fn flip_flop(&mut self, name: &str, kind: FlipFlopKind) -> Result<InstanceId, String> {
+        self.add(name, |circuit, pos| {
+            circuit.new_flip_flop(FlipFlop::new(pos, kind))
+        })
+    }
+
+    pub fn memory(
+        &mut self,
+        name: &str,
+        kind: MemoryKind,
+        address_width: u8,
+        data_width: u8,
+        contents: &[u64],
+    ) -> Result<InstanceId, String> {
+        let mut memory = Memory::new(pos2(0.0, 0.0), kind);
+        memory.resize(address_width, data_width);
+        memory.load(contents);
+        self.add(name, |circuit, pos| {
+            circuit.new_memory(Memory { pos, ..memory })
+        })
+    }
+
+    pub fn splitter(
+        &mut self,
+        name: &str,
+        kind: SplitterKind,
+        width: u8,
+    ) -> Result<InstanceId, String> {
+        self.add(name, |circuit, pos| {
+            circuit.new_splitter(Splitter {
+                width,
+                ..Splitter::new(pos, kind)
+            })
+        })
+    }
+
+    /// Connect an output pin to an input pin.
+    pub fn connect(&mut self, from: &str, to: &str) -> Result<(), String> {
+        let from_pin = resolve(&self.db, from, Some(PinKind::Output))?;
+        let to_pin = resolve(&self.db, to, Some(PinKind::Input))?;
+        let connection = Connection::new(from_pin, to_pin);
+        self.db
+            .circuit
+            .check_connection(connection)
+            .map_err(|e| format!("cannot connect {from} to {to}: {e}"))?;
+        self.db.circuit.connections.insert(connection);
+        Ok(())
+    }
+
+    /// Look up an instance by name
+    pub fn id(&self, name: &str) -> Result<InstanceId, String> {
+        self.db.circuit.find_by_name(name)
+    }
+
+    /// Direct access to the circuit for settings the builder does not cover
+    pub fn circuit_mut(&mut self) -> &mut Circuit {
+        &mut self.db.circuit
+    }
+
+    pub fn build(self) -> Simulation {
+        Simulation::new(self.db)
+    }
+
+    fn add(
+        &mut self,
+        name: &str,
+        create: impl FnOnce(&mut Circuit, egui::Pos2) -> InstanceId,
+    ) -> Result<InstanceId, String> {
+        if name.is_empty() || name.contains('.') {
+            return Err(format!("invalid instance name {name:?}"));
+        }
+        if self.db.circuit.find_by_name(name).is_ok() {
+            return Err(format!("instance {name:?} already exists"));
+        }
+        let n = self.db.circuit.names.len();
+        let pos = pos2(
+            (n % GRID_COLUMNS) as f32 * GRID_SPACING,
+            (n / GRID_COLUMNS) as f32 * GRID_SPACING,
+        );
+        let id = create(&mut self.db.circuit, pos);
+        self.db.circuit.set_name(id, name);
+        Ok(id)
+    }
+}
+
+/// A circuit together with its simulator.
+pub struct Simulation {
+    pub db: DB,
+    pub simulator: Simulator,
+}
+
+impl Simulation {
+    /// Simulate any circuit, for example one saved by the editor. Instances are found through the
+    /// names given to them.
+    pub fn new(db: DB) -> Self {
+        Self {
+            db,
+            simulator: Simulator::default(),
+        }
+    }
+
+    /// Turn an input on or off. Takes effect on the next settle or run.
+    pub fn set(&mut self, input: &str, on: bool) -> Result<(), String> {
+        let id = self.db.circuit.find_by_name(input)?;
+        let Some(power) = self.db.circuit.powers.get_mut(id) else {
+            return Err(format!("{input} is not an input"));
+        };
+        power.on = on;
+        Ok(())
+    }
+
+    /// Propagate changes without moving clocks. Fails if the circuit does not settle within the
+    /// simulator's event budget.
+    pub fn settle(&mut self) -> Result<(), String> {
+        self.simulator.compute(&self.db, &self.db.circuit);
+        self.finish()
+    }
+
+    /// Simulate until `time`, clocks follow simulated time.
+    pub fn run_until(&mut self, time: u64) -> Result<(), String> {
+        self.simulator.advance_to(&self.db, &self.db.circuit, time);
+        self.finish()
+    }
+
+    /// Simulate until the next clock edge, or one time unit when there are no clocks.
+    pub fn step(&mut self) -> Result<(), String> {
+        let next = self
+            .simulator
+            .next_clock_edge(&self.db.circuit)
+            .unwrap_or(self.simulator.time + 1);
+        self.run_until(next)
+    }
+
+    pub fn time(&self) -> u64 {
+        self.simulator.time
+    }
+
+    /// Value seen at a pin. An output without a pin name reads its input.
+    pub fn read(&self, pin: &str) -> Result<BusValue, String> {
+        let pin = resolve(&self.db, pin, None)?;
+        Ok(self.simulator.pin_value(&self.db.circuit, pin))
+    }
+
+    /// Value of a single bit pin
+    pub fn value(&self, pin: &str) -> Result<Value, String> {
+        let value = self.read(pin)?;
+        if value.width() != 1 {
+            return Err(format!("{pin} is {} bits wide", value.width()));
+        }
+        Ok(value.bit(0))
+    }
+
+    /// Record the value of a pin over time in `simulator.recorder`
+    pub fn probe(&mut self, pin: &str) -> Result<(), String> {
+        let pin = resolve(&self.db, pin, None)?;
+        self.simulator.recorder.add(pin);
+        Ok(())
+    }
+
+    /// Look up an instance by name
+    pub fn id(&self, name: &str) -> Result<InstanceId, String> {
+        self.db.circuit.find_by_name(name)
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        self.simulator.store_state(&mut self.db.circuit);
+        match self.simulator.status {
+            SimulationStatus::Unstable { .. } => Err(format!(
+                "circuit did not settle within {} events at t={}: {}",
+                self.simulator.event_budget,
+                self.simulator.time,
+                self.simulator.instability().unwrap_or_default()
+            )),
+            SimulationStatus::Stable { .. } | SimulationStatus::Running => Ok(()),
+        }
+    }
+}
+
+/// Find the pin named by `reference`. Without a pin name the instance must have exactly one pin
+/// of direction `kind`, or a single pin at all when `kind` is `None`.
+fn resolve(db: &DB, reference: &str, kind: Option<PinKind>) -> Result<Pin, String> {
+    let (name, pin_name) = match reference.split_once('.') {
+        Some((name, pin)) => (name, Some(pin)),
+        None => (reference, None),
+    };
+    let id = db.circuit.find_by_name(name)?;
+    let pins = pin_names(db, id);
+    if let Some(pin_name) = pin_name {
+        pins.iter()
+            .find(|(n, _)| n == pin_name)
+            .map(|&(_, pin)| pin)
+            .ok_or_else(|| {
+                let known: Vec<&str> = pins.iter().map(|(n, _)| n.as_str()).collect();
+                format!(
+                    "{name} has no pin {pin_name:?}, pins are {}",
+                    known.join(", ")
+                )
+            })
+    } else {
+        let mut candidates = pins
+            .iter()
+            .filter(|(_, pin)| kind.is_none_or(|k| pin.kind == k))
+            .map(|&(_, pin)| pin);
+        if let (Some(pin), None) = (candidates.next(), candidates.next()) {
+            Ok(pin)
+        } else {
+            Err(format!("{reference} is ambiguous, name a pin"))
+        }
+    }
+}
+
+/// Names of the pins of an instance, see the module documentation.
+pub fn pin_names(db: &DB, id: InstanceId) -> Vec<(String, Pin)> {
+    pin_names_in(&db.circuit, db, id)
+}
+
+/// Names of the pins of an instance of `circuit`, which can be the circuit of a module definition
+pub fn pin_names_in(circuit: &Circuit, db: &DB, id: InstanceId) -> Vec<(String, Pin)> {
+    let pins = circuit.pins_of(id, db);
+    let names: Vec<String> = match circuit.ty(id) {
+        InstanceKind::Gate(GateKind::Not) => vec!["in".to_owned(), "out".to_owned()],
+        InstanceKind::Gate(GateKind::TriState) => {
+            vec!["in".to_owned(), "en".to_owned(), "out".to_owned()]
+        }
+        InstanceKind::Gate(_) => vec!["a".to_owned(), "b".to_owned(), "out".to_owned()],
+        InstanceKind::Power | InstanceKind::Clock => vec!["out".to_owned()],
+        InstanceKind::Lamp => vec!["in".to_owned()],
+        InstanceKind::Tunnel => vec!["net".to_owned()],
+        InstanceKind::Wire => vec!["start".to_owned(), "end".to_owned()],
+        InstanceKind::FlipFlop(kind) => kind
+            .pins()
+            .iter()
+            .map(|role| role.label().to_lowercase().replace('!', "n"))
+            .collect(),
+        InstanceKind::Memory(_) => {
+            let memory = circuit.get_memory(id);
+            pins.iter()
+                .map(|pin| memory.pin_label(pin.index).to_lowercase())
+                .collect()
+        }
+        InstanceKind::Splitter(SplitterKind::Split) => pins
+            .iter()
+            .map(|pin| match pin.index {
+                0 => "in".to_owned(),
+                i => format!("out{}", i - 1),
+            })
+            .collect(),
+        InstanceKind::Splitter(SplitterKind::Join) => pins
+            .iter()
+            .map(|pin| match pin.index {
+                0 => "out".to_owned(),
+                i => format!("in{}", i - 1),
+            })
+            .collect(),
+        InstanceKind::Module(_) => pins.iter().map(|pin| format!("p{}", pin.index)).collect(),
+    };
+    names.into_iter().zip(pins).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CircuitBuilder;
+    use crate::db::{FlipFlopKind, SplitterKind};
+    use crate::simulator::Value;
+
+    #[test]
+    fn counter_built_from_code() -> Result<(), String> {
+        // Two bit ripple counter joined into a bus
+        let mut b = CircuitBuilder::new();
+        b.clock("clk", 10, 0)?;
+        b.input("one")?;
+        b.flip_flop("bit0", FlipFlopKind::T)?;
+        b.flip_flop("bit1", FlipFlopKind::T)?;
+        b.splitter("join", SplitterKind::Join, 2)?;
+        b.connect("one", "bit0.t")?;
+        b.connect("one", "bit1.t")?;
+        b.connect("clk", "bit0.clk")?;
+        b.connect("bit0.nq", "bit1.clk")?;
+        b.connect("bit0.q", "join.in0")?;
+        b.connect("bit1.q", "join.in1")?;
+
+        assert!(b.connect("join", "bit0.d").is_err());
+        assert!(b.connect("join", "bit1.t").is_err());
+        assert!(b.input("one").is_err());
+
+        let mut sim = b.build();
+        sim.set("one", true)?;
+        sim.settle()?;
+        let mut counts = Vec::new();
+        for _ in 0..4 {
+            // Falling and rising edge of the clock, then the flip flop delay
+            sim.step()?;
+            sim.step()?;
+            sim.settle()?;
+            counts.push(sim.read("join.out")?.to_u64());
+        }
+        assert_eq!(counts, vec![Some(1), Some(2), Some(3), Some(0)]);
+        assert_eq!(sim.value("bit0.q")?, Value::Zero);
+        Ok(())
+    }
+}
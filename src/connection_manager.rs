@@ -1,6 +1,5 @@
 #![allow(clippy::allow_attributes)]
 use crate::app::SNAP_THRESHOLD;
-use crate::assets;
 use crate::config::CanvasConfig;
 use crate::db::{Circuit, DB, InstanceId, InstanceKind, Pin};
 use egui::Pos2;
@@ -138,7 +137,6 @@ impl GridCell {
     }
 }
 
-#[derive(Default)]
 pub struct ConnectionManager {
     /// Instances that need connection updates
     pub(crate) dirty_instances: HashSet<InstanceId>,
@@ -149,20 +147,42 @@ pub struct ConnectionManager {
     pin_position_cache: HashMap<Pin, Pos2>,
 
     canvas_config: CanvasConfig,
+
+    /// How close a moved pin has to come to another to snap, shrinks as the canvas is zoomed in
+    snap_distance: f32,
 }
 
-impl ConnectionManager {
-    pub fn new(circuit: &Circuit, canvas_config: &CanvasConfig, db: &DB) -> Self {
-        let mut new = Self {
+impl Default for ConnectionManager {
+    fn default() -> Self {
+        Self {
             dirty_instances: Default::default(),
             spatial_index: Default::default(),
             pin_position_cache: Default::default(),
+            canvas_config: Default::default(),
+            snap_distance: SNAP_THRESHOLD,
+        }
+    }
+}
+
+impl ConnectionManager {
+    pub fn new(circuit: &Circuit, canvas_config: &CanvasConfig, db: &DB) -> Self {
+        let mut new = Self {
             canvas_config: canvas_config.clone(),
+            ..Default::default()
         };
         new.rebuild_spatial_index(circuit, db);
         new
     }
 
+    /// Keep the snap distance the same on the screen at this zoom
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.snap_distance = SNAP_THRESHOLD / zoom;
+    }
+
+    pub fn snap_distance(&self) -> f32 {
+        self.snap_distance
+    }
+
     /// Mark an instance as needing connection updates
     pub fn mark_instance_dirty(&mut self, instance_id: InstanceId) {
         self.dirty_instances.insert(instance_id);
@@ -201,11 +221,9 @@ impl ConnectionManager {
         for &pin in pins {
             let new_pos = circuit.pin_position(pin, &self.canvas_config, db);
 
-            // Remove from old cell if position changed
-            if let Some(old_pos) = self.pin_position_cache.get(&pin)
-                && *old_pos != new_pos
-            {
-                let old_cell = GridCell::from_pos(*old_pos);
+            // Remove from the old cell, the pin is added back below even if it did not move
+            if let Some(old_pos) = self.pin_position_cache.insert(pin, new_pos) {
+                let old_cell = GridCell::from_pos(old_pos);
                 if let Some(cell_pins) = self.spatial_index.get_mut(&old_cell) {
                     cell_pins.retain(|&p| p != pin);
                     if cell_pins.is_empty() {
@@ -250,7 +268,9 @@ impl ConnectionManager {
                         Connection::new(pin, other_pin)
                     };
 
-                    if distance <= SNAP_THRESHOLD && self.validate_connection(connection) {
+                    if distance <= self.snap_distance
+                        && self.validate_connection(circuit, connection)
+                    {
                         let is_wire = matches!(circuit.ty(other_pin.ins), InstanceKind::Wire);
                         if is_wire {
                             wire_connections.push(connection);
@@ -273,20 +293,8 @@ impl ConnectionManager {
     }
 
     /// Validate if a connection between two pins is allowed
-    fn validate_connection(&self, c: Connection) -> bool {
-        if c.a == c.b {
-            return false;
-        }
-
-        if c.a.ins == c.b.ins {
-            return false;
-        }
-
-        // One pin must be input, the other output
-        if c.a.kind == c.b.kind {
-            return false;
-        }
-        true
+    fn validate_connection(&self, circuit: &Circuit, c: Connection) -> bool {
+        circuit.check_connection(c).is_ok()
     }
 
     /// Snap a pin to match the position of another pin
@@ -296,51 +304,39 @@ impl ConnectionManager {
         match circuit.ty(src.ins) {
             InstanceKind::Wire => {
                 if src.index == 0 {
-                    let w = db.circuit.get_wire_mut(src.ins);
-                    w.start = target;
+                    db.circuit.get_wire_mut(src.ins).set_start(target);
                 } else if src.index == 1 {
-                    let w = db.circuit.get_wire_mut(src.ins);
-                    w.end = target;
+                    db.circuit.get_wire_mut(src.ins).set_end(target);
                 } else {
                     unreachable!();
                 }
             }
-            InstanceKind::Gate(gk) => {
-                let g = db.circuit.get_gate_mut(src.ins);
-                let info = gk.graphics().pins[src.index as usize];
-                let pin_offset = info.offset;
-                let current = g.pos + pin_offset;
+            InstanceKind::Gate(_)
+            | InstanceKind::Power
+            | InstanceKind::Lamp
+            | InstanceKind::Clock
+            | InstanceKind::Module(_)
+            | InstanceKind::Tunnel => {
+                let current = circuit.pin_position(src, &self.canvas_config, db);
                 let desired = target - current;
                 db.move_instance_and_propagate(src.ins, desired, &self.canvas_config);
             }
-            InstanceKind::Power => {
-                let p = db.circuit.get_power_mut(src.ins);
-                let info = assets::POWER_ON_GRAPHICS.pins[src.index as usize];
-                let pin_offset = info.offset;
-                let current = p.pos + pin_offset;
+            InstanceKind::FlipFlop(kind) => {
+                let f = db.circuit.get_flip_flop(src.ins);
+                let info = kind.graphics().pins[src.index as usize];
+                let current = f.pos + info.offset;
                 let desired = target - current;
                 db.move_instance_and_propagate(src.ins, desired, &self.canvas_config);
             }
-            InstanceKind::Lamp => {
-                let l = db.circuit.get_lamp_mut(src.ins);
-                let info = assets::LAMP_GRAPHICS.pins[src.index as usize];
-                let pin_offset = info.offset;
-                let current = l.pos + pin_offset;
+            InstanceKind::Memory(_) => {
+                let m = db.circuit.get_memory(src.ins);
+                let current = m.pos + m.pin_offset(src.index);
                 let desired = target - current;
                 db.move_instance_and_propagate(src.ins, desired, &self.canvas_config);
             }
-            InstanceKind::Clock => {
-                let c = db.circuit.get_clock_mut(src.ins);
-                let info = assets::CLOCK_GRAPHICS.pins[src.index as usize];
-                let pin_offset = info.offset;
-                let current = c.pos + pin_offset;
-                let desired = target - current;
-                db.move_instance_and_propagate(src.ins, desired, &self.canvas_config);
-            }
-            InstanceKind::Module(_) => {
-                let pin_offset = circuit.pin_offset(src, &self.canvas_config, db);
-                let cc = db.circuit.get_module_mut(src.ins);
-                let current = cc.pos + pin_offset;
+            InstanceKind::Splitter(_) => {
+                let s = db.circuit.get_splitter(src.ins);
+                let current = s.pos + s.pin_offset(src.index);
                 let desired = target - current;
                 db.move_instance_and_propagate(src.ins, desired, &self.canvas_config);
             }
@@ -399,6 +395,7 @@ impl ConnectionManager {
                 let p2 = db
                     .circuit
                     .pin_position(connection.b, &self.canvas_config, db);
+                // Zoom only changes how far new snaps reach, kept connections use world units
                 if (p1 - p2).length() <= SNAP_THRESHOLD {
                     connections_to_keep.insert(*connection);
                 }
@@ -409,6 +406,10 @@ impl ConnectionManager {
             self.snap_pin_to_other(db, connection.a, connection.b);
             connections_to_keep.insert(*connection);
         }
+        // Snapping moves instances and the wires attached to them, so the index is built again
+        if !new_connections.is_empty() {
+            self.rebuild_spatial_index(&db.circuit, db);
+        }
 
         // Check if connections actually changed
         let connections_changed = db.circuit.connections != connections_to_keep;
@@ -420,6 +421,21 @@ impl ConnectionManager {
         connections_changed
     }
 
+    /// Same pins indexed in the same cells and at the same positions, in any order
+    #[cfg(test)]
+    pub(crate) fn same_index(&self, other: &Self) -> bool {
+        let cells = |manager: &Self| {
+            let mut cells: Vec<(i32, i32, Pin)> = manager
+                .spatial_index
+                .iter()
+                .flat_map(|(cell, pins)| pins.iter().map(move |&pin| (cell.0, cell.1, pin)))
+                .collect();
+            cells.sort_unstable();
+            cells
+        };
+        cells(self) == cells(other) && self.pin_position_cache == other.pin_position_cache
+    }
+
     /// Get debug information about the connection manager
     pub fn debug_info(&self) -> String {
         format!(
@@ -431,11 +447,13 @@ impl ConnectionManager {
 
 #[cfg(test)]
 mod tests {
-    use super::Connection;
+    use super::{Connection, ConnectionManager};
     use crate::{
         assets::PinKind,
-        db::{InstanceId, Pin},
+        db::{DB, Gate, GateKind, InstanceId, Lamp, Orientation, Pin, Wire},
+        simulator::{gate_inp1, lamp_input, wire_end},
     };
+    use egui::{Pos2, pos2, vec2};
     use std::collections::HashSet;
 
     fn create_test_pins() -> (Pin, Pin, Pin) {
@@ -479,4 +497,115 @@ mod tests {
 
         assert_ne!(conn1, conn2);
     }
+
+    #[test]
+    fn connection_rejects_width_mismatch() {
+        let mut db = DB::default();
+        let wire = db.circuit.new_wire(Wire {
+            width: 4,
+            ..Wire::new(Pos2::ZERO, Pos2::ZERO)
+        });
+        let lamp = db.circuit.new_lamp(Lamp::new(Pos2::ZERO));
+        let manager = ConnectionManager::default();
+        let conn = Connection::new(wire_end(wire), lamp_input(lamp));
+
+        assert!(!manager.validate_connection(&db.circuit, conn));
+        db.circuit.get_wire_mut(wire).width = 1;
+        assert!(manager.validate_connection(&db.circuit, conn));
+    }
+
+    #[test]
+    fn index_follows_moved_and_snapped_pins() {
+        let mut db = DB::default();
+        // Enough other instances that moving one updates the index instead of rebuilding it
+        for i in 0..20 {
+            db.circuit
+                .new_lamp(Lamp::new(pos2(1000.0, 100.0 * i as f32)));
+        }
+        let not = db.circuit.new_gate(Gate::new(Pos2::ZERO, GateKind::Not));
+        let mut manager = ConnectionManager::new(&db.circuit, &Default::default(), &db);
+        for _ in 0..2 {
+            db.circuit.get_gate_mut(not).pos += vec2(200.0, 0.0);
+            manager.mark_instance_dirty(not);
+            manager.update_connections(&mut db);
+        }
+        let input = db
+            .circuit
+            .pin_position(gate_inp1(not), &manager.canvas_config, &db);
+        let wire = db
+            .circuit
+            .new_wire(Wire::new(input - vec2(100.0, 0.0), input - vec2(3.0, 0.0)));
+        manager.mark_instance_dirty(wire);
+        assert!(manager.update_connections(&mut db));
+
+        let fresh = ConnectionManager::new(&db.circuit, &manager.canvas_config, &db);
+        assert!(manager.same_index(&fresh));
+    }
+
+    #[test]
+    fn zoom_never_disconnects() {
+        let mut db = DB::default();
+        let not = db.circuit.new_gate(Gate::new(Pos2::ZERO, GateKind::Not));
+        let mut manager = ConnectionManager::new(&db.circuit, &Default::default(), &db);
+        let input = db
+            .circuit
+            .pin_position(gate_inp1(not), &manager.canvas_config, &db);
+        let wire = db
+            .circuit
+            .new_wire(Wire::new(input - vec2(100.0, 0.0), input - vec2(6.0, 0.0)));
+        let connection = Connection::new(wire_end(wire), gate_inp1(not));
+        db.circuit.connections.insert(connection);
+
+        // Any change checks the connections, pins 6 apart stay connected at every zoom even
+        // though new snaps only reach 2.5 at 400%
+        let elsewhere = db.circuit.new_lamp(Lamp::new(pos2(1000.0, 0.0)));
+        for zoom in [0.25, 1.0, 4.0] {
+            manager.set_zoom(zoom);
+            manager.mark_instance_dirty(elsewhere);
+            assert!(!manager.update_connections(&mut db), "{zoom}");
+            assert!(db.circuit.connections.contains(&connection), "{zoom}");
+        }
+    }
+
+    #[test]
+    fn turned_gate_connects_on_its_turned_pins() {
+        let mut db = DB::default();
+        let not = db.circuit.new_gate(Gate::new(Pos2::ZERO, GateKind::Not));
+        let gate = db.circuit.get_gate_mut(not);
+        gate.orientation = gate.orientation.rotated();
+
+        let mut manager = ConnectionManager::default();
+        let pin = gate_inp1(not);
+        let top = db.circuit.pin_position(pin, &manager.canvas_config, &db);
+        assert_eq!(top, pos2(0.0, -40.0));
+
+        let wire = db
+            .circuit
+            .new_wire(Wire::new(pos2(0.0, -100.0), pos2(2.0, -42.0)));
+        manager.rebuild_spatial_index(&db.circuit, &db);
+        manager.mark_instance_dirty(wire);
+        assert!(manager.update_connections(&mut db));
+        assert!(db.circuit.connected_pins(pin).contains(&wire_end(wire)));
+        assert_eq!(
+            db.circuit.pin_position(pin, &manager.canvas_config, &db),
+            db.circuit.get_wire(wire).end
+        );
+
+        // Flipping mirrors what is on the canvas, whatever the instance was turned to
+        let offset = vec2(-37.0, 14.5);
+        let mut orientation = Orientation::default();
+        for step in 0..8 {
+            let seen = orientation.apply(offset);
+            assert_eq!(
+                orientation.flipped().apply(offset),
+                vec2(-seen.x, seen.y),
+                "{orientation:?}"
+            );
+            orientation = if step == 3 {
+                orientation.flipped()
+            } else {
+                orientation.rotated()
+            };
+        }
+    }
 }
@@ -6,7 +6,6 @@ use egui::{Pos2, Vec2, pos2};
 use slotmap::{SecondaryMap, SlotMap};
 
 use crate::assets::PinKind;
-use crate::connection_manager::ConnectionKind;
 use crate::{
     assets::{self},
     config::CanvasConfig,
@@ -67,10 +66,41 @@ pub struct Circuit {
     pub lamps: SecondaryMap<InstanceId, Lamp>,
     pub clocks: SecondaryMap<InstanceId, Clock>,
     pub modules: SecondaryMap<InstanceId, Module>,
+    #[serde(default)]
+    pub splitters: SecondaryMap<InstanceId, Splitter>,
+    #[serde(default)]
+    pub flip_flops: SecondaryMap<InstanceId, FlipFlop>,
+    #[serde(default)]
+    pub memories: SecondaryMap<InstanceId, Memory>,
+    #[serde(default)]
+    pub tunnels: SecondaryMap<InstanceId, Tunnel>,
+    /// Optional user given names, used to refer to inputs and outputs from outside the editor
+    #[serde(default)]
+    pub names: SecondaryMap<InstanceId, String>,
     pub connections: HashSet<Connection>,
     pub labels: SlotMap<LabelId, Label>,
 }
 
+/// Slot maps have no equality of their own, their entries are compared in order
+impl PartialEq for Circuit {
+    fn eq(&self, other: &Self) -> bool {
+        self.types.iter().eq(other.types.iter())
+            && self.gates == other.gates
+            && self.powers == other.powers
+            && self.wires == other.wires
+            && self.lamps == other.lamps
+            && self.clocks == other.clocks
+            && self.modules == other.modules
+            && self.splitters == other.splitters
+            && self.flip_flops == other.flip_flops
+            && self.memories == other.memories
+            && self.tunnels == other.tunnels
+            && self.names == other.names
+            && self.connections == other.connections
+            && self.labels.iter().eq(other.labels.iter())
+    }
+}
+
 impl Circuit {
     pub fn ty(&self, id: InstanceId) -> InstanceKind {
         self.types
@@ -100,7 +130,20 @@ impl Circuit {
             InstanceKind::Module(_) => {
                 self.modules.remove(id);
             }
+            InstanceKind::Splitter(_) => {
+                self.splitters.remove(id);
+            }
+            InstanceKind::FlipFlop(_) => {
+                self.flip_flops.remove(id);
+            }
+            InstanceKind::Memory(_) => {
+                self.memories.remove(id);
+            }
+            InstanceKind::Tunnel => {
+                self.tunnels.remove(id);
+            }
         };
+        self.names.remove(id);
         self.types.remove(id);
         self.connections.retain(|c| !c.involves_instance(id));
     }
@@ -135,6 +178,30 @@ impl Circuit {
         k
     }
 
+    pub fn new_splitter(&mut self, s: Splitter) -> InstanceId {
+        let k = self.types.insert(InstanceKind::Splitter(s.kind));
+        self.splitters.insert(k, s);
+        k
+    }
+
+    pub fn new_flip_flop(&mut self, f: FlipFlop) -> InstanceId {
+        let k = self.types.insert(InstanceKind::FlipFlop(f.kind));
+        self.flip_flops.insert(k, f);
+        k
+    }
+
+    pub fn new_memory(&mut self, m: Memory) -> InstanceId {
+        let k = self.types.insert(InstanceKind::Memory(m.kind));
+        self.memories.insert(k, m);
+        k
+    }
+
+    pub fn new_tunnel(&mut self, t: Tunnel) -> InstanceId {
+        let k = self.types.insert(InstanceKind::Tunnel);
+        self.tunnels.insert(k, t);
+        k
+    }
+
     pub fn new_module_id(&mut self, m: crate::module::Module) -> InstanceId {
         let k = self.types.insert(InstanceKind::Module(m.definition_id));
         self.modules.insert(k, m);
@@ -189,6 +256,108 @@ impl Circuit {
         self.modules.get_mut(id).expect("modules not found (mut)")
     }
 
+    /// Orientation of an instance, `None` for kinds that cannot be turned
+    pub fn orientation(&self, id: InstanceId) -> Option<Orientation> {
+        match self.ty(id) {
+            InstanceKind::Gate(_) => Some(self.get_gate(id).orientation),
+            InstanceKind::Power => Some(self.get_power(id).orientation),
+            InstanceKind::Lamp => Some(self.get_lamp(id).orientation),
+            InstanceKind::Clock => Some(self.get_clock(id).orientation),
+            InstanceKind::Module(_) => Some(self.get_module(id).orientation),
+            InstanceKind::Tunnel => Some(self.get_tunnel(id).orientation),
+            InstanceKind::Wire
+            | InstanceKind::Splitter(_)
+            | InstanceKind::FlipFlop(_)
+            | InstanceKind::Memory(_) => None,
+        }
+    }
+
+    /// Does nothing for kinds that cannot be turned
+    pub fn set_orientation(&mut self, id: InstanceId, orientation: Orientation) {
+        match self.ty(id) {
+            InstanceKind::Gate(_) => self.get_gate_mut(id).orientation = orientation,
+            InstanceKind::Power => self.get_power_mut(id).orientation = orientation,
+            InstanceKind::Lamp => self.get_lamp_mut(id).orientation = orientation,
+            InstanceKind::Clock => self.get_clock_mut(id).orientation = orientation,
+            InstanceKind::Module(_) => self.get_module_mut(id).orientation = orientation,
+            InstanceKind::Tunnel => self.get_tunnel_mut(id).orientation = orientation,
+            InstanceKind::Wire
+            | InstanceKind::Splitter(_)
+            | InstanceKind::FlipFlop(_)
+            | InstanceKind::Memory(_) => {}
+        }
+    }
+
+    pub fn get_splitter(&self, id: InstanceId) -> &Splitter {
+        self.splitters.get(id).expect("splitter not found")
+    }
+
+    pub fn get_splitter_mut(&mut self, id: InstanceId) -> &mut Splitter {
+        self.splitters
+            .get_mut(id)
+            .expect("splitter not found (mut)")
+    }
+
+    pub fn get_flip_flop(&self, id: InstanceId) -> &FlipFlop {
+        self.flip_flops.get(id).expect("flip flop not found")
+    }
+
+    pub fn get_flip_flop_mut(&mut self, id: InstanceId) -> &mut FlipFlop {
+        self.flip_flops
+            .get_mut(id)
+            .expect("flip flop not found (mut)")
+    }
+
+    pub fn get_memory(&self, id: InstanceId) -> &Memory {
+        self.memories.get(id).expect("memory not found")
+    }
+
+    pub fn get_memory_mut(&mut self, id: InstanceId) -> &mut Memory {
+        self.memories.get_mut(id).expect("memory not found (mut)")
+    }
+
+    pub fn get_tunnel(&self, id: InstanceId) -> &Tunnel {
+        self.tunnels.get(id).expect("tunnel not found")
+    }
+
+    pub fn get_tunnel_mut(&mut self, id: InstanceId) -> &mut Tunnel {
+        self.tunnels.get_mut(id).expect("tunnel not found (mut)")
+    }
+
+    /// Tunnels named like `id`, `id` included. Names are global: a `clk` tunnel inside a module
+    /// instance reaches every `clk` tunnel at the top level and in the other instances.
+    pub fn same_tunnels(&self, id: InstanceId) -> Vec<InstanceId> {
+        let name = &self.get_tunnel(id).name;
+        self.tunnels
+            .iter()
+            .filter(|(_, tunnel)| tunnel.name == *name)
+            .map(|(other, _)| other)
+            .collect()
+    }
+
+    pub fn name(&self, id: InstanceId) -> Option<&str> {
+        self.names.get(id).map(String::as_str)
+    }
+
+    /// Give an instance a name, an empty name removes it
+    pub fn set_name(&mut self, id: InstanceId, name: &str) {
+        if name.is_empty() {
+            self.names.remove(id);
+        } else {
+            self.names.insert(id, name.to_owned());
+        }
+    }
+
+    /// Instance with the given name, an error if there is none or more than one
+    pub fn find_by_name(&self, name: &str) -> Result<InstanceId, String> {
+        let mut found = self.names.iter().filter(|(_, n)| *n == name);
+        match (found.next(), found.next()) {
+            (Some((id, _)), None) => Ok(id),
+            (None, _) => Err(format!("no instance named {name:?}")),
+            (Some(_), Some(_)) => Err(format!("more than one instance is named {name:?}")),
+        }
+    }
+
     pub fn new_label(&mut self, label: Label) -> LabelId {
         self.labels.insert(label)
     }
@@ -221,6 +390,22 @@ impl Circuit {
         self.modules.keys().collect()
     }
 
+    pub fn splitter_ids(&self) -> Vec<InstanceId> {
+        self.splitters.keys().collect()
+    }
+
+    pub fn flip_flop_ids(&self) -> Vec<InstanceId> {
+        self.flip_flops.keys().collect()
+    }
+
+    pub fn memory_ids(&self) -> Vec<InstanceId> {
+        self.memories.keys().collect()
+    }
+
+    pub fn tunnel_ids(&self) -> Vec<InstanceId> {
+        self.tunnels.keys().collect()
+    }
+
     pub fn wire_ids(&self) -> Vec<InstanceId> {
         self.wires.keys().collect()
     }
@@ -282,19 +467,9 @@ impl Circuit {
                 };
 
                 // Get pin state if simulator is available
-                let state_str = if let Some(sim) = simulator {
-                    if let Some(&value) = sim.current.get(pin) {
-                        match value {
-                            crate::simulator::Value::One => " O",
-                            crate::simulator::Value::Zero => " N",
-                            crate::simulator::Value::X => " X",
-                        }
-                    } else {
-                        ""
-                    }
-                } else {
-                    ""
-                };
+                let state_str = simulator
+                    .map(|sim| value_state_str(sim.pin_value(self, *pin)))
+                    .unwrap_or_default();
 
                 writeln!(
                     out,
@@ -352,19 +527,9 @@ impl Circuit {
                         };
 
                         // Get pin state if simulator is available
-                        let member_state_str = if let Some(sim) = simulator {
-                            if let Some(&value) = sim.current.get(member_pin) {
-                                match value {
-                                    crate::simulator::Value::One => " O",
-                                    crate::simulator::Value::Zero => " N",
-                                    crate::simulator::Value::X => " X",
-                                }
-                            } else {
-                                ""
-                            }
-                        } else {
-                            ""
-                        };
+                        let member_state_str = simulator
+                            .map(|sim| value_state_str(sim.pin_value(self, *member_pin)))
+                            .unwrap_or_default();
 
                         writeln!(
                             out,
@@ -401,13 +566,23 @@ impl Circuit {
     /// Short header for an instance (e.g., "AND [0v1]" or "Power [1v1] ON")
     fn instance_header(&self, id: InstanceId, kind: InstanceKind, db: &DB) -> String {
         match kind {
-            InstanceKind::Gate(gk) => format!("{gk:?} [{id}]"),
+            InstanceKind::Gate(gk) => {
+                let delay = self.get_gate(id).delay();
+                format!("{gk:?} [{id}] delay {delay}")
+            }
             InstanceKind::Power => {
                 let p = self.get_power(id);
                 let state = if p.on { "ON" } else { "OFF" };
                 format!("Power [{id}] {state}")
             }
-            InstanceKind::Wire => format!("Wire [{id}]"),
+            InstanceKind::Wire => {
+                let width = self.get_wire(id).width;
+                if width > 1 {
+                    format!("Wire [{id}] {width} bits")
+                } else {
+                    format!("Wire [{id}]")
+                }
+            }
             InstanceKind::Lamp => format!("Lamp [{id}]"),
             InstanceKind::Clock => format!("Clock [{id}]"),
             InstanceKind::Module(def_id) => {
@@ -418,6 +593,19 @@ impl Circuit {
                     .unwrap_or("?");
                 format!("Module \"{name}\" [{id}]")
             }
+            InstanceKind::Splitter(kind) => {
+                let width = self.get_splitter(id).width;
+                format!("{kind:?} [{id}] {width} bits")
+            }
+            InstanceKind::FlipFlop(kind) => {
+                let q = self.get_flip_flop(id).state.q;
+                format!("{kind:?} [{id}] Q={}", q.as_char())
+            }
+            InstanceKind::Memory(kind) => {
+                let m = self.get_memory(id);
+                format!("{kind:?} [{id}] {}x{} bits", m.contents.len(), m.data_width)
+            }
+            InstanceKind::Tunnel => format!("Tunnel \"{}\" [{id}]", self.get_tunnel(id).name),
         }
     }
 
@@ -435,12 +623,22 @@ impl Circuit {
         out
     }
 
-    // Connected pins to this pin
+    // Connected pins to this pin. A tunnel is connected to whatever any tunnel of its name is.
     pub fn connected_pins(&self, pin: Pin) -> Vec<Pin> {
+        let pins = if matches!(self.ty(pin.ins), InstanceKind::Tunnel) {
+            self.same_tunnels(pin.ins)
+                .into_iter()
+                .map(|id| Pin::new(id, 0, assets::PinKind::Output))
+                .collect()
+        } else {
+            vec![pin]
+        };
         let mut res = Vec::new();
         for c in &self.connections {
-            if let Some((_, other)) = c.get_pin_first(pin) {
-                res.push(other);
+            for &pin in &pins {
+                if let Some((_, other)) = c.get_pin_first(pin) {
+                    res.push(other);
+                }
             }
         }
         res
@@ -530,6 +728,62 @@ impl Circuit {
                     .collect()
             }
             InstanceKind::Module(def_id) => self.get_module(id).pins(),
+            InstanceKind::Splitter(_) => self.get_splitter(id).pins(id),
+            InstanceKind::FlipFlop(kind) => kind
+                .graphics()
+                .pins
+                .iter()
+                .enumerate()
+                .map(|(i, p)| Pin::new(id, i as u32, p.kind))
+                .collect(),
+            InstanceKind::Memory(_) => self.get_memory(id).pins(id),
+            // Drives the wires leaving it and reads the ones ending on it
+            InstanceKind::Tunnel => vec![Pin::new(id, 0, assets::PinKind::Output)],
+        }
+    }
+
+    /// Reasons a connection between two pins would not make sense.
+    pub fn check_connection(&self, c: Connection) -> Result<(), String> {
+        if c.a.ins == c.b.ins {
+            return Err("cannot connect an instance to itself".to_owned());
+        }
+        let tunnels = [c.a, c.b].map(|pin| matches!(self.ty(pin.ins), InstanceKind::Tunnel));
+        if tunnels == [true, true] {
+            return Err("tunnels connect by name, not by touching".to_owned());
+        }
+        if c.a.kind == c.b.kind && tunnels == [false, false] {
+            return Err(format!(
+                "one pin must be an input and the other an output, both are {:?}",
+                c.a.kind
+            ));
+        }
+        let (a, b) = (self.pin_width(c.a), self.pin_width(c.b));
+        if a != b {
+            return Err(format!("pin widths differ ({a} and {b} bits)"));
+        }
+        Ok(())
+    }
+
+    /// Number of bits carried by a pin.
+    pub fn pin_width(&self, pin: Pin) -> u8 {
+        match self.ty(pin.ins) {
+            // Enable of a tri-state buffer is a single bit whatever the data width is
+            InstanceKind::Gate(GateKind::TriState) if pin.index == 1 => 1,
+            InstanceKind::Gate(_) => self.get_gate(pin.ins).width,
+            InstanceKind::Wire => self.get_wire(pin.ins).width,
+            InstanceKind::Power
+            | InstanceKind::Lamp
+            | InstanceKind::Clock
+            | InstanceKind::FlipFlop(_) => 1,
+            InstanceKind::Memory(_) => self.get_memory(pin.ins).pin_width(pin.index),
+            InstanceKind::Module(_) => self
+                .get_module(pin.ins)
+                .pins
+                .get(&pin)
+                .map(|internal| self.pin_width(*internal))
+                .unwrap_or(1),
+            InstanceKind::Splitter(_) => self.get_splitter(pin.ins).pin_width(pin.index),
+            InstanceKind::Tunnel => self.get_tunnel(pin.ins).width,
         }
     }
 
@@ -538,12 +792,12 @@ impl Circuit {
             InstanceKind::Gate(gk) => {
                 let g = self.get_gate(pin.ins);
                 let info = gk.graphics().pins[pin.index as usize];
-                g.pos + info.offset
+                g.pos + g.orientation.apply(info.offset)
             }
             InstanceKind::Power => {
                 let p = self.get_power(pin.ins);
                 let info = p.graphics().pins[pin.index as usize];
-                p.pos + info.offset
+                p.pos + p.orientation.apply(info.offset)
             }
             InstanceKind::Wire => {
                 let w = self.get_wire(pin.ins);
@@ -552,17 +806,34 @@ impl Circuit {
             InstanceKind::Lamp => {
                 let l = self.get_lamp(pin.ins);
                 let info = l.graphics().pins[pin.index as usize];
-                l.pos + info.offset
+                l.pos + l.orientation.apply(info.offset)
             }
             InstanceKind::Clock => {
                 let c = self.get_clock(pin.ins);
                 let info = c.graphics().pins[pin.index as usize];
-                c.pos + info.offset
+                c.pos + c.orientation.apply(info.offset)
             }
             InstanceKind::Module(_) => {
                 let cc = self.get_module(pin.ins);
                 cc.pos + self.pin_offset(pin, canvas_config, db)
             }
+            InstanceKind::Splitter(_) => {
+                let s = self.get_splitter(pin.ins);
+                s.pos + s.pin_offset(pin.index)
+            }
+            InstanceKind::FlipFlop(kind) => {
+                let f = self.get_flip_flop(pin.ins);
+                let info = kind.graphics().pins[pin.index as usize];
+                f.pos + info.offset
+            }
+            InstanceKind::Memory(_) => {
+                let m = self.get_memory(pin.ins);
+                m.pos + m.pin_offset(pin.index)
+            }
+            InstanceKind::Tunnel => {
+                let t = self.get_tunnel(pin.ins);
+                t.pos + t.pin_offset()
+            }
         }
     }
 
@@ -570,12 +841,12 @@ impl Circuit {
         match self.ty(pin.ins) {
             InstanceKind::Gate(gk) => {
                 let info = gk.graphics().pins[pin.index as usize];
-                info.offset
+                self.get_gate(pin.ins).orientation.apply(info.offset)
             }
             InstanceKind::Power => {
                 let p = self.get_power(pin.ins);
                 let info = p.graphics().pins[pin.index as usize];
-                info.offset
+                p.orientation.apply(info.offset)
             }
             InstanceKind::Wire => {
                 let w = self.get_wire(pin.ins);
@@ -589,18 +860,24 @@ impl Circuit {
             InstanceKind::Lamp => {
                 let l = self.get_lamp(pin.ins);
                 let info = l.graphics().pins[pin.index as usize];
-                info.offset
+                l.orientation.apply(info.offset)
             }
             InstanceKind::Clock => {
                 let c = self.get_clock(pin.ins);
                 let info = c.graphics().pins[pin.index as usize];
-                info.offset
+                c.orientation.apply(info.offset)
             }
             InstanceKind::Module(def_id) => {
                 let module_def = db.get_module_def(def_id);
                 let module = db.circuit.get_module(pin.ins);
-                module_def.calculate_pin_offset(db, &module.pins(), &pin, canvas_config)
+                let offset =
+                    module_def.calculate_pin_offset(db, &module.pins(), &pin, canvas_config);
+                module.orientation.apply(offset)
             }
+            InstanceKind::Splitter(_) => self.get_splitter(pin.ins).pin_offset(pin.index),
+            InstanceKind::FlipFlop(kind) => kind.graphics().pins[pin.index as usize].offset,
+            InstanceKind::Memory(_) => self.get_memory(pin.ins).pin_offset(pin.index),
+            InstanceKind::Tunnel => self.get_tunnel(pin.ins).pin_offset(),
         }
     }
 
@@ -638,9 +915,7 @@ impl Circuit {
                 p.pos += delta;
             }
             InstanceKind::Wire => {
-                let w = self.get_wire_mut(id);
-                w.start += delta;
-                w.end += delta;
+                self.get_wire_mut(id).translate(delta);
             }
             InstanceKind::Lamp => {
                 let l = self.get_lamp_mut(id);
@@ -654,6 +929,22 @@ impl Circuit {
                 let cc = self.get_module_mut(id);
                 cc.pos += delta;
             }
+            InstanceKind::Splitter(_) => {
+                let s = self.get_splitter_mut(id);
+                s.pos += delta;
+            }
+            InstanceKind::FlipFlop(_) => {
+                let f = self.get_flip_flop_mut(id);
+                f.pos += delta;
+            }
+            InstanceKind::Memory(_) => {
+                let m = self.get_memory_mut(id);
+                m.pos += delta;
+            }
+            InstanceKind::Tunnel => {
+                let t = self.get_tunnel_mut(id);
+                t.pos += delta;
+            }
         }
 
         // Get connected instances before we recurse
@@ -680,9 +971,9 @@ impl Circuit {
                                 let new_pin_pos = self.pin_position(moved_pin, canvas_config, db);
                                 let w = self.get_wire_mut(connected_id);
                                 if wire_pin.index == 0 {
-                                    w.start = new_pin_pos;
+                                    w.set_start(new_pin_pos);
                                 } else {
-                                    w.end = new_pin_pos;
+                                    w.set_end(new_pin_pos);
                                 }
                             }
                         }
@@ -694,7 +985,11 @@ impl Circuit {
                 | InstanceKind::Power
                 | InstanceKind::Lamp
                 | InstanceKind::Clock
-                | InstanceKind::Module(_) => {
+                | InstanceKind::Module(_)
+                | InstanceKind::Splitter(_)
+                | InstanceKind::FlipFlop(_)
+                | InstanceKind::Memory(_)
+                | InstanceKind::Tunnel => {
                     // For non-wires, propagate the same delta
                     self.move_instance_and_propagate_recursive(
                         connected_id,
@@ -800,9 +1095,7 @@ impl DB {
                     p.pos += delta;
                 }
                 InstanceKind::Wire => {
-                    let w = self.circuit.get_wire_mut(*id);
-                    w.start += delta;
-                    w.end += delta;
+                    self.circuit.get_wire_mut(*id).translate(delta);
                 }
                 InstanceKind::Lamp => {
                     let l = self.circuit.get_lamp_mut(*id);
@@ -816,6 +1109,22 @@ impl DB {
                     let cc = self.circuit.get_module_mut(*id);
                     cc.pos += delta;
                 }
+                InstanceKind::Splitter(_) => {
+                    let s = self.circuit.get_splitter_mut(*id);
+                    s.pos += delta;
+                }
+                InstanceKind::FlipFlop(_) => {
+                    let f = self.circuit.get_flip_flop_mut(*id);
+                    f.pos += delta;
+                }
+                InstanceKind::Memory(_) => {
+                    let m = self.circuit.get_memory_mut(*id);
+                    m.pos += delta;
+                }
+                InstanceKind::Tunnel => {
+                    let t = self.circuit.get_tunnel_mut(*id);
+                    t.pos += delta;
+                }
             }
         }
 
@@ -827,9 +1136,9 @@ impl DB {
                     // Otherwise resize the wire
                     let w = self.circuit.get_wire_mut(pin.ins);
                     if pin.index == 0 {
-                        w.start += delta;
+                        w.set_start(w.start + delta);
                     } else {
-                        w.end += delta;
+                        w.set_end(w.end + delta);
                     }
                 }
             }
@@ -868,9 +1177,7 @@ impl DB {
                 p.pos += delta;
             }
             InstanceKind::Wire => {
-                let w = self.circuit.get_wire_mut(id);
-                w.start += delta;
-                w.end += delta;
+                self.circuit.get_wire_mut(id).translate(delta);
             }
             InstanceKind::Lamp => {
                 let l = self.circuit.get_lamp_mut(id);
@@ -884,6 +1191,22 @@ impl DB {
                 let cc = self.circuit.get_module_mut(id);
                 cc.pos += delta;
             }
+            InstanceKind::Splitter(_) => {
+                let s = self.circuit.get_splitter_mut(id);
+                s.pos += delta;
+            }
+            InstanceKind::FlipFlop(_) => {
+                let f = self.circuit.get_flip_flop_mut(id);
+                f.pos += delta;
+            }
+            InstanceKind::Memory(_) => {
+                let m = self.circuit.get_memory_mut(id);
+                m.pos += delta;
+            }
+            InstanceKind::Tunnel => {
+                let t = self.circuit.get_tunnel_mut(id);
+                t.pos += delta;
+            }
         }
 
         let connected = self.circuit.connected_insntances(id);
@@ -907,9 +1230,9 @@ impl DB {
                                     self.circuit.pin_position(moved_pin, canvas_config, self);
                                 let w = self.circuit.get_wire_mut(connected_id);
                                 if wire_pin.index == 0 {
-                                    w.start = new_pin_pos;
+                                    w.set_start(new_pin_pos);
                                 } else {
-                                    w.end = new_pin_pos;
+                                    w.set_end(new_pin_pos);
                                 }
                             }
                         }
@@ -920,7 +1243,11 @@ impl DB {
                 | InstanceKind::Power
                 | InstanceKind::Lamp
                 | InstanceKind::Clock
-                | InstanceKind::Module(_) => {
+                | InstanceKind::Module(_)
+                | InstanceKind::Splitter(_)
+                | InstanceKind::FlipFlop(_)
+                | InstanceKind::Memory(_)
+                | InstanceKind::Tunnel => {
                     self.move_instance_and_propagate_recursive(
                         connected_id,
                         delta,
@@ -937,7 +1264,7 @@ impl DB {
     }
 }
 
-#[derive(serde::Deserialize, serde::Serialize, Copy, Debug, Clone)]
+#[derive(serde::Deserialize, serde::Serialize, Copy, Debug, Clone, PartialEq, Eq)]
 pub enum InstanceKind {
     Gate(GateKind),
     Power,
@@ -945,6 +1272,10 @@ pub enum InstanceKind {
     Lamp,
     Clock,
     Module(ModuleDefId),
+    Splitter(SplitterKind),
+    FlipFlop(FlipFlopKind),
+    Memory(MemoryKind),
+    Tunnel,
 }
 
 #[derive(serde::Deserialize, serde::Serialize, PartialEq, Eq, Copy, Debug, Clone)]
@@ -956,14 +1287,84 @@ pub enum GateKind {
     Xor,
     Xnor,
     Not,
+    /// Passes its data input through while enable is One, drives `Z` otherwise
+    TriState,
+}
+
+/// How an instance is turned on the canvas: mirrored left to right first, then rotated
+/// clockwise in quarter turns. Pin offsets and the drawing both follow it.
+#[derive(serde::Deserialize, serde::Serialize, Default, Copy, Debug, Clone, PartialEq, Eq)]
+pub struct Orientation {
+    /// 0 to 3
+    pub quarter_turns: u8,
+    /// Inputs on the right and outputs on the left before rotating
+    pub mirrored: bool,
+}
+
+impl Orientation {
+    /// Turned another 90 degrees clockwise
+    pub fn rotated(self) -> Self {
+        Self {
+            quarter_turns: (self.quarter_turns + 1) % 4,
+            ..self
+        }
+    }
+
+    /// Mirrored left to right as it is seen on the canvas
+    pub fn flipped(self) -> Self {
+        // Mirroring a turned instance is mirroring it first and turning it the other way
+        Self {
+            quarter_turns: (4 - self.quarter_turns % 4) % 4,
+            mirrored: !self.mirrored,
+        }
+    }
+
+    /// Offset from the center of an instance drawn with this orientation
+    pub fn apply(self, offset: Vec2) -> Vec2 {
+        let mut v = if self.mirrored {
+            Vec2::new(-offset.x, offset.y)
+        } else {
+            offset
+        };
+        for _ in 0..self.quarter_turns % 4 {
+            v = Vec2::new(-v.y, v.x);
+        }
+        v
+    }
+
+    /// Size of the box around an instance of `size` drawn with this orientation
+    pub fn size(self, size: Vec2) -> Vec2 {
+        if self.quarter_turns % 2 == 1 {
+            Vec2::new(size.y, size.x)
+        } else {
+            size
+        }
+    }
+
+    /// Clockwise rotation in radians
+    pub fn angle(self) -> f32 {
+        f32::from(self.quarter_turns % 4) * std::f32::consts::FRAC_PI_2
+    }
 }
 
-#[derive(serde::Deserialize, serde::Serialize, Copy, Debug, Clone)]
+#[derive(serde::Deserialize, serde::Serialize, Copy, Debug, Clone, PartialEq, Eq)]
 pub struct Gate {
     /// pos is the position of gate on the canvas. It's an absolute value. So it needs to be subbed
     /// `viewport_offset` to get the relative position of this object on the screen.
     pub pos: Pos2,
     pub kind: GateKind,
+    /// Propagation delay override. `None` uses the default delay of `kind`.
+    #[serde(default)]
+    pub delay: Option<u64>,
+    /// Number of bits on every pin. Wider gates work bitwise.
+    #[serde(default = "default_width")]
+    pub width: u8,
+    #[serde(default)]
+    pub orientation: Orientation,
+}
+
+pub fn default_width() -> u8 {
+    1
 }
 
 impl GateKind {
@@ -976,28 +1377,63 @@ impl GateKind {
             Self::Xor => assets::XOR_GRAPHICS.clone(),
             Self::Xnor => assets::XNOR_GRAPHICS.clone(),
             Self::Not => assets::NOT_GRAPHICS.clone(),
+            Self::TriState => assets::TRISTATE_GRAPHICS.clone(),
+        }
+    }
+
+    /// Default propagation delay in simulated time units.
+    pub fn default_delay(&self) -> u64 {
+        match self {
+            Self::Not | Self::Nand | Self::Nor | Self::TriState => 1,
+            Self::And | Self::Or => 2,
+            Self::Xor | Self::Xnor => 3,
         }
     }
 }
 
 impl Gate {
+    pub fn new(pos: Pos2, kind: GateKind) -> Self {
+        Self {
+            pos,
+            kind,
+            delay: None,
+            width: 1,
+            orientation: Orientation::default(),
+        }
+    }
+
     pub fn display(&self, id: InstanceId) -> String {
         format!("{:?} {}", self.kind, id)
     }
+
+    /// Propagation delay of this gate in simulated time units.
+    pub fn delay(&self) -> u64 {
+        self.delay.unwrap_or_else(|| self.kind.default_delay())
+    }
 }
 
 // Gate end
 
 // Power
 
-#[derive(serde::Deserialize, serde::Serialize, Copy, Debug, Clone)]
+#[derive(serde::Deserialize, serde::Serialize, Copy, Debug, Clone, PartialEq, Eq)]
 pub struct Power {
     // Center position
     pub pos: Pos2,
     pub on: bool,
+    #[serde(default)]
+    pub orientation: Orientation,
 }
 
 impl Power {
+    pub fn new(pos: Pos2, on: bool) -> Self {
+        Self {
+            pos,
+            on,
+            orientation: Orientation::default(),
+        }
+    }
+
     pub fn display(&self, id: InstanceId) -> String {
         format!("Power {{ id: {}, on: {}}}", id, self.on)
     }
@@ -1015,12 +1451,21 @@ impl Power {
 
 // Lamp
 
-#[derive(serde::Deserialize, serde::Serialize, Copy, Debug, Clone)]
+#[derive(serde::Deserialize, serde::Serialize, Copy, Debug, Clone, PartialEq, Eq)]
 pub struct Lamp {
     pub pos: Pos2,
+    #[serde(default)]
+    pub orientation: Orientation,
 }
 
 impl Lamp {
+    pub fn new(pos: Pos2) -> Self {
+        Self {
+            pos,
+            orientation: Orientation::default(),
+        }
+    }
+
     pub fn graphics(&self) -> assets::InstanceGraphics {
         assets::LAMP_GRAPHICS.clone()
     }
@@ -1034,12 +1479,79 @@ impl Lamp {
 
 // Clock
 
-#[derive(serde::Deserialize, serde::Serialize, Copy, Debug, Clone)]
+pub const DEFAULT_CLOCK_PERIOD: u64 = 20;
+pub const DEFAULT_DUTY_CYCLE: u8 = 50;
+
+/// Square wave derived from simulated time. The output rises at `phase` and then once every
+/// `period`, staying One for `duty_cycle` percent of the period.
+#[derive(serde::Deserialize, serde::Serialize, Copy, Debug, Clone, PartialEq, Eq)]
 pub struct Clock {
     pub pos: Pos2,
+    #[serde(default = "default_clock_period")]
+    pub period: u64,
+    #[serde(default)]
+    pub phase: u64,
+    #[serde(default = "default_duty_cycle")]
+    pub duty_cycle: u8,
+    #[serde(default)]
+    pub orientation: Orientation,
+}
+
+fn default_clock_period() -> u64 {
+    DEFAULT_CLOCK_PERIOD
+}
+
+fn default_duty_cycle() -> u8 {
+    DEFAULT_DUTY_CYCLE
 }
 
 impl Clock {
+    pub fn new(pos: Pos2) -> Self {
+        Self {
+            pos,
+            period: DEFAULT_CLOCK_PERIOD,
+            phase: 0,
+            duty_cycle: DEFAULT_DUTY_CYCLE,
+            orientation: Orientation::default(),
+        }
+    }
+
+    /// Period is at least 2 so both halves last at least one time unit
+    fn effective_period(&self) -> u64 {
+        self.period.max(2)
+    }
+
+    /// Time units the output stays One in each period
+    pub fn high_time(&self) -> u64 {
+        let period = self.effective_period();
+        (period * u64::from(self.duty_cycle) / 100).clamp(1, period - 1)
+    }
+
+    /// Position inside the current period, 0 being the rising edge
+    fn offset_at(&self, time: u64) -> u64 {
+        let period = self.effective_period();
+        (time % period + period - self.phase % period) % period
+    }
+
+    pub fn value_at(&self, time: u64) -> crate::simulator::Value {
+        if self.offset_at(time) < self.high_time() {
+            crate::simulator::Value::One
+        } else {
+            crate::simulator::Value::Zero
+        }
+    }
+
+    /// First time after `time` at which the output changes
+    pub fn next_edge_after(&self, time: u64) -> u64 {
+        let offset = self.offset_at(time);
+        let high = self.high_time();
+        if offset < high {
+            time + (high - offset)
+        } else {
+            time + (self.effective_period() - offset)
+        }
+    }
+
     pub fn graphics(&self) -> assets::InstanceGraphics {
         assets::CLOCK_GRAPHICS.clone()
     }
@@ -1051,9 +1563,416 @@ impl Clock {
 
 // Clock end
 
+// Flip flop
+
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Eq, Copy, Debug, Clone)]
+pub enum FlipFlopKind {
+    SrLatch,
+    DLatch,
+    D,
+    Jk,
+    T,
+}
+
+/// Role of a flip flop pin
+#[derive(PartialEq, Eq, Copy, Debug, Clone)]
+pub enum FlipFlopPin {
+    Set,
+    Reset,
+    D,
+    J,
+    K,
+    T,
+    Clock,
+    Enable,
+    Q,
+    NotQ,
+}
+
+impl FlipFlopPin {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Set => "S",
+            Self::Reset => "R",
+            Self::D => "D",
+            Self::J => "J",
+            Self::K => "K",
+            Self::T => "T",
+            Self::Clock => "CLK",
+            Self::Enable => "E",
+            Self::Q => "Q",
+            Self::NotQ => "!Q",
+        }
+    }
+}
+
+impl FlipFlopKind {
+    pub fn graphics(&self) -> assets::InstanceGraphics {
+        match self {
+            Self::SrLatch => assets::SR_LATCH_GRAPHICS.clone(),
+            Self::DLatch => assets::D_LATCH_GRAPHICS.clone(),
+            Self::D => assets::D_FLIP_FLOP_GRAPHICS.clone(),
+            Self::Jk => assets::JK_FLIP_FLOP_GRAPHICS.clone(),
+            Self::T => assets::T_FLIP_FLOP_GRAPHICS.clone(),
+        }
+    }
+
+    /// Role of every pin, in the same order as the pins in `graphics`.
+    pub fn pins(&self) -> &'static [FlipFlopPin] {
+        use FlipFlopPin::{Clock, D, Enable, J, K, NotQ, Q, Reset, Set, T};
+        match self {
+            Self::SrLatch => &[Set, Reset, Q, NotQ],
+            Self::DLatch => &[D, Enable, Q, NotQ],
+            Self::D => &[Set, D, Clock, Reset, Q, NotQ],
+            Self::Jk => &[Set, J, Clock, K, Reset, Q, NotQ],
+            Self::T => &[Set, T, Clock, Reset, Q, NotQ],
+        }
+    }
+
+    pub fn pin_index(&self, role: FlipFlopPin) -> Option<u32> {
+        self.pins()
+            .iter()
+            .position(|&r| r == role)
+            .map(|i| i as u32)
+    }
+}
+
+/// Stored state of a flip flop, kept on the instance so it is saved with the circuit.
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Eq, Copy, Debug, Clone)]
+pub struct FlipFlopState {
+    pub q: crate::simulator::Value,
+    /// Clock value seen on the last evaluation, used to detect rising edges. `Z` until a clock
+    /// value has been seen, so a clock that starts high is not an edge.
+    pub clock: crate::simulator::Value,
+}
+
+impl Default for FlipFlopState {
+    fn default() -> Self {
+        Self {
+            q: crate::simulator::Value::Zero,
+            clock: crate::simulator::Value::Z,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Copy, Debug, Clone, PartialEq, Eq)]
+pub struct FlipFlop {
+    pub pos: Pos2,
+    pub kind: FlipFlopKind,
+    #[serde(default)]
+    pub state: FlipFlopState,
+}
+
+impl FlipFlop {
+    pub fn new(pos: Pos2, kind: FlipFlopKind) -> Self {
+        Self {
+            pos,
+            kind,
+            state: FlipFlopState::default(),
+        }
+    }
+
+    pub fn display(&self, id: InstanceId) -> String {
+        format!("{:?} {}", self.kind, id)
+    }
+}
+
+// Flip flop end
+
+// Memory
+
+pub const MAX_ADDRESS_WIDTH: u8 = 16;
+pub const DEFAULT_ADDRESS_WIDTH: u8 = 4;
+pub const DEFAULT_DATA_WIDTH: u8 = 8;
+pub const MEMORY_SIZE: Vec2 = Vec2::new(70.0, 90.0);
+
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Eq, Copy, Debug, Clone)]
+pub enum MemoryKind {
+    /// Read only, contents are edited in the memory viewer or loaded from a hex file
+    Rom,
+    /// Written on the rising clock edge while write enable is One
+    Ram,
+}
+
+/// ROM pins are address (0) and data out (1).
+/// RAM pins are address (0), data in (1), write enable (2), clock (3) and data out (4).
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Memory {
+    pub pos: Pos2,
+    pub kind: MemoryKind,
+    pub address_width: u8,
+    pub data_width: u8,
+    /// One word per address, always `1 << address_width` long
+    pub contents: Vec<u64>,
+    /// Clock value seen on the last evaluation, used to detect rising edges
+    #[serde(default = "default_clock")]
+    pub clock: crate::simulator::Value,
+}
+
+fn default_clock() -> crate::simulator::Value {
+    crate::simulator::Value::Zero
+}
+
+impl Memory {
+    pub fn new(pos: Pos2, kind: MemoryKind) -> Self {
+        Self {
+            pos,
+            kind,
+            address_width: DEFAULT_ADDRESS_WIDTH,
+            data_width: DEFAULT_DATA_WIDTH,
+            contents: vec![0; 1 << DEFAULT_ADDRESS_WIDTH],
+            clock: default_clock(),
+        }
+    }
+
+    pub fn display(&self, id: InstanceId) -> String {
+        format!("{:?} {}", self.kind, id)
+    }
+
+    pub fn size(&self) -> Vec2 {
+        MEMORY_SIZE
+    }
+
+    pub fn data_mask(&self) -> u64 {
+        if self.data_width >= 64 {
+            u64::MAX
+        } else {
+            (1 << self.data_width) - 1
+        }
+    }
+
+    /// Change the address and data widths, keeping the words that still fit.
+    pub fn resize(&mut self, address_width: u8, data_width: u8) {
+        self.address_width = address_width.clamp(1, MAX_ADDRESS_WIDTH);
+        self.data_width = data_width.clamp(1, crate::simulator::MAX_BUS_WIDTH);
+        let mask = self.data_mask();
+        self.contents.resize(1 << self.address_width, 0);
+        for word in &mut self.contents {
+            *word &= mask;
+        }
+    }
+
+    /// Replace the contents starting at address 0. Returns how many words did not fit.
+    pub fn load(&mut self, words: &[u64]) -> usize {
+        let mask = self.data_mask();
+        self.contents.fill(0);
+        for (slot, word) in self.contents.iter_mut().zip(words) {
+            *slot = word & mask;
+        }
+        words.len().saturating_sub(self.contents.len())
+    }
+
+    pub fn read(&self, address: u64) -> u64 {
+        usize::try_from(address)
+            .ok()
+            .and_then(|a| self.contents.get(a))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn pin_kinds(&self) -> &'static [(PinKind, &'static str)] {
+        match self.kind {
+            MemoryKind::Rom => &[(PinKind::Input, "A"), (PinKind::Output, "D")],
+            MemoryKind::Ram => &[
+                (PinKind::Input, "A"),
+                (PinKind::Input, "DIN"),
+                (PinKind::Input, "WE"),
+                (PinKind::Input, "CLK"),
+                (PinKind::Output, "DOUT"),
+            ],
+        }
+    }
+
+    pub fn pins(&self, id: InstanceId) -> Vec<Pin> {
+        self.pin_kinds()
+            .iter()
+            .enumerate()
+            .map(|(i, (kind, _))| Pin::new(id, i as u32, *kind))
+            .collect()
+    }
+
+    pub fn pin_label(&self, index: u32) -> &'static str {
+        self.pin_kinds()
+            .get(index as usize)
+            .map(|(_, label)| *label)
+            .unwrap_or("?")
+    }
+
+    pub fn pin_width(&self, index: u32) -> u8 {
+        match (self.kind, index) {
+            (_, 0) => self.address_width,
+            (MemoryKind::Rom, 1) | (MemoryKind::Ram, 1 | 4) => self.data_width,
+            _ => 1,
+        }
+    }
+
+    /// Inputs are spread over the left side, the data output sits in the middle of the right side.
+    pub fn pin_offset(&self, index: u32) -> Vec2 {
+        let kinds = self.pin_kinds();
+        let Some((kind, _)) = kinds.get(index as usize) else {
+            return Vec2::ZERO;
+        };
+        match kind {
+            PinKind::Output => Vec2::new(MEMORY_SIZE.x / 2.0, 0.0),
+            PinKind::Input => {
+                let inputs = kinds.iter().filter(|(k, _)| *k == PinKind::Input).count();
+                let spacing = MEMORY_SIZE.y / (inputs + 1) as f32;
+                Vec2::new(
+                    -MEMORY_SIZE.x / 2.0,
+                    -MEMORY_SIZE.y / 2.0 + spacing * (index + 1) as f32,
+                )
+            }
+        }
+    }
+}
+
+// Memory end
+
+// Splitter
+
+pub const DEFAULT_SPLITTER_WIDTH: u8 = 4;
+pub const SPLITTER_PIN_SPACING: f32 = 15.0;
+pub const SPLITTER_BODY_WIDTH: f32 = 30.0;
+
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Eq, Copy, Debug, Clone)]
+pub enum SplitterKind {
+    /// Splits a bus into its bits
+    Split,
+    /// Joins bits into a bus
+    Join,
+}
+
+/// Connects a bus to its individual bits.
+/// A `Split` has the bus as input pin 0 and bit `n` as output pin `n + 1`.
+/// A `Join` has the bus as output pin 0 and bit `n` as input pin `n + 1`.
+#[derive(serde::Deserialize, serde::Serialize, Copy, Debug, Clone, PartialEq, Eq)]
+pub struct Splitter {
+    pub pos: Pos2,
+    pub kind: SplitterKind,
+    /// Width of the bus side
+    pub width: u8,
+}
+
+impl Splitter {
+    pub fn new(pos: Pos2, kind: SplitterKind) -> Self {
+        Self {
+            pos,
+            kind,
+            width: DEFAULT_SPLITTER_WIDTH,
+        }
+    }
+
+    pub fn display(&self, id: InstanceId) -> String {
+        format!("{:?} {} ({} bits)", self.kind, id, self.width)
+    }
+
+    pub fn size(&self) -> Vec2 {
+        Vec2::new(
+            SPLITTER_BODY_WIDTH,
+            f32::from(self.width) * SPLITTER_PIN_SPACING,
+        )
+    }
+
+    pub fn pins(&self, id: InstanceId) -> Vec<Pin> {
+        (0..=u32::from(self.width))
+            .map(|i| Pin::new(id, i, self.pin_kind(i)))
+            .collect()
+    }
+
+    /// The bus pin comes first so that changing the width keeps its connection
+    fn bus_pin_index(&self) -> u32 {
+        0
+    }
+
+    fn pin_kind(&self, index: u32) -> PinKind {
+        match (self.kind, index == self.bus_pin_index()) {
+            (SplitterKind::Split, true) | (SplitterKind::Join, false) => PinKind::Input,
+            (SplitterKind::Split, false) | (SplitterKind::Join, true) => PinKind::Output,
+        }
+    }
+
+    pub fn pin_width(&self, index: u32) -> u8 {
+        if index == self.bus_pin_index() {
+            self.width
+        } else {
+            1
+        }
+    }
+
+    /// Bus pin sits in the middle of one side, bit pins are stacked on the other side with bit 0
+    /// on top.
+    pub fn pin_offset(&self, index: u32) -> Vec2 {
+        let size = self.size();
+        let (bus_x, bits_x) = match self.kind {
+            SplitterKind::Split => (-size.x / 2.0, size.x / 2.0),
+            SplitterKind::Join => (size.x / 2.0, -size.x / 2.0),
+        };
+        if index == self.bus_pin_index() {
+            return Vec2::new(bus_x, 0.0);
+        }
+        let bit = index - 1;
+        let top = -size.y / 2.0 + SPLITTER_PIN_SPACING / 2.0;
+        Vec2::new(bits_x, top + bit as f32 * SPLITTER_PIN_SPACING)
+    }
+}
+
+// Splitter end
+
+// Tunnel
+
+pub const TUNNEL_SIZE: Vec2 = Vec2::new(64.0, 24.0);
+
+/// Named connection point, every tunnel with the same name is on the same net, inside modules too
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Tunnel {
+    pub pos: Pos2,
+    pub name: String,
+    #[serde(default = "default_width")]
+    pub width: u8,
+    #[serde(default)]
+    pub orientation: Orientation,
+}
+
+impl Tunnel {
+    pub fn new(pos: Pos2, name: impl Into<String>) -> Self {
+        Self {
+            pos,
+            name: name.into(),
+            width: 1,
+            orientation: Orientation::default(),
+        }
+    }
+
+    pub fn display(&self, id: InstanceId) -> String {
+        format!("Tunnel {id} \"{}\"", self.name)
+    }
+
+    /// The pin sits on the tip of the flag
+    pub fn pin_offset(&self) -> Vec2 {
+        self.orientation.apply(Vec2::new(-TUNNEL_SIZE.x / 2.0, 0.0))
+    }
+
+    /// Outline of the flag around its center, pointing at the pin
+    pub fn outline(&self) -> Vec<Vec2> {
+        let (w, h) = (TUNNEL_SIZE.x / 2.0, TUNNEL_SIZE.y / 2.0);
+        [
+            Vec2::new(-w, 0.0),
+            Vec2::new(h - w, -h),
+            Vec2::new(w, -h),
+            Vec2::new(w, h),
+            Vec2::new(h - w, h),
+        ]
+        .into_iter()
+        .map(|corner| self.orientation.apply(corner))
+        .collect()
+    }
+}
+
+// Tunnel end
+
 // Label
 
-#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq, Eq)]
 pub struct Label {
     pub pos: Pos2,
     pub text: String,
@@ -1073,11 +1992,17 @@ impl Label {
 
 // Label end
 
-#[derive(serde::Deserialize, serde::Serialize, Copy, Debug, Clone)]
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq, Eq)]
 pub struct Wire {
     pub start: Pos2,
     pub end: Pos2,
+    /// Corners of the wire between `start` and `end`
+    #[serde(default)]
+    pub bends: Vec<Pos2>,
     pub input_index: u32,
+    /// Number of bits carried by the wire
+    #[serde(default = "default_width")]
+    pub width: u8,
 }
 
 impl Wire {
@@ -1092,28 +2017,69 @@ impl Wire {
         Self {
             start,
             end,
+            bends: Vec::new(),
             input_index: 0,
+            width: 1,
         }
     }
 
-    pub fn closest_point_on_line(&self, p: Pos2) -> Pos2 {
-        let a = self.start;
-        let b = self.end;
-        let ab: Vec2 = b - a;
-        let ap: Vec2 = p - a;
+    /// Start, bends and end in order
+    pub fn points(&self) -> Vec<Pos2> {
+        let mut points = Vec::with_capacity(self.bends.len() + 2);
+        points.push(self.start);
+        points.extend_from_slice(&self.bends);
+        points.push(self.end);
+        points
+    }
 
-        let ab_len2 = ab.x * ab.x + ab.y * ab.y;
-        if ab_len2 == 0.0 {
-            return a;
+    pub fn translate(&mut self, delta: Vec2) {
+        self.start += delta;
+        self.end += delta;
+        for bend in &mut self.bends {
+            *bend += delta;
         }
+    }
 
-        let t = ((ap.x * ab.x + ap.y * ab.y) / ab_len2).clamp(0.0, 1.0);
+    /// Move the start, the first bend follows so the first segment keeps its direction
+    pub fn set_start(&mut self, pos: Pos2) {
+        if let Some(bend) = self.bends.first_mut() {
+            follow(self.start, pos, bend);
+        }
+        self.start = pos;
+    }
 
-        a + ab * t
+    /// Move the end, the last bend follows so the last segment keeps its direction
+    pub fn set_end(&mut self, pos: Pos2) {
+        if let Some(bend) = self.bends.last_mut() {
+            follow(self.end, pos, bend);
+        }
+        self.end = pos;
     }
 
-    pub fn dist_to_closest_point_on_line(&self, p: Pos2) -> f32 {
-        let closest = self.closest_point_on_line(p);
+    /// Move a bend, neighbouring bends follow so straight segments stay straight
+    pub fn move_bend(&mut self, index: usize, pos: Pos2) {
+        let old = self.bends[index];
+        if index > 0 {
+            follow(old, pos, &mut self.bends[index - 1]);
+        }
+        if index + 1 < self.bends.len() {
+            follow(old, pos, &mut self.bends[index + 1]);
+        }
+        self.bends[index] = pos;
+    }
+
+    /// Closest point of the wire to `p` and the index of the segment it is on
+    pub fn closest_point(&self, p: Pos2) -> (usize, Pos2) {
+        self.points()
+            .windows(2)
+            .map(|segment| closest_point_on_segment(segment[0], segment[1], p))
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.distance_sq(p).total_cmp(&b.distance_sq(p)))
+            .unwrap_or((0, self.start))
+    }
+
+    pub fn distance_to(&self, p: Pos2) -> f32 {
+        let (_, closest) = self.closest_point(p);
         (p - closest).length()
     }
 
@@ -1125,6 +2091,29 @@ impl Wire {
     }
 }
 
+/// Keep the segment from `anchor` to `bend` horizontal or vertical as `anchor` moves to `pos`
+fn follow(anchor: Pos2, pos: Pos2, bend: &mut Pos2) {
+    if bend.y == anchor.y {
+        bend.y = pos.y;
+    } else if bend.x == anchor.x {
+        bend.x = pos.x;
+    }
+}
+
+fn closest_point_on_segment(a: Pos2, b: Pos2, p: Pos2) -> Pos2 {
+    let ab: Vec2 = b - a;
+    let ap: Vec2 = p - a;
+
+    let ab_len2 = ab.x * ab.x + ab.y * ab.y;
+    if ab_len2 == 0.0 {
+        return a;
+    }
+
+    let t = ((ap.x * ab.x + ap.y * ab.y) / ab_len2).clamp(0.0, 1.0);
+
+    a + ab * t
+}
+
 // A specific pin on an instance
 #[derive(
     serde::Deserialize, serde::Serialize, Copy, Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd,
@@ -1159,6 +2148,27 @@ impl Pin {
                 clock.display(self.ins)
             }
             InstanceKind::Module(_) => format!("Module {}", self.ins),
+            InstanceKind::Splitter(_) => {
+                let splitter = circuit.get_splitter(self.ins);
+                splitter.display(self.ins)
+            }
+            InstanceKind::FlipFlop(kind) => {
+                let flip_flop = circuit.get_flip_flop(self.ins);
+                format!(
+                    "{} {}",
+                    flip_flop.display(self.ins),
+                    kind.pins()[self.index as usize].label()
+                )
+            }
+            InstanceKind::Memory(_) => {
+                let memory = circuit.get_memory(self.ins);
+                format!(
+                    "{} {}",
+                    memory.display(self.ins),
+                    memory.pin_label(self.index)
+                )
+            }
+            InstanceKind::Tunnel => circuit.get_tunnel(self.ins).display(self.ins),
         };
         format!("{:?} #{} in {} ", self.kind, self.index, instance_display,)
     }
@@ -1189,6 +2199,10 @@ impl Pin {
                     .unwrap_or("?");
                 format!("Mod:{name}")
             }
+            InstanceKind::Splitter(kind) => format!("{kind:?}"),
+            InstanceKind::FlipFlop(kind) => format!("{kind:?}"),
+            InstanceKind::Memory(kind) => format!("{kind:?}"),
+            InstanceKind::Tunnel => "Tunnel".to_owned(),
         };
         format!("{}[{}]#{}", type_name, self.ins, self.index)
     }
@@ -1196,25 +2210,18 @@ impl Pin {
     pub fn new(ins: InstanceId, index: u32, kind: PinKind) -> Self {
         Self { ins, index, kind }
     }
+}
 
-    // If this pin is a module pin then it's passing the current into the internal pin.
-    // This function returns that internal pin
-    // TODO: This is shit. Just make a map of external to internal pins?
-    pub fn is_passthrough(&self, db: &DB) -> Option<Self> {
-        if matches!(db.circuit.ty(self.ins), InstanceKind::Module(_)) {
-            return None;
-        }
-        let conns = db.circuit.connections_containing(*self);
-
-        let bi_conns: Vec<Connection> = conns
-            .iter()
-            .filter(|c| c.kind == ConnectionKind::BI)
-            .copied()
-            .collect();
-        if let Some(conn) = bi_conns.into_iter().next() {
-            let connected_pin = conn.get_other_pin(*self);
-            return Some(connected_pin);
-        }
-        None
+/// Pin state as shown in `Circuit::display`: O (one), N (zero), X and Z for single bits, the bits
+/// for buses.
+fn value_state_str(value: crate::simulator::BusValue) -> String {
+    if value.width() > 1 {
+        return format!(" {value}");
+    }
+    match value.bit(0) {
+        crate::simulator::Value::One => " O".to_owned(),
+        crate::simulator::Value::Zero => " N".to_owned(),
+        crate::simulator::Value::X => " X".to_owned(),
+        crate::simulator::Value::Z => " Z".to_owned(),
     }
 }
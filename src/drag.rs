@@ -5,7 +5,7 @@ use egui::{CornerRadius, Pos2, Rect, Stroke, StrokeKind, Ui, Vec2, pos2};
 use crate::app::{App, COLOR_HOVER_PIN_TO_WIRE, COLOR_SELECTION_BOX, MIN_WIRE_SIZE};
 
 use crate::assets::PinKind;
-use crate::db::{InstanceId, InstanceKind, LabelId, Pin, Wire};
+use crate::db::{InstanceId, InstanceKind, LabelId, Pin, TUNNEL_SIZE, Wire};
 
 #[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy)]
 pub enum CanvasDrag {
@@ -31,6 +31,15 @@ pub enum Drag {
         id: InstanceId,
         start: bool,
     },
+    /// Drawing a wire from a pin, routed around components
+    Route {
+        id: InstanceId,
+        start: bool,
+    },
+    Bend {
+        id: InstanceId,
+        index: usize,
+    },
     Selecting {
         start: Pos2,
     },
@@ -49,6 +58,18 @@ impl App {
         if self.drag.is_some() {
             return;
         }
+        let label = match drag {
+            Drag::Canvas(_) => Some("Move"),
+            Drag::Label { .. } => Some("Move label"),
+            Drag::Resize { .. } => Some("Resize wire"),
+            Drag::Bend { .. } => Some("Move bend"),
+            Drag::PinToWire { .. } | Drag::Route { .. } => Some("Draw wire"),
+            Drag::BranchWire { .. } => Some("Branch wire"),
+            Drag::Selecting { .. } => None,
+        };
+        if let Some(label) = label {
+            self.history.begin(label, &self.db);
+        }
         self.drag = Some(drag);
     }
 
@@ -84,10 +105,11 @@ impl App {
                             InstanceKind::Lamp => self.db.circuit.get_lamp(id).pos,
                             InstanceKind::Clock => self.db.circuit.get_clock(id).pos,
                             InstanceKind::Module(_) => self.db.circuit.get_module(id).pos,
-                            InstanceKind::Wire => {
-                                let w = self.db.circuit.get_wire(id);
-                                pos2((w.start.x + w.end.x) * 0.5, (w.start.y + w.end.y) * 0.5)
-                            }
+                            InstanceKind::Splitter(_) => self.db.circuit.get_splitter(id).pos,
+                            InstanceKind::FlipFlop(_) => self.db.circuit.get_flip_flop(id).pos,
+                            InstanceKind::Memory(_) => self.db.circuit.get_memory(id).pos,
+                            InstanceKind::Tunnel => self.db.circuit.get_tunnel(id).pos,
+                            InstanceKind::Wire => self.db.circuit.get_wire(id).center(),
                         };
                         let desired = new_pos - current_pos;
 
@@ -105,6 +127,10 @@ impl App {
                             | InstanceKind::Power
                             | InstanceKind::Lamp
                             | InstanceKind::Module(_)
+                            | InstanceKind::Splitter(_)
+                            | InstanceKind::FlipFlop(_)
+                            | InstanceKind::Memory(_)
+                            | InstanceKind::Tunnel
                             | InstanceKind::Clock => {
                                 let current_pos = match self.db.circuit.ty(id) {
                                     InstanceKind::Gate(_) => self.db.circuit.get_gate(id).pos,
@@ -112,6 +138,14 @@ impl App {
                                     InstanceKind::Lamp => self.db.circuit.get_lamp(id).pos,
                                     InstanceKind::Clock => self.db.circuit.get_clock(id).pos,
                                     InstanceKind::Module(_) => self.db.circuit.get_module(id).pos,
+                                    InstanceKind::Splitter(_) => {
+                                        self.db.circuit.get_splitter(id).pos
+                                    }
+                                    InstanceKind::FlipFlop(_) => {
+                                        self.db.circuit.get_flip_flop(id).pos
+                                    }
+                                    InstanceKind::Memory(_) => self.db.circuit.get_memory(id).pos,
+                                    InstanceKind::Tunnel => self.db.circuit.get_tunnel(id).pos,
                                     InstanceKind::Wire => unreachable!(),
                                 };
                                 let desired = new_pos - current_pos;
@@ -121,11 +155,8 @@ impl App {
                             }
                             InstanceKind::Wire => {
                                 let w = self.db.circuit.get_wire_mut(id);
-                                let center =
-                                    pos2((w.start.x + w.end.x) * 0.5, (w.start.y + w.end.y) * 0.5);
-                                let desired = new_pos - center;
-                                w.start += desired;
-                                w.end += desired;
+                                let desired = new_pos - w.center();
+                                w.translate(desired);
                                 desired.length_sq() > 0.0
                             }
                         };
@@ -157,14 +188,14 @@ impl App {
                     let new_start = mouse;
                     let wire_length = (wire.end - new_start).length();
                     if wire_length >= MIN_WIRE_SIZE && wire.start != new_start {
-                        wire.start = new_start;
+                        wire.set_start(new_start);
                         moved = true;
                     }
                 } else {
                     let new_end = mouse;
                     let wire_length = (wire.start - new_end).length();
                     if wire_length >= MIN_WIRE_SIZE && wire.end != new_end {
-                        wire.end = new_end;
+                        wire.set_end(new_end);
                         moved = true;
                     }
                 }
@@ -173,6 +204,13 @@ impl App {
                     self.connection_manager.mark_instance_dirty(id);
                 }
             }
+            Some(Drag::Route { id, start }) => {
+                self.route_wire(id, start, mouse);
+                self.connection_manager.mark_instance_dirty(id);
+            }
+            Some(Drag::Bend { id, index }) => {
+                self.db.circuit.get_wire_mut(id).move_bend(index, mouse);
+            }
             Some(Drag::PinToWire { source_pin }) => {
                 let start = source_pin.pos(&self.db, &self.canvas_config);
                 let end_abs = mouse;
@@ -185,10 +223,15 @@ impl App {
                     } else {
                         (start, mouse)
                     };
-                    let wire = Wire::new(new_w_start, new_w_end);
+                    let wire = Wire {
+                        width: self.db.circuit.pin_width(source_pin),
+                        ..Wire::new(new_w_start, new_w_end)
+                    };
                     let wire_id = self.db.circuit.new_wire(wire);
 
-                    self.drag = Some(Drag::Resize {
+                    // Components do not move while the wire is drawn
+                    self.obstacles = self.route_obstacles();
+                    self.drag = Some(Drag::Route {
                         id: wire_id,
                         start: source_pin.kind == PinKind::Input,
                     });
@@ -211,7 +254,10 @@ impl App {
 
                 if drag_distance >= MIN_WIRE_SIZE {
                     self.split_wire_at_point(original_wire_id, split_point);
-                    let branch_wire = Wire::new(split_point, mouse);
+                    let branch_wire = Wire {
+                        width: self.db.circuit.get_wire(original_wire_id).width,
+                        ..Wire::new(split_point, mouse)
+                    };
                     let branch_wire_id = self.db.circuit.new_wire(branch_wire);
 
                     self.drag = Some(Drag::Resize {
@@ -271,37 +317,58 @@ impl App {
                 let rect = Rect::from_min_max(min, max);
                 let mut sel: HashSet<InstanceId> = HashSet::new();
                 for (id, g) in &self.circuit().gates {
-                    let r = Rect::from_center_size(g.pos, self.canvas_config.base_gate_size);
+                    let r = Rect::from_center_size(
+                        g.pos,
+                        g.orientation.size(self.canvas_config.base_gate_size),
+                    );
                     if rect.contains_rect(r) {
                         sel.insert(id);
                     }
                 }
                 for (id, p) in &self.circuit().powers {
-                    let r = Rect::from_center_size(p.pos, self.canvas_config.base_gate_size);
+                    let r = Rect::from_center_size(
+                        p.pos,
+                        p.orientation.size(self.canvas_config.base_gate_size),
+                    );
                     if rect.contains_rect(r) {
                         sel.insert(id);
                     }
                 }
                 for (id, l) in &self.circuit().lamps {
-                    let r = Rect::from_center_size(l.pos, self.canvas_config.base_gate_size);
+                    let r = Rect::from_center_size(
+                        l.pos,
+                        l.orientation.size(self.canvas_config.base_gate_size),
+                    );
                     if rect.contains_rect(r) {
                         sel.insert(id);
                     }
                 }
                 for (id, c) in &self.circuit().clocks {
-                    let r = Rect::from_center_size(c.pos, self.canvas_config.base_gate_size);
+                    let r = Rect::from_center_size(
+                        c.pos,
+                        c.orientation.size(self.canvas_config.base_gate_size),
+                    );
                     if rect.contains_rect(r) {
                         sel.insert(id);
                     }
                 }
                 for (id, m) in &self.circuit().modules {
-                    let r = Rect::from_center_size(m.pos, self.canvas_config.base_gate_size);
+                    let r = Rect::from_center_size(
+                        m.pos,
+                        m.orientation.size(self.canvas_config.base_gate_size),
+                    );
+                    if rect.contains_rect(r) {
+                        sel.insert(id);
+                    }
+                }
+                for (id, t) in &self.circuit().tunnels {
+                    let r = Rect::from_center_size(t.pos, t.orientation.size(TUNNEL_SIZE));
                     if rect.contains_rect(r) {
                         sel.insert(id);
                     }
                 }
                 for (id, w) in &self.circuit().wires {
-                    if rect.contains(w.start) && rect.contains(w.end) {
+                    if w.points().iter().all(|&p| rect.contains(p)) {
                         sel.insert(id);
                     }
                 }
@@ -311,11 +378,14 @@ impl App {
                     .copied()
                     .collect();
             }
-            Drag::Resize { id, start: _ } => {
+            Drag::Resize { id, start: _ } | Drag::Route { id, start: _ } => {
                 self.connection_manager.mark_instance_dirty(id);
                 self.current_dirty = true;
+                self.obstacles.clear();
             }
-            Drag::PinToWire { source_pin: _ } | Drag::Label { id: _, offset: _ } => {
+            Drag::PinToWire { source_pin: _ }
+            | Drag::Label { id: _, offset: _ }
+            | Drag::Bend { id: _, index: _ } => {
                 // Wire was never created if drag distance was too short
                 // Label position already updated during dragging
                 // Nothing to clean up
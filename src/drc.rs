@@ -0,0 +1,369 @@
+//! Design rule checks.
+//!
+//! A lint pass over the circuit on its nets, see [`crate::net`]. It finds inputs nothing drives,
+//! which the simulator reads as floating, outputs tied together, lamps with no source, wire ends
+//! left open, loops of combinational instances, latches built from gates and module definitions
+//! without outputs. Findings on the members of a module are reported on the module, as that is
+//! what can be shown.
+
+use egui::Color32;
+
+use crate::App;
+use crate::assets::PinKind;
+use crate::db::{DB, FlipFlopPin, GateKind, InstanceId, InstanceKind, Pin};
+use crate::loops::{self, LoopKind};
+use crate::net::Netlist;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    FloatingInput,
+    TiedOutputs,
+    UndrivenLamp,
+    DanglingWire,
+    CombinationalLoop,
+    GateLatch,
+    ModuleWithoutOutputs,
+}
+
+impl Rule {
+    pub fn severity(self) -> Severity {
+        match self {
+            Self::TiedOutputs | Self::CombinationalLoop => Severity::Error,
+            Self::FloatingInput
+            | Self::UndrivenLamp
+            | Self::DanglingWire
+            | Self::GateLatch
+            | Self::ModuleWithoutOutputs => Severity::Warning,
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Self::FloatingInput => "Floating input",
+            Self::TiedOutputs => "Outputs tied together",
+            Self::UndrivenLamp => "Undriven lamp",
+            Self::DanglingWire => "Dangling wire",
+            Self::CombinationalLoop => "Combinational loop",
+            Self::GateLatch => "Latch made of gates",
+            Self::ModuleWithoutOutputs => "Module without outputs",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Violation {
+    pub rule: Rule,
+    /// Visible instances the finding is about
+    pub instances: Vec<InstanceId>,
+    pub pins: Vec<Pin>,
+    pub message: String,
+}
+
+/// Every finding in the circuit of `db`, errors first
+pub fn check(db: &DB) -> Vec<Violation> {
+    let circuit = &db.circuit;
+    let nets = Netlist::build(db, circuit);
+    let mut violations = Vec::new();
+    let mut report = |rule: Rule, pins: Vec<Pin>, ids: Vec<InstanceId>, message: String| {
+        let mut instances: Vec<InstanceId> = ids.into_iter().map(|id| shown(db, id)).collect();
+        instances.sort_unstable();
+        instances.dedup();
+        violations.push(Violation {
+            rule,
+            instances,
+            pins,
+            message,
+        });
+    };
+
+    for net in &nets.nets {
+        if net.drivers.is_empty() {
+            for &pin in &net.readers {
+                match circuit.ty(pin.ins) {
+                    InstanceKind::Lamp => report(
+                        Rule::UndrivenLamp,
+                        vec![pin],
+                        vec![pin.ins],
+                        format!("{} has no source", describe(db, pin.ins)),
+                    ),
+                    _ if reads_floating(db, pin) => report(
+                        Rule::FloatingInput,
+                        vec![pin],
+                        vec![pin.ins],
+                        format!(
+                            "input {} of {} is not driven",
+                            pin.index,
+                            describe(db, pin.ins)
+                        ),
+                    ),
+                    _ => {}
+                }
+            }
+        }
+
+        let tri_state =
+            |pin: &Pin| matches!(circuit.ty(pin.ins), InstanceKind::Gate(GateKind::TriState));
+        if net.drivers.len() > 1 && !net.drivers.iter().all(tri_state) {
+            let names: Vec<String> = net.drivers.iter().map(|&p| describe(db, p.ins)).collect();
+            report(
+                Rule::TiedOutputs,
+                net.drivers.clone(),
+                net.drivers.iter().map(|pin| pin.ins).collect(),
+                format!("outputs of {} drive the same net", names.join(", ")),
+            );
+        }
+    }
+
+    for id in circuit.wire_ids() {
+        let open: Vec<Pin> = circuit
+            .pins_of(id, db)
+            .into_iter()
+            .filter(|&pin| circuit.connections_containing(pin).is_empty())
+            .collect();
+        if !open.is_empty() {
+            let ends = if open.len() == 2 {
+                "both ends"
+            } else {
+                "one end"
+            };
+            report(
+                Rule::DanglingWire,
+                open,
+                vec![id],
+                format!("{} has {ends} unconnected", describe(db, id)),
+            );
+        }
+    }
+
+    for found in loops::find(circuit, &nets) {
+        let names: Vec<String> = found.instances.iter().map(|&id| describe(db, id)).collect();
+        let rule = match found.kind {
+            LoopKind::Latch => Rule::GateLatch,
+            LoopKind::Ring | LoopKind::Feedback => Rule::CombinationalLoop,
+        };
+        report(
+            rule,
+            Vec::new(),
+            found.instances.clone(),
+            format!("{} form a {}", names.join(", "), found.describe()),
+        );
+    }
+
+    for (definition_id, definition) in &db.module_definitions {
+        let has_output = definition
+            .get_unconnected_internal_pins(db)
+            .iter()
+            .any(|pin| pin.kind == PinKind::Output);
+        if !has_output {
+            let placed = circuit
+                .modules
+                .iter()
+                .filter(|(_, module)| module.definition_id == definition_id)
+                .map(|(id, _)| id)
+                .collect();
+            report(
+                Rule::ModuleWithoutOutputs,
+                Vec::new(),
+                placed,
+                format!("module \"{}\" has no output pins", definition.name),
+            );
+        }
+    }
+
+    violations.sort_by_key(|v| v.rule.severity());
+    violations
+}
+
+/// Flip flop set and reset are inactive when left open, other inputs read a floating value
+fn reads_floating(db: &DB, pin: Pin) -> bool {
+    match db.circuit.ty(pin.ins) {
+        InstanceKind::FlipFlop(kind) => !matches!(
+            kind.pins().get(pin.index as usize),
+            Some(FlipFlopPin::Set | FlipFlopPin::Reset)
+        ),
+        _ => true,
+    }
+}
+
+/// The instance itself when it is visible, otherwise the module it is hidden in
+fn shown(db: &DB, mut id: InstanceId) -> InstanceId {
+    while let Some(owner) = db.get_module_owner(id) {
+        id = owner;
+    }
+    id
+}
+
+fn describe(db: &DB, id: InstanceId) -> String {
+    let circuit = &db.circuit;
+    let kind = match circuit.ty(id) {
+        InstanceKind::Gate(kind) => format!("{kind:?} gate"),
+        InstanceKind::Power => "input".to_owned(),
+        InstanceKind::Lamp => "lamp".to_owned(),
+        InstanceKind::Clock => "clock".to_owned(),
+        InstanceKind::Wire => "wire".to_owned(),
+        InstanceKind::Tunnel => format!("tunnel \"{}\"", circuit.get_tunnel(id).name),
+        InstanceKind::Module(definition_id) => format!(
+            "module \"{}\"",
+            db.module_definitions
+                .get(definition_id)
+                .map_or("?", |definition| definition.name.as_str())
+        ),
+        InstanceKind::Splitter(kind) => format!("{kind:?}"),
+        InstanceKind::FlipFlop(kind) => format!("{kind:?}"),
+        InstanceKind::Memory(kind) => format!("{kind:?}").to_uppercase(),
+    };
+    match circuit.name(id) {
+        Some(name) => format!("{kind} \"{name}\""),
+        None => format!("{kind} {id}"),
+    }
+}
+
+/// Findings as text, one per line
+pub fn format_report(violations: &[Violation]) -> String {
+    violations
+        .iter()
+        .map(|v| {
+            let severity = match v.rule.severity() {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+            format!("{severity}: {}: {}\n", v.rule.title(), v.message)
+        })
+        .collect()
+}
+
+/// State of the design rule check window
+pub struct DrcView {
+    pub violations: Vec<Violation>,
+}
+
+impl App {
+    pub fn open_drc(&mut self) {
+        self.drc = Some(DrcView {
+            violations: check(&self.db),
+        });
+    }
+
+    pub fn draw_drc(&mut self, ctx: &egui::Context) {
+        let Some(view) = &self.drc else {
+            return;
+        };
+        let mut open = true;
+        let mut recheck = false;
+        let mut show: Option<Vec<InstanceId>> = None;
+        egui::Window::new("Design rule check")
+            .open(&mut open)
+            .default_width(360.0)
+            .show(ctx, |ui| {
+                let errors = view
+                    .violations
+                    .iter()
+                    .filter(|v| v.rule.severity() == Severity::Error)
+                    .count();
+                ui.horizontal(|ui| {
+                    ui.label(format!(
+                        "{errors} errors, {} warnings",
+                        view.violations.len() - errors
+                    ));
+                    recheck = ui.button("Check again").clicked();
+                });
+                ui.separator();
+                if view.violations.is_empty() {
+                    ui.colored_label(Color32::from_rgb(60, 160, 60), "No problems found");
+                }
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for violation in &view.violations {
+                        let color = match violation.rule.severity() {
+                            Severity::Error => ui.visuals().error_fg_color,
+                            Severity::Warning => ui.visuals().warn_fg_color,
+                        };
+                        ui.horizontal(|ui| {
+                            ui.colored_label(color, violation.rule.title());
+                            let text =
+                                egui::Label::new(&violation.message).sense(egui::Sense::click());
+                            let response = ui.add_enabled(!violation.instances.is_empty(), text);
+                            if response.on_hover_text("Select and show").clicked() {
+                                show = Some(violation.instances.clone());
+                            }
+                        });
+                    }
+                });
+            });
+
+        if recheck {
+            self.open_drc();
+        }
+        if !open {
+            self.drc = None;
+        }
+        if let Some(instances) = show {
+            self.selected = instances.into_iter().collect();
+            self.pan_to_selection();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::Pos2;
+
+    use super::{Rule, check};
+    use crate::connection_manager::Connection;
+    use crate::db::{DB, Gate, GateKind, Lamp, Power, Wire};
+    use crate::simulator::{
+        gate_inp1, gate_inp2, gate_output, lamp_input, power_output, wire_start,
+    };
+
+    #[test]
+    fn reports_each_rule() {
+        let mut db = DB::default();
+        let power = db.circuit.new_power(Power::new(Pos2::ZERO, true));
+        let other = db.circuit.new_power(Power::new(Pos2::ZERO, false));
+        let and = db.circuit.new_gate(Gate::new(Pos2::ZERO, GateKind::And));
+        let lamp = db.circuit.new_lamp(Lamp::new(Pos2::ZERO));
+        let dark = db.circuit.new_lamp(Lamp::new(Pos2::ZERO));
+        let wire = db.circuit.new_wire(Wire::new(Pos2::ZERO, Pos2::ZERO));
+        let first = db.circuit.new_gate(Gate::new(Pos2::ZERO, GateKind::Nor));
+        let second = db.circuit.new_gate(Gate::new(Pos2::ZERO, GateKind::Nor));
+        let not = db.circuit.new_gate(Gate::new(Pos2::ZERO, GateKind::Not));
+        for (from, to) in [
+            (power_output(power), gate_inp1(and)),
+            (gate_output(and), lamp_input(lamp)),
+            (power_output(other), lamp_input(lamp)),
+            (power_output(power), wire_start(wire)),
+            (gate_output(first), gate_inp1(second)),
+            (gate_output(second), gate_inp1(first)),
+            (power_output(other), gate_inp2(first)),
+            (power_output(other), gate_inp2(second)),
+            (gate_output(not), gate_inp1(not)),
+        ] {
+            db.circuit.connections.insert(Connection::new(from, to));
+        }
+
+        let violations = check(&db);
+        let found = |rule: Rule| {
+            violations
+                .iter()
+                .filter(|v| v.rule == rule)
+                .map(|v| v.instances.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(found(Rule::FloatingInput), [vec![and]]);
+        assert_eq!(found(Rule::UndrivenLamp), [vec![dark]]);
+        assert_eq!(found(Rule::DanglingWire), [vec![wire]]);
+        let mut tied = vec![and, other];
+        tied.sort_unstable();
+        assert_eq!(found(Rule::TiedOutputs), [tied]);
+        let mut cycle = vec![first, second];
+        cycle.sort_unstable();
+        assert_eq!(found(Rule::GateLatch), [cycle]);
+        assert_eq!(found(Rule::CombinationalLoop), [vec![not]]);
+        assert_eq!(violations[0].rule, Rule::TiedOutputs);
+    }
+}
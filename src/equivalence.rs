@@ -0,0 +1,806 @@
+//! Equivalence checking of module definitions and typed boolean expressions.
+//!
+//! The pins of a definition are the pins its instances leave unconnected, in the order of the
+//! module's pins. The two sides are matched by name when both use the same names and by position
+//! otherwise. Up to [`MAX_INPUT_BITS`] input bits every combination is simulated, as for a truth
+//! table. Wider circuits must be made of gates, splitters, constants and modules of those: both
+//! sides are flattened, nested modules included, and encoded for the SAT solver, which looks for
+//! inputs on which some output differs.
+
+use std::collections::HashMap;
+
+use egui::{Color32, RichText};
+
+use crate::App;
+use crate::assets::PinKind;
+use crate::boolean::{Expr, TruthFunction};
+use crate::db::{Circuit, DB, GateKind, InstanceId, InstanceKind, ModuleDefId, Pin, SplitterKind};
+use crate::net::Netlist;
+use crate::sat::{Lit, Outcome, Solver, model_value};
+use crate::simulator::{BusValue, gate_inp1, gate_inp2, joiner_input, splitter_input};
+use crate::truth_table::{MAX_INPUT_BITS, TruthTable};
+
+/// Conflicts the SAT solver may run into before giving up
+const MAX_CONFLICTS: usize = 200_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Design {
+    Definition(ModuleDefId),
+    /// One `name = expression` per line
+    Expressions(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// Every input combination was simulated
+    Exhaustive,
+    Sat,
+}
+
+pub struct OutputValues {
+    pub left_name: String,
+    pub right_name: String,
+    pub left: BusValue,
+    pub right: BusValue,
+}
+
+pub struct Counterexample {
+    pub inputs: Vec<(String, BusValue)>,
+    pub outputs: Vec<OutputValues>,
+}
+
+pub enum Verdict {
+    Equivalent,
+    Different(Counterexample),
+    /// The SAT solver gave up
+    Unknown,
+}
+
+pub struct Report {
+    pub left: String,
+    pub right: String,
+    pub method: Method,
+    pub matched_by_name: bool,
+    pub verdict: Verdict,
+}
+
+struct Port {
+    name: String,
+    width: u8,
+    /// Pin of the flattened definition
+    pin: Option<Pin>,
+}
+
+/// A side of the check
+struct Side {
+    title: String,
+    inputs: Vec<Port>,
+    outputs: Vec<Port>,
+    source: Source,
+}
+
+enum Source {
+    /// Definition flattened into its own circuit
+    Definition {
+        id: ModuleDefId,
+        flat: Box<DB>,
+    },
+    Expressions(TruthFunction, Vec<Expr>),
+}
+
+impl Side {
+    fn new(db: &DB, design: &Design) -> Result<Self, String> {
+        match design {
+            Design::Definition(id) => {
+                let definition = db
+                    .module_definitions
+                    .get(*id)
+                    .ok_or("the module definition no longer exists")?;
+                let mut flat = DB {
+                    circuit: Circuit::default(),
+                    module_definitions: db.module_definitions.clone(),
+                };
+                let copies = definition.copy_members_into(&mut flat);
+                let (mut inputs, mut outputs) = (Vec::new(), Vec::new());
+                // Same order as the truth table and the pins of the module
+                for port in definition.ports(db, false) {
+                    let world = Pin::new(copies[&port.pin.ins], port.pin.index, port.pin.kind);
+                    let ports = match port.kind {
+                        PinKind::Input => &mut inputs,
+                        PinKind::Output => &mut outputs,
+                    };
+                    ports.push(Port {
+                        name: port.name,
+                        width: flat.circuit.pin_width(world),
+                        pin: Some(world),
+                    });
+                }
+                Ok(Self {
+                    title: definition.name.clone(),
+                    inputs,
+                    outputs,
+                    source: Source::Definition {
+                        id: *id,
+                        flat: Box::new(flat),
+                    },
+                })
+            }
+            Design::Expressions(text) => {
+                let function = TruthFunction::from_expressions(text)?;
+                let exprs = text
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(|line| Expr::parse(line.split_once('=').map_or(line, |(_, e)| e)))
+                    .collect::<Result<_, _>>()?;
+                let port = |name: &String| Port {
+                    name: name.clone(),
+                    width: 1,
+                    pin: None,
+                };
+                Ok(Self {
+                    title: "the expressions".to_owned(),
+                    inputs: function.inputs.iter().map(port).collect(),
+                    outputs: function.outputs.iter().map(|o| port(&o.name)).collect(),
+                    source: Source::Expressions(function, exprs),
+                })
+            }
+        }
+    }
+
+    fn input_bits(&self) -> u32 {
+        self.inputs.iter().map(|port| u32::from(port.width)).sum()
+    }
+
+    /// Output values of every input combination, the first input being the most significant
+    fn table(&self, db: &DB) -> Result<Vec<Vec<BusValue>>, String> {
+        match &self.source {
+            Source::Definition { id, .. } => Ok(TruthTable::for_definition(db, *id)?
+                .rows
+                .into_iter()
+                .map(|row| row.outputs)
+                .collect()),
+            Source::Expressions(function, _) => Ok((0..function.rows())
+                .map(|row| {
+                    function
+                        .outputs
+                        .iter()
+                        .map(|output| {
+                            BusValue::from_u64(1, u64::from(output.values[row] == Some(true)))
+                        })
+                        .collect()
+                })
+                .collect()),
+        }
+    }
+
+    /// Literals of every output bit, least significant first, given those of the inputs
+    fn encode(&self, solver: &mut Solver, inputs: &[Vec<Lit>]) -> Result<Vec<Vec<Lit>>, String> {
+        match &self.source {
+            Source::Definition { flat, .. } => {
+                let mut encoder = Encoder {
+                    circuit: &flat.circuit,
+                    nets: Netlist::build(flat, &flat.circuit),
+                    solver,
+                    inputs: self
+                        .inputs
+                        .iter()
+                        .zip(inputs)
+                        .filter_map(|(port, lits)| Some((port.pin?, lits.clone())))
+                        .collect(),
+                    outputs: HashMap::new(),
+                    visiting: Vec::new(),
+                };
+                self.outputs
+                    .iter()
+                    .map(|port| {
+                        let pin = port.pin.ok_or("ports of a definition have pins")?;
+                        encoder
+                            .value_at(pin)
+                            .map_err(|e| format!("{}: {e}", self.title))
+                    })
+                    .collect()
+            }
+            Source::Expressions(function, exprs) => {
+                let vars: HashMap<&str, Lit> = function
+                    .inputs
+                    .iter()
+                    .zip(inputs)
+                    .map(|(name, lits)| (name.as_str(), lits[0]))
+                    .collect();
+                Ok(exprs
+                    .iter()
+                    .map(|expr| vec![encode_expr(solver, expr, &vars)])
+                    .collect())
+            }
+        }
+    }
+}
+
+/// Check that `left` and `right` compute the same function
+pub fn check(db: &DB, left: &Design, right: &Design) -> Result<Report, String> {
+    check_up_to(db, left, right, MAX_INPUT_BITS)
+}
+
+/// Check, simulating every combination up to `max_exhaustive_bits` input bits
+fn check_up_to(
+    db: &DB,
+    left: &Design,
+    right: &Design,
+    max_exhaustive_bits: u32,
+) -> Result<Report, String> {
+    let left = Side::new(db, left)?;
+    let right = Side::new(db, right)?;
+    if left.inputs.len() != right.inputs.len() || left.outputs.len() != right.outputs.len() {
+        return Err(format!(
+            "{} has {} inputs and {} outputs but {} has {} inputs and {} outputs",
+            left.title,
+            left.inputs.len(),
+            left.outputs.len(),
+            right.title,
+            right.inputs.len(),
+            right.outputs.len()
+        ));
+    }
+    let (inputs, by_name) = match_ports(&left.inputs, &right.inputs);
+    let (outputs, outputs_by_name) = match_ports(&left.outputs, &right.outputs);
+    let matched_by_name = by_name && outputs_by_name;
+    let (inputs, outputs) = if matched_by_name {
+        (inputs, outputs)
+    } else {
+        let positions: Vec<usize> = (0..left.inputs.len()).collect();
+        (positions, (0..left.outputs.len()).collect())
+    };
+    for (ports, other, matching) in [
+        (&left.inputs, &right.inputs, &inputs),
+        (&left.outputs, &right.outputs, &outputs),
+    ] {
+        for (port, &m) in ports.iter().zip(matching) {
+            if port.width != other[m].width {
+                return Err(format!(
+                    "{} is {} bits wide but {} is {} bits wide",
+                    port.name, port.width, other[m].name, other[m].width
+                ));
+            }
+        }
+    }
+
+    let input_bits = left.input_bits();
+    let (method, verdict) = if input_bits <= max_exhaustive_bits {
+        let verdict = exhaustive(db, &left, &right, &inputs, &outputs)?;
+        (Method::Exhaustive, verdict)
+    } else {
+        (Method::Sat, sat(&left, &right, &inputs, &outputs)?)
+    };
+    Ok(Report {
+        left: left.title,
+        right: right.title,
+        method,
+        matched_by_name,
+        verdict,
+    })
+}
+
+/// Port of `right` for each port of `left` by name, and whether every name has a match
+fn match_ports(left: &[Port], right: &[Port]) -> (Vec<usize>, bool) {
+    let matching: Option<Vec<usize>> = left
+        .iter()
+        .map(|port| right.iter().position(|other| other.name == port.name))
+        .collect();
+    match matching {
+        Some(matching) => (matching, true),
+        None => (Vec::new(), false),
+    }
+}
+
+fn exhaustive(
+    db: &DB,
+    left: &Side,
+    right: &Side,
+    inputs: &[usize],
+    outputs: &[usize],
+) -> Result<Verdict, String> {
+    let left_table = left.table(db)?;
+    let right_table = right.table(db)?;
+    for (row, left_values) in left_table.iter().enumerate() {
+        // Values of the inputs of the left side, then the matching row of the right side
+        let mut shift = left.input_bits();
+        let values: Vec<u64> = left
+            .inputs
+            .iter()
+            .map(|port| {
+                shift -= u32::from(port.width);
+                (row as u64 >> shift) & ((1 << port.width) - 1)
+            })
+            .collect();
+        let mut right_values = vec![0; right.inputs.len()];
+        for (i, &m) in inputs.iter().enumerate() {
+            right_values[m] = values[i];
+        }
+        let right_row = right
+            .inputs
+            .iter()
+            .zip(&right_values)
+            .fold(0_u64, |index, (port, &value)| index << port.width | value);
+        let right_values = &right_table[right_row as usize];
+
+        if outputs
+            .iter()
+            .enumerate()
+            .any(|(i, &m)| left_values[i] != right_values[m])
+        {
+            return Ok(Verdict::Different(Counterexample {
+                inputs: left
+                    .inputs
+                    .iter()
+                    .zip(values)
+                    .map(|(port, value)| (port.name.clone(), BusValue::from_u64(port.width, value)))
+                    .collect(),
+                outputs: output_values(left, right, outputs, left_values, right_values),
+            }));
+        }
+    }
+    Ok(Verdict::Equivalent)
+}
+
+fn output_values(
+    left: &Side,
+    right: &Side,
+    outputs: &[usize],
+    left_values: &[BusValue],
+    right_values: &[BusValue],
+) -> Vec<OutputValues> {
+    outputs
+        .iter()
+        .enumerate()
+        .map(|(i, &m)| OutputValues {
+            left_name: left.outputs[i].name.clone(),
+            right_name: right.outputs[m].name.clone(),
+            left: left_values[i],
+            right: right_values[m],
+        })
+        .collect()
+}
+
+/// Search for inputs where an output bit of the two sides differs
+fn sat(left: &Side, right: &Side, inputs: &[usize], outputs: &[usize]) -> Result<Verdict, String> {
+    let mut solver = Solver::default();
+    let left_inputs: Vec<Vec<Lit>> = left
+        .inputs
+        .iter()
+        .map(|port| (0..port.width).map(|_| solver.new_var()).collect())
+        .collect();
+    let mut right_inputs = vec![Vec::new(); right.inputs.len()];
+    for (i, &m) in inputs.iter().enumerate() {
+        right_inputs[m].clone_from(&left_inputs[i]);
+    }
+    let left_outputs = left.encode(&mut solver, &left_inputs)?;
+    let right_outputs = right.encode(&mut solver, &right_inputs)?;
+
+    let mut differences = Vec::new();
+    for (i, &m) in outputs.iter().enumerate() {
+        for (&a, &b) in left_outputs[i].iter().zip(&right_outputs[m]) {
+            differences.push(solver.xor(a, b));
+        }
+    }
+    solver.add_clause(&differences);
+
+    let model = match solver.solve(MAX_CONFLICTS) {
+        Outcome::Unsatisfiable => return Ok(Verdict::Equivalent),
+        Outcome::Unknown => return Ok(Verdict::Unknown),
+        Outcome::Satisfiable(model) => model,
+    };
+    let bus = |lits: &[Lit]| {
+        let value = lits.iter().enumerate().fold(0, |value, (bit, &lit)| {
+            value | u64::from(model_value(&model, lit)) << bit
+        });
+        BusValue::from_u64(lits.len() as u8, value)
+    };
+    let left_values: Vec<BusValue> = left_outputs.iter().map(|lits| bus(lits)).collect();
+    let right_values: Vec<BusValue> = right_outputs.iter().map(|lits| bus(lits)).collect();
+    Ok(Verdict::Different(Counterexample {
+        inputs: left
+            .inputs
+            .iter()
+            .zip(&left_inputs)
+            .map(|(port, lits)| (port.name.clone(), bus(lits)))
+            .collect(),
+        outputs: output_values(left, right, outputs, &left_values, &right_values),
+    }))
+}
+
+fn encode_expr(solver: &mut Solver, expr: &Expr, vars: &HashMap<&str, Lit>) -> Lit {
+    match expr {
+        Expr::Const(value) => solver.constant(*value),
+        Expr::Var(name) => vars[name.as_str()],
+        Expr::Not(a) => !encode_expr(solver, a, vars),
+        Expr::And(a, b) | Expr::Xor(a, b) | Expr::Or(a, b) => {
+            let a = encode_expr(solver, a, vars);
+            let b = encode_expr(solver, b, vars);
+            match expr {
+                Expr::And(..) => solver.and(a, b),
+                Expr::Xor(..) => solver.xor(a, b),
+                _ => solver.or(a, b),
+            }
+        }
+    }
+}
+
+/// Encodes the instances of a flattened definition, following the nets back from its outputs.
+/// The pins of nested modules only join the nets inside and outside them.
+struct Encoder<'a> {
+    circuit: &'a Circuit,
+    nets: Netlist,
+    solver: &'a mut Solver,
+    /// Literals of the input pins
+    inputs: HashMap<Pin, Vec<Lit>>,
+    /// Literals of the output pins encoded so far
+    outputs: HashMap<Pin, Vec<Lit>>,
+    /// Instances being encoded, to notice feedback
+    visiting: Vec<InstanceId>,
+}
+
+impl Encoder<'_> {
+    fn describe(&self, id: InstanceId) -> String {
+        match (self.circuit.name(id), self.circuit.ty(id)) {
+            (Some(name), _) => name.to_owned(),
+            (None, InstanceKind::Gate(kind)) => format!("a {kind:?} gate"),
+            (None, kind) => format!("a {kind:?}"),
+        }
+    }
+
+    /// Literals of the value seen at `pin`
+    fn value_at(&mut self, pin: Pin) -> Result<Vec<Lit>, String> {
+        let drivers = self.nets.sources(pin, |p| self.inputs.contains_key(&p));
+        let driver = match drivers[..] {
+            [driver] => driver,
+            [] => {
+                return Err(format!(
+                    "an input of {} is not driven",
+                    self.describe(pin.ins)
+                ));
+            }
+            _ => {
+                return Err(format!(
+                    "{} and {} drive the same net",
+                    self.describe(drivers[0].ins),
+                    self.describe(drivers[1].ins)
+                ));
+            }
+        };
+        if let Some(lits) = self.inputs.get(&driver).or(self.outputs.get(&driver)) {
+            return Ok(lits.clone());
+        }
+        let id = driver.ins;
+        if self.visiting.contains(&id) {
+            return Err(format!(
+                "{} is part of a feedback loop, only combinational circuits can be proven",
+                self.describe(id)
+            ));
+        }
+        self.visiting.push(id);
+        let lits = self.output(driver)?;
+        self.visiting.pop();
+        self.outputs.insert(driver, lits.clone());
+        Ok(lits)
+    }
+
+    fn output(&mut self, pin: Pin) -> Result<Vec<Lit>, String> {
+        let id = pin.ins;
+        match self.circuit.ty(id) {
+            InstanceKind::Power => Ok(vec![self.solver.constant(self.circuit.get_power(id).on)]),
+            InstanceKind::Gate(GateKind::TriState) => Err(format!(
+                "{} is a tri-state buffer, its output can float",
+                self.describe(id)
+            )),
+            InstanceKind::Gate(GateKind::Not) => Ok(self
+                .value_at(gate_inp1(id))?
+                .into_iter()
+                .map(|lit| !lit)
+                .collect()),
+            InstanceKind::Gate(kind) => {
+                let a = self.value_at(gate_inp1(id))?;
+                let b = self.value_at(gate_inp2(id))?;
+                Ok(a.into_iter()
+                    .zip(b)
+                    .map(|(a, b)| match kind {
+                        GateKind::And => self.solver.and(a, b),
+                        GateKind::Nand => !self.solver.and(a, b),
+                        GateKind::Or => self.solver.or(a, b),
+                        GateKind::Nor => !self.solver.or(a, b),
+                        GateKind::Xor => self.solver.xor(a, b),
+                        GateKind::Xnor => !self.solver.xor(a, b),
+                        GateKind::Not | GateKind::TriState => unreachable!("Handled above"),
+                    })
+                    .collect())
+            }
+            InstanceKind::Splitter(SplitterKind::Split) => {
+                let bits = self.value_at(splitter_input(id))?;
+                Ok(vec![bits[pin.index as usize - 1]])
+            }
+            InstanceKind::Splitter(SplitterKind::Join) => {
+                let width = self.circuit.get_splitter(id).width;
+                let mut bits = Vec::new();
+                for bit in 0..width {
+                    bits.extend(self.value_at(joiner_input(id, bit))?);
+                }
+                Ok(bits)
+            }
+            _ => Err(format!(
+                "{} is not combinational logic, only gates, splitters, constants and modules of \
+                 those can be proven beyond {MAX_INPUT_BITS} input bits",
+                self.describe(id)
+            )),
+        }
+    }
+}
+
+/// State of the equivalence window
+pub struct EquivalenceView {
+    pub left: Design,
+    pub right: Design,
+    pub result: Option<Result<Report, String>>,
+}
+
+impl Default for EquivalenceView {
+    fn default() -> Self {
+        Self {
+            left: Design::Expressions("y = a ^ b".to_owned()),
+            right: Design::Expressions("y = (a | b) & !(a & b)".to_owned()),
+            result: None,
+        }
+    }
+}
+
+fn design_picker(ui: &mut egui::Ui, label: &str, design: &mut Design, db: &DB) {
+    let selected = match design {
+        Design::Definition(id) => db
+            .module_definitions
+            .get(*id)
+            .map_or("(deleted)".to_owned(), |d| d.name.clone()),
+        Design::Expressions(_) => "Expressions".to_owned(),
+    };
+    egui::ComboBox::from_label(label)
+        .selected_text(selected)
+        .show_ui(ui, |ui| {
+            for (id, definition) in &db.module_definitions {
+                ui.selectable_value(design, Design::Definition(id), &definition.name);
+            }
+            if ui
+                .selectable_label(matches!(design, Design::Expressions(_)), "Expressions")
+                .clicked()
+                && !matches!(design, Design::Expressions(_))
+            {
+                *design = Design::Expressions(String::new());
+            }
+        });
+    if let Design::Expressions(text) = design {
+        ui.add(
+            egui::TextEdit::multiline(text)
+                .code_editor()
+                .desired_rows(3)
+                .hint_text("y = (a & b) | !c"),
+        );
+    }
+}
+
+impl App {
+    pub fn draw_equivalence(&mut self, ctx: &egui::Context) {
+        let Some(view) = &mut self.equivalence else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new("Check equivalence")
+            .open(&mut open)
+            .resizable(true)
+            .show(ctx, |ui| {
+                design_picker(ui, "First", &mut view.left, &self.db);
+                design_picker(ui, "Second", &mut view.right, &self.db);
+                if ui.button("Check").clicked() {
+                    view.result = Some(check(&self.db, &view.left, &view.right));
+                }
+                ui.separator();
+                match &view.result {
+                    None => {}
+                    Some(Err(e)) => {
+                        ui.colored_label(ui.visuals().error_fg_color, e);
+                    }
+                    Some(Ok(report)) => draw_report(ui, report),
+                }
+            });
+        if !open {
+            self.equivalence = None;
+        }
+    }
+}
+
+fn draw_report(ui: &mut egui::Ui, report: &Report) {
+    let how = match report.method {
+        Method::Exhaustive => "by simulating every input combination",
+        Method::Sat => "with the SAT solver",
+    };
+    let matching = if report.matched_by_name {
+        "Pins were matched by name."
+    } else {
+        "Pins were matched by position."
+    };
+    match &report.verdict {
+        Verdict::Equivalent => {
+            ui.colored_label(
+                Color32::from_rgb(60, 160, 60),
+                format!(
+                    "{} and {} are equivalent, proven {how}.",
+                    report.left, report.right
+                ),
+            );
+            ui.label(matching);
+        }
+        Verdict::Unknown => {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                format!("The SAT solver gave up after {MAX_CONFLICTS} conflicts."),
+            );
+        }
+        Verdict::Different(counterexample) => {
+            ui.colored_label(
+                ui.visuals().error_fg_color,
+                format!("{} and {} differ, found {how}.", report.left, report.right),
+            );
+            ui.label(matching);
+            ui.strong("Counterexample");
+            egui::Grid::new("counterexample")
+                .striped(true)
+                .spacing([16.0, 4.0])
+                .show(ui, |ui| {
+                    for (name, value) in &counterexample.inputs {
+                        ui.label(name);
+                        ui.monospace(value.to_string());
+                        ui.end_row();
+                    }
+                    ui.end_row();
+                    ui.strong("Output");
+                    ui.strong(&report.left);
+                    ui.strong(&report.right);
+                    ui.end_row();
+                    let error = ui.visuals().error_fg_color;
+                    for output in &counterexample.outputs {
+                        let differs = output.left != output.right;
+                        let text = |text: String| {
+                            let text = RichText::new(text).monospace();
+                            if differs {
+                                text.color(error).strong()
+                            } else {
+                                text
+                            }
+                        };
+                        let name = if output.left_name == output.right_name {
+                            output.left_name.clone()
+                        } else {
+                            format!("{} / {}", output.left_name, output.right_name)
+                        };
+                        ui.label(text(name));
+                        ui.label(text(output.left.to_string()));
+                        ui.label(text(output.right.to_string()));
+                        ui.end_row();
+                    }
+                });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use egui::pos2;
+
+    use super::{Design, Method, Verdict, check_up_to};
+    use crate::builder::CircuitBuilder;
+    use crate::connection_manager::Connection;
+    use crate::db::{DB, Gate, GateKind, Wire};
+    use crate::module::definition_from_selection;
+    use crate::simulator::{gate_inp1, gate_inp2, wire_end};
+    use crate::truth_table::MAX_INPUT_BITS;
+
+    /// Definition of XOR made of four NANDs, its inputs are wires fanning out to the gates
+    fn nand_xor(db: &mut DB) -> Result<Design, String> {
+        let mut b = CircuitBuilder::new();
+        for gate in ["n1", "n2", "n3", "n4"] {
+            b.gate(gate, GateKind::Nand)?;
+        }
+        for (from, to) in [
+            ("n1", "n2.b"),
+            ("n1", "n3.a"),
+            ("n2", "n4.a"),
+            ("n3", "n4.b"),
+        ] {
+            b.connect(from, to)?;
+        }
+        let (n1, n2, n3) = (b.id("n1")?, b.id("n2")?, b.id("n3")?);
+        for (name, y, readers) in [
+            ("a", -100.0, [gate_inp1(n1), gate_inp1(n2)]),
+            ("b", -50.0, [gate_inp2(n1), gate_inp2(n3)]),
+        ] {
+            let circuit = b.circuit_mut();
+            let wire = circuit.new_wire(Wire::new(pos2(0.0, y), pos2(40.0, y)));
+            circuit.set_name(wire, name);
+            for reader in readers {
+                circuit
+                    .connections
+                    .insert(Connection::new(wire_end(wire), reader));
+            }
+        }
+        let sim = b.build();
+        let all: HashSet<_> = sim.db.circuit.types.keys().collect();
+        let definition = definition_from_selection(&sim.db, "xor".to_owned(), &all)?;
+        Ok(Design::Definition(db.module_definitions.insert(definition)))
+    }
+
+    #[test]
+    fn proves_and_refutes() -> Result<(), String> {
+        let mut db = DB::default();
+        let xor = nand_xor(&mut db)?;
+        let same = Design::Expressions("y = a ^ b".to_owned());
+        let wrong = Design::Expressions("y = a | b".to_owned());
+        // Every input combination, then the SAT solver
+        for bits in [12, 0] {
+            let report = check_up_to(&db, &xor, &same, bits)?;
+            assert!(matches!(report.verdict, Verdict::Equivalent));
+            assert_eq!(report.method == Method::Sat, bits == 0);
+            assert!(!report.matched_by_name);
+
+            let report = check_up_to(&db, &xor, &wrong, bits)?;
+            let Verdict::Different(counterexample) = report.verdict else {
+                panic!("a ^ b and a | b differ for a = b = 1");
+            };
+            for (_, value) in &counterexample.inputs {
+                assert_eq!(value.to_u64(), Some(1));
+            }
+            assert_ne!(
+                counterexample.outputs[0].left,
+                counterexample.outputs[0].right
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn proves_nested_modules_with_sat() -> Result<(), String> {
+        let mut db = DB::default();
+        let Design::Definition(xor) = nand_xor(&mut db)? else {
+            unreachable!("nand_xor defines a module");
+        };
+        // 2 + 16 input bits, too many to simulate every combination
+        let define = |db: &mut DB, name: &str, nested: bool, kind: GateKind| {
+            let mut world = DB {
+                module_definitions: db.module_definitions.clone(),
+                ..DB::default()
+            };
+            if nested {
+                world.new_module(xor, pos2(0.0, 0.0));
+            } else {
+                world.circuit.new_gate(Gate::new(pos2(0.0, 0.0), kind));
+            }
+            world.circuit.new_gate(Gate {
+                width: 8,
+                ..Gate::new(pos2(100.0, 0.0), GateKind::And)
+            });
+            let top = world
+                .circuit
+                .types
+                .keys()
+                .filter(|&id| world.get_module_owner(id).is_none());
+            let definition = definition_from_selection(&world, name.to_owned(), &top.collect())?;
+            Ok::<_, String>(Design::Definition(db.module_definitions.insert(definition)))
+        };
+        let nested = define(&mut db, "nested", true, GateKind::Xor)?;
+        let same = define(&mut db, "flat", false, GateKind::Xor)?;
+        let wrong = define(&mut db, "wrong", false, GateKind::Or)?;
+
+        let report = check_up_to(&db, &nested, &same, MAX_INPUT_BITS)?;
+        assert_eq!(report.method, Method::Sat);
+        assert!(matches!(report.verdict, Verdict::Equivalent));
+        let report = check_up_to(&db, &nested, &wrong, MAX_INPUT_BITS)?;
+        assert!(matches!(report.verdict, Verdict::Different(_)));
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+//! Parsers for memory images. Two formats are supported:
+//!
+//! - Intel HEX, recognized by lines starting with `:`. Supports data, end of file, extended
+//!   segment address and extended linear address records.
+//! - Raw hex text: words separated by whitespace, optionally as `count*word` runs. A leading
+//!   `v2.0 raw` header is skipped and `#` starts a comment.
+
+/// Largest image accepted, in bytes for Intel HEX and in words for raw hex
+const MAX_IMAGE_SIZE: usize = 1 << 24;
+
+/// Parse a memory image into words of `data_width` bits. Words wider than a byte are stored in
+/// consecutive bytes of an Intel HEX file, least significant byte first.
+pub fn parse(text: &str, data_width: u8) -> Result<Vec<u64>, String> {
+    let is_intel = text
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty())
+        .is_some_and(|l| l.starts_with(':'));
+    if is_intel {
+        parse_intel_hex(text, data_width)
+    } else {
+        parse_raw(text)
+    }
+}
+
+fn parse_raw(text: &str) -> Result<Vec<u64>, String> {
+    let mut words = Vec::new();
+    for (line_no, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line_no == 0 && line == "v2.0 raw" {
+            continue;
+        }
+        for token in line.split_whitespace() {
+            let (count, word) = match token.split_once('*') {
+                Some((count, word)) => {
+                    let count = count
+                        .parse::<usize>()
+                        .map_err(|e| format!("line {}: bad count {count:?}: {e}", line_no + 1))?;
+                    (count, word)
+                }
+                None => (1, token),
+            };
+            if words.len() + count > MAX_IMAGE_SIZE {
+                return Err(format!("line {}: image is too large", line_no + 1));
+            }
+            let word = u64::from_str_radix(word, 16)
+                .map_err(|e| format!("line {}: bad word {word:?}: {e}", line_no + 1))?;
+            words.extend(std::iter::repeat_n(word, count));
+        }
+    }
+    Ok(words)
+}
+
+fn parse_intel_hex(text: &str, data_width: u8) -> Result<Vec<u64>, String> {
+    let bytes_per_word = usize::from(data_width.div_ceil(8).max(1));
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut base: usize = 0;
+
+    for (line_no, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let err = |msg: &str| format!("line {}: {msg}", line_no + 1);
+        let Some(record) = line.strip_prefix(':') else {
+            return Err(err("record does not start with ':'"));
+        };
+        if record.len() % 2 != 0 {
+            return Err(err("odd number of hex digits"));
+        }
+        let record: Vec<u8> = (0..record.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&record[i..i + 2], 16))
+            .collect::<Result<_, _>>()
+            .map_err(|e| err(&e.to_string()))?;
+        if record.len() < 5 {
+            return Err(err("record is too short"));
+        }
+        let len = usize::from(record[0]);
+        if record.len() != len + 5 {
+            return Err(err("record length does not match its byte count"));
+        }
+        let checksum = record.iter().fold(0u8, |acc, b| acc.wrapping_add(*b));
+        if checksum != 0 {
+            return Err(err("bad checksum"));
+        }
+
+        let offset = usize::from(u16::from_be_bytes([record[1], record[2]]));
+        let data = &record[4..4 + len];
+        match record[3] {
+            0x00 => {
+                let start = base + offset;
+                if start + len > MAX_IMAGE_SIZE {
+                    return Err(err("address is out of range"));
+                }
+                if bytes.len() < start + len {
+                    bytes.resize(start + len, 0);
+                }
+                bytes[start..start + len].copy_from_slice(data);
+            }
+            0x01 => break,
+            0x02 if len == 2 => base = usize::from(u16::from_be_bytes([data[0], data[1]])) << 4,
+            0x04 if len == 2 => base = usize::from(u16::from_be_bytes([data[0], data[1]])) << 16,
+            // Start address records do not affect the contents
+            0x03 | 0x05 => {}
+            ty => return Err(err(&format!("unsupported record type {ty:02X}"))),
+        }
+    }
+
+    Ok(bytes
+        .chunks(bytes_per_word)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .rev()
+                .fold(0u64, |acc, &b| (acc << 8) | u64::from(b))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+
+    #[test]
+    fn raw_hex_with_runs_and_comments() {
+        let text = "v2.0 raw\n01 ff # comment\n3*a 0\n";
+        assert_eq!(parse(text, 8), Ok(vec![0x01, 0xff, 0xa, 0xa, 0xa, 0x0]));
+    }
+
+    #[test]
+    fn intel_hex_records() {
+        let text = ":0300020011223395\n:00000001FF\n";
+        assert_eq!(parse(text, 8), Ok(vec![0x00, 0x00, 0x11, 0x22, 0x33]));
+        assert_eq!(parse(text, 16), Ok(vec![0x0000, 0x2211, 0x33]));
+    }
+
+    #[test]
+    fn intel_hex_bad_checksum() {
+        assert!(parse(":0300020011223300\n", 8).is_err());
+    }
+}
@@ -0,0 +1,569 @@
+//! Undo and redo of editing operations.
+//!
+//! Every edit is recorded as a [`Change`], the parts of the circuit it touched as they were on the
+//! other side of it. Undoing swaps them back into the circuit and keeps what they replaced for
+//! redo, so a step costs as much as the edit and not as much as the circuit. The circuit before an
+//! edit is only kept until the edit is over, then compared with the circuit after it. Edits that
+//! span several frames, like drags and label typing, are opened with [`History::begin`] and
+//! closed by [`History::finish`]. Edits that changed nothing leave no step.
+
+use std::collections::VecDeque;
+
+use egui::{Key, KeyboardShortcut, Modifiers};
+use slotmap::{SecondaryMap, SlotMap};
+
+use crate::app::App;
+use crate::connection_manager::Connection;
+use crate::db::{
+    Clock, DB, FlipFlop, Gate, InstanceId, InstanceKind, Label, LabelId, Lamp, Memory, ModuleDefId,
+    Power, Splitter, Tunnel, Wire,
+};
+use crate::module::{Module, ModuleDefinition};
+
+/// Oldest steps are dropped past this many
+pub const MAX_STEPS: usize = 100;
+
+pub const UNDO: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::Z);
+pub const REDO: KeyboardShortcut =
+    KeyboardShortcut::new(Modifiers::COMMAND.plus(Modifiers::SHIFT), Key::Z);
+pub const REDO_Y: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::Y);
+
+/// Entries of one instance map that an edit touched, `None` where there was no entry
+#[derive(Debug, Clone)]
+struct Entries<V>(Vec<(InstanceId, Option<V>)>);
+
+impl<V: Clone + PartialEq> Entries<V> {
+    /// Entries of `before` that are not the same in `after`
+    fn between(before: &SecondaryMap<InstanceId, V>, after: &SecondaryMap<InstanceId, V>) -> Self {
+        let mut entries: Vec<(InstanceId, Option<V>)> = before
+            .iter()
+            .filter(|&(id, value)| after.get(id) != Some(value))
+            .map(|(id, value)| (id, Some(value.clone())))
+            .collect();
+        entries.extend(
+            after
+                .keys()
+                .filter(|&id| !before.contains_key(id))
+                .map(|id| (id, None)),
+        );
+        Self(entries)
+    }
+
+    /// Put the entries into `map`, keeping the ones they replace
+    fn swap(&mut self, map: &mut SecondaryMap<InstanceId, V>) {
+        for (id, value) in &mut self.0 {
+            *value = match value.take() {
+                Some(value) => map.insert(*id, value),
+                None => map.remove(*id),
+            };
+        }
+    }
+}
+
+/// The other side of an edit, as far as the edit touched it.
+///
+/// Slot maps are kept whole when they changed, their keys can only be brought back that way. They
+/// hold kinds and labels, which are small next to the instances themselves.
+#[derive(Debug, Clone)]
+pub struct Change {
+    types: Option<SlotMap<InstanceId, InstanceKind>>,
+    gates: Entries<Gate>,
+    powers: Entries<Power>,
+    wires: Entries<Wire>,
+    lamps: Entries<Lamp>,
+    clocks: Entries<Clock>,
+    modules: Entries<Module>,
+    splitters: Entries<Splitter>,
+    flip_flops: Entries<FlipFlop>,
+    memories: Entries<Memory>,
+    tunnels: Entries<Tunnel>,
+    names: Entries<String>,
+    /// Connections only on this side of the edit
+    connections: Vec<Connection>,
+    /// Connections only on the side the change is applied to
+    replaced_connections: Vec<Connection>,
+    labels: Option<SlotMap<LabelId, Label>>,
+    definitions: Option<SlotMap<ModuleDefId, ModuleDefinition>>,
+}
+
+impl Change {
+    /// What has to be put into `after` to get `before` back
+    pub fn between(before: &DB, after: &DB) -> Self {
+        let (b, a) = (&before.circuit, &after.circuit);
+        Self {
+            types: (!b.types.iter().eq(a.types.iter())).then(|| b.types.clone()),
+            gates: Entries::between(&b.gates, &a.gates),
+            powers: Entries::between(&b.powers, &a.powers),
+            wires: Entries::between(&b.wires, &a.wires),
+            lamps: Entries::between(&b.lamps, &a.lamps),
+            clocks: Entries::between(&b.clocks, &a.clocks),
+            modules: Entries::between(&b.modules, &a.modules),
+            splitters: Entries::between(&b.splitters, &a.splitters),
+            flip_flops: Entries::between(&b.flip_flops, &a.flip_flops),
+            memories: Entries::between(&b.memories, &a.memories),
+            tunnels: Entries::between(&b.tunnels, &a.tunnels),
+            names: Entries::between(&b.names, &a.names),
+            connections: b.connections.difference(&a.connections).copied().collect(),
+            replaced_connections: a.connections.difference(&b.connections).copied().collect(),
+            labels: (!b.labels.iter().eq(a.labels.iter())).then(|| b.labels.clone()),
+            definitions: (!before
+                .module_definitions
+                .iter()
+                .eq(after.module_definitions.iter()))
+            .then(|| before.module_definitions.clone()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.types.is_none()
+            && self.gates.0.is_empty()
+            && self.powers.0.is_empty()
+            && self.wires.0.is_empty()
+            && self.lamps.0.is_empty()
+            && self.clocks.0.is_empty()
+            && self.modules.0.is_empty()
+            && self.splitters.0.is_empty()
+            && self.flip_flops.0.is_empty()
+            && self.memories.0.is_empty()
+            && self.tunnels.0.is_empty()
+            && self.names.0.is_empty()
+            && self.connections.is_empty()
+            && self.replaced_connections.is_empty()
+            && self.labels.is_none()
+            && self.definitions.is_none()
+    }
+
+    /// Put the recorded side into `db`. The change then holds what it replaced, applying it
+    /// again goes back.
+    pub fn apply(&mut self, db: &mut DB) {
+        let circuit = &mut db.circuit;
+        if let Some(types) = &mut self.types {
+            std::mem::swap(types, &mut circuit.types);
+        }
+        self.gates.swap(&mut circuit.gates);
+        self.powers.swap(&mut circuit.powers);
+        self.wires.swap(&mut circuit.wires);
+        self.lamps.swap(&mut circuit.lamps);
+        self.clocks.swap(&mut circuit.clocks);
+        self.modules.swap(&mut circuit.modules);
+        self.splitters.swap(&mut circuit.splitters);
+        self.flip_flops.swap(&mut circuit.flip_flops);
+        self.memories.swap(&mut circuit.memories);
+        self.tunnels.swap(&mut circuit.tunnels);
+        self.names.swap(&mut circuit.names);
+        for connection in &self.replaced_connections {
+            circuit.connections.remove(connection);
+        }
+        circuit.connections.extend(self.connections.iter().copied());
+        std::mem::swap(&mut self.connections, &mut self.replaced_connections);
+        if let Some(labels) = &mut self.labels {
+            std::mem::swap(labels, &mut circuit.labels);
+        }
+        if let Some(definitions) = &mut self.definitions {
+            std::mem::swap(definitions, &mut db.module_definitions);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub label: String,
+    change: Change,
+}
+
+/// How an edit in progress ends
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Span {
+    /// With the next step
+    Frame,
+    /// With [`History::finish`]
+    Frames,
+    /// With the next step that is not a property edit of this instance
+    Properties(InstanceId),
+}
+
+/// Edit in progress, with the circuit before it
+#[derive(Debug)]
+struct Open {
+    label: String,
+    before: DB,
+    span: Span,
+}
+
+#[derive(Debug, Default)]
+pub struct History {
+    undo: VecDeque<Step>,
+    redo: Vec<Step>,
+    open: Option<Open>,
+}
+
+impl History {
+    /// Record `before` as the circuit preceding a new edit, `before` is the circuit as it is now
+    pub fn push(&mut self, label: impl Into<String>, before: DB) {
+        self.open(label.into(), before, Span::Frame);
+        self.redo.clear();
+    }
+
+    /// Like [`History::push`], but repeated edits of the same instance make a single step
+    pub fn push_merged(&mut self, label: impl Into<String>, id: InstanceId, before: DB) {
+        if self
+            .open
+            .as_ref()
+            .is_some_and(|open| open.span == Span::Properties(id))
+        {
+            return;
+        }
+        self.open(label.into(), before, Span::Properties(id));
+        self.redo.clear();
+    }
+
+    /// Start an edit spanning several frames, an edit already in progress keeps its start
+    pub fn begin(&mut self, label: impl Into<String>, db: &DB) {
+        if self
+            .open
+            .as_ref()
+            .is_some_and(|open| open.span == Span::Frames)
+        {
+            return;
+        }
+        self.open(label.into(), db.clone(), Span::Frames);
+    }
+
+    /// End the edit started with [`History::begin`], recording it when it changed something
+    pub fn finish(&mut self, db: &DB) {
+        if self
+            .open
+            .as_ref()
+            .is_some_and(|open| open.span == Span::Frames)
+        {
+            self.close(db);
+        }
+    }
+
+    fn open(&mut self, label: String, before: DB, span: Span) {
+        self.close(&before);
+        self.open = Some(Open {
+            label,
+            before,
+            span,
+        });
+    }
+
+    /// Record the edit in progress against the circuit after it
+    fn close(&mut self, db: &DB) {
+        let Some(open) = self.open.take() else {
+            return;
+        };
+        let change = Change::between(&open.before, db);
+        if change.is_empty() {
+            return;
+        }
+        self.redo.clear();
+        self.undo.push_back(Step {
+            label: open.label,
+            change,
+        });
+        if self.undo.len() > MAX_STEPS {
+            self.undo.pop_front();
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.open.is_some() || !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn undo_label(&self) -> Option<&str> {
+        match &self.open {
+            Some(open) => Some(open.label.as_str()),
+            None => self.undo.back().map(|step| step.label.as_str()),
+        }
+    }
+
+    pub fn redo_label(&self) -> Option<&str> {
+        self.redo.last().map(|step| step.label.as_str())
+    }
+
+    /// Take the last step back in `db`, keeping it for redo. Returns false when there is
+    /// nothing to undo.
+    pub fn undo(&mut self, db: &mut DB) -> bool {
+        self.close(db);
+        let Some(mut step) = self.undo.pop_back() else {
+            return false;
+        };
+        step.change.apply(db);
+        self.redo.push(step);
+        true
+    }
+
+    /// Make the last undone step again in `db`, keeping it for undo
+    pub fn redo(&mut self, db: &mut DB) -> bool {
+        self.close(db);
+        let Some(mut step) = self.redo.pop() else {
+            return false;
+        };
+        step.change.apply(db);
+        self.undo.push_back(step);
+        true
+    }
+
+    /// Labels of the steps done, oldest first, then of the undone steps that redo would apply
+    pub fn labels(&self) -> (Vec<&str>, Vec<&str>) {
+        let done = self
+            .undo
+            .iter()
+            .map(|step| step.label.as_str())
+            .chain(self.open.as_ref().map(|open| open.label.as_str()))
+            .collect();
+        let undone = self
+            .redo
+            .iter()
+            .rev()
+            .map(|step| step.label.as_str())
+            .collect();
+        (done, undone)
+    }
+}
+
+impl App {
+    /// Record the circuit before an edit made in this frame
+    pub fn checkpoint(&mut self, label: impl Into<String>) {
+        self.history.finish(&self.db);
+        self.history.push(label, self.db.clone());
+    }
+
+    /// Record the circuit before a property edit, consecutive edits of `id` make one step
+    pub fn checkpoint_property(&mut self, id: InstanceId) {
+        self.history.finish(&self.db);
+        self.history
+            .push_merged("Edit properties", id, self.db.clone());
+    }
+
+    pub fn undo(&mut self) {
+        self.history.finish(&self.db);
+        if self.history.undo(&mut self.db) {
+            self.restore();
+        }
+    }
+
+    pub fn redo(&mut self) {
+        self.history.finish(&self.db);
+        if self.history.redo(&mut self.db) {
+            self.restore();
+        }
+    }
+
+    /// Bring the editor state in line with a circuit put back by undo or redo
+    fn restore(&mut self) {
+        self.hovered = None;
+        self.drag = None;
+        self.potential_connections.clear();
+        self.editing_label = None;
+        self.label_edit_buffer.clear();
+        self.selected
+            .retain(|&id| self.db.circuit.types.contains_key(id));
+        if let Some(view) = &self.viewing_module
+            && !self.db.circuit.types.contains_key(view.module_id)
+        {
+            self.viewing_module = None;
+        }
+        self.connection_manager.dirty_instances.clear();
+        self.connection_manager
+            .rebuild_spatial_index(&self.db.circuit, &self.db);
+        self.simulator.forget_removed(&self.db.circuit);
+        self.current_dirty = true;
+    }
+
+    /// Undo and redo shortcuts, ignored while typing or dragging
+    pub fn handle_history_keys(&mut self, ctx: &egui::Context) {
+        if ctx.wants_keyboard_input() || self.drag.is_some() {
+            return;
+        }
+        // Ctrl+Z also matches with shift held, so redo is checked first
+        let (undo, redo) = ctx.input_mut(|i| {
+            let redo = i.consume_shortcut(&REDO) || i.consume_shortcut(&REDO_Y);
+            (i.consume_shortcut(&UNDO), redo)
+        });
+        if redo {
+            self.redo();
+        } else if undo {
+            self.undo();
+        }
+    }
+
+    /// Window listing the steps, clicking one goes back or forward to it
+    pub fn draw_history(&mut self, ctx: &egui::Context) {
+        if !self.show_history {
+            return;
+        }
+        let mut open = true;
+        let mut travel: Option<isize> = None;
+        egui::Window::new("History")
+            .open(&mut open)
+            .default_width(220.0)
+            .show(ctx, |ui| {
+                let (done, undone) = self.history.labels();
+                let steps = done.len();
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    if ui.selectable_label(steps == 0, "Start").clicked() {
+                        travel = Some(-(steps as isize));
+                    }
+                    for (i, label) in done.iter().enumerate() {
+                        if ui.selectable_label(i + 1 == steps, *label).clicked() {
+                            travel = Some(i as isize + 1 - steps as isize);
+                        }
+                    }
+                    let weak = ui.visuals().weak_text_color();
+                    for (i, label) in undone.iter().enumerate() {
+                        let text = egui::RichText::new(*label).color(weak);
+                        if ui.selectable_label(false, text).clicked() {
+                            travel = Some(i as isize + 1);
+                        }
+                    }
+                });
+            });
+        self.show_history = open;
+
+        match travel {
+            Some(count) if count < 0 => (0..-count).for_each(|_| self.undo()),
+            Some(count) => (0..count).for_each(|_| self.redo()),
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::{Vec2, pos2, vec2};
+
+    use super::History;
+    use crate::app::App;
+    use crate::connection_manager::ConnectionManager;
+    use crate::db::{DB, Lamp, Power, Wire};
+    use crate::drag::{CanvasDrag, Drag};
+    use crate::simulator::{Simulator, lamp_input, power_output, wire_end};
+
+    /// Settle the simulation like a frame does, then compare the editor state with the one built
+    /// from scratch for the same circuit
+    fn assert_rebuilt(app: &mut App) {
+        if app.current_dirty {
+            app.simulator.compute(&app.db, &app.db.circuit);
+            app.simulator.store_state(&mut app.db.circuit);
+            app.current_dirty = false;
+        }
+        let fresh = ConnectionManager::new(&app.db.circuit, &app.canvas_config, &app.db);
+        assert!(app.connection_manager.same_index(&fresh));
+        let mut simulator = Simulator::default();
+        simulator.compute(&app.db, &app.db.circuit);
+        assert_eq!(app.simulator.current, simulator.current);
+    }
+
+    fn lamp_is_on(app: &App, lamp: crate::db::InstanceId) -> bool {
+        app.simulator
+            .pin_value(&app.db.circuit, lamp_input(lamp))
+            .any_one()
+    }
+
+    #[test]
+    fn undo_and_redo_steps() {
+        let mut history = History::default();
+        let mut db = DB::default();
+
+        history.begin("Nothing", &db);
+        history.finish(&db);
+        assert!(!history.can_undo());
+
+        history.push("Add lamp", db.clone());
+        let lamp = db.circuit.new_lamp(Lamp::new(egui::pos2(20.0, 20.0)));
+        history.push_merged("Edit properties", lamp, db.clone());
+        db.circuit.set_name(lamp, "q");
+        history.push_merged("Edit properties", lamp, db.clone());
+        db.circuit.set_name(lamp, "out");
+        assert_eq!(history.labels().0, ["Add lamp", "Edit properties"]);
+
+        assert!(history.undo(&mut db));
+        assert_eq!(db.circuit.name(lamp), None);
+        assert!(history.undo(&mut db));
+        assert!(db.circuit.lamps.is_empty());
+        assert!(!history.undo(&mut db));
+
+        assert!(history.redo(&mut db));
+        assert!(history.redo(&mut db));
+        assert_eq!(db.circuit.name(lamp), Some("out"));
+        assert!(!history.can_redo());
+
+        assert!(history.undo(&mut db));
+        history.push("Delete", db.clone());
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn undo_in_the_app_matches_a_rebuild() {
+        let mut app = App::default();
+        let power = app.db.circuit.new_power(Power::new(pos2(0.0, 0.0), true));
+        let lamp = app.db.circuit.new_lamp(Lamp::new(pos2(300.0, 200.0)));
+        let pin_position = |app: &App, pin| {
+            app.db
+                .circuit
+                .pin_position(pin, &app.canvas_config, &app.db)
+        };
+        let start = pin_position(&app, power_output(power));
+        let wire = app
+            .db
+            .circuit
+            .new_wire(Wire::new(start, start + vec2(100.0, 0.0)));
+        app.connection_manager
+            .rebuild_spatial_index(&app.db.circuit, &app.db);
+        app.connection_manager.mark_instance_dirty(wire);
+        app.connection_manager.update_connections(&mut app.db);
+        assert_rebuilt(&mut app);
+        assert!(!lamp_is_on(&app, lamp));
+
+        // Drop the lamp close enough to the wire end to snap onto it
+        app.set_drag(Drag::Canvas(CanvasDrag::Single {
+            id: lamp,
+            offset: Vec2::ZERO,
+        }));
+        let end = app.db.circuit.get_wire(wire).end;
+        let miss = end - pin_position(&app, lamp_input(lamp)) + vec2(3.0, 2.0);
+        app.db.circuit.get_lamp_mut(lamp).pos += miss;
+        app.handle_drag_end(end);
+        app.connection_manager.update_connections(&mut app.db);
+        app.history.finish(&app.db);
+        assert_eq!(pin_position(&app, lamp_input(lamp)), end);
+        assert!(
+            app.db
+                .circuit
+                .connected_pins(lamp_input(lamp))
+                .contains(&wire_end(wire))
+        );
+        assert_rebuilt(&mut app);
+        assert!(lamp_is_on(&app, lamp));
+
+        app.checkpoint("Delete");
+        app.delete_instance(wire);
+        assert_rebuilt(&mut app);
+        assert!(!lamp_is_on(&app, lamp));
+        assert_eq!(app.history.labels().0, ["Move", "Delete"]);
+
+        app.undo();
+        assert!(app.db.circuit.wires.contains_key(wire));
+        assert_rebuilt(&mut app);
+        assert!(lamp_is_on(&app, lamp));
+
+        app.undo();
+        assert_eq!(app.db.circuit.get_lamp(lamp).pos, pos2(300.0, 200.0));
+        assert!(app.db.circuit.connected_pins(lamp_input(lamp)).is_empty());
+        assert_rebuilt(&mut app);
+        assert!(!lamp_is_on(&app, lamp));
+
+        app.redo();
+        app.redo();
+        assert!(!app.db.circuit.wires.contains_key(wire));
+        assert!(!app.history.can_redo());
+        assert_rebuilt(&mut app);
+    }
+}
@@ -2,11 +2,30 @@
 
 pub mod app;
 pub mod assets;
+pub mod boolean;
+pub mod builder;
 pub mod config;
 pub mod connection_manager;
 pub mod db;
 pub mod drag;
+pub mod drc;
+pub mod equivalence;
+pub mod hex;
+pub mod history;
+pub mod loops;
+pub mod minimization;
 pub mod module;
+pub mod net;
+pub mod routing;
+pub mod sat;
 pub mod save_load;
 pub use app::App;
 pub mod simulator;
+pub mod synthesis;
+pub mod test_vectors;
+pub mod truth_table;
+pub mod vcd;
+pub mod verilog;
+pub mod verilog_import;
+pub mod waveform;
+pub mod zoom;
@@ -0,0 +1,254 @@
+//! Feedback loops through combinational logic.
+//!
+//! Gates, splitters and ROMs whose outputs reach their own inputs without going through a flip
+//! flop or RAM are found as the strongly connected components of the graph from each driver to
+//! the instances reading its net. A ring of gates that only invert or pass their input on has a
+//! fixed number of inversions around it: an even number makes a latch holding one of two states,
+//! an odd number can never settle.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::db::{Circuit, GateKind, InstanceId, InstanceKind, MemoryKind, Pin};
+use crate::net::Netlist;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopKind {
+    /// Even number of inversions, like two cross coupled Nand or Nor gates
+    Latch,
+    /// Odd number of inversions, it oscillates whatever its other inputs are
+    Ring,
+    /// Feedback that is not a plain ring of gates
+    Feedback,
+}
+
+#[derive(Debug, Clone)]
+pub struct Loop {
+    pub kind: LoopKind,
+    /// Members in id order
+    pub instances: Vec<InstanceId>,
+}
+
+impl Loop {
+    pub fn describe(&self) -> String {
+        let n = self.instances.len();
+        match self.kind {
+            LoopKind::Latch => format!("latch of {n} gates"),
+            LoopKind::Ring => format!("ring of {n} gates with an odd number of inversions"),
+            LoopKind::Feedback => format!("feedback loop through {n} instances"),
+        }
+    }
+}
+
+/// Every loop of the circuit
+pub fn find(circuit: &Circuit, nets: &Netlist) -> Vec<Loop> {
+    let combinational = |id: InstanceId| {
+        matches!(
+            circuit.ty(id),
+            InstanceKind::Gate(_)
+                | InstanceKind::Splitter(_)
+                | InstanceKind::Memory(MemoryKind::Rom)
+        )
+    };
+    let mut edges: HashMap<InstanceId, Vec<InstanceId>> = HashMap::new();
+    for net in &nets.nets {
+        for driver in net.drivers.iter().filter(|pin| combinational(pin.ins)) {
+            let next = edges.entry(driver.ins).or_default();
+            next.extend(
+                net.readers
+                    .iter()
+                    .map(|pin| pin.ins)
+                    .filter(|&id| combinational(id)),
+            );
+        }
+    }
+    for next in edges.values_mut() {
+        next.sort_unstable();
+        next.dedup();
+    }
+    let mut nodes: Vec<InstanceId> = edges.keys().copied().collect();
+    nodes.sort_unstable();
+
+    strongly_connected(&nodes, &edges)
+        .into_iter()
+        .filter(|component| component.len() > 1 || edges[&component[0]].contains(&component[0]))
+        .map(|instances| Loop {
+            kind: classify(circuit, &edges, &instances),
+            instances,
+        })
+        .collect()
+}
+
+fn classify(
+    circuit: &Circuit,
+    edges: &HashMap<InstanceId, Vec<InstanceId>>,
+    members: &[InstanceId],
+) -> LoopKind {
+    // In a plain ring every member feeds exactly one other member
+    let ring = members.iter().all(|id| {
+        edges[id]
+            .iter()
+            .filter(|next| members.binary_search(next).is_ok())
+            .count()
+            == 1
+    });
+    let mut inversions = 0;
+    for &id in members {
+        match circuit.ty(id) {
+            InstanceKind::Gate(GateKind::Not | GateKind::Nand | GateKind::Nor) => inversions += 1,
+            InstanceKind::Gate(GateKind::And | GateKind::Or) => {}
+            _ => return LoopKind::Feedback,
+        }
+    }
+    match (ring, inversions % 2) {
+        (false, _) => LoopKind::Feedback,
+        (true, 0) => LoopKind::Latch,
+        (true, _) => LoopKind::Ring,
+    }
+}
+
+/// Short reason why the simulation did not settle, from the loops and the pins still toggling
+/// when it gave up
+pub fn explain(loops: &[Loop], toggling: &HashSet<Pin>) -> String {
+    let active: Vec<&Loop> = loops
+        .iter()
+        .filter(|l| toggling.iter().any(|pin| l.instances.contains(&pin.ins)))
+        .collect();
+    let Some(first) = active
+        .iter()
+        .find(|l| l.kind == LoopKind::Ring)
+        .or_else(|| active.first())
+    else {
+        return if toggling.is_empty() {
+            "No loop found, the circuit may need a larger event budget".to_owned()
+        } else {
+            format!(
+                "{} pins kept changing outside any loop, the circuit may need a larger event budget",
+                toggling.len()
+            )
+        };
+    };
+    let what = match first.kind {
+        LoopKind::Ring => "never settles",
+        LoopKind::Latch => "keeps flipping, its inputs probably released both states at once",
+        LoopKind::Feedback => "keeps changing",
+    };
+    let others = match active.len() {
+        1 => String::new(),
+        n => format!(" ({} more loops are changing)", n - 1),
+    };
+    format!("A {} {what}{others}", first.describe())
+}
+
+/// Tarjan's algorithm, iterative so long chains do not overflow the stack
+fn strongly_connected(
+    nodes: &[InstanceId],
+    edges: &HashMap<InstanceId, Vec<InstanceId>>,
+) -> Vec<Vec<InstanceId>> {
+    let mut index: HashMap<InstanceId, usize> = HashMap::new();
+    let mut low: HashMap<InstanceId, usize> = HashMap::new();
+    let mut on_stack: HashSet<InstanceId> = HashSet::new();
+    let mut stack: Vec<InstanceId> = Vec::new();
+    let mut components = Vec::new();
+    let no_edges = Vec::new();
+
+    for &root in nodes {
+        if index.contains_key(&root) {
+            continue;
+        }
+        // Node and the position of the next edge to follow from it
+        let mut work = vec![(root, 0)];
+        while let Some(&(node, next)) = work.last() {
+            if next == 0 {
+                let order = index.len();
+                index.insert(node, order);
+                low.insert(node, order);
+                stack.push(node);
+                on_stack.insert(node);
+            }
+            if let Some(&successor) = edges.get(&node).unwrap_or(&no_edges).get(next) {
+                if let Some((_, next)) = work.last_mut() {
+                    *next += 1;
+                }
+                if !index.contains_key(&successor) {
+                    work.push((successor, 0));
+                } else if on_stack.contains(&successor) {
+                    let lowest = low[&node].min(index[&successor]);
+                    low.insert(node, lowest);
+                }
+                continue;
+            }
+
+            work.pop();
+            if let Some(&(parent, _)) = work.last() {
+                let lowest = low[&parent].min(low[&node]);
+                low.insert(parent, lowest);
+            }
+            if low[&node] == index[&node] {
+                let mut component = Vec::new();
+                while let Some(member) = stack.pop() {
+                    on_stack.remove(&member);
+                    component.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                component.sort_unstable();
+                components.push(component);
+            }
+        }
+    }
+    components
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::Pos2;
+
+    use super::{LoopKind, find};
+    use crate::connection_manager::Connection;
+    use crate::db::{DB, Gate, GateKind, InstanceId};
+    use crate::net::Netlist;
+    use crate::simulator::{gate_inp1, gate_inp2, gate_output};
+
+    /// Gates of `kinds` feeding each other in a ring through their first input
+    fn ring(db: &mut DB, kinds: &[GateKind]) -> Vec<InstanceId> {
+        let gates: Vec<InstanceId> = kinds
+            .iter()
+            .map(|&kind| db.circuit.new_gate(Gate::new(Pos2::ZERO, kind)))
+            .collect();
+        for (i, &gate) in gates.iter().enumerate() {
+            let next = gates[(i + 1) % gates.len()];
+            db.circuit
+                .connections
+                .insert(Connection::new(gate_output(gate), gate_inp1(next)));
+        }
+        gates
+    }
+
+    #[test]
+    fn tells_latches_from_rings() {
+        let mut db = DB::default();
+        let latch = ring(&mut db, &[GateKind::Nor, GateKind::Nor]);
+        let oscillator = ring(&mut db, &[GateKind::Not, GateKind::Not, GateKind::Not]);
+        let xor = ring(&mut db, &[GateKind::Xor, GateKind::And]);
+        // A chain into the latch is not part of it
+        let feeding = db.circuit.new_gate(Gate::new(Pos2::ZERO, GateKind::Not));
+        db.circuit
+            .connections
+            .insert(Connection::new(gate_output(feeding), gate_inp2(latch[0])));
+
+        let loops = find(&db.circuit, &Netlist::build(&db, &db.circuit));
+        let kind_of = |members: &[InstanceId]| {
+            let found = loops
+                .iter()
+                .find(|l| l.instances.contains(&members[0]))
+                .expect("loop is found");
+            assert_eq!(found.instances.len(), members.len());
+            found.kind
+        };
+        assert_eq!(loops.len(), 3);
+        assert_eq!(kind_of(&latch), LoopKind::Latch);
+        assert_eq!(kind_of(&oscillator), LoopKind::Ring);
+        assert_eq!(kind_of(&xor), LoopKind::Feedback);
+    }
+}
@@ -0,0 +1,447 @@
+//! Boolean expressions of a selected combinational circuit and their minimized forms.
+//!
+//! Powers and pins the selection leaves unconnected are the inputs, lamps and unconnected output
+//! pins are the outputs, as for truth tables. The expression of an output follows the connections
+//! back through wires, tunnels and gates to the inputs. Each output is then minimized to a sum of
+//! products and shown on a Karnaugh map with the chosen groups outlined.
+
+use std::collections::{HashMap, HashSet};
+
+use egui::{Color32, FontId, Pos2, Rect, Sense, Stroke, StrokeKind, Ui, Vec2};
+
+use crate::App;
+use crate::assets::PinKind;
+use crate::boolean::{Expr, Term, covers, minimize, sum_of_products};
+use crate::db::{Circuit, DB, GateKind, InstanceId, InstanceKind, Pin};
+use crate::module::definition_from_selection;
+use crate::net::Netlist;
+use crate::simulator::{gate_inp1, gate_inp2};
+use crate::truth_table::MAX_INPUT_BITS;
+
+/// Largest number of inputs drawn as a Karnaugh map
+pub const MAX_KMAP_INPUTS: usize = 6;
+
+const CELL_SIZE: f32 = 32.0;
+
+const GROUP_COLORS: [Color32; 6] = [
+    Color32::from_rgb(220, 50, 47),
+    Color32::from_rgb(38, 139, 210),
+    Color32::from_rgb(60, 160, 60),
+    Color32::from_rgb(230, 140, 0),
+    Color32::from_rgb(160, 70, 200),
+    Color32::from_rgb(0, 170, 170),
+];
+
+pub struct OutputExpression {
+    pub name: String,
+    /// Expression read off the gates
+    pub extracted: Expr,
+    /// Value in every row of the truth table
+    pub values: Vec<Option<bool>>,
+    /// Groups of the minimized sum of products
+    pub terms: Vec<Term>,
+    pub minimized: Expr,
+}
+
+pub struct ExpressionView {
+    pub inputs: Vec<String>,
+    pub outputs: Vec<OutputExpression>,
+    /// Output drawn on the Karnaugh map
+    pub shown: usize,
+}
+
+impl ExpressionView {
+    pub fn for_selection(db: &DB, instances: &HashSet<InstanceId>) -> Result<Self, String> {
+        let definition = definition_from_selection(db, "selection".to_owned(), instances)?;
+        let circuit = &definition.circuit;
+        let mut extractor = Extractor {
+            circuit,
+            nets: Netlist::build(db, circuit),
+            variables: HashMap::new(),
+            gates: HashMap::new(),
+            visiting: HashSet::new(),
+        };
+
+        let mut inputs = Vec::new();
+        let mut outputs = Vec::new();
+        for port in definition.ports(db, true) {
+            match port.kind {
+                PinKind::Input => {
+                    extractor.variables.insert(port.pin, port.name.clone());
+                    inputs.push(port.name);
+                }
+                PinKind::Output => outputs.push((port.name, port.pin)),
+            }
+        }
+
+        if outputs.is_empty() {
+            return Err(
+                "there are no outputs, leave an output pin unconnected or add a lamp".to_owned(),
+            );
+        }
+        if inputs.len() > MAX_INPUT_BITS as usize {
+            return Err(format!(
+                "{} inputs are too many, at most {MAX_INPUT_BITS} are supported",
+                inputs.len()
+            ));
+        }
+
+        let outputs = outputs
+            .into_iter()
+            .map(|(name, pin)| {
+                let extracted = extractor
+                    .expr_at(pin)
+                    .map_err(|e| format!("output {name}: {e}"))?;
+                let values: Vec<Option<bool>> = (0..1 << inputs.len())
+                    .map(|row| Some(extracted.eval(&inputs, row)))
+                    .collect();
+                let terms = minimize(&values, inputs.len());
+                Ok(OutputExpression {
+                    minimized: sum_of_products(&inputs, &terms),
+                    name,
+                    extracted,
+                    values,
+                    terms,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self {
+            inputs,
+            outputs,
+            shown: 0,
+        })
+    }
+}
+
+struct Extractor<'a> {
+    circuit: &'a Circuit,
+    nets: Netlist,
+    /// Pins standing for an input
+    variables: HashMap<Pin, String>,
+    gates: HashMap<InstanceId, Expr>,
+    /// Gates whose expression is being built, to notice feedback
+    visiting: HashSet<InstanceId>,
+}
+
+impl Extractor<'_> {
+    fn describe(&self, id: InstanceId) -> String {
+        match self.circuit.name(id) {
+            Some(name) => name.to_owned(),
+            None => match self.circuit.ty(id) {
+                InstanceKind::Gate(kind) => format!("a {kind:?} gate"),
+                kind => format!("a {kind:?}"),
+            },
+        }
+    }
+
+    /// Expression of the value seen at `pin`, found through the one output on its net
+    fn expr_at(&mut self, pin: Pin) -> Result<Expr, String> {
+        let drivers = self.nets.sources(pin, |p| self.variables.contains_key(&p));
+        match drivers[..] {
+            [driver] => match self.variables.get(&driver) {
+                Some(name) => Ok(Expr::Var(name.clone())),
+                None => self.gate_expr(driver.ins),
+            },
+            [] => Err(format!(
+                "an input of {} is not driven",
+                self.describe(pin.ins)
+            )),
+            _ => Err(format!(
+                "{} and {} drive the same net",
+                self.describe(drivers[0].ins),
+                self.describe(drivers[1].ins)
+            )),
+        }
+    }
+
+    fn gate_expr(&mut self, id: InstanceId) -> Result<Expr, String> {
+        if let Some(expr) = self.gates.get(&id) {
+            return Ok(expr.clone());
+        }
+        let InstanceKind::Gate(kind) = self.circuit.ty(id) else {
+            return Err(format!(
+                "{} has no boolean expression, select only gates, wires, powers and lamps",
+                self.describe(id)
+            ));
+        };
+        if self.circuit.get_gate(id).width != 1 {
+            return Err(format!("{} works on buses", self.describe(id)));
+        }
+        if !self.visiting.insert(id) {
+            return Err(format!(
+                "{} is part of a feedback loop, only combinational circuits have an expression",
+                self.describe(id)
+            ));
+        }
+
+        let a = Box::new(self.expr_at(gate_inp1(id))?);
+        let expr = match kind {
+            GateKind::Not => Expr::Not(a),
+            GateKind::TriState => {
+                return Err(format!(
+                    "{} is a tri-state buffer, its output can float",
+                    self.describe(id)
+                ));
+            }
+            _ => {
+                let b = Box::new(self.expr_at(gate_inp2(id))?);
+                match kind {
+                    GateKind::And => Expr::And(a, b),
+                    GateKind::Or => Expr::Or(a, b),
+                    GateKind::Xor => Expr::Xor(a, b),
+                    GateKind::Nand => Expr::Not(Box::new(Expr::And(a, b))),
+                    GateKind::Nor => Expr::Not(Box::new(Expr::Or(a, b))),
+                    GateKind::Xnor => Expr::Not(Box::new(Expr::Xor(a, b))),
+                    GateKind::Not | GateKind::TriState => unreachable!("Handled above"),
+                }
+            }
+        };
+        self.visiting.remove(&id);
+        self.gates.insert(id, expr.clone());
+        Ok(expr)
+    }
+}
+
+impl App {
+    pub fn expressions_of_selection(&mut self) {
+        match ExpressionView::for_selection(&self.db, &self.selected) {
+            Ok(view) => self.expressions = Some(view),
+            Err(e) => log::error!("Cannot derive boolean expressions: {e}"),
+        }
+    }
+
+    pub fn draw_expressions(&mut self, ctx: &egui::Context) {
+        let Some(view) = &mut self.expressions else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new("Boolean expressions")
+            .open(&mut open)
+            .resizable(true)
+            .show(ctx, |ui| {
+                egui::Grid::new("expressions")
+                    .striped(true)
+                    .spacing([16.0, 4.0])
+                    .show(ui, |ui| {
+                        ui.strong("Output");
+                        ui.strong("From the gates");
+                        ui.strong("Minimized");
+                        ui.end_row();
+                        for output in &view.outputs {
+                            ui.label(&output.name);
+                            ui.monospace(output.extracted.to_string());
+                            ui.monospace(output.minimized.to_string());
+                            ui.end_row();
+                        }
+                    });
+                ui.separator();
+
+                if view.outputs.len() > 1 {
+                    egui::ComboBox::from_label("Karnaugh map of")
+                        .selected_text(&view.outputs[view.shown].name)
+                        .show_ui(ui, |ui| {
+                            for (i, output) in view.outputs.iter().enumerate() {
+                                ui.selectable_value(&mut view.shown, i, &output.name);
+                            }
+                        });
+                }
+                let output = &view.outputs[view.shown];
+                if view.inputs.is_empty() {
+                    ui.label("The output is constant");
+                } else if view.inputs.len() > MAX_KMAP_INPUTS {
+                    ui.label(format!(
+                        "Karnaugh maps are drawn for up to {MAX_KMAP_INPUTS} inputs, \
+                         the minimized form comes from the Quine-McCluskey method"
+                    ));
+                } else {
+                    draw_kmap(ui, &view.inputs, output);
+                }
+                for (i, term) in output.terms.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        let (rect, _) = ui.allocate_exact_size(Vec2::splat(12.0), Sense::hover());
+                        ui.painter()
+                            .rect_filled(rect, 2.0, GROUP_COLORS[i % GROUP_COLORS.len()]);
+                        let term = sum_of_products(&view.inputs, std::slice::from_ref(term));
+                        ui.monospace(term.to_string());
+                    });
+                }
+            });
+        if !open {
+            self.expressions = None;
+        }
+    }
+}
+
+fn gray(i: usize) -> usize {
+    i ^ (i >> 1)
+}
+
+/// Karnaugh map of `output`, with one 4x4 map for each value of the inputs beyond the last four
+fn draw_kmap(ui: &mut Ui, inputs: &[String], output: &OutputExpression) {
+    let n = inputs.len();
+    let map_inputs = n.min(4);
+    let outer = n - map_inputs;
+    let row_inputs = map_inputs / 2;
+    let column_inputs = map_inputs - row_inputs;
+    let corner = format!(
+        "{}\\{}",
+        inputs[outer..outer + row_inputs].join(" "),
+        inputs[outer + row_inputs..].join(" ")
+    );
+    let font = FontId::monospace(12.0);
+    let text_color = ui.visuals().text_color();
+    let line = Stroke::new(1.0, ui.visuals().weak_text_color());
+
+    ui.horizontal_wrapped(|ui| {
+        for map in 0..1 << outer {
+            ui.vertical(|ui| {
+                if outer > 0 {
+                    let values: Vec<String> = (0..outer)
+                        .map(|i| format!("{}={}", inputs[i], map >> (outer - 1 - i) & 1))
+                        .collect();
+                    ui.label(values.join(", "));
+                }
+                let rows = 1 << row_inputs;
+                let columns = 1 << column_inputs;
+                let size = Vec2::new(columns as f32 + 1.0, rows as f32 + 1.0) * CELL_SIZE;
+                let (rect, _) = ui.allocate_exact_size(size, Sense::hover());
+                let painter = ui.painter_at(rect);
+                let cell = |row: usize, column: usize| {
+                    Rect::from_min_size(
+                        rect.min + Vec2::new(column as f32, row as f32) * CELL_SIZE,
+                        Vec2::splat(CELL_SIZE),
+                    )
+                };
+                let label = |pos: Pos2, text: String| {
+                    painter.text(
+                        pos,
+                        egui::Align2::CENTER_CENTER,
+                        text,
+                        font.clone(),
+                        text_color,
+                    );
+                };
+
+                label(cell(0, 0).center(), corner.clone());
+                for column in 0..columns {
+                    let code = format!("{:0width$b}", gray(column), width = column_inputs);
+                    label(cell(0, column + 1).center(), code);
+                }
+                for row in 0..rows {
+                    let code = if row_inputs == 0 {
+                        String::new()
+                    } else {
+                        format!("{:0width$b}", gray(row), width = row_inputs)
+                    };
+                    label(cell(row + 1, 0).center(), code);
+                    for column in 0..columns {
+                        let index = map << map_inputs | gray(row) << column_inputs | gray(column);
+                        let rect = cell(row + 1, column + 1);
+                        painter.rect_stroke(rect, 0.0, line, StrokeKind::Inside);
+                        let value = match output.values[index] {
+                            Some(false) => "0",
+                            Some(true) => "1",
+                            None => "x",
+                        };
+                        label(rect.center(), value.to_owned());
+                        for (i, term) in output.terms.iter().enumerate() {
+                            if covers(term, n, index) {
+                                // Each color has its own inset so overlapping groups stay apart
+                                let group = i % GROUP_COLORS.len();
+                                let inset = 2.0 + 2.5 * group as f32;
+                                painter.rect_stroke(
+                                    rect.shrink(inset),
+                                    3.0,
+                                    Stroke::new(1.5, GROUP_COLORS[group]),
+                                    StrokeKind::Inside,
+                                );
+                            }
+                        }
+                    }
+                }
+            });
+            ui.add_space(8.0);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use egui::Pos2;
+
+    use super::ExpressionView;
+    use crate::builder::CircuitBuilder;
+    use crate::connection_manager::Connection;
+    use crate::db::{GateKind, Tunnel};
+    use crate::simulator::{gate_output, lamp_input, tunnel_pin};
+
+    #[test]
+    fn consensus_term_is_removed() -> Result<(), String> {
+        // a & b | !a & c | b & c, the last product is redundant
+        let mut b = CircuitBuilder::new();
+        for input in ["a", "b", "c"] {
+            b.input(input)?;
+        }
+        b.gate("na", GateKind::Not)?;
+        for gate in ["ab", "nac", "bc"] {
+            b.gate(gate, GateKind::And)?;
+        }
+        b.gate("o1", GateKind::Or)?;
+        b.gate("o2", GateKind::Or)?;
+        b.output("y")?;
+        for (from, to) in [
+            ("a", "na.in"),
+            ("a", "ab.a"),
+            ("b", "ab.b"),
+            ("na", "nac.a"),
+            ("c", "nac.b"),
+            ("b", "bc.a"),
+            ("c", "bc.b"),
+            ("ab", "o1.a"),
+            ("nac", "o1.b"),
+            ("o1", "o2.a"),
+            ("bc", "o2.b"),
+            ("o2", "y"),
+        ] {
+            b.connect(from, to)?;
+        }
+        let sim = b.build();
+        let all: HashSet<_> = sim.db.circuit.types.keys().collect();
+        let view = ExpressionView::for_selection(&sim.db, &all)?;
+        assert_eq!(view.outputs.len(), 1);
+        let output = &view.outputs[0];
+        assert_eq!(output.extracted.variables().len(), 3);
+        assert_eq!(output.terms.len(), 2);
+        assert_eq!(output.minimized.to_string(), "a & b | !a & c");
+        Ok(())
+    }
+
+    #[test]
+    fn follows_tunnels() -> Result<(), String> {
+        let mut b = CircuitBuilder::new();
+        b.input("a")?;
+        b.input("b")?;
+        b.gate("n1", GateKind::And)?;
+        b.output("y")?;
+        b.connect("a", "n1.a")?;
+        b.connect("b", "n1.b")?;
+        let (n1, y) = (b.id("n1")?, b.id("y")?);
+        let circuit = b.circuit_mut();
+        let here = circuit.new_tunnel(Tunnel::new(Pos2::ZERO, "t"));
+        let there = circuit.new_tunnel(Tunnel::new(Pos2::ZERO, "t"));
+        for (from, to) in [
+            (gate_output(n1), tunnel_pin(here)),
+            (tunnel_pin(there), lamp_input(y)),
+        ] {
+            circuit.connections.insert(Connection::new(from, to));
+        }
+        let sim = b.build();
+        let all: HashSet<_> = sim.db.circuit.types.keys().collect();
+        let view = ExpressionView::for_selection(&sim.db, &all)?;
+        assert_eq!(view.outputs.len(), 1);
+        assert_eq!(view.outputs[0].minimized.to_string(), "a & b");
+        Ok(())
+    }
+}
@@ -9,9 +9,11 @@ use serde::{Deserialize as _, Deserializer, Serialize as _, Serializer};
 use crate::{
     app::App,
     assets::PinKind,
+    builder::pin_names_in,
     config::CanvasConfig,
     connection_manager::Connection,
-    db::{Circuit, DB, InstanceId, InstanceKind, ModuleDefId, Pin},
+    db::{Circuit, DB, InstanceId, InstanceKind, ModuleDefId, Orientation, Pin},
+    simulator::{lamp_input, power_output},
 };
 
 pub fn serialize<S>(map: &BTreeMap<Pin, Pin>, serializer: S) -> Result<S::Ok, S::Error>
@@ -30,7 +32,41 @@ where
     Ok(vec.into_iter().collect())
 }
 
-#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+/// Input or output of a module definition
+pub struct Port {
+    pub name: String,
+    /// Direction of the port, a power standing for an input has an output pin
+    pub kind: PinKind,
+    /// Pin of an instance of the definition
+    pub pin: Pin,
+}
+
+/// Name of the instance, with the pin name when it has more than one pin. Unnamed instances
+/// are numbered.
+fn port_name(
+    circuit: &Circuit,
+    db: &DB,
+    id: InstanceId,
+    pin: Option<Pin>,
+    prefix: &str,
+    index: usize,
+) -> String {
+    let Some(name) = circuit.name(id) else {
+        return format!("{prefix}{index}");
+    };
+    let names = pin_names_in(circuit, db, id);
+    match pin {
+        Some(pin) if names.len() > 1 && !matches!(circuit.ty(id), InstanceKind::Wire) => {
+            names.iter().find(|(_, p)| *p == pin).map_or_else(
+                || format!("{name}.{}", pin.index),
+                |(pin_name, _)| format!("{name}.{pin_name}"),
+            )
+        }
+        _ => name.to_owned(),
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq, Eq)]
 pub struct Module {
     pub pos: Pos2,
     pub definition_id: ModuleDefId,
@@ -38,9 +74,11 @@ pub struct Module {
     // external pin to internal pin mapping entry point of module
     #[serde(serialize_with = "serialize", deserialize_with = "deserialize")]
     pub pins: BTreeMap<Pin, Pin>,
+    #[serde(default)]
+    pub orientation: Orientation,
 }
 
-#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
 pub struct ModuleDefinition {
     pub name: String,
     pub circuit: Circuit,
@@ -77,6 +115,45 @@ impl ModuleDefinition {
         pos: Pos2,
         db: &mut DB,
     ) -> Module {
+        let def_id_to_world_id = self.copy_members_into(db);
+
+        // Create connections from module pins to internal component pins
+        let instances_in_order = self.instances_in_order();
+        let mut pins = BTreeMap::new();
+        let mut last_pin_index = 0;
+
+        for element_id in instances_in_order {
+            let element_world_id = def_id_to_world_id[&element_id];
+            let item_pins = self.circuit.pins_of(element_id, db);
+
+            for internal_pin in item_pins {
+                if self.circuit.connected_pins(internal_pin).is_empty() {
+                    let kind = internal_pin.kind;
+                    let external_pin = Pin::new(module_id, last_pin_index, kind);
+                    let internal_pin =
+                        Pin::new(element_world_id, internal_pin.index, internal_pin.kind);
+                    pins.insert(external_pin, internal_pin);
+                    let conn = Connection::new_bi(external_pin, internal_pin);
+                    db.circuit.connections.insert(conn);
+                    last_pin_index += 1;
+                }
+            }
+        }
+
+        let instance_members = def_id_to_world_id.values().copied().collect();
+        Module {
+            pos,
+            definition_id,
+            instance_members,
+            pins,
+            orientation: Orientation::default(),
+        }
+    }
+
+    /// Copy every instance and internal connection of the definition into the circuit of `db`,
+    /// nested modules are flattened. Returns the copy of each instance by its id in the
+    /// definition.
+    pub fn copy_members_into(&self, db: &mut DB) -> HashMap<InstanceId, InstanceId> {
         let mut def_id_to_world_id: HashMap<InstanceId, InstanceId> = HashMap::new();
 
         // First, create all instances
@@ -91,7 +168,7 @@ impl ModuleDefinition {
                     db.circuit.new_power(power)
                 }
                 InstanceKind::Wire => {
-                    let wire = *self.circuit.get_wire(member_id);
+                    let wire = self.circuit.get_wire(member_id).clone();
                     db.circuit.new_wire(wire)
                 }
                 InstanceKind::Lamp => {
@@ -102,17 +179,35 @@ impl ModuleDefinition {
                     let clock = *self.circuit.get_clock(member_id);
                     db.circuit.new_clock(clock)
                 }
+                InstanceKind::Splitter(_) => {
+                    let splitter = *self.circuit.get_splitter(member_id);
+                    db.circuit.new_splitter(splitter)
+                }
+                InstanceKind::FlipFlop(_) => {
+                    let flip_flop = *self.circuit.get_flip_flop(member_id);
+                    db.circuit.new_flip_flop(flip_flop)
+                }
+                InstanceKind::Memory(_) => {
+                    let memory = self.circuit.get_memory(member_id).clone();
+                    db.circuit.new_memory(memory)
+                }
+                InstanceKind::Tunnel => {
+                    let tunnel = self.circuit.get_tunnel(member_id).clone();
+                    db.circuit.new_tunnel(tunnel)
+                }
                 InstanceKind::Module(child_module_def_id) => {
                     let child_module = self.circuit.get_module(member_id).clone();
                     let child_module_pos = child_module.pos;
                     let child_module_def = db.get_module_def(child_module_def_id).clone();
                     let placed_child_module = db.circuit.new_module_id(child_module);
-                    child_module_def.flatten_into_circuit(
+                    // The flattened module knows its own members and pins
+                    let flattened = child_module_def.flatten_into_circuit(
                         child_module_def_id,
                         placed_child_module,
                         child_module_pos,
                         db,
                     );
+                    db.circuit.modules.insert(placed_child_module, flattened);
                     placed_child_module
                 }
             };
@@ -134,37 +229,9 @@ impl ModuleDefinition {
             }
         }
 
-        // Create connections from module pins to internal component pins
-        let instances_in_order = self.instances_in_order();
-        let mut pins = BTreeMap::new();
-        let mut last_pin_index = 0;
-
-        for element_id in instances_in_order {
-            let element_world_id = def_id_to_world_id[&element_id];
-            let item_pins = self.circuit.pins_of(element_id, db);
-
-            for internal_pin in item_pins {
-                if self.circuit.connected_pins(internal_pin).is_empty() {
-                    let kind = internal_pin.kind;
-                    let external_pin = Pin::new(module_id, last_pin_index, kind);
-                    let internal_pin =
-                        Pin::new(element_world_id, internal_pin.index, internal_pin.kind);
-                    pins.insert(external_pin, internal_pin);
-                    let conn = Connection::new_bi(external_pin, internal_pin);
-                    db.circuit.connections.insert(conn);
-                    last_pin_index += 1;
-                }
-            }
-        }
-
-        let instance_members = def_id_to_world_id.values().copied().collect();
-        Module {
-            pos,
-            definition_id,
-            instance_members,
-            pins,
-        }
+        def_id_to_world_id
     }
+
     pub fn display_definition(&self, _db: &DB, id: ModuleDefId) -> String {
         // Show only a summary, not the full internal circuit
         format!(
@@ -207,6 +274,10 @@ impl ModuleDefinition {
                 InstanceKind::Lamp => self.circuit.get_lamp(self_id).pos,
                 InstanceKind::Clock => self.circuit.get_clock(self_id).pos,
                 InstanceKind::Module(module_def_id) => self.circuit.get_module(self_id).pos,
+                InstanceKind::Splitter(_) => self.circuit.get_splitter(self_id).pos,
+                InstanceKind::FlipFlop(_) => self.circuit.get_flip_flop(self_id).pos,
+                InstanceKind::Memory(_) => self.circuit.get_memory(self_id).pos,
+                InstanceKind::Tunnel => self.circuit.get_tunnel(self_id).pos,
             };
 
             let other_id = *o;
@@ -217,6 +288,10 @@ impl ModuleDefinition {
                 InstanceKind::Lamp => self.circuit.get_lamp(other_id).pos,
                 InstanceKind::Clock => self.circuit.get_clock(other_id).pos,
                 InstanceKind::Module(_) => self.circuit.get_module(other_id).pos,
+                InstanceKind::Splitter(_) => self.circuit.get_splitter(other_id).pos,
+                InstanceKind::FlipFlop(_) => self.circuit.get_flip_flop(other_id).pos,
+                InstanceKind::Memory(_) => self.circuit.get_memory(other_id).pos,
+                InstanceKind::Tunnel => self.circuit.get_tunnel(other_id).pos,
             };
 
             if self_pos.y > other_pos.y {
@@ -236,6 +311,39 @@ impl ModuleDefinition {
         instances
     }
 
+    /// Inputs and outputs of the definition, the pins its instances leave unconnected in the
+    /// order of [`Self::get_unconnected_internal_pins`]. With `switches`, as for a selection,
+    /// powers are inputs and lamps are outputs too.
+    pub fn ports(&self, db: &DB, switches: bool) -> Vec<Port> {
+        let circuit = &self.circuit;
+        let mut ports: Vec<Port> = Vec::new();
+        for id in self.instances_in_order() {
+            let pins = match circuit.ty(id) {
+                InstanceKind::Power if switches => vec![(PinKind::Input, power_output(id), None)],
+                InstanceKind::Lamp if switches => vec![(PinKind::Output, lamp_input(id), None)],
+                _ => circuit
+                    .pins_of(id, db)
+                    .into_iter()
+                    .filter(|&pin| circuit.connected_pins(pin).is_empty())
+                    .map(|pin| (pin.kind, pin, Some(pin)))
+                    .collect(),
+            };
+            for (kind, pin, named) in pins {
+                let prefix = match kind {
+                    PinKind::Input => "in",
+                    PinKind::Output => "out",
+                };
+                let index = ports.iter().filter(|port| port.kind == kind).count();
+                ports.push(Port {
+                    name: port_name(circuit, db, id, named, prefix, index),
+                    kind,
+                    pin,
+                });
+            }
+        }
+        ports
+    }
+
     pub fn get_unconnected_internal_pins(&self, db: &DB) -> Vec<Pin> {
         let mut unconnected_pins = Vec::new();
 
@@ -316,85 +424,216 @@ impl App {
         name: String,
         instances: &HashSet<InstanceId>,
     ) -> Result<(), String> {
-        if instances.is_empty() {
-            return Err("No components selected".to_owned());
-        }
-
-        let mut sum_x = 0.0;
-        let mut sum_y = 0.0;
-        for &id in instances {
-            let pos = match self.db.circuit.ty(id) {
-                crate::db::InstanceKind::Gate(_) => self.db.circuit.get_gate(id).pos,
-                crate::db::InstanceKind::Power => self.db.circuit.get_power(id).pos,
-                crate::db::InstanceKind::Wire => self.db.circuit.get_wire(id).center(),
-                crate::db::InstanceKind::Lamp => self.db.circuit.get_lamp(id).pos,
-                crate::db::InstanceKind::Clock => self.db.circuit.get_clock(id).pos,
-                crate::db::InstanceKind::Module(_) => self.db.circuit.get_module(id).pos,
-            };
-            sum_x += pos.x;
-            sum_y += pos.y;
-        }
-        let center = Pos2::new(
-            sum_x / instances.len() as f32,
-            sum_y / instances.len() as f32,
-        );
+        let definition = definition_from_selection(&self.db, name, instances)?;
+        self.db.module_definitions.insert(definition);
+        Ok(())
+    }
+}
 
-        let mut circuit = Circuit::default();
-        let mut id_map = std::collections::HashMap::new();
+/// Definition holding copies of `instances` and the connections between them, centered on
+/// their middle
+pub fn definition_from_selection(
+    db: &DB,
+    name: String,
+    instances: &HashSet<InstanceId>,
+) -> Result<ModuleDefinition, String> {
+    if instances.is_empty() {
+        return Err("No components selected".to_owned());
+    }
 
-        for &old_id in instances {
-            let new_id = match self.db.circuit.ty(old_id) {
-                crate::db::InstanceKind::Gate(kind) => {
-                    let mut gate = *self.db.circuit.get_gate(old_id);
-                    gate.pos -= center.to_vec2();
-                    circuit.new_gate(gate)
-                }
-                crate::db::InstanceKind::Power => {
-                    let mut power = *self.db.circuit.get_power(old_id);
-                    power.pos -= center.to_vec2();
-                    circuit.new_power(power)
-                }
-                crate::db::InstanceKind::Wire => {
-                    let mut wire = *self.db.circuit.get_wire(old_id);
-                    wire.start -= center.to_vec2();
-                    wire.end -= center.to_vec2();
-                    circuit.new_wire(wire)
-                }
-                crate::db::InstanceKind::Lamp => {
-                    let mut lamp = *self.db.circuit.get_lamp(old_id);
-                    lamp.pos -= center.to_vec2();
-                    circuit.new_lamp(lamp)
-                }
-                crate::db::InstanceKind::Clock => {
-                    let mut clock = *self.db.circuit.get_clock(old_id);
-                    clock.pos -= center.to_vec2();
-                    circuit.new_clock(clock)
-                }
-                crate::db::InstanceKind::Module(def_id) => {
-                    let mut module = self.db.circuit.get_module(old_id).clone();
-                    module.pos -= center.to_vec2();
-                    circuit.new_module_id(module)
-                }
-            };
-            id_map.insert(old_id, new_id);
+    let mut sum_x = 0.0;
+    let mut sum_y = 0.0;
+    for &id in instances {
+        let pos = match db.circuit.ty(id) {
+            crate::db::InstanceKind::Gate(_) => db.circuit.get_gate(id).pos,
+            crate::db::InstanceKind::Power => db.circuit.get_power(id).pos,
+            crate::db::InstanceKind::Wire => db.circuit.get_wire(id).center(),
+            crate::db::InstanceKind::Lamp => db.circuit.get_lamp(id).pos,
+            crate::db::InstanceKind::Clock => db.circuit.get_clock(id).pos,
+            crate::db::InstanceKind::Module(_) => db.circuit.get_module(id).pos,
+            crate::db::InstanceKind::Splitter(_) => db.circuit.get_splitter(id).pos,
+            crate::db::InstanceKind::FlipFlop(_) => db.circuit.get_flip_flop(id).pos,
+            crate::db::InstanceKind::Memory(_) => db.circuit.get_memory(id).pos,
+            crate::db::InstanceKind::Tunnel => db.circuit.get_tunnel(id).pos,
+        };
+        sum_x += pos.x;
+        sum_y += pos.y;
+    }
+    let center = Pos2::new(
+        sum_x / instances.len() as f32,
+        sum_y / instances.len() as f32,
+    );
+
+    let mut circuit = Circuit::default();
+    let mut id_map = std::collections::HashMap::new();
+
+    for &old_id in instances {
+        let new_id = match db.circuit.ty(old_id) {
+            crate::db::InstanceKind::Gate(kind) => {
+                let mut gate = *db.circuit.get_gate(old_id);
+                gate.pos -= center.to_vec2();
+                circuit.new_gate(gate)
+            }
+            crate::db::InstanceKind::Power => {
+                let mut power = *db.circuit.get_power(old_id);
+                power.pos -= center.to_vec2();
+                circuit.new_power(power)
+            }
+            crate::db::InstanceKind::Wire => {
+                let mut wire = db.circuit.get_wire(old_id).clone();
+                wire.translate(-center.to_vec2());
+                circuit.new_wire(wire)
+            }
+            crate::db::InstanceKind::Lamp => {
+                let mut lamp = *db.circuit.get_lamp(old_id);
+                lamp.pos -= center.to_vec2();
+                circuit.new_lamp(lamp)
+            }
+            crate::db::InstanceKind::Clock => {
+                let mut clock = *db.circuit.get_clock(old_id);
+                clock.pos -= center.to_vec2();
+                circuit.new_clock(clock)
+            }
+            crate::db::InstanceKind::Module(def_id) => {
+                let mut module = db.circuit.get_module(old_id).clone();
+                module.pos -= center.to_vec2();
+                module.instance_members.clear();
+                let new_id = circuit.new_module_id(module);
+                // External pins belong to the copy
+                let module = circuit.get_module_mut(new_id);
+                module.pins = module
+                    .pins
+                    .iter()
+                    .map(|(&external, &internal)| {
+                        (
+                            Pin {
+                                ins: new_id,
+                                ..external
+                            },
+                            internal,
+                        )
+                    })
+                    .collect();
+                new_id
+            }
+            crate::db::InstanceKind::Splitter(_) => {
+                let mut splitter = *db.circuit.get_splitter(old_id);
+                splitter.pos -= center.to_vec2();
+                circuit.new_splitter(splitter)
+            }
+            crate::db::InstanceKind::FlipFlop(_) => {
+                let mut flip_flop = *db.circuit.get_flip_flop(old_id);
+                flip_flop.pos -= center.to_vec2();
+                circuit.new_flip_flop(flip_flop)
+            }
+            crate::db::InstanceKind::Memory(_) => {
+                let mut memory = db.circuit.get_memory(old_id).clone();
+                memory.pos -= center.to_vec2();
+                circuit.new_memory(memory)
+            }
+            crate::db::InstanceKind::Tunnel => {
+                let mut tunnel = db.circuit.get_tunnel(old_id).clone();
+                tunnel.pos -= center.to_vec2();
+                circuit.new_tunnel(tunnel)
+            }
+        };
+        if let Some(name) = db.circuit.name(old_id) {
+            circuit.set_name(new_id, name);
         }
+        id_map.insert(old_id, new_id);
+    }
 
-        for conn in &self.db.circuit.connections {
-            if let (Some(&new_a_id), Some(&new_b_id)) =
-                (id_map.get(&conn.a.ins), id_map.get(&conn.b.ins))
-            {
-                let new_conn = crate::connection_manager::Connection::new(
-                    Pin::new(new_a_id, conn.a.index, conn.a.kind),
-                    Pin::new(new_b_id, conn.b.index, conn.b.kind),
-                );
-                circuit.connections.insert(new_conn);
-            }
+    for conn in &db.circuit.connections {
+        if let (Some(&new_a_id), Some(&new_b_id)) =
+            (id_map.get(&conn.a.ins), id_map.get(&conn.b.ins))
+        {
+            let new_conn = crate::connection_manager::Connection::new(
+                Pin::new(new_a_id, conn.a.index, conn.a.kind),
+                Pin::new(new_b_id, conn.b.index, conn.b.kind),
+            );
+            circuit.connections.insert(new_conn);
         }
+    }
 
-        let definition = ModuleDefinition { name, circuit };
+    Ok(ModuleDefinition { name, circuit })
+}
 
-        self.db.module_definitions.insert(definition);
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use egui::pos2;
+
+    use super::definition_from_selection;
+    use crate::assets::PinKind;
+    use crate::connection_manager::Connection;
+    use crate::db::{DB, Gate, GateKind, InstanceKind};
+    use crate::simulator::{gate_inp1, gate_output};
+    use crate::truth_table::TruthTable;
+
+    #[test]
+    fn copies_a_module_that_contains_a_nested_module() -> Result<(), String> {
+        let mut db = DB::default();
+        db.circuit
+            .new_gate(Gate::new(pos2(0.0, 0.0), GateKind::Not));
+        let all: HashSet<_> = db.circuit.types.keys().collect();
+        let inverter = definition_from_selection(&db, "inverter".to_owned(), &all)?;
+        let inverter = db.module_definitions.insert(inverter);
+
+        // The placed inverter feeding a second Not gate
+        let mut world = DB {
+            module_definitions: db.module_definitions.clone(),
+            ..DB::default()
+        };
+        // Gates ahead of the module so that its id differs from the one of its copy
+        let not = world
+            .circuit
+            .new_gate(Gate::new(pos2(100.0, 0.0), GateKind::Not));
+        world
+            .circuit
+            .new_gate(Gate::new(pos2(0.0, 100.0), GateKind::Not));
+        let nested = world.new_module(inverter, pos2(0.0, 0.0));
+        let output = world
+            .circuit
+            .pins_of(nested, &world)
+            .into_iter()
+            .find(|pin| pin.kind == PinKind::Output)
+            .ok_or("the inverter has an output pin")?;
+        world
+            .circuit
+            .connections
+            .insert(Connection::new(output, gate_inp1(not)));
+        let selection = HashSet::from([nested, not]);
+        let buffer = definition_from_selection(&world, "buffer".to_owned(), &selection)?;
+
+        // The copy owns its pins and leaves its members to be flattened again
+        let (copy, module) = buffer
+            .circuit
+            .modules
+            .iter()
+            .next()
+            .ok_or("the nested module is copied")?;
+        assert!(module.instance_members.is_empty());
+        assert!(module.pins.keys().all(|pin| pin.ins == copy));
+        let copied_not = buffer
+            .circuit
+            .types
+            .iter()
+            .find_map(|(id, kind)| matches!(kind, InstanceKind::Gate(_)).then_some(id))
+            .ok_or("the gate is copied")?;
+        assert_eq!(buffer.circuit.connected_pins(gate_output(copied_not)), []);
+        assert_eq!(
+            buffer.circuit.connected_pins(gate_inp1(copied_not)).len(),
+            1
+        );
 
+        // Two inversions make a buffer
+        let buffer = db.module_definitions.insert(buffer);
+        let table = TruthTable::for_definition(&db, buffer)?;
+        assert_eq!((table.inputs.len(), table.outputs.len()), (1, 1));
+        for row in &table.rows {
+            assert_eq!(row.outputs[0].to_u64(), Some(row.inputs[0]));
+        }
         Ok(())
     }
 }
@@ -0,0 +1,305 @@
+//! Nets of a circuit.
+//!
+//! A net is a set of pins that are electrically the same point. Pins joined by a connection are on
+//! the same net, and so are both ends of a wire, the two sides of a module pin and every tunnel of
+//! a name, wherever it sits: tunnel names are global, so a `clk` tunnel inside any module instance
+//! is on the net of the top level `clk`. Wires, tunnels and modules only conduct, so the value of
+//! a net comes from the output pins of the other instances on it, its drivers, and is read by
+//! their input pins.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::assets::PinKind;
+use crate::db::{Circuit, DB, InstanceId, InstanceKind, Pin};
+
+pub type NetId = usize;
+
+#[derive(Debug, Clone, Default)]
+pub struct Net {
+    pub pins: Vec<Pin>,
+    /// Output pins that put a value on the net
+    pub drivers: Vec<Pin>,
+    /// Input pins that read the value of the net
+    pub readers: Vec<Pin>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Netlist {
+    pub nets: Vec<Net>,
+    net_of: HashMap<Pin, NetId>,
+}
+
+/// Instances that join the pins around them instead of driving or reading them
+pub fn is_conductor(kind: InstanceKind) -> bool {
+    matches!(
+        kind,
+        InstanceKind::Wire | InstanceKind::Tunnel | InstanceKind::Module(_)
+    )
+}
+
+impl Netlist {
+    pub fn build(db: &DB, circuit: &Circuit) -> Self {
+        let instances: Vec<InstanceId> = circuit.types.keys().collect();
+        Self::join(db, circuit, &instances, is_conductor)
+    }
+
+    /// Nets between `instances` alone, where module instances stand for their definition: their
+    /// pins drive and read the nets like those of a gate instead of reaching the members inside.
+    pub fn of_instances(db: &DB, circuit: &Circuit, instances: &[InstanceId]) -> Self {
+        Self::join(db, circuit, instances, |kind| {
+            matches!(kind, InstanceKind::Wire | InstanceKind::Tunnel)
+        })
+    }
+
+    fn join(
+        db: &DB,
+        circuit: &Circuit,
+        instances: &[InstanceId],
+        conducts: impl Fn(InstanceKind) -> bool,
+    ) -> Self {
+        let included: HashSet<InstanceId> = instances.iter().copied().collect();
+        let mut pins: Vec<Pin> = Vec::new();
+        let mut index: HashMap<Pin, usize> = HashMap::new();
+        let mut add = |pin: Pin, pins: &mut Vec<Pin>| {
+            *index.entry(pin).or_insert_with(|| {
+                pins.push(pin);
+                pins.len() - 1
+            })
+        };
+
+        let mut joins: Vec<(usize, usize)> = Vec::new();
+        let mut tunnels: HashMap<&str, usize> = HashMap::new();
+        for &id in instances {
+            let kind = circuit.ty(id);
+            let own: Vec<usize> = circuit
+                .pins_of(id, db)
+                .into_iter()
+                .map(|pin| add(pin, &mut pins))
+                .collect();
+            match kind {
+                InstanceKind::Wire => joins.push((own[0], own[1])),
+                InstanceKind::Tunnel => {
+                    let first = *tunnels
+                        .entry(circuit.get_tunnel(id).name.as_str())
+                        .or_insert(own[0]);
+                    joins.push((first, own[0]));
+                }
+                _ => {}
+            }
+        }
+        for connection in &circuit.connections {
+            if included.contains(&connection.a.ins) && included.contains(&connection.b.ins) {
+                joins.push((add(connection.a, &mut pins), add(connection.b, &mut pins)));
+            }
+        }
+
+        let mut sets = DisjointSets::new(pins.len());
+        for (a, b) in joins {
+            sets.union(a, b);
+        }
+
+        let mut netlist = Self::default();
+        let mut net_of_root: HashMap<usize, NetId> = HashMap::new();
+        for (i, &pin) in pins.iter().enumerate() {
+            let net_id = *net_of_root.entry(sets.find(i)).or_insert_with(|| {
+                netlist.nets.push(Net::default());
+                netlist.nets.len() - 1
+            });
+            let net = &mut netlist.nets[net_id];
+            net.pins.push(pin);
+            if !conducts(circuit.ty(pin.ins)) {
+                match pin.kind {
+                    PinKind::Output => net.drivers.push(pin),
+                    PinKind::Input => net.readers.push(pin),
+                }
+            }
+            netlist.net_of.insert(pin, net_id);
+        }
+        netlist
+    }
+
+    pub fn net_of(&self, pin: Pin) -> Option<NetId> {
+        self.net_of.get(&pin).copied()
+    }
+
+    pub fn net(&self, pin: Pin) -> Option<&Net> {
+        self.net_of(pin).map(|id| &self.nets[id])
+    }
+
+    /// Output pins driving the net of `pin`
+    pub fn drivers(&self, pin: Pin) -> &[Pin] {
+        self.net(pin).map_or(&[], |net| &net.drivers)
+    }
+
+    /// Pins giving the net of `pin` its value: its drivers and the pins on it `is_source` picks,
+    /// like the inputs of a circuit being analysed
+    pub fn sources(&self, pin: Pin, is_source: impl Fn(Pin) -> bool) -> Vec<Pin> {
+        self.net(pin).map_or_else(Vec::new, |net| {
+            net.pins
+                .iter()
+                .copied()
+                .filter(|&p| is_source(p) || net.drivers.contains(&p))
+                .collect()
+        })
+    }
+
+    /// Instances reading each driver, they are evaluated again when it changes
+    pub fn fanout(&self) -> HashMap<Pin, Vec<InstanceId>> {
+        let mut fanout: HashMap<Pin, Vec<InstanceId>> = HashMap::new();
+        for net in &self.nets {
+            let mut readers: Vec<InstanceId> = net.readers.iter().map(|pin| pin.ins).collect();
+            readers.sort_unstable();
+            readers.dedup();
+            for &driver in &net.drivers {
+                fanout.insert(driver, readers.clone());
+            }
+        }
+        fanout
+    }
+}
+
+/// Union-find over pin indices, with path halving
+struct DisjointSets {
+    parent: Vec<usize>,
+}
+
+impl DisjointSets {
+    fn new(len: usize) -> Self {
+        Self {
+            parent: (0..len).collect(),
+        }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parent[a] = b;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::Pos2;
+
+    use super::Netlist;
+    use crate::assets::PinKind;
+    use crate::connection_manager::Connection;
+    use crate::db::{Circuit, DB, Gate, GateKind, InstanceKind, Lamp, Power, Tunnel, Wire};
+    use crate::module::ModuleDefinition;
+    use crate::simulator::{gate_inp1, lamp_input, power_output, tunnel_pin, wire_end, wire_start};
+
+    #[test]
+    fn wires_and_tunnels_form_one_net() {
+        let mut db = DB::default();
+        let power = db.circuit.new_power(Power::new(Pos2::ZERO, true));
+        let wires: Vec<_> = (0..3)
+            .map(|_| db.circuit.new_wire(Wire::new(Pos2::ZERO, Pos2::ZERO)))
+            .collect();
+        let here = db.circuit.new_tunnel(Tunnel::new(Pos2::ZERO, "bus"));
+        let there = db.circuit.new_tunnel(Tunnel::new(Pos2::ZERO, "bus"));
+        let lamp = db.circuit.new_lamp(Lamp::new(Pos2::ZERO));
+        let other = db.circuit.new_lamp(Lamp::new(Pos2::ZERO));
+        for (from, to) in [
+            (power_output(power), wire_start(wires[0])),
+            (wire_end(wires[0]), wire_start(wires[1])),
+            (wire_end(wires[1]), tunnel_pin(here)),
+            (tunnel_pin(there), wire_start(wires[2])),
+            (wire_end(wires[2]), lamp_input(lamp)),
+        ] {
+            db.circuit.connections.insert(Connection::new(from, to));
+        }
+
+        let netlist = Netlist::build(&db, &db.circuit);
+        let net = netlist.net(lamp_input(lamp)).expect("lamp is on a net");
+        assert_eq!(net.drivers, [power_output(power)]);
+        assert_eq!(net.readers, [lamp_input(lamp)]);
+        assert_eq!(net.pins.len(), 10);
+        assert_eq!(
+            netlist.net_of(wire_start(wires[0])),
+            netlist.net_of(wire_end(wires[2]))
+        );
+        assert!(netlist.drivers(lamp_input(other)).is_empty());
+        assert_eq!(netlist.fanout()[&power_output(power)], [lamp]);
+    }
+
+    #[test]
+    fn tunnels_reach_into_every_module_instance() {
+        // Tunnel "clk" -> not gate, the gate output is the only module pin
+        let mut circuit = Circuit::default();
+        let sink = circuit.new_tunnel(Tunnel::new(Pos2::ZERO, "clk"));
+        let not = circuit.new_gate(Gate::new(Pos2::ZERO, GateKind::Not));
+        circuit
+            .connections
+            .insert(Connection::new(tunnel_pin(sink), gate_inp1(not)));
+
+        let mut db = DB::default();
+        let definition = db.module_definitions.insert(ModuleDefinition {
+            name: "inv".to_owned(),
+            circuit,
+        });
+        let modules = [
+            db.new_module(definition, Pos2::ZERO),
+            db.new_module(definition, Pos2::ZERO),
+        ];
+        let power = db.circuit.new_power(Power::new(Pos2::ZERO, true));
+        let top = db.circuit.new_tunnel(Tunnel::new(Pos2::ZERO, "clk"));
+        db.circuit
+            .connections
+            .insert(Connection::new(power_output(power), tunnel_pin(top)));
+
+        let netlist = Netlist::build(&db, &db.circuit);
+        assert_eq!(db.circuit.same_tunnels(top).len(), 3);
+        for module in modules {
+            let not = *db
+                .circuit
+                .get_module(module)
+                .instance_members
+                .iter()
+                .find(|&&id| matches!(db.circuit.ty(id), InstanceKind::Gate(GateKind::Not)))
+                .expect("member is copied");
+            assert_eq!(netlist.drivers(gate_inp1(not)), [power_output(power)]);
+        }
+    }
+
+    #[test]
+    fn module_pins_drive_the_nets_around_them() {
+        let mut definition = Circuit::default();
+        definition.new_gate(Gate::new(Pos2::ZERO, GateKind::Not));
+        let mut db = DB::default();
+        let definition = db.module_definitions.insert(ModuleDefinition {
+            name: "inv".to_owned(),
+            circuit: definition,
+        });
+        let module = db.new_module(definition, Pos2::ZERO);
+        let lamp = db.circuit.new_lamp(Lamp::new(Pos2::ZERO));
+        let output = db
+            .circuit
+            .pins_of(module, &db)
+            .into_iter()
+            .find(|pin| pin.kind == PinKind::Output)
+            .expect("the module has an output");
+        db.circuit
+            .connections
+            .insert(Connection::new(output, lamp_input(lamp)));
+
+        // Through the module to the gate inside, or stopping at the module pin
+        let inner = db.circuit.get_module(module).pins[&output];
+        assert_eq!(
+            Netlist::build(&db, &db.circuit).drivers(lamp_input(lamp)),
+            [inner]
+        );
+        assert_eq!(db.circuit.ty(inner.ins), InstanceKind::Gate(GateKind::Not));
+        let netlist = Netlist::of_instances(&db, &db.circuit, &[module, lamp]);
+        assert_eq!(netlist.drivers(lamp_input(lamp)), [output]);
+        assert!(netlist.net_of(inner).is_none());
+    }
+}
@@ -0,0 +1,209 @@
+//! Orthogonal routing of wires around components.
+//!
+//! Paths are searched on a lattice of [`GRID_SIZE`] steps through both ends of the wire. Every
+//! step costs its length, every turn costs [`BEND_COST`] and steps through a component cost
+//! [`BLOCKED_COST`] times their length, so wires go around components when there is a way and
+//! through them when there is not.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use egui::{Pos2, Rect, pos2};
+
+use crate::app::{App, GRID_SIZE, PIN_MOVE_HINT_D};
+use crate::db::{InstanceId, InstanceKind};
+
+/// Lattice lines beyond the ends on every side
+const MARGIN_STEPS: f32 = 4.0;
+/// Larger searches fall back to a single corner
+const MAX_NODES: usize = 20_000;
+const BEND_COST: u32 = 2 * GRID_SIZE as u32;
+const BLOCKED_COST: u32 = 10;
+
+/// Bends of a path from `from` to `to` made of horizontal and vertical segments
+pub fn route(from: Pos2, to: Pos2, obstacles: &[Rect]) -> Vec<Pos2> {
+    let xs = axis(from.x, to.x);
+    let ys = axis(from.y, to.y);
+    if xs.len() * ys.len() > MAX_NODES {
+        return corner(from, to);
+    }
+    let area = Rect::from_min_max(pos2(xs[0], ys[0]), pos2(xs[xs.len() - 1], ys[ys.len() - 1]));
+    let obstacles: Vec<Rect> = obstacles
+        .iter()
+        .map(|rect| rect.shrink(1.0))
+        .filter(|rect| rect.intersects(area))
+        .collect();
+    let index = |x: usize, y: usize, dir: usize| (y * xs.len() + x) * 4 + dir;
+    let find = |axis: &[f32], v: f32| axis.iter().position(|&a| a == v).unwrap_or(0);
+    let (start_x, start_y) = (find(&xs, from.x), find(&ys, from.y));
+    let (end_x, end_y) = (find(&xs, to.x), find(&ys, to.y));
+
+    // States are a node and the direction it was entered from, left, right, up or down
+    let mut cost = vec![u32::MAX; xs.len() * ys.len() * 4];
+    let mut previous = vec![usize::MAX; cost.len()];
+    let mut queue = BinaryHeap::new();
+    for dir in 0..4 {
+        cost[index(start_x, start_y, dir)] = 0;
+        queue.push(Reverse((0, start_x, start_y, dir)));
+    }
+    let mut reached = None;
+    while let Some(Reverse((c, x, y, dir))) = queue.pop() {
+        if c > cost[index(x, y, dir)] {
+            continue;
+        }
+        if (x, y) == (end_x, end_y) {
+            reached = Some(index(x, y, dir));
+            break;
+        }
+        let steps = [
+            (x.checked_sub(1), Some(y)),
+            ((x + 1 < xs.len()).then_some(x + 1), Some(y)),
+            (Some(x), y.checked_sub(1)),
+            (Some(x), (y + 1 < ys.len()).then_some(y + 1)),
+        ];
+        for (next_dir, step) in steps.into_iter().enumerate() {
+            let (Some(nx), Some(ny)) = step else {
+                continue;
+            };
+            let (a, b) = (pos2(xs[x], ys[y]), pos2(xs[nx], ys[ny]));
+            let length = a.distance(b).round() as u32;
+            let middle = a.lerp(b, 0.5);
+            let mut step_cost = if obstacles.iter().any(|rect| rect.contains(middle)) {
+                length * BLOCKED_COST
+            } else {
+                length
+            };
+            if (x, y) != (start_x, start_y) && next_dir != dir {
+                step_cost += BEND_COST;
+            }
+            let next = index(nx, ny, next_dir);
+            if c + step_cost < cost[next] {
+                cost[next] = c + step_cost;
+                previous[next] = index(x, y, dir);
+                queue.push(Reverse((c + step_cost, nx, ny, next_dir)));
+            }
+        }
+    }
+    let Some(mut state) = reached else {
+        return corner(from, to);
+    };
+
+    let mut points = Vec::new();
+    while state != usize::MAX {
+        let node = state / 4;
+        points.push(pos2(xs[node % xs.len()], ys[node / xs.len()]));
+        state = previous[state];
+    }
+    points.reverse();
+    points.dedup();
+    straighten(&points)
+}
+
+/// Positions `a` and `b` and steps of [`GRID_SIZE`] from `a` around them
+fn axis(a: f32, b: f32) -> Vec<f32> {
+    let low = a.min(b) - MARGIN_STEPS * GRID_SIZE;
+    let high = a.max(b) + MARGIN_STEPS * GRID_SIZE;
+    let first = ((low - a) / GRID_SIZE).ceil() as i32;
+    let last = ((high - a) / GRID_SIZE).floor() as i32;
+    let mut values: Vec<f32> = (first..=last)
+        .map(|k| a + k as f32 * GRID_SIZE)
+        .chain([b])
+        .collect();
+    values.sort_by(f32::total_cmp);
+    values.dedup();
+    values
+}
+
+fn corner(from: Pos2, to: Pos2) -> Vec<Pos2> {
+    if from.x == to.x || from.y == to.y {
+        Vec::new()
+    } else {
+        vec![pos2(to.x, from.y)]
+    }
+}
+
+/// Points of a path where it turns
+fn straighten(points: &[Pos2]) -> Vec<Pos2> {
+    points
+        .windows(3)
+        .filter(|w| !(w[0].x == w[2].x || w[0].y == w[2].y))
+        .map(|w| w[1])
+        .collect()
+}
+
+impl App {
+    /// Areas of the visible components wires are routed around
+    pub fn route_obstacles(&self) -> Vec<Rect> {
+        self.db
+            .circuit
+            .types
+            .iter()
+            .filter(|&(id, kind)| !matches!(kind, InstanceKind::Wire) && !self.db.is_hidden(id))
+            .map(|(id, _)| self.instance_bounds(id))
+            .collect()
+    }
+
+    /// Draw the wire from its fixed end to `mouse` around the components of [`App::obstacles`]
+    pub fn route_wire(&mut self, id: InstanceId, start: bool, mouse: Pos2) {
+        let obstacles = &self.obstacles;
+        let wire = self.db.circuit.get_wire_mut(id);
+        if start {
+            let mut bends = route(wire.end, mouse, obstacles);
+            bends.reverse();
+            wire.start = mouse;
+            wire.bends = bends;
+        } else {
+            wire.bends = route(wire.start, mouse, obstacles);
+            wire.end = mouse;
+        }
+    }
+
+    /// Remove the bend of a wire under `mouse`, or add one there if there is none
+    pub fn toggle_bend(&mut self, id: InstanceId, mouse: Pos2) {
+        let wire = self.db.circuit.get_wire(id);
+        let near = wire
+            .bends
+            .iter()
+            .position(|bend| bend.distance(mouse) < PIN_MOVE_HINT_D / self.zoom);
+        let (segment, point) = wire.closest_point(mouse);
+        if let Some(index) = near {
+            self.checkpoint("Remove bend");
+            self.db.circuit.get_wire_mut(id).bends.remove(index);
+        } else {
+            self.checkpoint("Add bend");
+            self.db
+                .circuit
+                .get_wire_mut(id)
+                .bends
+                .insert(segment, point);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::{Rect, pos2, vec2};
+
+    use super::route;
+
+    #[test]
+    fn routes_around_a_component() {
+        let from = pos2(0.0, 0.0);
+        let to = pos2(203.0, 7.0);
+        let gate = Rect::from_center_size(pos2(100.0, 0.0), vec2(85.0, 75.0));
+        let bends = route(from, to, &[gate]);
+
+        let mut points = vec![from];
+        points.extend(&bends);
+        points.push(to);
+        assert!(bends.len() >= 2);
+        for segment in points.windows(2) {
+            let (a, b) = (segment[0], segment[1]);
+            assert!(a.x == b.x || a.y == b.y, "{a:?} to {b:?} is not straight");
+            for i in 0..=20 {
+                let p = a.lerp(b, i as f32 / 20.0);
+                assert!(!gate.shrink(1.0).contains(p), "{p:?} is inside the gate");
+            }
+        }
+    }
+}
@@ -0,0 +1,400 @@
+//! A small CDCL SAT solver, used to prove circuits equivalent.
+//!
+//! Clauses are watched by two literals, conflicts are analyzed to the first unique implication
+//! point and the learnt clause is kept. Decisions follow variable activity with saved phases and
+//! the search restarts after a growing number of conflicts. Gates are added with the Tseitin
+//! encoding through [`Solver::and`], [`Solver::or`] and [`Solver::xor`].
+
+use std::collections::BinaryHeap;
+use std::ops::Not;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Lit(u32);
+
+impl Lit {
+    fn new(var: usize, negated: bool) -> Self {
+        Self((var as u32) << 1 | u32::from(negated))
+    }
+
+    pub fn var(self) -> usize {
+        (self.0 >> 1) as usize
+    }
+
+    pub fn is_negated(self) -> bool {
+        self.0 & 1 == 1
+    }
+
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl Not for Lit {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Self(self.0 ^ 1)
+    }
+}
+
+pub enum Outcome {
+    /// Value of every variable
+    Satisfiable(Vec<bool>),
+    Unsatisfiable,
+    /// The conflict budget ran out
+    Unknown,
+}
+
+#[derive(Default)]
+pub struct Solver {
+    clauses: Vec<Vec<Lit>>,
+    /// Clauses watching each literal, by literal index
+    watches: Vec<Vec<usize>>,
+    assigns: Vec<Option<bool>>,
+    level: Vec<usize>,
+    reason: Vec<Option<usize>>,
+    trail: Vec<Lit>,
+    /// Start of each decision level in the trail
+    trail_lim: Vec<usize>,
+    propagated: usize,
+    activity: Vec<f64>,
+    increment: f64,
+    /// Decision candidates, possibly stale, by activity
+    order: BinaryHeap<(u64, usize)>,
+    phase: Vec<bool>,
+    /// Always true, set when first asked for
+    true_lit: Option<Lit>,
+    /// False once a clause can never be satisfied
+    unsatisfiable: bool,
+}
+
+impl Solver {
+    pub fn new_var(&mut self) -> Lit {
+        let var = self.assigns.len();
+        self.assigns.push(None);
+        self.level.push(0);
+        self.reason.push(None);
+        self.activity.push(0.0);
+        self.phase.push(false);
+        self.watches.push(Vec::new());
+        self.watches.push(Vec::new());
+        self.order.push((0, var));
+        Lit::new(var, false)
+    }
+
+    pub fn constant(&mut self, value: bool) -> Lit {
+        let lit = if let Some(lit) = self.true_lit {
+            lit
+        } else {
+            let lit = self.new_var();
+            self.add_clause(&[lit]);
+            self.true_lit = Some(lit);
+            lit
+        };
+        if value { lit } else { !lit }
+    }
+
+    pub fn and(&mut self, a: Lit, b: Lit) -> Lit {
+        let out = self.new_var();
+        self.add_clause(&[!out, a]);
+        self.add_clause(&[!out, b]);
+        self.add_clause(&[out, !a, !b]);
+        out
+    }
+
+    pub fn or(&mut self, a: Lit, b: Lit) -> Lit {
+        !self.and(!a, !b)
+    }
+
+    pub fn xor(&mut self, a: Lit, b: Lit) -> Lit {
+        let out = self.new_var();
+        self.add_clause(&[!out, a, b]);
+        self.add_clause(&[!out, !a, !b]);
+        self.add_clause(&[out, !a, b]);
+        self.add_clause(&[out, a, !b]);
+        out
+    }
+
+    fn value(&self, lit: Lit) -> Option<bool> {
+        self.assigns[lit.var()].map(|value| value != lit.is_negated())
+    }
+
+    /// Add a clause before solving
+    pub fn add_clause(&mut self, lits: &[Lit]) {
+        let mut clause: Vec<Lit> = Vec::with_capacity(lits.len());
+        for &lit in lits {
+            match self.value(lit) {
+                Some(true) => return,
+                None if clause.contains(&!lit) => return,
+                None if !clause.contains(&lit) => clause.push(lit),
+                Some(false) | None => {}
+            }
+        }
+        match clause[..] {
+            [] => self.unsatisfiable = true,
+            [lit] => {
+                self.assign(lit, None);
+                if self.propagate().is_some() {
+                    self.unsatisfiable = true;
+                }
+            }
+            _ => {
+                self.attach(clause);
+            }
+        }
+    }
+
+    fn attach(&mut self, clause: Vec<Lit>) -> usize {
+        let index = self.clauses.len();
+        self.watches[clause[0].index()].push(index);
+        self.watches[clause[1].index()].push(index);
+        self.clauses.push(clause);
+        index
+    }
+
+    fn assign(&mut self, lit: Lit, reason: Option<usize>) {
+        let var = lit.var();
+        self.assigns[var] = Some(!lit.is_negated());
+        self.level[var] = self.trail_lim.len();
+        self.reason[var] = reason;
+        self.trail.push(lit);
+    }
+
+    /// Assign what the clauses imply, returns a conflicting clause
+    fn propagate(&mut self) -> Option<usize> {
+        while self.propagated < self.trail.len() {
+            let false_lit = !self.trail[self.propagated];
+            self.propagated += 1;
+            let watching = std::mem::take(&mut self.watches[false_lit.index()]);
+            let mut kept = Vec::with_capacity(watching.len());
+            let mut conflict = None;
+            for (i, &c) in watching.iter().enumerate() {
+                if conflict.is_some() {
+                    kept.extend_from_slice(&watching[i..]);
+                    break;
+                }
+                let clause = &mut self.clauses[c];
+                if clause[0] == false_lit {
+                    clause.swap(0, 1);
+                }
+                let first = clause[0];
+                if self.assigns[first.var()].is_some_and(|v| v != first.is_negated()) {
+                    kept.push(c);
+                    continue;
+                }
+                let replacement = (2..clause.len()).find(|&k| {
+                    let lit = clause[k];
+                    self.assigns[lit.var()].is_none_or(|v| v != lit.is_negated())
+                });
+                if let Some(k) = replacement {
+                    clause.swap(1, k);
+                    let watch = clause[1].index();
+                    self.watches[watch].push(c);
+                    continue;
+                }
+                kept.push(c);
+                if self.value(first) == Some(false) {
+                    conflict = Some(c);
+                } else {
+                    self.assign(first, Some(c));
+                }
+            }
+            self.watches[false_lit.index()] = kept;
+            if conflict.is_some() {
+                return conflict;
+            }
+        }
+        None
+    }
+
+    /// Learnt clause with the asserting literal first, and the level to go back to
+    fn analyze(&mut self, mut conflict: usize) -> (Vec<Lit>, usize) {
+        let mut seen = vec![false; self.assigns.len()];
+        let mut learnt = vec![Lit(0)];
+        let mut pending = 0;
+        let mut index = self.trail.len();
+        let current = self.trail_lim.len();
+        let mut implied: Option<Lit> = None;
+        loop {
+            let skip = usize::from(implied.is_some());
+            for k in skip..self.clauses[conflict].len() {
+                let lit = self.clauses[conflict][k];
+                let var = lit.var();
+                if seen[var] || self.level[var] == 0 {
+                    continue;
+                }
+                seen[var] = true;
+                self.bump(var);
+                if self.level[var] == current {
+                    pending += 1;
+                } else {
+                    learnt.push(lit);
+                }
+            }
+            loop {
+                index -= 1;
+                if seen[self.trail[index].var()] {
+                    break;
+                }
+            }
+            let lit = self.trail[index];
+            seen[lit.var()] = false;
+            pending -= 1;
+            implied = Some(lit);
+            if pending == 0 {
+                learnt[0] = !lit;
+                break;
+            }
+            conflict = self.reason[lit.var()].expect("implied literals have a reason");
+        }
+
+        let mut back_to = 0;
+        if learnt.len() > 1 {
+            let (i, level) = (1..learnt.len())
+                .map(|i| (i, self.level[learnt[i].var()]))
+                .max_by_key(|&(_, level)| level)
+                .unwrap_or((1, 0));
+            learnt.swap(1, i);
+            back_to = level;
+        }
+        self.increment *= 1.05;
+        (learnt, back_to)
+    }
+
+    fn bump(&mut self, var: usize) {
+        self.activity[var] += self.increment;
+        if self.activity[var] > 1e100 {
+            for activity in &mut self.activity {
+                *activity *= 1e-100;
+            }
+            self.increment *= 1e-100;
+            self.order = (0..self.assigns.len())
+                .map(|v| (self.activity[v].to_bits(), v))
+                .collect();
+        } else {
+            self.order.push((self.activity[var].to_bits(), var));
+        }
+    }
+
+    fn backtrack(&mut self, level: usize) {
+        if self.trail_lim.len() <= level {
+            return;
+        }
+        let start = self.trail_lim[level];
+        for lit in self.trail.drain(start..) {
+            let var = lit.var();
+            self.phase[var] = !lit.is_negated();
+            self.assigns[var] = None;
+            self.reason[var] = None;
+            self.order.push((self.activity[var].to_bits(), var));
+        }
+        self.trail_lim.truncate(level);
+        self.propagated = self.trail.len();
+    }
+
+    fn decide(&mut self) -> Option<Lit> {
+        while let Some((activity, var)) = self.order.pop() {
+            if self.assigns[var].is_none() && activity == self.activity[var].to_bits() {
+                return Some(Lit::new(var, !self.phase[var]));
+            }
+        }
+        // Stale entries may hide unassigned variables
+        (0..self.assigns.len())
+            .find(|&var| self.assigns[var].is_none())
+            .map(|var| Lit::new(var, !self.phase[var]))
+    }
+
+    /// Search for an assignment satisfying every clause, giving up after `max_conflicts`
+    pub fn solve(&mut self, max_conflicts: usize) -> Outcome {
+        if self.increment == 0.0 {
+            self.increment = 1.0;
+        }
+        if self.unsatisfiable || self.propagate().is_some() {
+            self.unsatisfiable = true;
+            return Outcome::Unsatisfiable;
+        }
+        let mut conflicts = 0;
+        let mut restart_at = 100.0_f64;
+        let mut since_restart = 0_u32;
+        loop {
+            if let Some(conflict) = self.propagate() {
+                if self.trail_lim.is_empty() {
+                    self.unsatisfiable = true;
+                    return Outcome::Unsatisfiable;
+                }
+                conflicts += 1;
+                since_restart += 1;
+                if conflicts > max_conflicts {
+                    self.backtrack(0);
+                    return Outcome::Unknown;
+                }
+                let (learnt, level) = self.analyze(conflict);
+                self.backtrack(level);
+                if let [lit] = learnt[..] {
+                    self.assign(lit, None);
+                } else {
+                    let asserting = learnt[0];
+                    let c = self.attach(learnt);
+                    self.assign(asserting, Some(c));
+                }
+                continue;
+            }
+            if f64::from(since_restart) > restart_at {
+                since_restart = 0;
+                restart_at *= 1.5;
+                self.backtrack(0);
+                continue;
+            }
+            let Some(decision) = self.decide() else {
+                let model = self.assigns.iter().map(|v| v == &Some(true)).collect();
+                self.backtrack(0);
+                return Outcome::Satisfiable(model);
+            };
+            self.trail_lim.push(self.trail.len());
+            self.assign(decision, None);
+        }
+    }
+}
+
+/// Value of `lit` in a satisfying assignment
+pub fn model_value(model: &[bool], lit: Lit) -> bool {
+    model[lit.var()] != lit.is_negated()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Outcome, Solver, model_value};
+
+    #[test]
+    fn pigeons_do_not_fit() {
+        // Four pigeons in three holes
+        let mut solver = Solver::default();
+        let holes: Vec<Vec<_>> = (0..4)
+            .map(|_| (0..3).map(|_| solver.new_var()).collect())
+            .collect();
+        for pigeon in &holes {
+            solver.add_clause(pigeon);
+        }
+        for hole in 0..3 {
+            for a in 0..4 {
+                for b in a + 1..4 {
+                    solver.add_clause(&[!holes[a][hole], !holes[b][hole]]);
+                }
+            }
+        }
+        assert!(matches!(solver.solve(10_000), Outcome::Unsatisfiable));
+
+        let mut solver = Solver::default();
+        let a = solver.new_var();
+        let b = solver.new_var();
+        let x = solver.xor(a, b);
+        let y = solver.and(a, !b);
+        solver.add_clause(&[x]);
+        solver.add_clause(&[!y]);
+        let Outcome::Satisfiable(model) = solver.solve(100) else {
+            panic!("a ^ b without a & !b holds for a = 0, b = 1");
+        };
+        assert!(!model_value(&model, a));
+        assert!(model_value(&model, b));
+    }
+}
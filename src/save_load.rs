@@ -1,4 +1,13 @@
+use egui::Vec2;
+
 use crate::App;
+use crate::connection_manager::ConnectionManager;
+use crate::db::{DB, InstanceId};
+use crate::hex;
+use crate::simulator::Simulator;
+use crate::vcd;
+use crate::verilog;
+use crate::verilog_import;
 
 impl App {
     #[cfg(not(target_arch = "wasm32"))]
@@ -21,45 +30,92 @@ impl App {
 
     #[cfg(target_arch = "wasm32")]
     pub fn save_to_file(&self) -> Result<(), Box<dyn std::error::Error>> {
-        use wasm_bindgen::JsCast;
-        use web_sys::{Blob, BlobPropertyBag, Url, window};
-
         let json = serde_json::to_string_pretty(self)?;
+        download_text("circuit.json", "application/json", &json);
+        Ok(())
+    }
 
-        let Some(window) = window() else {
-            return Ok(());
-        };
-        let Ok(document) = window.document().ok_or("No document") else {
-            return Ok(());
-        };
+    /// Write the probed waveforms as a VCD file
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn export_vcd(&self) -> Result<(), Box<dyn std::error::Error>> {
+        save_text("VCD files", "vcd", "waveform.vcd", &self.vcd())
+    }
+
+    /// Write the probed waveforms as a VCD file
+    #[cfg(target_arch = "wasm32")]
+    pub fn export_vcd(&self) -> Result<(), Box<dyn std::error::Error>> {
+        download_text("waveform.vcd", "text/plain", &self.vcd());
+        Ok(())
+    }
 
-        let blob_parts = js_sys::Array::new();
-        blob_parts.push(&wasm_bindgen::JsValue::from_str(&json));
+    /// Write the circuit and its module definitions as structural Verilog
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn export_verilog(&self) -> Result<(), Box<dyn std::error::Error>> {
+        save_text(
+            "Verilog files",
+            "v",
+            "circuit.v",
+            &verilog::export(&self.db),
+        )
+    }
 
-        let blob_property_bag = BlobPropertyBag::new();
-        blob_property_bag.set_type("application/json");
+    /// Write the circuit and its module definitions as structural Verilog
+    #[cfg(target_arch = "wasm32")]
+    pub fn export_verilog(&self) -> Result<(), Box<dyn std::error::Error>> {
+        download_text("circuit.v", "text/plain", &verilog::export(&self.db));
+        Ok(())
+    }
 
-        let Ok(blob) = Blob::new_with_str_sequence_and_options(&blob_parts, &blob_property_bag)
-        else {
-            return Ok(());
-        };
-        let Ok(url) = Url::create_object_url_with_blob(&blob) else {
-            return Ok(());
-        };
-        let Ok(element) = document.create_element("a") else {
+    /// Write the open truth table as CSV
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn export_truth_table_csv(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(table) = &self.truth_table else {
             return Ok(());
         };
-        let Ok(html_element) = element.dyn_into::<web_sys::HtmlElement>() else {
+        save_text("CSV files", "csv", "truth_table.csv", &table.to_csv())
+    }
+
+    /// Write the open truth table as CSV
+    #[cfg(target_arch = "wasm32")]
+    pub fn export_truth_table_csv(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(table) = &self.truth_table {
+            download_text("truth_table.csv", "text/csv", &table.to_csv());
+        }
+        Ok(())
+    }
+
+    /// Write the open truth table as a Markdown table
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn export_truth_table_markdown(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(table) = &self.truth_table else {
             return Ok(());
         };
+        save_text(
+            "Markdown files",
+            "md",
+            "truth_table.md",
+            &table.to_markdown(),
+        )
+    }
 
-        html_element.set_attribute("href", &url).ok();
-        html_element.set_attribute("download", "circuit.json").ok();
-        html_element.click();
-        Url::revoke_object_url(&url).ok();
+    /// Write the open truth table as a Markdown table
+    #[cfg(target_arch = "wasm32")]
+    pub fn export_truth_table_markdown(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(table) = &self.truth_table {
+            download_text("truth_table.md", "text/markdown", &table.to_markdown());
+        }
         Ok(())
     }
 
+    fn vcd(&self) -> String {
+        vcd::export(
+            &self.db,
+            &self.canvas_config,
+            &self.simulator.recorder,
+            self.simulator.time,
+        )
+    }
+
     #[cfg(not(target_arch = "wasm32"))]
     pub fn load_from_file(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         use std::fs;
@@ -188,4 +244,262 @@ impl App {
             }
         }
     }
+
+    /// Replace the contents of a memory with a parsed Intel HEX or raw hex image
+    pub fn apply_memory_image(&mut self, id: InstanceId, text: &str) -> Result<(), String> {
+        let Some(memory) = self.db.circuit.memories.get_mut(id) else {
+            return Err("memory no longer exists".to_owned());
+        };
+        let words = hex::parse(text, memory.data_width)?;
+        let dropped = memory.load(&words);
+        if dropped > 0 {
+            log::warn!("Memory image has {dropped} words more than the memory holds");
+        }
+        self.memory_edited(id);
+        log::info!("Loaded {} words into memory {id}", words.len());
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_memory_from_file(
+        &mut self,
+        id: InstanceId,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        use std::fs;
+
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Hex files", &["hex", "ihx", "txt"])
+            .pick_file()
+        else {
+            return Ok(());
+        };
+
+        let text = fs::read_to_string(&path)?;
+        self.apply_memory_image(id, &text)?;
+        Ok(())
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn load_memory_from_file(
+        &mut self,
+        id: InstanceId,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.pending_memory_load = Some(id);
+        pick_text_file(".hex,.ihx,.txt,text/plain", "simu_pending_memory");
+        Ok(())
+    }
+
+    pub fn process_pending_memory_load(&mut self) {
+        #[cfg(target_arch = "wasm32")]
+        {
+            let Some(id) = self.pending_memory_load else {
+                return;
+            };
+            let Some(storage) = web_sys::window().and_then(|w| w.local_storage().ok().flatten())
+            else {
+                return;
+            };
+            if let Ok(Some(text)) = storage.get_item("simu_pending_memory") {
+                storage.remove_item("simu_pending_memory").ok();
+                self.pending_memory_load = None;
+                if let Err(e) = self.apply_memory_image(id, &text) {
+                    log::error!("Failed to load memory image: {e}");
+                }
+            }
+        }
+    }
+
+    /// Replace the circuit with one built from a structural Verilog netlist
+    pub fn apply_verilog(&mut self, text: &str) -> Result<(), String> {
+        let db = verilog_import::import(text)?;
+        self.checkpoint("Import Verilog");
+        self.db = db;
+        self.hovered = None;
+        self.selected.clear();
+        self.drag = None;
+        self.connection_manager =
+            ConnectionManager::new(&self.db.circuit, &self.canvas_config, &self.db);
+        self.simulator = Simulator::new();
+        self.viewport_offset = Vec2::ZERO;
+        self.zoom = 1.0;
+        self.current_dirty = true;
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn import_verilog(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Verilog files", &["v", "vg", "sv"])
+            .pick_file()
+        else {
+            return Ok(());
+        };
+
+        let text = std::fs::read_to_string(&path)?;
+        self.apply_verilog(&text)?;
+        log::info!("Imported Verilog from: {}", path.display());
+        Ok(())
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn import_verilog(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        pick_text_file(".v,.vg,.sv,text/plain", "simu_pending_verilog");
+        Ok(())
+    }
+
+    pub fn process_pending_verilog_import(&mut self) {
+        #[cfg(target_arch = "wasm32")]
+        {
+            let Some(storage) = web_sys::window().and_then(|w| w.local_storage().ok().flatten())
+            else {
+                return;
+            };
+            if let Ok(Some(text)) = storage.get_item("simu_pending_verilog") {
+                storage.remove_item("simu_pending_verilog").ok();
+                if let Err(e) = self.apply_verilog(&text) {
+                    log::error!("Failed to import Verilog: {e}");
+                }
+            }
+        }
+    }
+}
+
+/// Let the user pick a text file and store its contents under `storage_key` in local storage,
+/// where a `process_pending_*` method picks it up on a later frame
+#[cfg(target_arch = "wasm32")]
+fn pick_text_file(accept: &str, storage_key: &'static str) {
+    use wasm_bindgen::JsCast;
+    use web_sys::HtmlInputElement;
+
+    let Some(window) = web_sys::window() else {
+        return;
+    };
+    let Ok(document) = window.document().ok_or("No document") else {
+        return;
+    };
+    let Ok(element) = document.create_element("input") else {
+        return;
+    };
+    let Ok(input) = element.dyn_into::<HtmlInputElement>() else {
+        return;
+    };
+
+    input.set_type("file");
+    input.set_accept(accept);
+
+    let closure = wasm_bindgen::closure::Closure::wrap(Box::new(move |event: web_sys::Event| {
+        let Some(target) = event.target() else {
+            return;
+        };
+        let Ok(input) = target.dyn_into::<HtmlInputElement>() else {
+            return;
+        };
+        let Some(file_list) = input.files() else {
+            return;
+        };
+        let Some(file) = file_list.get(0) else {
+            return;
+        };
+
+        let Ok(file_reader) = web_sys::FileReader::new() else {
+            return;
+        };
+        let file_reader_clone = file_reader.clone();
+
+        let onload_closure =
+            wasm_bindgen::closure::Closure::wrap(Box::new(move |_event: web_sys::Event| {
+                let Ok(result) = file_reader_clone.result() else {
+                    return;
+                };
+                let Some(text) = result.as_string() else {
+                    return;
+                };
+
+                if let Some(win) = web_sys::window()
+                    && let Ok(Some(storage)) = win.local_storage()
+                {
+                    storage.set_item(storage_key, &text).ok();
+                }
+            }) as Box<dyn FnMut(_)>);
+
+        file_reader.set_onload(Some(onload_closure.as_ref().unchecked_ref()));
+        onload_closure.forget();
+        file_reader.read_as_text(&file).ok();
+    }) as Box<dyn FnMut(_)>);
+
+    input.set_onchange(Some(closure.as_ref().unchecked_ref()));
+    closure.forget();
+    input.click();
+}
+
+/// Ask for a file name and write `text` to it
+#[cfg(not(target_arch = "wasm32"))]
+fn save_text(
+    filter: &str,
+    extension: &str,
+    file_name: &str,
+    text: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(path) = rfd::FileDialog::new()
+        .add_filter(filter, &[extension])
+        .set_file_name(file_name)
+        .save_file()
+    else {
+        return Ok(());
+    };
+
+    std::fs::write(&path, text)?;
+    log::info!("Exported to: {}", path.display());
+    Ok(())
+}
+
+/// Offer `text` as a file download from the browser
+#[cfg(target_arch = "wasm32")]
+fn download_text(file_name: &str, mime: &str, text: &str) {
+    use wasm_bindgen::JsCast;
+    use web_sys::{Blob, BlobPropertyBag, Url, window};
+
+    let Some(window) = window() else {
+        return;
+    };
+    let Ok(document) = window.document().ok_or("No document") else {
+        return;
+    };
+
+    let blob_parts = js_sys::Array::new();
+    blob_parts.push(&wasm_bindgen::JsValue::from_str(text));
+
+    let blob_property_bag = BlobPropertyBag::new();
+    blob_property_bag.set_type(mime);
+
+    let Ok(blob) = Blob::new_with_str_sequence_and_options(&blob_parts, &blob_property_bag) else {
+        return;
+    };
+    let Ok(url) = Url::create_object_url_with_blob(&blob) else {
+        return;
+    };
+    let Ok(element) = document.create_element("a") else {
+        return;
+    };
+    let Ok(html_element) = element.dyn_into::<web_sys::HtmlElement>() else {
+        return;
+    };
+
+    html_element.set_attribute("href", &url).ok();
+    html_element.set_attribute("download", file_name).ok();
+    html_element.click();
+    Url::revoke_object_url(&url).ok();
+}
+
+/// The part of a saved file that describes the circuit, the rest is editor state
+#[derive(serde::Deserialize)]
+struct SavedCircuit {
+    db: DB,
+}
+
+/// Read the circuit out of a file written by [`App::save_to_file`]
+pub fn db_from_json(json: &str) -> Result<DB, String> {
+    serde_json::from_str::<SavedCircuit>(json)
+        .map(|saved| saved.db)
+        .map_err(|e| format!("invalid circuit file: {e}"))
 }
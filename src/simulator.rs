@@ -1,25 +1,46 @@
-use std::collections::{HashMap, HashSet};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
 
 use log;
 
 use crate::{
     assets::PinKind,
-    db::{Circuit, DB, GateKind, InstanceId, InstanceKind, Pin},
+    db::{
+        Circuit, DB, FlipFlopKind, FlipFlopPin, FlipFlopState, GateKind, InstanceId, InstanceKind,
+        MemoryKind, Pin, SplitterKind,
+    },
+    loops::{self, Loop},
+    net::Netlist,
+    waveform::Recorder,
 };
 
-const MAX_ITERATIONS: usize = 10;
-const STABILIZATION_THRESHOLD: usize = 3;
+/// Default number of events a single `compute` may process before giving up.
+pub const DEFAULT_EVENT_BUDGET: usize = 100_000;
 
-#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+/// Time between a flip flop input change and its outputs following it.
+pub const FLIP_FLOP_DELAY: u64 = 1;
+
+/// Time between a memory address or contents change and its data output following it.
+pub const MEMORY_DELAY: u64 = 1;
+
+/// Widest bus a wire or pin can carry.
+pub const MAX_BUS_WIDTH: u8 = 64;
+
+#[derive(
+    serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
 pub enum Value {
     Zero,
     One,
+    /// Unknown or conflicting
     X,
+    /// High impedance, nothing drives the pin
+    Z,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SimulationStatus {
-    Stable { iterations: usize },
+    Stable { events: usize },
     Unstable { max_reached: bool },
     Running,
 }
@@ -30,16 +51,49 @@ impl Default for SimulationStatus {
     }
 }
 
+/// A pin value change that takes effect at a simulated time.
+/// Ordered by time first, then by the order it was scheduled in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct ScheduledChange {
+    time: u64,
+    seq: u64,
+    pin: Pin,
+    value: BusValue,
+}
+
 impl Value {
-    fn is_one(self) -> bool {
-        self == Self::One
+    pub fn as_char(self) -> char {
+        match self {
+            Self::Zero => '0',
+            Self::One => '1',
+            Self::X => 'X',
+            Self::Z => 'Z',
+        }
+    }
+
+    /// Gate inputs read a floating `Z` as unknown.
+    fn driven(self) -> Self {
+        match self {
+            Self::Z => Self::X,
+            v => v,
+        }
+    }
+
+    /// Value of a net with two drivers. `Z` gives way to the other driver, drivers that disagree
+    /// give `X`.
+    fn resolve(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Z, v) | (v, Self::Z) => v,
+            (a, b) if a == b => a,
+            _ => Self::X,
+        }
     }
 
     fn not(self) -> Self {
         match self {
             Self::Zero => Self::One,
             Self::One => Self::Zero,
-            Self::X => Self::X,
+            Self::X | Self::Z => Self::X,
         }
     }
 
@@ -61,8 +115,8 @@ impl Value {
 
     fn xor(self, other: Self) -> Self {
         match (self, other) {
-            (Self::Zero, v) | (v, Self::Zero) => v,
-            (Self::One, Self::One) => Self::Zero,
+            (Self::Zero, Self::Zero) | (Self::One, Self::One) => Self::Zero,
+            (Self::Zero, Self::One) | (Self::One, Self::Zero) => Self::One,
             _ => Self::X,
         }
     }
@@ -76,20 +130,240 @@ impl Value {
     }
 }
 
-#[derive(Default)]
+/// Value of an N-bit bus where every bit carries its own `Value`. Bit 0 is the least significant
+/// bit. A single-bit signal is a bus of width 1.
+#[derive(
+    serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub struct BusValue {
+    width: u8,
+    /// Bits that are `One`
+    ones: u64,
+    /// Bits that are `X`
+    unknown: u64,
+    /// Bits that are `Z`
+    floating: u64,
+}
+
+impl BusValue {
+    /// A bus of `width` bits that all carry `value`.
+    pub fn splat(width: u8, value: Value) -> Self {
+        let width = width.clamp(1, MAX_BUS_WIDTH);
+        let mut bus = Self {
+            width,
+            ones: 0,
+            unknown: 0,
+            floating: 0,
+        };
+        for i in 0..width {
+            bus.set_bit(i, value);
+        }
+        bus
+    }
+
+    /// A fully known bus holding the lowest `width` bits of `n`.
+    pub fn from_u64(width: u8, n: u64) -> Self {
+        let width = width.clamp(1, MAX_BUS_WIDTH);
+        Self {
+            width,
+            ones: n & Self::mask(width),
+            unknown: 0,
+            floating: 0,
+        }
+    }
+
+    fn mask(width: u8) -> u64 {
+        if width >= 64 {
+            u64::MAX
+        } else {
+            (1 << width) - 1
+        }
+    }
+
+    pub fn width(self) -> u8 {
+        self.width
+    }
+
+    pub fn bit(self, i: u8) -> Value {
+        if i >= self.width || self.unknown & (1 << i) != 0 {
+            Value::X
+        } else if self.floating & (1 << i) != 0 {
+            Value::Z
+        } else if self.ones & (1 << i) != 0 {
+            Value::One
+        } else {
+            Value::Zero
+        }
+    }
+
+    pub fn set_bit(&mut self, i: u8, value: Value) {
+        if i >= self.width {
+            return;
+        }
+        let m = 1 << i;
+        self.ones &= !m;
+        self.unknown &= !m;
+        self.floating &= !m;
+        match value {
+            Value::Zero => {}
+            Value::One => self.ones |= m,
+            Value::X => self.unknown |= m,
+            Value::Z => self.floating |= m,
+        }
+    }
+
+    /// Numeric value of the bus, `None` if any bit is unknown or floating.
+    pub fn to_u64(self) -> Option<u64> {
+        (self.unknown == 0 && self.floating == 0).then_some(self.ones)
+    }
+
+    pub fn any_one(self) -> bool {
+        self.ones != 0
+    }
+
+    fn map(self, f: impl Fn(Value) -> Value) -> Self {
+        let mut out = self;
+        for i in 0..self.width {
+            out.set_bit(i, f(self.bit(i)));
+        }
+        out
+    }
+
+    /// Combine two buses bit by bit. Buses of different width produce all `X`.
+    fn zip(self, other: Self, f: impl Fn(Value, Value) -> Value) -> Self {
+        if self.width != other.width {
+            return Self::splat(self.width.max(other.width), Value::X);
+        }
+        let mut out = self;
+        for i in 0..self.width {
+            out.set_bit(i, f(self.bit(i), other.bit(i)));
+        }
+        out
+    }
+
+    /// Combine the values of two drivers of the same net.
+    fn resolve(self, other: Self) -> Self {
+        self.zip(other, Value::resolve)
+    }
+
+    /// True when both buses drive a bit to different values, or their widths differ.
+    fn conflicts_with(self, other: Self) -> bool {
+        if self.width != other.width {
+            return true;
+        }
+        let both_driven = !self.floating & !other.floating & Self::mask(self.width);
+        let differ = (self.ones ^ other.ones) | (self.unknown ^ other.unknown);
+        both_driven & differ != 0
+    }
+
+    fn driven(self) -> Self {
+        self.map(Value::driven)
+    }
+
+    fn not(self) -> Self {
+        self.map(Value::not)
+    }
+
+    fn and(self, other: Self) -> Self {
+        self.zip(other, Value::and)
+    }
+
+    fn or(self, other: Self) -> Self {
+        self.zip(other, Value::or)
+    }
+
+    fn xor(self, other: Self) -> Self {
+        self.zip(other, Value::xor)
+    }
+
+    fn xnor(self, other: Self) -> Self {
+        self.zip(other, Value::xnor)
+    }
+}
+
+impl From<Value> for BusValue {
+    fn from(value: Value) -> Self {
+        Self::splat(1, value)
+    }
+}
+
+/// Bits from the most significant to the least significant one, e.g. `01X1`.
+impl std::fmt::Display for BusValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for i in (0..self.width).rev() {
+            write!(f, "{}", self.bit(i).as_char())?;
+        }
+        Ok(())
+    }
+}
+
+/// Working copy of a RAM while it is simulated.
+#[derive(Debug, Clone)]
+pub struct MemoryState {
+    pub contents: Vec<u64>,
+    pub clock: Value,
+}
+
 pub struct Simulator {
     /// Final result - maps each pin to its current value
-    pub current: HashMap<Pin, Value>,
+    pub current: HashMap<Pin, BusValue>,
     /// Keep what has been already evaluated
     pub evaluated: HashSet<InstanceId>,
-    /// Number of iterations taken in last compute
-    pub last_iterations: usize,
+    /// Flip flop state as of the last evaluation. Instances not in here start from the state
+    /// stored on the instance. Written back with `store_state`.
+    pub flip_flops: HashMap<InstanceId, FlipFlopState>,
+    /// RAM contents as of the last evaluation, same rules as `flip_flops`
+    pub memories: HashMap<InstanceId, MemoryState>,
+    /// Pins whose net has active drivers that disagree
+    pub conflicts: HashSet<Pin>,
+    /// Nets of the circuit as of the last compute
+    pub nets: Netlist,
+    /// Pins that still changed during the last tenth of the event budget of a compute that did
+    /// not settle
+    pub oscillating: HashSet<Pin>,
+    /// Loops of the circuit, found when a compute does not settle
+    pub loops: Vec<Loop>,
+    /// Number of events processed in last compute
+    pub last_events: usize,
     /// Current status of the simulation
     pub status: SimulationStatus,
-    /// Current iteration number
-    pub current_iteration: usize,
-    /// Are clocks on?
-    pub clocks_on: bool,
+    /// Maximum number of events a single compute may process
+    pub event_budget: usize,
+    /// Simulated time in abstract time units. Gate delays are expressed in the same unit.
+    pub time: u64,
+    /// History of the probed pins
+    pub recorder: Recorder,
+    /// Pins written by the evaluation in progress whose value changed
+    changed: Vec<Pin>,
+    /// Pending value changes, earliest first
+    scheduled: BinaryHeap<Reverse<ScheduledChange>>,
+    /// Last value scheduled for a pin together with the `seq` of that change
+    projected: HashMap<Pin, (u64, BusValue)>,
+    next_seq: u64,
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self {
+            current: HashMap::new(),
+            evaluated: HashSet::new(),
+            flip_flops: HashMap::new(),
+            memories: HashMap::new(),
+            conflicts: HashSet::new(),
+            nets: Netlist::default(),
+            oscillating: HashSet::new(),
+            loops: Vec::new(),
+            last_events: 0,
+            status: SimulationStatus::default(),
+            event_budget: DEFAULT_EVENT_BUDGET,
+            time: 0,
+            recorder: Recorder::default(),
+            changed: Vec::new(),
+            scheduled: BinaryHeap::new(),
+            projected: HashMap::new(),
+            next_seq: 0,
+        }
+    }
 }
 
 impl Simulator {
@@ -97,95 +371,210 @@ impl Simulator {
         Self::default()
     }
 
+    pub fn with_event_budget(mut self, event_budget: usize) -> Self {
+        self.event_budget = event_budget;
+        self
+    }
+
     fn rebuild_sorted_instances(&self, circuit: &Circuit) -> Vec<InstanceId> {
         let mut ids: Vec<InstanceId> = circuit.types.keys().collect();
         ids.sort_unstable();
         ids
     }
 
+    /// Settle the circuit. Every instance is evaluated once, after that only the fanout of pins
+    /// whose value changed is scheduled again. When nothing is left to evaluate at the current
+    /// time, simulated time advances to the next pending change. Stops when no events are left or
+    /// `event_budget` is exhausted. Clocks keep the value they have when the compute starts.
     pub fn compute(&mut self, db: &DB, circuit: &Circuit) -> HashSet<Pin> {
-        log::debug!("=== Begin simulation ===");
+        self.run(db, circuit, None)
+    }
 
-        self.current_iteration = 0;
-        self.status = SimulationStatus::Running;
+    /// Run the simulation up to time `until`, clocks follow simulated time on the way. Ends with
+    /// `time` at `until` unless the event budget runs out first.
+    pub fn advance_to(&mut self, db: &DB, circuit: &Circuit, until: u64) -> HashSet<Pin> {
+        self.run(db, circuit, Some(until))
+    }
 
-        let mut previous_state: HashMap<Pin, Value>;
-        let mut stable_count = 0;
+    /// Why the last compute did not settle, `None` when it did
+    pub fn instability(&self) -> Option<String> {
+        matches!(self.status, SimulationStatus::Unstable { .. })
+            .then(|| loops::explain(&self.loops, &self.oscillating))
+    }
 
-        let power_ids: Vec<_> = circuit.powers.keys().collect();
-        for &id in &power_ids {
-            self.evaluate_power(circuit, id);
-        }
+    /// Earliest time after now at which a clock output changes
+    pub fn next_clock_edge(&self, circuit: &Circuit) -> Option<u64> {
+        circuit
+            .clocks
+            .values()
+            .map(|clock| clock.next_edge_after(self.time))
+            .min()
+    }
 
-        let sorted_instances = self.rebuild_sorted_instances(circuit);
-        while self.current_iteration < MAX_ITERATIONS {
-            previous_state = self.current.clone();
+    fn run(&mut self, db: &DB, circuit: &Circuit, until: Option<u64>) -> HashSet<Pin> {
+        log::debug!("=== Begin simulation at t={} ===", self.time);
 
-            for &id in &sorted_instances {
-                self.evaluate(db, circuit, id);
+        self.status = SimulationStatus::Running;
+        self.conflicts.clear();
+        self.oscillating.clear();
+        self.loops.clear();
+
+        self.nets = Netlist::build(db, circuit);
+        let fanout = self.nets.fanout();
+        let mut queue: VecDeque<InstanceId> = self.rebuild_sorted_instances(circuit).into();
+        let mut queued: HashSet<InstanceId> = queue.iter().copied().collect();
+        let mut events = 0;
+        let mut settled = false;
+
+        while events < self.event_budget {
+            if let Some(id) = queue.pop_front() {
+                queued.remove(&id);
+                events += 1;
+                self.evaluate(circuit, id);
+            } else {
+                let next_change = self.scheduled.peek().map(|Reverse(change)| change.time);
+                let next_edge = until.and_then(|_| self.next_clock_edge(circuit));
+                match next_change.into_iter().chain(next_edge).min() {
+                    Some(time) if until.is_none_or(|until| time <= until) => {
+                        events += 1;
+                        if time > self.time {
+                            self.record_probes(circuit);
+                        }
+                        self.time = time;
+                        // Clock edges go first so changes at the same time see the new clock value
+                        if next_edge == Some(time) {
+                            for id in circuit.clock_ids() {
+                                if queued.insert(id) {
+                                    queue.push_back(id);
+                                }
+                            }
+                        } else if let Some(Reverse(change)) = self.scheduled.pop() {
+                            self.apply(change);
+                        }
+                    }
+                    _ => {
+                        settled = true;
+                        break;
+                    }
+                }
             }
 
-            self.current_iteration += 1;
-
-            if self.current == previous_state {
-                stable_count += 1;
-                if stable_count >= STABILIZATION_THRESHOLD {
-                    self.last_iterations = self.current_iteration;
-                    self.status = SimulationStatus::Stable {
-                        iterations: self.current_iteration,
-                    };
-                    log::debug!(
-                        "Simulation stabilized after {} iterations",
-                        self.current_iteration
-                    );
-                    break;
+            let tail = events * 10 >= self.event_budget * 9;
+            for pin in std::mem::take(&mut self.changed) {
+                if tail {
+                    self.oscillating.insert(pin);
+                }
+                let Some(readers) = fanout.get(&pin) else {
+                    continue;
+                };
+                for &reader in readers {
+                    if queued.insert(reader) {
+                        queue.push_back(reader);
+                    }
                 }
-            } else {
-                stable_count = 0;
             }
         }
 
-        if self.current_iteration >= MAX_ITERATIONS {
-            self.last_iterations = MAX_ITERATIONS;
+        self.last_events = events;
+        if settled {
+            if let Some(until) = until {
+                self.time = self.time.max(until);
+            }
+            self.record_probes(circuit);
+            self.status = SimulationStatus::Stable { events };
+            log::debug!(
+                "Simulation stabilized after {events} events at t={}",
+                self.time
+            );
+        } else {
             self.status = SimulationStatus::Unstable { max_reached: true };
-            log::warn!("Simulation reached max iterations without stabilizing");
+            self.loops = loops::find(circuit, &self.nets);
+            log::warn!(
+                "Simulation used its budget of {events} events without stabilizing: {}",
+                loops::explain(&self.loops, &self.oscillating)
+            );
         }
 
         self.current
             .iter()
-            .filter_map(|(pin, val)| if val.is_one() { Some(*pin) } else { None })
+            .filter_map(|(pin, val)| if val.any_one() { Some(*pin) } else { None })
             .collect()
     }
 
-    fn evaluate(&mut self, db: &DB, circuit: &Circuit, id: InstanceId) {
+    /// Drive `pin` to `value` after `delay` time units. A zero delay takes effect immediately.
+    /// Changes are never cancelled (transport delay), so short pulses and glitches propagate.
+    fn drive(&mut self, pin: Pin, value: BusValue, delay: u64) {
+        if delay == 0 {
+            self.set(pin, value);
+            return;
+        }
+
+        let projected = self
+            .projected
+            .get(&pin)
+            .map(|&(_, v)| v)
+            .or_else(|| self.current.get(&pin).copied());
+        if projected == Some(value) {
+            return;
+        }
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.projected.insert(pin, (seq, value));
+        self.scheduled.push(Reverse(ScheduledChange {
+            time: self.time + delay,
+            seq,
+            pin,
+            value,
+        }));
+    }
+
+    fn apply(&mut self, change: ScheduledChange) {
+        if self
+            .projected
+            .get(&change.pin)
+            .is_some_and(|&(seq, _)| seq == change.seq)
+        {
+            self.projected.remove(&change.pin);
+        }
+        self.set(change.pin, change.value);
+    }
+
+    /// Store a pin value and remember the pin if the value changed.
+    fn set(&mut self, pin: Pin, value: BusValue) {
+        if self.current.insert(pin, value) != Some(value) {
+            self.changed.push(pin);
+        }
+    }
+
+    fn evaluate(&mut self, circuit: &Circuit, id: InstanceId) {
         self.evaluated.insert(id);
 
         match circuit.ty(id) {
-            InstanceKind::Wire => {
-                self.evaluate_wire(db, circuit, id);
-            }
             InstanceKind::Gate(_) => {
-                self.evaluate_gate(db, circuit, id);
+                self.evaluate_gate(circuit, id);
             }
             InstanceKind::Lamp => {
-                self.evaluate_lamp(db, circuit, id);
+                self.evaluate_lamp(circuit, id);
+            }
+            InstanceKind::Power => {
+                self.evaluate_power(circuit, id);
             }
-            InstanceKind::Power => {}
             InstanceKind::Clock => {
-                if self.clocks_on {
-                    self.current.insert(clock_output(id), Value::One);
-                } else {
-                    self.current.insert(clock_output(id), Value::Zero);
-                }
+                let val = circuit.get_clock(id).value_at(self.time);
+                self.set(clock_output(id), val.into());
             }
-            InstanceKind::Module(module_def_id) => {
-                for pin in circuit.get_module(id).pins() {
-                    let v = self.get_pin_value(db, circuit, pin);
-                    self.current.insert(pin, v);
-                    let mapped_pin = pin.is_passthrough(db).unwrap_or(pin);
-                    self.current.insert(mapped_pin, v);
-                }
+            InstanceKind::Splitter(_) => {
+                self.evaluate_splitter(circuit, id);
+            }
+            InstanceKind::FlipFlop(_) => {
+                self.evaluate_flip_flop(circuit, id);
             }
+            InstanceKind::Memory(_) => {
+                self.evaluate_memory(circuit, id);
+            }
+            // Values cross conductors as part of their nets
+            InstanceKind::Wire | InstanceKind::Tunnel | InstanceKind::Module(_) => {}
         }
     }
 
@@ -193,44 +582,35 @@ impl Simulator {
         let p = circuit.get_power(id);
         let out = power_output(id);
         let val = if p.on { Value::One } else { Value::Zero };
-        self.current.insert(out, val);
-    }
-
-    fn evaluate_wire(&mut self, db: &DB, circuit: &Circuit, id: InstanceId) {
-        let input = {
-            let start = wire_start(id);
-            let end = wire_end(id);
-
-            if start.kind == PinKind::Input {
-                start
-            } else {
-                end
-            }
-        };
-        let other = if wire_start(id) == input {
-            wire_end(id)
-        } else {
-            wire_start(id)
-        };
-
-        let result = self.get_pin_value(db, circuit, input);
-
-        self.current.insert(input, result);
-        self.current.insert(other, result);
+        self.set(out, val.into());
     }
 
-    fn evaluate_gate(&mut self, db: &DB, circuit: &Circuit, id: InstanceId) {
+    fn evaluate_gate(&mut self, circuit: &Circuit, id: InstanceId) {
         let InstanceKind::Gate(kind) = circuit.ty(id) else {
             return;
         };
+        let gate = circuit.get_gate(id);
+        let (delay, width) = (gate.delay(), gate.width);
+
+        if matches!(kind, GateKind::TriState) {
+            let data = self.get_pin_value(circuit, gate_inp1(id));
+            let enable = self.get_pin_value(circuit, gate_inp2(id));
+            let out_val = match enable.bit(0) {
+                Value::One => data.driven(),
+                Value::Zero => BusValue::splat(width, Value::Z),
+                Value::X | Value::Z => BusValue::splat(width, Value::X),
+            };
+            self.drive(gate_output(id), out_val, delay);
+            return;
+        }
 
         // Not has one input so handle specially
         if matches!(kind, GateKind::Not) {
             let inp1 = gate_inp1(id);
             let out = Pin::new(id, 1, PinKind::Output);
-            let a = self.get_pin_value(db, circuit, inp1);
+            let a = self.get_pin_value(circuit, inp1);
             let out_val = a.not();
-            self.current.insert(out, out_val);
+            self.drive(out, out_val, delay);
             return;
         }
 
@@ -238,8 +618,8 @@ impl Simulator {
         let inp2 = gate_inp2(id);
         let out = gate_output(id);
 
-        let a = self.get_pin_value(db, circuit, inp1);
-        let b = self.get_pin_value(db, circuit, inp2);
+        let a = self.get_pin_value(circuit, inp1);
+        let b = self.get_pin_value(circuit, inp2);
 
         let out_val = match kind {
             GateKind::And => a.and(b),
@@ -248,36 +628,262 @@ impl Simulator {
             GateKind::Nor => a.or(b).not(),
             GateKind::Xor => a.xor(b),
             GateKind::Xnor => a.xnor(b),
-            GateKind::Not => unreachable!("Handled above"),
+            GateKind::Not | GateKind::TriState => unreachable!("Handled above"),
         };
 
-        self.current.insert(out, out_val);
+        self.drive(out, out_val, delay);
     }
 
-    fn evaluate_lamp(&mut self, db: &DB, circuit: &Circuit, id: InstanceId) {
-        let inp = lamp_input(id);
-        let val = self.get_pin_value(db, circuit, inp);
-        self.current.insert(inp, val);
+    fn evaluate_splitter(&mut self, circuit: &Circuit, id: InstanceId) {
+        let splitter = circuit.get_splitter(id);
+        match splitter.kind {
+            SplitterKind::Split => {
+                let bus = self.get_pin_value(circuit, splitter_input(id));
+                for bit in 0..splitter.width {
+                    self.set(splitter_output(id, bit), bus.bit(bit).into());
+                }
+            }
+            SplitterKind::Join => {
+                let mut bus = BusValue::splat(splitter.width, Value::Zero);
+                for bit in 0..splitter.width {
+                    let value = self.get_pin_value(circuit, joiner_input(id, bit));
+                    bus.set_bit(bit, value.bit(0));
+                }
+                self.set(joiner_output(id), bus);
+            }
+        }
     }
 
-    fn get_pin_value(&self, db: &DB, circuit: &Circuit, pin: Pin) -> Value {
-        let mapped_pin = pin.is_passthrough(db).unwrap_or(pin);
-        let conns = circuit.connections_containing(mapped_pin);
+    fn evaluate_flip_flop(&mut self, circuit: &Circuit, id: InstanceId) {
+        let flip_flop = circuit.get_flip_flop(id);
+        let kind = flip_flop.kind;
+        let state = self.flip_flops.get(&id).copied().unwrap_or(flip_flop.state);
 
-        let mut result = Value::Zero;
-        for conn in conns {
-            let connected_pin = conn.get_other_pin(mapped_pin);
-            if connected_pin.kind != PinKind::Output {
+        let mut inputs = Vec::new();
+        for &role in kind.pins() {
+            if matches!(role, FlipFlopPin::Q | FlipFlopPin::NotQ) {
                 continue;
             }
+            let value = self.get_pin_value(circuit, flip_flop_pin(id, kind, role));
+            inputs.push((role, value.bit(0)));
+        }
+        let input = |role: FlipFlopPin| {
+            inputs
+                .iter()
+                .find(|(r, _)| *r == role)
+                .map(|&(_, v)| v)
+                .unwrap_or(Value::Z)
+        };
+
+        let state = next_flip_flop_state(kind, state, input);
+        self.flip_flops.insert(id, state);
+        self.drive(
+            flip_flop_pin(id, kind, FlipFlopPin::Q),
+            state.q.into(),
+            FLIP_FLOP_DELAY,
+        );
+        self.drive(
+            flip_flop_pin(id, kind, FlipFlopPin::NotQ),
+            state.q.not().into(),
+            FLIP_FLOP_DELAY,
+        );
+    }
+
+    /// ROMs read their contents straight from the instance. RAMs write the word at the address
+    /// on a rising clock edge while write enable is One, a write with an unknown address or data
+    /// is ignored. Reads are asynchronous for both.
+    fn evaluate_memory(&mut self, circuit: &Circuit, id: InstanceId) {
+        let memory = circuit.get_memory(id);
+        let address = self.get_pin_value(circuit, memory_address(id)).to_u64();
 
-            if let Some(&val) = self.current.get(&connected_pin) {
-                result = result.or(val);
+        let word = match memory.kind {
+            MemoryKind::Rom => address.map(|a| memory.read(a)),
+            MemoryKind::Ram => {
+                let data = self.get_pin_value(circuit, memory_data_in(id)).to_u64();
+                let write_enable = self.get_pin_value(circuit, memory_write_enable(id)).bit(0);
+                let clock = self.get_pin_value(circuit, memory_clock(id)).bit(0);
+
+                let state = self.memories.entry(id).or_insert_with(|| MemoryState {
+                    contents: memory.contents.clone(),
+                    clock: memory.clock,
+                });
+                // Same as flip flops, a floating clock keeps its last value
+                let clock = if clock == Value::Z {
+                    state.clock
+                } else {
+                    clock
+                };
+                let rising_edge = state.clock == Value::Zero && clock == Value::One;
+                state.clock = clock;
+                if rising_edge
+                    && write_enable == Value::One
+                    && let (Some(a), Some(d)) = (address, data)
+                    && let Some(slot) = usize::try_from(a)
+                        .ok()
+                        .and_then(|a| state.contents.get_mut(a))
+                {
+                    *slot = d & memory.data_mask();
+                }
+                address.map(|a| {
+                    usize::try_from(a)
+                        .ok()
+                        .and_then(|a| state.contents.get(a))
+                        .copied()
+                        .unwrap_or(0)
+                })
+            }
+        };
+
+        let out = match word {
+            Some(word) => BusValue::from_u64(memory.data_width, word),
+            None => BusValue::splat(memory.data_width, Value::X),
+        };
+        self.drive(memory_output(id, memory.kind), out, MEMORY_DELAY);
+    }
+
+    /// Drop the kept state of instances that are gone from `circuit` or whose memory changed
+    /// size, they start again from what is stored on the instance.
+    pub fn forget_removed(&mut self, circuit: &Circuit) {
+        self.flip_flops
+            .retain(|&id, _| circuit.flip_flops.contains_key(id));
+        self.memories.retain(|&id, state| {
+            circuit
+                .memories
+                .get(id)
+                .is_some_and(|memory| memory.contents.len() == state.contents.len())
+        });
+    }
+
+    /// Write the flip flop states and RAM contents back to their instances.
+    pub fn store_state(&self, circuit: &mut Circuit) {
+        for (&id, &state) in &self.flip_flops {
+            if let Some(flip_flop) = circuit.flip_flops.get_mut(id) {
+                flip_flop.state = state;
+            }
+        }
+        for (&id, state) in &self.memories {
+            if let Some(memory) = circuit.memories.get_mut(id)
+                && memory.contents.len() == state.contents.len()
+            {
+                memory.contents.clone_from(&state.contents);
+                memory.clock = state.clock;
             }
         }
+    }
 
+    fn evaluate_lamp(&mut self, circuit: &Circuit, id: InstanceId) {
+        let inp = lamp_input(id);
+        let val = self.get_pin_value(circuit, inp);
+        self.set(inp, val);
+    }
+
+    /// Resolve the value seen at `pin` from every output driving its net. An undriven pin is `Z`,
+    /// a single active driver gives its value and active drivers that disagree give `X` and mark
+    /// the pin as a conflict.
+    fn get_pin_value(&mut self, circuit: &Circuit, pin: Pin) -> BusValue {
+        let (result, conflict) = self.resolve_pin(circuit, pin);
+        if conflict {
+            self.conflicts.insert(pin);
+        } else {
+            self.conflicts.remove(&pin);
+        }
         result
     }
+
+    /// Value seen at `pin` and whether its drivers disagree
+    fn resolve_pin(&self, circuit: &Circuit, pin: Pin) -> (BusValue, bool) {
+        let mut result = BusValue::splat(circuit.pin_width(pin), Value::Z);
+        let mut conflict = false;
+        for driver in self.nets.drivers(pin) {
+            if let Some(&val) = self.current.get(driver) {
+                conflict |= result.conflicts_with(val);
+                result = result.resolve(val);
+            }
+        }
+        (result, conflict)
+    }
+
+    /// Value of a pin as shown to the user. Pins that are never written, like gate inputs and
+    /// wire ends, show the value of their net.
+    pub fn pin_value(&self, circuit: &Circuit, pin: Pin) -> BusValue {
+        match self.current.get(&pin) {
+            Some(&value) => value,
+            None => self.resolve_pin(circuit, pin).0,
+        }
+    }
+
+    /// Add the values of the probed pins at the current time to their traces
+    fn record_probes(&mut self, circuit: &Circuit) {
+        if self.recorder.traces.is_empty() {
+            return;
+        }
+        self.recorder
+            .traces
+            .retain(|trace| circuit.types.contains_key(trace.pin.ins));
+        let values: Vec<BusValue> = self
+            .recorder
+            .traces
+            .iter()
+            .map(|trace| self.pin_value(circuit, trace.pin))
+            .collect();
+        self.recorder.record(self.time, &values);
+    }
+}
+
+/// Set and reset are active high. Unconnected control inputs are inactive, while unconnected data
+/// inputs are unknown.
+fn next_flip_flop_state(
+    kind: FlipFlopKind,
+    state: FlipFlopState,
+    input: impl Fn(FlipFlopPin) -> Value,
+) -> FlipFlopState {
+    let control = |role| match input(role) {
+        Value::Z => Value::Zero,
+        v => v,
+    };
+    let data = |role| input(role).driven();
+    let q = state.q;
+
+    // Set and reset drive the output directly, for latches they are the only inputs
+    let forced = match (control(FlipFlopPin::Set), control(FlipFlopPin::Reset)) {
+        (Value::Zero, Value::Zero) => None,
+        (Value::One, Value::Zero) => Some(Value::One),
+        (Value::Zero, Value::One) => Some(Value::Zero),
+        _ => Some(Value::X),
+    };
+
+    // A floating clock keeps its last value, so drivers evaluated later than the flip flop do not
+    // look like an edge
+    let clock = match input(FlipFlopPin::Clock) {
+        Value::Z => state.clock,
+        v => v,
+    };
+    let rising_edge = state.clock == Value::Zero && clock == Value::One;
+    let q = match kind {
+        _ if forced.is_some() => forced.unwrap_or(q),
+        FlipFlopKind::SrLatch => q,
+        FlipFlopKind::DLatch => match control(FlipFlopPin::Enable) {
+            Value::One => data(FlipFlopPin::D),
+            Value::Zero | Value::Z => q,
+            Value::X => q.resolve(data(FlipFlopPin::D)),
+        },
+        _ if !rising_edge => q,
+        FlipFlopKind::D => data(FlipFlopPin::D),
+        FlipFlopKind::Jk => match (data(FlipFlopPin::J), data(FlipFlopPin::K)) {
+            (Value::Zero, Value::Zero) => q,
+            (Value::Zero, Value::One) => Value::Zero,
+            (Value::One, Value::Zero) => Value::One,
+            (Value::One, Value::One) => q.not(),
+            _ => Value::X,
+        },
+        FlipFlopKind::T => match data(FlipFlopPin::T) {
+            Value::Zero => q,
+            Value::One => q.not(),
+            _ => Value::X,
+        },
+    };
+
+    FlipFlopState { q, clock }
 }
 
 pub fn gate_output_n(id: InstanceId, n: u32) -> Pin {
@@ -313,6 +919,532 @@ pub fn lamp_input(id: InstanceId) -> Pin {
     Pin::new(id, 0, PinKind::Input)
 }
 
+pub fn tunnel_pin(id: InstanceId) -> Pin {
+    Pin::new(id, 0, PinKind::Output)
+}
+
 pub fn clock_output(id: InstanceId) -> Pin {
     Pin::new(id, 0, PinKind::Output)
 }
+
+pub fn splitter_input(id: InstanceId) -> Pin {
+    Pin::new(id, 0, PinKind::Input)
+}
+
+pub fn splitter_output(id: InstanceId, bit: u8) -> Pin {
+    Pin::new(id, u32::from(bit) + 1, PinKind::Output)
+}
+
+pub fn joiner_input(id: InstanceId, bit: u8) -> Pin {
+    Pin::new(id, u32::from(bit) + 1, PinKind::Input)
+}
+
+pub fn joiner_output(id: InstanceId) -> Pin {
+    Pin::new(id, 0, PinKind::Output)
+}
+
+pub fn memory_address(id: InstanceId) -> Pin {
+    Pin::new(id, 0, PinKind::Input)
+}
+
+pub fn memory_data_in(id: InstanceId) -> Pin {
+    Pin::new(id, 1, PinKind::Input)
+}
+
+pub fn memory_write_enable(id: InstanceId) -> Pin {
+    Pin::new(id, 2, PinKind::Input)
+}
+
+pub fn memory_clock(id: InstanceId) -> Pin {
+    Pin::new(id, 3, PinKind::Input)
+}
+
+pub fn memory_output(id: InstanceId, kind: MemoryKind) -> Pin {
+    match kind {
+        MemoryKind::Rom => Pin::new(id, 1, PinKind::Output),
+        MemoryKind::Ram => Pin::new(id, 4, PinKind::Output),
+    }
+}
+
+pub fn flip_flop_pin(id: InstanceId, kind: FlipFlopKind, role: FlipFlopPin) -> Pin {
+    let index = kind
+        .pin_index(role)
+        .unwrap_or_else(|| panic!("{kind:?} has no {role:?} pin"));
+    let pin_kind = if matches!(role, FlipFlopPin::Q | FlipFlopPin::NotQ) {
+        PinKind::Output
+    } else {
+        PinKind::Input
+    };
+    Pin::new(id, index, pin_kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        BusValue, SimulationStatus, Simulator, Value, clock_output, flip_flop_pin, gate_inp1,
+        gate_inp2, gate_output, joiner_input, joiner_output, lamp_input, memory_address,
+        memory_clock, memory_data_in, memory_output, memory_write_enable, power_output,
+        splitter_input, splitter_output, tunnel_pin, wire_end, wire_start,
+    };
+    use crate::{
+        assets::PinKind,
+        connection_manager::Connection,
+        db::{
+            Clock, DB, FlipFlop, FlipFlopKind, FlipFlopPin, Gate, GateKind, InstanceId, Lamp,
+            Memory, MemoryKind, Pin, Power, Splitter, SplitterKind, Tunnel, Wire,
+        },
+        loops::LoopKind,
+    };
+    use egui::Pos2;
+
+    fn new_not(db: &mut DB) -> InstanceId {
+        db.circuit.new_gate(Gate::new(Pos2::ZERO, GateKind::Not))
+    }
+
+    fn not_input(id: InstanceId) -> Pin {
+        Pin::new(id, 0, PinKind::Input)
+    }
+
+    fn not_output(id: InstanceId) -> Pin {
+        Pin::new(id, 1, PinKind::Output)
+    }
+
+    #[test]
+    fn deep_not_chain_settles() {
+        let mut db = DB::default();
+        let power = db.circuit.new_power(Power::new(Pos2::ZERO, true));
+        let mut prev = power_output(power);
+        for _ in 0..64 {
+            let not = new_not(&mut db);
+            db.circuit
+                .connections
+                .insert(Connection::new(prev, not_input(not)));
+            prev = not_output(not);
+        }
+        let lamp = db.circuit.new_lamp(Lamp::new(Pos2::ZERO));
+        db.circuit
+            .connections
+            .insert(Connection::new(prev, lamp_input(lamp)));
+
+        let mut sim = Simulator::new();
+        sim.compute(&db, &db.circuit);
+
+        assert!(
+            matches!(sim.status, SimulationStatus::Stable { .. }),
+            "status: {:?}",
+            sim.status
+        );
+        assert_eq!(sim.current.get(&lamp_input(lamp)), Some(&Value::One.into()));
+    }
+
+    #[test]
+    fn tunnels_carry_values_by_name() {
+        let mut db = DB::default();
+        let power = db.circuit.new_power(Power::new(Pos2::ZERO, true));
+        let into = db.circuit.new_wire(Wire::new(Pos2::ZERO, Pos2::ZERO));
+        let here = db.circuit.new_tunnel(Tunnel::new(Pos2::ZERO, "data"));
+        let there = db.circuit.new_tunnel(Tunnel::new(Pos2::ZERO, "data"));
+        let elsewhere = db.circuit.new_tunnel(Tunnel::new(Pos2::ZERO, "other"));
+        let out = db.circuit.new_wire(Wire::new(Pos2::ZERO, Pos2::ZERO));
+        let lamp = db.circuit.new_lamp(Lamp::new(Pos2::ZERO));
+        let dark = db.circuit.new_lamp(Lamp::new(Pos2::ZERO));
+        for (from, to) in [
+            (power_output(power), wire_start(into)),
+            (wire_end(into), tunnel_pin(here)),
+            (tunnel_pin(there), wire_start(out)),
+            (wire_end(out), lamp_input(lamp)),
+            (tunnel_pin(elsewhere), lamp_input(dark)),
+        ] {
+            db.circuit.connections.insert(Connection::new(from, to));
+        }
+
+        let mut sim = Simulator::new();
+        sim.compute(&db, &db.circuit);
+
+        assert_eq!(sim.current.get(&lamp_input(lamp)), Some(&Value::One.into()));
+        assert_ne!(sim.current.get(&lamp_input(dark)), Some(&Value::One.into()));
+    }
+
+    #[test]
+    fn gate_delays_advance_simulated_time() {
+        let mut db = DB::default();
+        let power = db.circuit.new_power(Power::new(Pos2::ZERO, false));
+        let first = new_not(&mut db);
+        let second = new_not(&mut db);
+        db.circuit.get_gate_mut(second).delay = Some(5);
+        let lamp = db.circuit.new_lamp(Lamp::new(Pos2::ZERO));
+        for (from, to) in [
+            (power_output(power), not_input(first)),
+            (not_output(first), not_input(second)),
+            (not_output(second), lamp_input(lamp)),
+        ] {
+            db.circuit.connections.insert(Connection::new(from, to));
+        }
+
+        let mut sim = Simulator::new();
+        sim.compute(&db, &db.circuit);
+
+        assert_eq!(sim.time, 6);
+        assert_eq!(
+            sim.current.get(&lamp_input(lamp)),
+            Some(&Value::Zero.into())
+        );
+    }
+
+    #[test]
+    fn ring_oscillator_runs_out_of_budget() {
+        // Nand with a disabled enable gives the ring a known value, otherwise it stays at X
+        let mut db = DB::default();
+        let enable = db.circuit.new_power(Power::new(Pos2::ZERO, false));
+        let nand = db.circuit.new_gate(Gate::new(Pos2::ZERO, GateKind::Nand));
+        let nots: Vec<InstanceId> = (0..2).map(|_| new_not(&mut db)).collect();
+        for (from, to) in [
+            (power_output(enable), gate_inp1(nand)),
+            (gate_output(nand), not_input(nots[0])),
+            (not_output(nots[0]), not_input(nots[1])),
+            (not_output(nots[1]), gate_inp2(nand)),
+        ] {
+            db.circuit.connections.insert(Connection::new(from, to));
+        }
+
+        let mut sim = Simulator::new().with_event_budget(1_000);
+        sim.compute(&db, &db.circuit);
+        assert!(matches!(sim.status, SimulationStatus::Stable { .. }));
+
+        db.circuit.get_power_mut(enable).on = true;
+        sim.compute(&db, &db.circuit);
+
+        assert_eq!(sim.status, SimulationStatus::Unstable { max_reached: true });
+        assert_eq!(sim.last_events, 1_000);
+        assert!(sim.oscillating.contains(&not_output(nots[0])));
+        assert_eq!(sim.loops.len(), 1);
+        assert_eq!(sim.loops[0].kind, LoopKind::Ring);
+        assert!(
+            sim.instability()
+                .is_some_and(|why| why.contains("ring of 3 gates"))
+        );
+    }
+
+    #[test]
+    fn bus_gate_works_bitwise_through_join_and_split() {
+        let mut db = DB::default();
+        let join = db
+            .circuit
+            .new_splitter(Splitter::new(Pos2::ZERO, SplitterKind::Join));
+        let split = db
+            .circuit
+            .new_splitter(Splitter::new(Pos2::ZERO, SplitterKind::Split));
+        let not = new_not(&mut db);
+        db.circuit.get_gate_mut(not).width = 4;
+
+        let mut lamps = Vec::new();
+        for (bit, on) in [true, false, true, true].into_iter().enumerate() {
+            let bit = bit as u8;
+            let power = db.circuit.new_power(Power::new(Pos2::ZERO, on));
+            let lamp = db.circuit.new_lamp(Lamp::new(Pos2::ZERO));
+            db.circuit.connections.insert(Connection::new(
+                power_output(power),
+                joiner_input(join, bit),
+            ));
+            db.circuit.connections.insert(Connection::new(
+                splitter_output(split, bit),
+                lamp_input(lamp),
+            ));
+            lamps.push(lamp);
+        }
+        db.circuit
+            .connections
+            .insert(Connection::new(joiner_output(join), not_input(not)));
+        db.circuit
+            .connections
+            .insert(Connection::new(not_output(not), splitter_input(split)));
+
+        let mut sim = Simulator::new();
+        sim.compute(&db, &db.circuit);
+
+        assert_eq!(
+            sim.current.get(&not_output(not)),
+            Some(&BusValue::from_u64(4, 0b0010))
+        );
+        let lamp_values: Vec<Value> = lamps
+            .iter()
+            .map(|&lamp| sim.current[&lamp_input(lamp)].bit(0))
+            .collect();
+        assert_eq!(
+            lamp_values,
+            [Value::Zero, Value::One, Value::Zero, Value::Zero]
+        );
+    }
+
+    #[test]
+    fn join_keeps_its_bus_when_widened() {
+        let mut db = DB::default();
+        let join = db.circuit.new_splitter(Splitter {
+            width: 2,
+            ..Splitter::new(Pos2::ZERO, SplitterKind::Join)
+        });
+        let not = new_not(&mut db);
+        db.circuit.get_gate_mut(not).width = 2;
+        db.circuit
+            .connections
+            .insert(Connection::new(joiner_output(join), not_input(not)));
+
+        db.circuit.get_splitter_mut(join).width = 4;
+        db.circuit.get_gate_mut(not).width = 4;
+        for (bit, on) in [true, false, true, true].into_iter().enumerate() {
+            let power = db.circuit.new_power(Power::new(Pos2::ZERO, on));
+            db.circuit.connections.insert(Connection::new(
+                power_output(power),
+                joiner_input(join, bit as u8),
+            ));
+        }
+        assert!(db.circuit.pins_of(join, &db).contains(&joiner_output(join)));
+        assert_eq!(db.circuit.pin_width(joiner_output(join)), 4);
+
+        let mut sim = Simulator::new();
+        sim.compute(&db, &db.circuit);
+        assert_eq!(
+            sim.current.get(&not_output(not)),
+            Some(&BusValue::from_u64(4, 0b0010))
+        );
+    }
+
+    /// Two tri-state buffers sharing the input of a lamp, each fed by its own switch.
+    fn shared_lamp(db: &mut DB, drivers: [(bool, bool); 2]) -> (InstanceId, Vec<InstanceId>) {
+        let lamp = db.circuit.new_lamp(Lamp::new(Pos2::ZERO));
+        let mut enables = Vec::new();
+        for (data, enabled) in drivers {
+            let buffer = db
+                .circuit
+                .new_gate(Gate::new(Pos2::ZERO, GateKind::TriState));
+            let data = db.circuit.new_power(Power::new(Pos2::ZERO, data));
+            let enable = db.circuit.new_power(Power::new(Pos2::ZERO, enabled));
+            for (from, to) in [
+                (power_output(data), gate_inp1(buffer)),
+                (power_output(enable), gate_inp2(buffer)),
+                (gate_output(buffer), lamp_input(lamp)),
+            ] {
+                db.circuit.connections.insert(Connection::new(from, to));
+            }
+            enables.push(enable);
+        }
+        (lamp, enables)
+    }
+
+    #[test]
+    fn undriven_pin_floats() {
+        let mut db = DB::default();
+        let lamp = db.circuit.new_lamp(Lamp::new(Pos2::ZERO));
+        let not = new_not(&mut db);
+
+        let mut sim = Simulator::new();
+        sim.compute(&db, &db.circuit);
+
+        assert_eq!(sim.current.get(&lamp_input(lamp)), Some(&Value::Z.into()));
+        assert_eq!(sim.current.get(&not_output(not)), Some(&Value::X.into()));
+    }
+
+    #[test]
+    fn tri_state_resolution() {
+        let mut db = DB::default();
+        let (lamp, enables) = shared_lamp(&mut db, [(true, false), (false, false)]);
+        let mut sim = Simulator::new();
+
+        sim.compute(&db, &db.circuit);
+        assert_eq!(sim.current[&lamp_input(lamp)], Value::Z.into());
+
+        db.circuit.get_power_mut(enables[0]).on = true;
+        sim.compute(&db, &db.circuit);
+        assert_eq!(sim.current[&lamp_input(lamp)], Value::One.into());
+        assert!(sim.conflicts.is_empty());
+
+        db.circuit.get_power_mut(enables[1]).on = true;
+        sim.compute(&db, &db.circuit);
+        assert_eq!(sim.current[&lamp_input(lamp)], Value::X.into());
+        assert!(sim.conflicts.contains(&lamp_input(lamp)));
+
+        db.circuit.get_power_mut(enables[0]).on = false;
+        sim.compute(&db, &db.circuit);
+        assert_eq!(sim.current[&lamp_input(lamp)], Value::Zero.into());
+        assert!(sim.conflicts.is_empty());
+    }
+
+    /// Connect a switch to every listed flip flop input and return the switches in order.
+    fn drive_flip_flop(
+        db: &mut DB,
+        id: InstanceId,
+        kind: FlipFlopKind,
+        roles: &[FlipFlopPin],
+    ) -> Vec<InstanceId> {
+        roles
+            .iter()
+            .map(|&role| {
+                let power = db.circuit.new_power(Power::new(Pos2::ZERO, false));
+                db.circuit.connections.insert(Connection::new(
+                    power_output(power),
+                    flip_flop_pin(id, kind, role),
+                ));
+                power
+            })
+            .collect()
+    }
+
+    fn set_switch(db: &mut DB, sim: &mut Simulator, power: InstanceId, on: bool) -> Value {
+        db.circuit.get_power_mut(power).on = on;
+        sim.compute(db, &db.circuit);
+        sim.store_state(&mut db.circuit);
+        db.circuit
+            .flip_flops
+            .values()
+            .next()
+            .map(|f| f.state.q)
+            .unwrap_or(Value::X)
+    }
+
+    #[test]
+    fn d_flip_flop_samples_on_rising_edge() {
+        let mut db = DB::default();
+        let kind = FlipFlopKind::D;
+        let ff = db.circuit.new_flip_flop(FlipFlop::new(Pos2::ZERO, kind));
+        let switches = drive_flip_flop(
+            &mut db,
+            ff,
+            kind,
+            &[FlipFlopPin::D, FlipFlopPin::Clock, FlipFlopPin::Reset],
+        );
+        let (d, clk, reset) = (switches[0], switches[1], switches[2]);
+        let mut sim = Simulator::new();
+
+        assert_eq!(set_switch(&mut db, &mut sim, d, true), Value::Zero);
+        assert_eq!(set_switch(&mut db, &mut sim, clk, true), Value::One);
+        assert_eq!(set_switch(&mut db, &mut sim, d, false), Value::One);
+        assert_eq!(set_switch(&mut db, &mut sim, clk, false), Value::One);
+        assert_eq!(
+            sim.current[&flip_flop_pin(ff, kind, FlipFlopPin::NotQ)],
+            Value::Zero.into()
+        );
+        assert_eq!(set_switch(&mut db, &mut sim, reset, true), Value::Zero);
+    }
+
+    #[test]
+    fn jk_flip_flop_toggles_and_state_is_reloaded() {
+        let mut db = DB::default();
+        let kind = FlipFlopKind::Jk;
+        let ff = db.circuit.new_flip_flop(FlipFlop::new(Pos2::ZERO, kind));
+        let switches = drive_flip_flop(
+            &mut db,
+            ff,
+            kind,
+            &[FlipFlopPin::J, FlipFlopPin::K, FlipFlopPin::Clock],
+        );
+        let (j, k, clk) = (switches[0], switches[1], switches[2]);
+        let mut sim = Simulator::new();
+
+        set_switch(&mut db, &mut sim, j, true);
+        set_switch(&mut db, &mut sim, k, true);
+        assert_eq!(set_switch(&mut db, &mut sim, clk, true), Value::One);
+        set_switch(&mut db, &mut sim, clk, false);
+        assert_eq!(set_switch(&mut db, &mut sim, clk, true), Value::Zero);
+        set_switch(&mut db, &mut sim, clk, false);
+        assert_eq!(set_switch(&mut db, &mut sim, clk, true), Value::One);
+
+        // A fresh simulator continues from the state saved on the instance
+        let mut sim = Simulator::new();
+        assert_eq!(set_switch(&mut db, &mut sim, j, false), Value::One);
+        assert_eq!(
+            sim.current[&flip_flop_pin(ff, kind, FlipFlopPin::Q)],
+            Value::One.into()
+        );
+    }
+
+    #[test]
+    fn ram_writes_on_clock_edge_and_reads_asynchronously() {
+        let mut db = DB::default();
+        let mut memory = Memory::new(Pos2::ZERO, MemoryKind::Ram);
+        memory.resize(1, 1);
+        let ram = db.circuit.new_memory(memory);
+        let [address, data, write_enable, clock] = [
+            memory_address(ram),
+            memory_data_in(ram),
+            memory_write_enable(ram),
+            memory_clock(ram),
+        ]
+        .map(|pin| {
+            let power = db.circuit.new_power(Power::new(Pos2::ZERO, false));
+            db.circuit
+                .connections
+                .insert(Connection::new(power_output(power), pin));
+            power
+        });
+        let out = memory_output(ram, MemoryKind::Ram);
+        let mut sim = Simulator::new();
+        let mut set = |db: &mut DB, power: InstanceId, on: bool| {
+            db.circuit.get_power_mut(power).on = on;
+            sim.compute(db, &db.circuit);
+            sim.store_state(&mut db.circuit);
+            sim.current[&out]
+        };
+
+        set(&mut db, address, true);
+        set(&mut db, data, true);
+        // Write enable is off, the edge does not write
+        assert_eq!(set(&mut db, clock, true), Value::Zero.into());
+        set(&mut db, clock, false);
+        set(&mut db, write_enable, true);
+        assert_eq!(set(&mut db, clock, true), Value::One.into());
+        assert_eq!(set(&mut db, address, false), Value::Zero.into());
+        assert_eq!(db.circuit.get_memory(ram).contents, vec![0, 1]);
+    }
+
+    #[test]
+    fn clocks_follow_simulated_time() {
+        let mut db = DB::default();
+        // Two non overlapping phases and a T flip flop dividing the first one by two
+        let kind = FlipFlopKind::T;
+        let ff = db.circuit.new_flip_flop(FlipFlop::new(Pos2::ZERO, kind));
+        let toggle = drive_flip_flop(&mut db, ff, kind, &[FlipFlopPin::T])[0];
+        db.circuit.get_power_mut(toggle).on = true;
+        // The first phase starts high, that is not a rising edge
+        let phase_1 = db.circuit.new_clock(Clock {
+            period: 10,
+            duty_cycle: 40,
+            ..Clock::new(Pos2::ZERO)
+        });
+        let phase_2 = db.circuit.new_clock(Clock {
+            period: 10,
+            phase: 5,
+            duty_cycle: 40,
+            ..Clock::new(Pos2::ZERO)
+        });
+        db.circuit.connections.insert(Connection::new(
+            clock_output(phase_1),
+            flip_flop_pin(ff, kind, FlipFlopPin::Clock),
+        ));
+        let mut sim = Simulator::new();
+        sim.compute(&db, &db.circuit);
+
+        let mut samples = Vec::new();
+        for until in [2, 4, 6, 12, 22, 32] {
+            sim.advance_to(&db, &db.circuit, until);
+            assert_eq!(sim.time, until);
+            samples.push([
+                sim.current[&clock_output(phase_1)].bit(0),
+                sim.current[&clock_output(phase_2)].bit(0),
+                sim.current[&flip_flop_pin(ff, kind, FlipFlopPin::Q)].bit(0),
+            ]);
+        }
+        use Value::{One, Zero};
+        assert_eq!(
+            samples,
+            vec![
+                [One, Zero, Zero],
+                [Zero, Zero, Zero],
+                [Zero, One, Zero],
+                [One, Zero, One],
+                [One, Zero, Zero],
+                [One, Zero, One],
+            ]
+        );
+        assert_eq!(sim.next_clock_edge(&db.circuit), Some(34));
+    }
+}
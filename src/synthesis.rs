@@ -0,0 +1,603 @@
+//! Circuits synthesized from boolean expressions or truth tables.
+//!
+//! Every output becomes a two level circuit from its minimal sum of products, see
+//! [`crate::boolean::minimize`]: the sum made of NOT, AND and OR gates, the same sum as NAND gates
+//! feeding a NAND, or the minimal product of sums as NOR gates feeding a NOR. The
+//! NAND and NOR forms only use two input gates and make inverters from a gate with both inputs
+//! tied. The gates are written as a structural Verilog netlist and placed by the importer.
+
+use std::collections::HashSet;
+use std::fmt::Write as _;
+
+use egui::{Color32, Ui, Vec2};
+
+use crate::App;
+use crate::app::GRID_SIZE;
+use crate::boolean::{MAX_INPUTS, Output, Term, TruthFunction, minimize};
+use crate::db::{Circuit, DB, InstanceId};
+use crate::module::ModuleDefinition;
+use crate::verilog::KEYWORDS;
+use crate::verilog_import;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Form {
+    SumOfProducts,
+    NandOnly,
+    NorOnly,
+}
+
+impl Form {
+    pub const ALL: [Self; 3] = [Self::SumOfProducts, Self::NandOnly, Self::NorOnly];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::SumOfProducts => "Sum of products",
+            Self::NandOnly => "NAND only",
+            Self::NorOnly => "NOR only",
+        }
+    }
+}
+
+/// Structural Verilog module `name` computing `function` in `form`
+pub fn verilog(function: &TruthFunction, form: Form, name: &str) -> String {
+    let mut netlist = Netlist {
+        gates: String::new(),
+        used: function
+            .inputs
+            .iter()
+            .chain(function.outputs.iter().map(|o| &o.name))
+            .cloned()
+            .collect(),
+        inverted: vec![None; function.inputs.len()],
+    };
+    for output in &function.outputs {
+        netlist.output(function, output, form);
+    }
+
+    let ports: Vec<String> = function
+        .inputs
+        .iter()
+        .chain(function.outputs.iter().map(|o| &o.name))
+        .map(|name| identifier(name))
+        .collect();
+    let mut text = format!("module {}({});\n", identifier(name), ports.join(", "));
+    for input in &function.inputs {
+        writeln!(text, "  input {};", identifier(input)).ok();
+    }
+    for output in &function.outputs {
+        writeln!(text, "  output {};", identifier(&output.name)).ok();
+    }
+    text.push_str(&netlist.gates);
+    text.push_str("endmodule\n");
+    text
+}
+
+/// Circuit computing `function`, or with `definition` set a circuit with only the module
+/// definition of that name
+pub fn synthesize(
+    function: &TruthFunction,
+    form: Form,
+    definition: Option<&str>,
+) -> Result<DB, String> {
+    function.check_names()?;
+    let Some(name) = definition else {
+        return verilog_import::import(&verilog(function, form, "synthesized"));
+    };
+
+    // The importer keeps every module as a definition, the top one included
+    let mut db = verilog_import::import(&verilog(function, form, name))?;
+    db.circuit = Circuit::default();
+    for (_, module) in &mut db.module_definitions {
+        module.name = name.to_owned();
+    }
+    Ok(db)
+}
+
+/// `name` written so Verilog reads it as an identifier even when it is a keyword
+fn identifier(name: &str) -> String {
+    if KEYWORDS.contains(&name) {
+        format!("\\{name} ")
+    } else {
+        name.to_owned()
+    }
+}
+
+struct Netlist {
+    gates: String,
+    used: HashSet<String>,
+    /// Net holding the complement of each input once it is needed
+    inverted: Vec<Option<String>>,
+}
+
+impl Netlist {
+    /// A net name that is not used yet
+    fn fresh(&mut self, base: &str) -> String {
+        let mut n = self.used.len();
+        loop {
+            let name = format!("{base}{n}");
+            if self.used.insert(name.clone()) {
+                return name;
+            }
+            n += 1;
+        }
+    }
+
+    /// Gate `kind` reading `inputs`, driving `out` or a new net
+    fn gate(&mut self, kind: &str, inputs: &[String], out: Option<&str>) -> String {
+        let out = out.map_or_else(|| self.fresh("n"), str::to_owned);
+        let pins: Vec<String> = std::iter::once(&out)
+            .chain(inputs)
+            .map(|net| identifier(net))
+            .collect();
+        writeln!(self.gates, "  {kind} ({});", pins.join(", ")).ok();
+        out
+    }
+
+    /// `out` set to `net`, or `net` itself when no output is asked for
+    fn alias(&mut self, net: String, out: Option<&str>) -> String {
+        match out {
+            Some(out) => {
+                writeln!(
+                    self.gates,
+                    "  assign {} = {};",
+                    identifier(out),
+                    identifier(&net)
+                )
+                .ok();
+                out.to_owned()
+            }
+            None => net,
+        }
+    }
+
+    /// `kind` is the two input inverting gate the whole form is made of
+    fn invert(&mut self, kind: &str, net: &str, out: Option<&str>) -> String {
+        match kind {
+            "not" => self.gate(kind, &[net.to_owned()], out),
+            _ => self.gate(kind, &[net.to_owned(), net.to_owned()], out),
+        }
+    }
+
+    /// NAND or NOR of all `nets` from two input gates
+    fn tree(&mut self, kind: &str, nets: &[String], out: Option<&str>) -> String {
+        match nets {
+            [net] => self.invert(kind, net, out),
+            [a, b] => self.gate(kind, &[a.clone(), b.clone()], out),
+            [rest @ .., last] => {
+                let inner = self.tree(kind, rest, None);
+                let inner = self.invert(kind, &inner, None);
+                self.gate(kind, &[inner, last.clone()], out)
+            }
+            [] => unreachable!("gates always have an input"),
+        }
+    }
+
+    /// Net of `input` or its complement made with an inverter of `kind`
+    fn literal(
+        &mut self,
+        function: &TruthFunction,
+        (input, value): (usize, bool),
+        kind: &str,
+    ) -> String {
+        let name = &function.inputs[input];
+        if value {
+            return name.clone();
+        }
+        if let Some(net) = &self.inverted[input] {
+            return net.clone();
+        }
+        let base = format!("{name}_n");
+        let net = if self.used.insert(base.clone()) {
+            base
+        } else {
+            self.fresh(&base)
+        };
+        self.invert(kind, name, Some(&net));
+        self.inverted[input] = Some(net.clone());
+        net
+    }
+
+    fn output(&mut self, function: &TruthFunction, output: &Output, form: Form) {
+        let name = output.name.as_str();
+        let inputs = function.inputs.len();
+        if !output.values.contains(&Some(true)) {
+            self.alias("1'b0".to_owned(), Some(name));
+            return;
+        }
+        if !output.values.contains(&Some(false)) {
+            self.alias("1'b1".to_owned(), Some(name));
+            return;
+        }
+        match form {
+            Form::SumOfProducts => {
+                let terms = minimize(&output.values, inputs);
+                // A single product drives the output itself
+                let out = (terms.len() == 1).then_some(name);
+                let products: Vec<String> = terms
+                    .iter()
+                    .map(|term| {
+                        let literals = self.literals(function, term, "not");
+                        match &literals[..] {
+                            [literal] => self.alias(literal.clone(), out),
+                            _ => self.gate("and", &literals, out),
+                        }
+                    })
+                    .collect();
+                if products.len() > 1 {
+                    self.gate("or", &products, Some(name));
+                }
+            }
+            Form::NandOnly => {
+                // a | b is !(!a & !b), so NANDs of the products feed a NAND
+                let terms = minimize(&output.values, inputs);
+                let products: Vec<String> = terms
+                    .iter()
+                    .map(|term| {
+                        let literals = self.literals(function, term, "nand");
+                        self.tree("nand", &literals, None)
+                    })
+                    .collect();
+                self.tree("nand", &products, Some(name));
+            }
+            Form::NorOnly => {
+                // The products covering the false rows, complemented, are the clauses of the sums
+                let complement: Vec<Option<bool>> =
+                    output.values.iter().map(|v| v.map(|v| !v)).collect();
+                let terms = minimize(&complement, inputs);
+                let sums: Vec<String> = terms
+                    .iter()
+                    .map(|term| {
+                        let clause: Term = term.iter().map(|&(i, v)| (i, !v)).collect();
+                        let literals = self.literals(function, &clause, "nor");
+                        self.tree("nor", &literals, None)
+                    })
+                    .collect();
+                self.tree("nor", &sums, Some(name));
+            }
+        }
+    }
+
+    fn literals(&mut self, function: &TruthFunction, term: &Term, kind: &str) -> Vec<String> {
+        term.iter()
+            .map(|&literal| self.literal(function, literal, kind))
+            .collect()
+    }
+}
+
+/// State of the synthesis window
+pub struct SynthesisView {
+    pub from_table: bool,
+    /// One `name = expression` per line
+    pub expressions: String,
+    /// Comma separated names of the table columns
+    pub inputs: String,
+    pub outputs: String,
+    /// Values of the table, by output and row
+    pub table: Vec<Vec<Option<bool>>>,
+    pub form: Form,
+    pub as_module: bool,
+    pub module_name: String,
+    pub error: Option<String>,
+}
+
+impl Default for SynthesisView {
+    fn default() -> Self {
+        Self {
+            from_table: false,
+            expressions: "y = (a & b) | !c".to_owned(),
+            inputs: "a, b".to_owned(),
+            outputs: "y".to_owned(),
+            table: Vec::new(),
+            form: Form::SumOfProducts,
+            as_module: false,
+            module_name: "Synthesized".to_owned(),
+            error: None,
+        }
+    }
+}
+
+impl SynthesisView {
+    fn names(text: &str) -> Vec<String> {
+        text.split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_owned)
+            .collect()
+    }
+
+    /// Function described by the window
+    pub fn function(&self) -> Result<TruthFunction, String> {
+        if !self.from_table {
+            return TruthFunction::from_expressions(&self.expressions);
+        }
+        let inputs = Self::names(&self.inputs);
+        let names = Self::names(&self.outputs);
+        if inputs.len() > MAX_INPUTS {
+            return Err(format!("at most {MAX_INPUTS} inputs are supported"));
+        }
+        if names.is_empty() {
+            return Err("the table needs an output".to_owned());
+        }
+        let rows = 1 << inputs.len();
+        let outputs = names
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| Output {
+                name,
+                values: (0..rows)
+                    .map(|row| {
+                        self.table
+                            .get(i)
+                            .and_then(|values| values.get(row).copied())
+                            .unwrap_or(Some(false))
+                    })
+                    .collect(),
+            })
+            .collect();
+        let function = TruthFunction { inputs, outputs };
+        function.check_names()?;
+        Ok(function)
+    }
+
+    /// Size the table to the named columns, keeping the values already entered
+    fn fit_table(&mut self) {
+        let inputs = Self::names(&self.inputs).len().min(MAX_INPUTS);
+        let outputs = Self::names(&self.outputs).len();
+        self.table.resize_with(outputs, Vec::new);
+        for values in &mut self.table {
+            values.resize(1 << inputs, Some(false));
+        }
+    }
+
+    fn draw_table(&mut self, ui: &mut Ui) {
+        self.fit_table();
+        let inputs = Self::names(&self.inputs);
+        let outputs = Self::names(&self.outputs);
+        egui::ScrollArea::both().max_height(300.0).show(ui, |ui| {
+            egui::Grid::new("synthesis_table")
+                .striped(true)
+                .spacing([16.0, 4.0])
+                .show(ui, |ui| {
+                    for name in &inputs {
+                        ui.strong(name);
+                    }
+                    for name in &outputs {
+                        ui.strong(name).highlight();
+                    }
+                    ui.end_row();
+                    for row in 0..1usize << inputs.len().min(MAX_INPUTS) {
+                        for input in 0..inputs.len().min(MAX_INPUTS) {
+                            ui.monospace(format!("{}", row >> (inputs.len() - 1 - input) & 1));
+                        }
+                        for values in &mut self.table {
+                            let value = &mut values[row];
+                            let text = match value {
+                                Some(false) => "0",
+                                Some(true) => "1",
+                                None => "x",
+                            };
+                            let button = egui::Button::new(egui::RichText::new(text).monospace())
+                                .fill(Color32::TRANSPARENT);
+                            if ui
+                                .add(button)
+                                .on_hover_text("Click to cycle 0, 1 and don't care")
+                                .clicked()
+                            {
+                                *value = match value {
+                                    Some(false) => Some(true),
+                                    Some(true) => None,
+                                    None => Some(false),
+                                };
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+        });
+    }
+}
+
+impl App {
+    /// Add the circuit of the synthesis window to the canvas or as a module definition
+    fn place_synthesized(&mut self) -> Result<(), String> {
+        let Some(view) = &self.synthesis else {
+            return Ok(());
+        };
+        let function = view.function()?;
+        if view.as_module {
+            let name = view.module_name.trim();
+            if name.is_empty() {
+                return Err("the module needs a name".to_owned());
+            }
+            let db = synthesize(&function, view.form, Some(name))?;
+            self.checkpoint("Synthesize module");
+            for (_, definition) in db.module_definitions {
+                self.db.module_definitions.insert(definition);
+            }
+            return Ok(());
+        }
+
+        let db = synthesize(&function, view.form, None)?;
+        self.checkpoint("Synthesize circuit");
+        let definition = ModuleDefinition {
+            name: String::new(),
+            circuit: db.circuit,
+        };
+        let copies = definition.copy_members_into(&mut self.db);
+        for (&member, &copy) in &copies {
+            if let Some(name) = definition.circuit.name(member) {
+                self.db.circuit.set_name(copy, name);
+            }
+        }
+        let ids: Vec<InstanceId> = copies.into_values().collect();
+        let delta = (self.viewport_offset / GRID_SIZE).round() * GRID_SIZE
+            + Vec2::new(10.0 * GRID_SIZE, 10.0 * GRID_SIZE);
+        self.db.move_nonwires_and_resize_wires(&ids, delta);
+        self.connection_manager
+            .rebuild_spatial_index(&self.db.circuit, &self.db);
+        self.selected = ids.into_iter().collect();
+        self.current_dirty = true;
+        Ok(())
+    }
+
+    pub fn draw_synthesis(&mut self, ctx: &egui::Context) {
+        let Some(view) = &mut self.synthesis else {
+            return;
+        };
+        let mut open = true;
+        let mut place = false;
+        egui::Window::new("Synthesize")
+            .open(&mut open)
+            .resizable(true)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut view.from_table, false, "Expressions");
+                    ui.selectable_value(&mut view.from_table, true, "Truth table");
+                });
+                ui.separator();
+                if view.from_table {
+                    egui::Grid::new("synthesis_columns").show(ui, |ui| {
+                        ui.label("Inputs:");
+                        ui.text_edit_singleline(&mut view.inputs);
+                        ui.end_row();
+                        ui.label("Outputs:");
+                        ui.text_edit_singleline(&mut view.outputs);
+                        ui.end_row();
+                    });
+                    view.draw_table(ui);
+                } else {
+                    ui.label("One output per line, like sum = a ^ b");
+                    ui.add(
+                        egui::TextEdit::multiline(&mut view.expressions)
+                            .code_editor()
+                            .desired_rows(4),
+                    );
+                    ui.small("! not, & and, ^ xor, | or");
+                }
+                ui.separator();
+                egui::ComboBox::from_label("Form")
+                    .selected_text(view.form.label())
+                    .show_ui(ui, |ui| {
+                        for form in Form::ALL {
+                            ui.selectable_value(&mut view.form, form, form.label());
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut view.as_module, "As module");
+                    ui.add_enabled(
+                        view.as_module,
+                        egui::TextEdit::singleline(&mut view.module_name),
+                    );
+                });
+                if let Some(error) = &view.error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+                if ui.button("Generate").clicked() {
+                    place = true;
+                }
+            });
+        if !open {
+            self.synthesis = None;
+            return;
+        }
+        if place {
+            let result = self.place_synthesized();
+            if let Some(view) = &mut self.synthesis {
+                view.error = result.err();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Form, synthesize, verilog};
+    use crate::boolean::TruthFunction;
+    use crate::builder::Simulation;
+    use crate::db::InstanceKind;
+    use crate::simulator::Value;
+    use crate::truth_table::TruthTable;
+
+    #[test]
+    fn forms_use_the_minimal_terms() {
+        let function = TruthFunction::from_expressions("y = (a & b) | !c").expect("parses");
+        let gates = |form| {
+            let text = verilog(&function, form, "f");
+            let mut gates: Vec<String> = text
+                .lines()
+                .filter(|line| line.starts_with("  ") && line.ends_with(");"))
+                .filter_map(|line| line.split_whitespace().next().map(str::to_owned))
+                .filter(|kind| kind != "f")
+                .collect();
+            gates.sort();
+            gates
+        };
+        // a & b and !c, instead of one product per true row
+        assert_eq!(gates(Form::SumOfProducts), ["and", "not", "or"]);
+        assert_eq!(gates(Form::NandOnly), ["nand"; 4]);
+        // (a | !c) & (b | !c)
+        assert_eq!(gates(Form::NorOnly), ["nor"; 4]);
+    }
+
+    #[test]
+    fn module_holds_only_the_function() -> Result<(), String> {
+        let function = TruthFunction::from_expressions("y = a & b")?;
+        let db = synthesize(&function, Form::NandOnly, Some("myand"))?;
+        assert!(db.circuit.types.is_empty());
+        let definitions: Vec<_> = db.module_definitions.iter().collect();
+        let [(id, definition)] = definitions[..] else {
+            panic!("{} definitions instead of one", definitions.len());
+        };
+        assert_eq!(definition.name, "myand");
+        let table = TruthTable::for_definition(&db, id)?;
+        let outputs: Vec<Option<u64>> = table
+            .rows
+            .iter()
+            .map(|row| row.outputs[0].to_u64())
+            .collect();
+        assert_eq!(outputs, [Some(0), Some(0), Some(0), Some(1)]);
+        Ok(())
+    }
+
+    #[test]
+    fn every_form_computes_the_function() -> Result<(), String> {
+        let function = TruthFunction::from_expressions("y = (a & b) | !c\nz = a ^ c")?;
+        for form in Form::ALL {
+            let db = synthesize(&function, form, None)?;
+            if form != Form::SumOfProducts {
+                let gate = if form == Form::NandOnly {
+                    "Nand"
+                } else {
+                    "Nor"
+                };
+                for (id, _) in &db.circuit.gates {
+                    let InstanceKind::Gate(kind) = db.circuit.ty(id) else {
+                        unreachable!("gates are gates");
+                    };
+                    assert_eq!(format!("{kind:?}"), gate);
+                }
+            }
+            let mut sim = Simulation::new(db);
+            for row in 0..function.rows() {
+                for (i, input) in function.inputs.iter().enumerate() {
+                    sim.set(input, function.input_bit(row, i))?;
+                }
+                sim.settle()?;
+                for output in &function.outputs {
+                    let expected = if output.values[row] == Some(true) {
+                        Value::One
+                    } else {
+                        Value::Zero
+                    };
+                    assert_eq!(
+                        sim.value(&output.name)?,
+                        expected,
+                        "{form:?} {} in row {row}",
+                        output.name
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,231 @@
+//! Check a circuit against a table of test vectors.
+//!
+//! The first line that is not empty or a `#` comment names the columns. A column named after an
+//! input (a named power source) is applied, every other column is read back and compared, so it
+//! can name an output or any pin as `instance.pin`. Each following line is one vector: inputs
+//! are set, the circuit is settled and the outputs are checked. Values are separated by commas
+//! or whitespace and written as decimal, `0x` hex or `0b` binary. `x` or `-` in an output column
+//! means the value is not checked.
+//!
+//! ```text
+//! # half adder
+//! a, b, sum, carry
+//! 0, 0, 0,   0
+//! 0, 1, 1,   0
+//! 1, 1, 0,   1
+//! ```
+
+use std::fmt::Write as _;
+
+use crate::{builder::Simulation, db::InstanceKind, simulator::BusValue};
+
+/// Outcome of one vector
+pub struct VectorResult {
+    /// Line of the vector in the test file, starting at 1
+    pub line: usize,
+    pub inputs: Vec<bool>,
+    /// `None` for values that are not checked
+    pub expected: Vec<Option<u64>>,
+    pub actual: Vec<BusValue>,
+    /// Set when the circuit did not settle within the event budget
+    pub error: Option<String>,
+}
+
+impl VectorResult {
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && (0..self.expected.len()).all(|i| self.output_matches(i))
+    }
+
+    fn output_matches(&self, i: usize) -> bool {
+        self.expected[i].is_none_or(|expected| self.actual[i].to_u64() == Some(expected))
+    }
+}
+
+pub struct Report {
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub vectors: Vec<VectorResult>,
+}
+
+impl Report {
+    pub fn failures(&self) -> usize {
+        self.vectors.iter().filter(|v| !v.passed()).count()
+    }
+
+    /// Table of the failing vectors, a mismatching output shows what it was and what was expected
+    pub fn diff_table(&self) -> String {
+        let header: Vec<String> = std::iter::once("line".to_owned())
+            .chain(self.inputs.iter().cloned())
+            .chain(self.outputs.iter().cloned())
+            .collect();
+        let mut rows = vec![header];
+        for vector in self.vectors.iter().filter(|v| !v.passed()) {
+            let mut row = vec![vector.line.to_string()];
+            row.extend(vector.inputs.iter().map(|&on| u8::from(on).to_string()));
+            for (i, actual) in vector.actual.iter().enumerate() {
+                let cell = match vector.expected[i] {
+                    Some(expected) if !vector.output_matches(i) => {
+                        let expected = BusValue::from_u64(actual.width(), expected);
+                        format!("{actual} (want {expected})")
+                    }
+                    _ => actual.to_string(),
+                };
+                row.push(cell);
+            }
+            if let Some(error) = &vector.error {
+                row.push(error.clone());
+            }
+            rows.push(row);
+        }
+
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        let widths: Vec<usize> = (0..columns)
+            .map(|c| {
+                rows.iter()
+                    .filter_map(|row| row.get(c))
+                    .map(|cell| cell.chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let mut out = String::new();
+        for row in &rows {
+            let line: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, &width)| format!("{cell:<width$}"))
+                .collect();
+            writeln!(out, "{}", line.join("  ").trim_end()).ok();
+        }
+        out
+    }
+}
+
+/// Apply every vector in `text` to the circuit in order.
+pub fn run(sim: &mut Simulation, text: &str) -> Result<Report, String> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.split('#').next().unwrap_or_default().trim()))
+        .filter(|(_, line)| !line.is_empty());
+
+    let Some((_, header)) = lines.next() else {
+        return Err("test file has no header line".to_owned());
+    };
+    let mut inputs = Vec::new();
+    let mut outputs = Vec::new();
+    // Column order as (is input, index in inputs or outputs)
+    let mut columns = Vec::new();
+    for name in fields(header) {
+        let is_input = sim
+            .id(name)
+            .is_ok_and(|id| matches!(sim.db.circuit.ty(id), InstanceKind::Power));
+        if is_input {
+            columns.push((true, inputs.len()));
+            inputs.push(name.to_owned());
+        } else {
+            // Fail early on names that do not resolve to a pin
+            sim.read(name)?;
+            columns.push((false, outputs.len()));
+            outputs.push(name.to_owned());
+        }
+    }
+
+    let mut vectors = Vec::new();
+    for (line, text) in lines {
+        let values: Vec<&str> = fields(text).collect();
+        if values.len() != columns.len() {
+            return Err(format!(
+                "line {line}: expected {} values, found {}",
+                columns.len(),
+                values.len()
+            ));
+        }
+        let mut vector = VectorResult {
+            line,
+            inputs: vec![false; inputs.len()],
+            expected: vec![None; outputs.len()],
+            actual: Vec::new(),
+            error: None,
+        };
+        for (&(is_input, index), value) in columns.iter().zip(values) {
+            if is_input {
+                vector.inputs[index] = match parse_value(value) {
+                    Some(0) => false,
+                    Some(1) => true,
+                    _ => return Err(format!("line {line}: input value {value:?} is not 0 or 1")),
+                };
+            } else if !matches!(value, "x" | "X" | "-") {
+                vector.expected[index] = Some(
+                    parse_value(value)
+                        .ok_or_else(|| format!("line {line}: bad value {value:?}"))?,
+                );
+            }
+        }
+
+        for (name, &on) in inputs.iter().zip(&vector.inputs) {
+            sim.set(name, on)?;
+        }
+        vector.error = sim.settle().err();
+        vector.actual = outputs
+            .iter()
+            .map(|name| sim.read(name))
+            .collect::<Result<_, _>>()?;
+        vectors.push(vector);
+    }
+
+    Ok(Report {
+        inputs,
+        outputs,
+        vectors,
+    })
+}
+
+fn fields(line: &str) -> impl Iterator<Item = &str> {
+    line.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|field| !field.is_empty())
+}
+
+fn parse_value(value: &str) -> Option<u64> {
+    if let Some(hex) = value.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = value.strip_prefix("0b") {
+        u64::from_str_radix(binary, 2).ok()
+    } else {
+        value.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run;
+    use crate::builder::CircuitBuilder;
+    use crate::db::GateKind;
+
+    #[test]
+    fn vectors_report_mismatches() -> Result<(), String> {
+        let mut b = CircuitBuilder::new();
+        b.input("a")?;
+        b.input("b")?;
+        b.gate("g", GateKind::Or)?;
+        b.output("y")?;
+        b.connect("a", "g.a")?;
+        b.connect("b", "g.b")?;
+        b.connect("g", "y")?;
+        let mut sim = b.build();
+
+        // The last vector expects AND instead of OR
+        let text = "# or gate\na b y g.out\n0 0 0 x\n1,0,1,1\n0 1 0 -\n";
+        let report = run(&mut sim, text)?;
+        assert_eq!(report.vectors.len(), 3);
+        assert_eq!(report.failures(), 1);
+        assert_eq!(
+            report.diff_table(),
+            "line  a  b  y           g.out\n5     0  1  1 (want 0)  1\n"
+        );
+
+        assert!(run(&mut sim, "a y\n1\n").is_err());
+        assert!(run(&mut sim, "a nope\n1 1\n").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,356 @@
+//! Truth tables of a selection or a module definition.
+//!
+//! Pins the instances leave unconnected are the inputs and outputs of the table. For a selection,
+//! powers are inputs and lamps are outputs as well, so a whole drawn circuit can be selected.
+//! Powers inside a module definition stay constants. Every combination of input values is applied
+//! in counting order, with the first input as the most significant bit, and the outputs are read
+//! once the circuit has settled. Circuits with state see the rows in that order.
+
+use std::collections::HashSet;
+use std::fmt::Write as _;
+
+use egui::{Color32, Ui};
+
+use crate::App;
+use crate::assets::PinKind;
+use crate::builder::Simulation;
+use crate::connection_manager::Connection;
+use crate::db::{
+    Circuit, DB, InstanceId, InstanceKind, ModuleDefId, Pin, Power, Splitter, SplitterKind,
+};
+use crate::module::{ModuleDefinition, definition_from_selection};
+use crate::simulator::{BusValue, joiner_input, joiner_output, power_output};
+
+/// Largest number of input bits, the table has 2^n rows
+pub const MAX_INPUT_BITS: u32 = 12;
+
+pub struct Column {
+    pub name: String,
+    pub width: u8,
+}
+
+pub struct Row {
+    pub inputs: Vec<u64>,
+    pub outputs: Vec<BusValue>,
+    /// False when the circuit did not settle for these inputs
+    pub settled: bool,
+}
+
+pub struct TruthTable {
+    pub title: String,
+    pub inputs: Vec<Column>,
+    pub outputs: Vec<Column>,
+    pub rows: Vec<Row>,
+}
+
+/// Power sources that set an input, one per bit starting at the least significant one
+struct Drive {
+    column: Column,
+    bits: Vec<InstanceId>,
+}
+
+impl TruthTable {
+    /// Table of the selected instances. A single module instance gives the table of its
+    /// definition.
+    pub fn for_selection(db: &DB, instances: &HashSet<InstanceId>) -> Result<Self, String> {
+        if let [id] = instances.iter().copied().collect::<Vec<_>>()[..]
+            && let InstanceKind::Module(definition) = db.circuit.ty(id)
+        {
+            return Self::for_definition(db, definition);
+        }
+        let definition = definition_from_selection(db, "selection".to_owned(), instances)?;
+        generate(db, &definition, true)
+    }
+
+    pub fn for_definition(db: &DB, id: ModuleDefId) -> Result<Self, String> {
+        generate(db, db.get_module_def(id), false)
+    }
+
+    fn header(&self) -> Vec<String> {
+        self.inputs
+            .iter()
+            .chain(&self.outputs)
+            .map(|column| column.name.clone())
+            .collect()
+    }
+
+    /// Text of every cell, inputs first
+    pub fn cells(&self, row: &Row) -> Vec<String> {
+        let inputs = self
+            .inputs
+            .iter()
+            .zip(&row.inputs)
+            .map(|(column, &value)| BusValue::from_u64(column.width, value).to_string());
+        inputs
+            .chain(row.outputs.iter().map(BusValue::to_string))
+            .collect()
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut out = String::new();
+        let header: Vec<String> = self.header().iter().map(|name| csv_field(name)).collect();
+        writeln!(out, "{}", header.join(",")).ok();
+        for row in &self.rows {
+            writeln!(out, "{}", self.cells(row).join(",")).ok();
+        }
+        out
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        let header: Vec<String> = self
+            .header()
+            .iter()
+            .map(|name| name.replace('|', "\\|"))
+            .collect();
+        writeln!(out, "| {} |", header.join(" | ")).ok();
+        let rule: Vec<&str> = header.iter().map(|_| "---").collect();
+        writeln!(out, "| {} |", rule.join(" | ")).ok();
+        for row in &self.rows {
+            writeln!(out, "| {} |", self.cells(row).join(" | ")).ok();
+        }
+        out
+    }
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_owned()
+    }
+}
+
+/// Simulate a copy of `definition` for every input combination
+fn generate(db: &DB, definition: &ModuleDefinition, switches: bool) -> Result<TruthTable, String> {
+    let mut harness = DB {
+        circuit: Circuit::default(),
+        module_definitions: db.module_definitions.clone(),
+    };
+    let copies = definition.copy_members_into(&mut harness);
+    let circuit = &definition.circuit;
+
+    let mut drives = Vec::new();
+    let mut outputs: Vec<(Column, Pin)> = Vec::new();
+    for port in definition.ports(db, switches) {
+        let copy = copies[&port.pin.ins];
+        let world = Pin::new(copy, port.pin.index, port.pin.kind);
+        let width = harness.circuit.pin_width(world);
+        let column = Column {
+            name: port.name,
+            width,
+        };
+        match port.kind {
+            // A power standing for an input is switched itself
+            PinKind::Input if matches!(circuit.ty(port.pin.ins), InstanceKind::Power) => {
+                drives.push(Drive {
+                    column,
+                    bits: vec![copy],
+                });
+            }
+            PinKind::Input => {
+                let bits = drive(&mut harness.circuit, world, width);
+                drives.push(Drive { column, bits });
+            }
+            PinKind::Output => outputs.push((column, world)),
+        }
+    }
+
+    if outputs.is_empty() {
+        return Err(
+            "there are no outputs, leave an output pin unconnected or add a lamp".to_owned(),
+        );
+    }
+    let input_bits: u32 = drives.iter().map(|d| u32::from(d.column.width)).sum();
+    if input_bits > MAX_INPUT_BITS {
+        return Err(format!(
+            "{input_bits} input bits are too many, at most {MAX_INPUT_BITS} are supported"
+        ));
+    }
+
+    let mut sim = Simulation::new(harness);
+    let mut rows = Vec::new();
+    for combination in 0..1_u64 << input_bits {
+        // The first input takes the most significant bits
+        let mut shift = input_bits;
+        let mut inputs = Vec::new();
+        for drive in &drives {
+            shift -= u32::from(drive.column.width);
+            let value = (combination >> shift) & ((1 << drive.column.width) - 1);
+            for (bit, &power) in drive.bits.iter().enumerate() {
+                sim.db.circuit.get_power_mut(power).on = value >> bit & 1 == 1;
+            }
+            inputs.push(value);
+        }
+        let settled = sim.settle().is_ok();
+        let outputs = outputs
+            .iter()
+            .map(|&(_, pin)| sim.simulator.pin_value(&sim.db.circuit, pin))
+            .collect();
+        rows.push(Row {
+            inputs,
+            outputs,
+            settled,
+        });
+    }
+
+    Ok(TruthTable {
+        title: definition.name.clone(),
+        inputs: drives.into_iter().map(|d| d.column).collect(),
+        outputs: outputs.into_iter().map(|(column, _)| column).collect(),
+        rows,
+    })
+}
+
+/// Power sources driving `pin`, joined into a bus for wide pins
+fn drive(circuit: &mut Circuit, pin: Pin, width: u8) -> Vec<InstanceId> {
+    let pos = egui::Pos2::ZERO;
+    if width == 1 {
+        let power = circuit.new_power(Power::new(pos, false));
+        circuit
+            .connections
+            .insert(Connection::new(power_output(power), pin));
+        return vec![power];
+    }
+    let joiner = circuit.new_splitter(Splitter {
+        width,
+        ..Splitter::new(pos, SplitterKind::Join)
+    });
+    circuit
+        .connections
+        .insert(Connection::new(joiner_output(joiner), pin));
+    (0..width)
+        .map(|bit| {
+            let power = circuit.new_power(Power::new(pos, false));
+            circuit.connections.insert(Connection::new(
+                power_output(power),
+                joiner_input(joiner, bit),
+            ));
+            power
+        })
+        .collect()
+}
+
+impl App {
+    pub fn truth_table_of_selection(&mut self) {
+        match TruthTable::for_selection(&self.db, &self.selected) {
+            Ok(table) => self.truth_table = Some(table),
+            Err(e) => log::error!("Cannot make a truth table: {e}"),
+        }
+    }
+
+    pub fn truth_table_of_definition(&mut self, id: ModuleDefId) {
+        match TruthTable::for_definition(&self.db, id) {
+            Ok(table) => self.truth_table = Some(table),
+            Err(e) => log::error!("Cannot make a truth table: {e}"),
+        }
+    }
+
+    pub fn draw_truth_table(&mut self, ctx: &egui::Context) {
+        let Some(table) = &self.truth_table else {
+            return;
+        };
+        let mut open = true;
+        let mut export = None;
+        egui::Window::new(format!("Truth table of {}", table.title))
+            .open(&mut open)
+            .resizable(true)
+            .default_height(400.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("Export CSV").clicked() {
+                        export = Some(false);
+                    }
+                    if ui.button("Export Markdown").clicked() {
+                        export = Some(true);
+                    }
+                    if ui.button("Copy Markdown").clicked() {
+                        ui.ctx().copy_text(table.to_markdown());
+                    }
+                });
+                let unsettled = table.rows.iter().filter(|row| !row.settled).count();
+                if unsettled > 0 {
+                    ui.colored_label(
+                        ui.visuals().warn_fg_color,
+                        format!("{unsettled} rows did not settle, they are shown in red"),
+                    );
+                }
+                ui.separator();
+                draw_table(ui, table);
+            });
+        if !open {
+            self.truth_table = None;
+        }
+        let result = match export {
+            Some(false) => self.export_truth_table_csv(),
+            Some(true) => self.export_truth_table_markdown(),
+            None => Ok(()),
+        };
+        if let Err(e) = result {
+            log::error!("Failed to export truth table: {e}");
+        }
+    }
+}
+
+fn draw_table(ui: &mut Ui, table: &TruthTable) {
+    egui::ScrollArea::both().show(ui, |ui| {
+        egui::Grid::new("truth_table")
+            .striped(true)
+            .spacing([16.0, 4.0])
+            .show(ui, |ui| {
+                for column in &table.inputs {
+                    ui.strong(&column.name);
+                }
+                for column in &table.outputs {
+                    ui.strong(&column.name).highlight();
+                }
+                ui.end_row();
+                for row in &table.rows {
+                    for (i, cell) in table.cells(row).into_iter().enumerate() {
+                        if !row.settled && i >= table.inputs.len() {
+                            ui.colored_label(Color32::RED, cell);
+                        } else {
+                            ui.monospace(cell);
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::TruthTable;
+    use crate::builder::CircuitBuilder;
+    use crate::db::GateKind;
+
+    #[test]
+    fn half_adder_table() -> Result<(), String> {
+        let mut b = CircuitBuilder::new();
+        b.input("a")?;
+        b.input("b")?;
+        b.gate("x", GateKind::Xor)?;
+        b.gate("c", GateKind::And)?;
+        b.output("sum")?;
+        b.connect("a", "x.a")?;
+        b.connect("b", "x.b")?;
+        b.connect("a", "c.a")?;
+        b.connect("b", "c.b")?;
+        b.connect("x", "sum")?;
+        let sim = b.build();
+
+        let selection: HashSet<_> = sim.db.circuit.types.keys().collect();
+        let table = TruthTable::for_selection(&sim.db, &selection)?;
+        // Columns follow the placement, top to bottom then left to right
+        assert_eq!(
+            table.to_markdown(),
+            "| a | b | c.out | sum |\n| --- | --- | --- | --- |\n| 0 | 0 | 0 | 0 |\n\
+             | 0 | 1 | 0 | 1 |\n| 1 | 0 | 0 | 1 |\n| 1 | 1 | 1 | 0 |\n"
+        );
+        assert!(table.to_csv().starts_with("a,b,c.out,sum\n0,0,0,0\n"));
+        Ok(())
+    }
+}
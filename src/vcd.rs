@@ -0,0 +1,291 @@
+//! Export of recorded waveforms as a Value Change Dump (IEEE 1364), readable by `GTKWave` and most
+//! HDL simulators.
+//!
+//! Every module instance becomes a scope nested in the scope of the module that owns it, with
+//! `top` at the root. Signals are named after the instance name, a label placed next to the
+//! instance or else the instance kind and id, followed by the pin name when the instance has more
+//! than one pin.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+
+use crate::builder::pin_names;
+use crate::config::CanvasConfig;
+use crate::db::{DB, InstanceId, InstanceKind, Pin};
+use crate::simulator::BusValue;
+use crate::verilog::kind_name;
+use crate::waveform::Recorder;
+
+/// Labels closer than this to a pin name its signal
+pub const LABEL_NAME_DISTANCE: f32 = 60.0;
+
+/// Scope tree of the dump, keyed by module instance. `None` is the top level.
+#[derive(Default)]
+struct Scope {
+    /// Index into the traces with the signal name
+    signals: Vec<(usize, String)>,
+    children: Vec<InstanceId>,
+}
+
+/// Render the traces of `recorder` as VCD, ending at `end_time`.
+pub fn export(db: &DB, canvas_config: &CanvasConfig, recorder: &Recorder, end_time: u64) -> String {
+    let circuit = &db.circuit;
+    let mut scopes: HashMap<Option<InstanceId>, Scope> = HashMap::new();
+    scopes.entry(None).or_default();
+
+    for (index, trace) in recorder.traces.iter().enumerate() {
+        let owner = db.get_module_owner(trace.pin.ins);
+        let name = signal_name(db, canvas_config, trace.pin);
+        let signals = &mut scopes.entry(owner).or_default().signals;
+        let mut unique = name.clone();
+        let mut n = 2;
+        while signals.iter().any(|(_, existing)| *existing == unique) {
+            unique = format!("{name}_{n}");
+            n += 1;
+        }
+        signals.push((index, unique));
+
+        // Make sure every module on the way up to the top has a scope
+        let mut child = owner;
+        while let Some(module) = child {
+            let parent = db.get_module_owner(module);
+            let children = &mut scopes.entry(parent).or_default().children;
+            if children.contains(&module) {
+                break;
+            }
+            children.push(module);
+            child = parent;
+        }
+    }
+
+    let mut out = String::new();
+    writeln!(out, "$version Simu {} $end", env!("CARGO_PKG_VERSION")).ok();
+    writeln!(out, "$comment one time unit is one gate delay $end").ok();
+    writeln!(out, "$timescale 1ns $end").ok();
+    write_scope(&mut out, db, &scopes, None, recorder);
+    writeln!(out, "$enddefinitions $end").ok();
+
+    // Changes of all traces merged in time order
+    let mut changes: BTreeMap<u64, Vec<(usize, BusValue)>> = BTreeMap::new();
+    for (index, trace) in recorder.traces.iter().enumerate() {
+        for &(time, value) in &trace.changes {
+            changes.entry(time).or_default().push((index, value));
+        }
+    }
+    let start = changes.keys().next().copied().unwrap_or(0);
+    writeln!(out, "#{start}").ok();
+    writeln!(out, "$dumpvars").ok();
+    for (index, trace) in recorder.traces.iter().enumerate() {
+        let initial = trace.value_at(start);
+        let width = initial.map_or_else(|| circuit.pin_width(trace.pin), BusValue::width);
+        writeln!(out, "{}", format_value(initial, width, &identifier(index))).ok();
+    }
+    writeln!(out, "$end").ok();
+    for (&time, values) in changes.range(start + 1..) {
+        writeln!(out, "#{time}").ok();
+        for &(index, value) in values {
+            writeln!(
+                out,
+                "{}",
+                format_value(Some(value), value.width(), &identifier(index))
+            )
+            .ok();
+        }
+    }
+    if end_time > changes.keys().next_back().copied().unwrap_or(start) {
+        writeln!(out, "#{end_time}").ok();
+    }
+    out
+}
+
+fn write_scope(
+    out: &mut String,
+    db: &DB,
+    scopes: &HashMap<Option<InstanceId>, Scope>,
+    module: Option<InstanceId>,
+    recorder: &Recorder,
+) {
+    let name = match module {
+        Some(id) => db.circuit.name(id).map_or_else(
+            || format!("{}_{id}", db.circuit.get_module(id).name(db)),
+            str::to_owned,
+        ),
+        None => "top".to_owned(),
+    };
+    writeln!(out, "$scope module {} $end", sanitize(&name)).ok();
+    if let Some(scope) = scopes.get(&module) {
+        for (index, name) in &scope.signals {
+            let width = db.circuit.pin_width(recorder.traces[*index].pin);
+            let range = if width > 1 {
+                format!(" [{}:0]", width - 1)
+            } else {
+                String::new()
+            };
+            writeln!(
+                out,
+                "$var wire {width} {} {}{range} $end",
+                identifier(*index),
+                sanitize(name)
+            )
+            .ok();
+        }
+        for &child in &scope.children {
+            write_scope(out, db, scopes, Some(child), recorder);
+        }
+    }
+    writeln!(out, "$upscope $end").ok();
+}
+
+fn signal_name(db: &DB, canvas_config: &CanvasConfig, pin: Pin) -> String {
+    let circuit = &db.circuit;
+    let pins = pin_names(db, pin.ins);
+    let base = circuit
+        .name(pin.ins)
+        .map(str::to_owned)
+        .or_else(|| nearest_label(db, canvas_config, pin))
+        .unwrap_or_else(|| format!("{}_{}", kind_name(db, circuit.ty(pin.ins)), pin.ins));
+    // Wires carry one signal even though they have two pins
+    if pins.len() <= 1 || matches!(circuit.ty(pin.ins), InstanceKind::Wire) {
+        return base;
+    }
+    match pins.iter().find(|(_, p)| *p == pin) {
+        Some((pin_name, _)) => format!("{base}.{pin_name}"),
+        None => format!("{base}.{}", pin.index),
+    }
+}
+
+/// Text of the label closest to `pin`, for instances shown on the canvas
+fn nearest_label(db: &DB, canvas_config: &CanvasConfig, pin: Pin) -> Option<String> {
+    if db.is_hidden(pin.ins) {
+        return None;
+    }
+    let pos = db.circuit.pin_position(pin, canvas_config, db);
+    db.circuit
+        .labels
+        .values()
+        .filter(|label| !label.text.trim().is_empty())
+        .map(|label| (label.pos.distance(pos), label))
+        .filter(|&(distance, _)| distance <= LABEL_NAME_DISTANCE)
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, label)| label.text.trim().to_owned())
+}
+
+/// VCD identifiers use the printable characters `!` to `~`
+fn identifier(mut index: usize) -> String {
+    const FIRST: u8 = b'!';
+    const COUNT: usize = (b'~' - b'!' + 1) as usize;
+    let mut id = String::new();
+    loop {
+        id.push(char::from(FIRST + (index % COUNT) as u8));
+        index /= COUNT;
+        if index == 0 {
+            return id;
+        }
+        index -= 1;
+    }
+}
+
+fn format_value(value: Option<BusValue>, width: u8, id: &str) -> String {
+    let bits = value.map_or_else(|| "x".repeat(usize::from(width)), |v| v.to_string());
+    let bits = bits.to_lowercase();
+    if width == 1 {
+        format!("{bits}{id}")
+    } else {
+        format!("b{bits} {id}")
+    }
+}
+
+/// Keep names to characters every VCD reader accepts. Readers take a `.` as a hierarchy
+/// separator, so it becomes `_` and the hierarchy only comes from the scopes.
+fn sanitize(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '[' | ']') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.is_empty() {
+        "_".to_owned()
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{export, identifier};
+    use crate::builder::CircuitBuilder;
+    use crate::config::CanvasConfig;
+    use crate::db::GateKind;
+    use crate::verilog;
+
+    #[test]
+    fn identifiers_are_unique() {
+        assert_eq!(identifier(0), "!");
+        assert_eq!(identifier(93), "~");
+        assert_eq!(identifier(94), "!!");
+        assert_eq!(identifier(95), "\"!");
+    }
+
+    #[test]
+    fn dump_of_an_inverted_clock() -> Result<(), String> {
+        let mut b = CircuitBuilder::new();
+        b.clock("clk", 10, 0)?;
+        b.gate("inv", GateKind::Not)?;
+        b.connect("clk", "inv.in")?;
+        let mut sim = b.build();
+        sim.probe("clk")?;
+        sim.probe("inv.out")?;
+        sim.run_until(10)?;
+
+        let vcd = export(
+            &sim.db,
+            &CanvasConfig::default(),
+            &sim.simulator.recorder,
+            sim.time(),
+        );
+        let body = vcd
+            .split_once("$enddefinitions $end\n")
+            .map(|(header, body)| {
+                assert!(header.contains("$scope module top $end"), "{header}");
+                assert!(header.contains("$var wire 1 ! clk $end"), "{header}");
+                assert!(header.contains("$var wire 1 \" inv_out $end"), "{header}");
+                body
+            })
+            .ok_or("no definitions")?;
+        assert_eq!(
+            body,
+            "#0\n$dumpvars\n1!\nz\"\n$end\n#1\n0\"\n#5\n0!\n#6\n1\"\n#10\n1!\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unnamed_probes_match_the_verilog_instance() -> Result<(), String> {
+        let mut b = CircuitBuilder::new();
+        b.input("a")?;
+        b.gate("inv", GateKind::Not)?;
+        b.connect("a", "inv.in")?;
+        let mut sim = b.build();
+        sim.probe("inv.out")?;
+        sim.run_until(1)?;
+        let inv = sim.id("inv")?;
+        sim.db.circuit.set_name(inv, "");
+
+        let vcd = export(
+            &sim.db,
+            &CanvasConfig::default(),
+            &sim.simulator.recorder,
+            sim.time(),
+        );
+        let base = format!("not_{inv}");
+        assert!(vcd.contains(&format!(" {base}_out $end")), "{vcd}");
+        let verilog = verilog::export(&sim.db);
+        assert!(verilog.contains(&format!(" {base}(")), "{verilog}");
+        Ok(())
+    }
+}
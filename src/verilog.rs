@@ -0,0 +1,726 @@
+//! Export of circuits as structural Verilog.
+//!
+//! The top level circuit becomes module `top` and every module definition becomes a Verilog
+//! module of its own. In `top`, powers are input ports, lamps are output ports and clocks are
+//! clock inputs. A module definition has one port `p<n>` per pin its instances leave
+//! unconnected, in the order of `ModuleDefinition::get_unconnected_internal_pins`, so the port
+//! numbers match the pins of the module on the canvas. Powers inside a definition are constants
+//! and clocks inside a definition are passed in as extra clock ports.
+//!
+//! Gates map to Verilog primitives, using arrays of instances for buses. Flip flops, splitters and
+//! memories are written as the small behavioural blocks synthesis tools infer them from. Pins
+//! that are not connected end up on a net nothing drives, which floats like in the simulator.
+//! Tunnels only join the tunnels of the same Verilog module: a tunnel that reaches into a module
+//! instance in the simulator has to be wired to a pin of the module to be exported.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+
+use crate::assets::PinKind;
+use crate::db::{
+    Circuit, DB, FlipFlopKind, FlipFlopPin, GateKind, InstanceId, InstanceKind, MemoryKind,
+    ModuleDefId, Pin, SplitterKind,
+};
+use crate::net::{NetId, Netlist};
+use crate::simulator::{
+    Value, clock_output, flip_flop_pin, gate_inp1, gate_inp2, gate_output, joiner_input,
+    joiner_output, lamp_input, memory_address, memory_clock, memory_data_in, memory_output,
+    memory_write_enable, power_output, splitter_input, splitter_output,
+};
+
+pub(crate) const KEYWORDS: &[&str] = &[
+    "always",
+    "and",
+    "assign",
+    "begin",
+    "buf",
+    "bufif0",
+    "bufif1",
+    "case",
+    "default",
+    "else",
+    "end",
+    "endcase",
+    "endmodule",
+    "for",
+    "if",
+    "initial",
+    "inout",
+    "input",
+    "integer",
+    "module",
+    "nand",
+    "negedge",
+    "nor",
+    "not",
+    "or",
+    "output",
+    "posedge",
+    "reg",
+    "wire",
+    "xnor",
+    "xor",
+];
+
+/// Lowercase name of an instance kind, which unnamed instances are called after in the Verilog
+/// and VCD exports.
+pub(crate) fn kind_name(db: &DB, kind: InstanceKind) -> String {
+    let name = match kind {
+        InstanceKind::Gate(kind) => format!("{kind:?}"),
+        InstanceKind::Power => "power".to_owned(),
+        InstanceKind::Wire => "wire".to_owned(),
+        InstanceKind::Lamp => "lamp".to_owned(),
+        InstanceKind::Clock => "clock".to_owned(),
+        InstanceKind::Module(definition_id) => db.get_module_def(definition_id).name.clone(),
+        InstanceKind::Splitter(kind) => format!("{kind:?}"),
+        InstanceKind::FlipFlop(kind) => format!("{kind:?}"),
+        InstanceKind::Memory(kind) => format!("{kind:?}"),
+        InstanceKind::Tunnel => "tunnel".to_owned(),
+    };
+    name.to_lowercase()
+}
+
+/// Verilog for the top level circuit followed by every module definition it may use.
+pub fn export(db: &DB) -> String {
+    let mut module_names = Names::default();
+    module_names.take("top");
+    let definition_names: HashMap<ModuleDefId, String> = db
+        .module_definitions
+        .iter()
+        .map(|(id, definition)| (id, module_names.fresh(&definition.name)))
+        .collect();
+
+    // Children first, so the clock ports they need are known when their parents are written
+    let mut order = Vec::new();
+    for (id, _) in &db.module_definitions {
+        visit_definition(db, id, &mut order);
+    }
+
+    let mut clock_ports = HashMap::new();
+    let mut out = String::new();
+    for id in order {
+        let module = Exporter::new(db, &definition_names, Some(id)).write(&mut clock_ports);
+        out.push_str(&module);
+        out.push('\n');
+    }
+    let top = Exporter::new(db, &definition_names, None).write(&mut clock_ports);
+    out.push_str(&top);
+    out
+}
+
+fn visit_definition(db: &DB, id: ModuleDefId, order: &mut Vec<ModuleDefId>) {
+    if order.contains(&id) {
+        return;
+    }
+    for ty in db.get_module_def(id).circuit.types.values() {
+        if let InstanceKind::Module(child) = *ty {
+            visit_definition(db, child, order);
+        }
+    }
+    order.push(id);
+}
+
+/// Identifiers already used in one Verilog scope
+#[derive(Default)]
+struct Names {
+    used: HashSet<String>,
+}
+
+impl Names {
+    fn take(&mut self, name: &str) {
+        self.used.insert(name.to_owned());
+    }
+
+    /// A valid identifier based on `name` that is not used yet
+    fn fresh(&mut self, name: &str) -> String {
+        let mut base: String = name
+            .trim()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        if !base.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            base.insert(0, '_');
+        }
+        if KEYWORDS.contains(&base.as_str()) {
+            base.push('_');
+        }
+        let mut name = base.clone();
+        let mut n = 1;
+        while self.used.contains(&name) {
+            name = format!("{base}_{n}");
+            n += 1;
+        }
+        self.used.insert(name.clone());
+        name
+    }
+}
+
+struct Port {
+    name: String,
+    kind: PinKind,
+    width: u8,
+    source: PortSource,
+}
+
+enum PortSource {
+    Pin(Pin),
+    /// Clock port of a child module instance, passed through to it
+    ChildClock(InstanceId, String),
+}
+
+/// Writes one Verilog module for the top level circuit or a module definition
+struct Exporter<'a> {
+    db: &'a DB,
+    circuit: &'a Circuit,
+    definition: Option<ModuleDefId>,
+    definition_names: &'a HashMap<ModuleDefId, String>,
+    instances: Vec<InstanceId>,
+    /// Nets between the pins of `instances`
+    nets: Netlist,
+    net_names: HashMap<NetId, String>,
+    net_widths: HashMap<NetId, u8>,
+    names: Names,
+    ports: Vec<Port>,
+    declarations: String,
+    body: String,
+}
+
+impl<'a> Exporter<'a> {
+    fn new(
+        db: &'a DB,
+        definition_names: &'a HashMap<ModuleDefId, String>,
+        definition: Option<ModuleDefId>,
+    ) -> Self {
+        let circuit = match definition {
+            Some(id) => &db.get_module_def(id).circuit,
+            None => &db.circuit,
+        };
+        // Instances inside modules are exported with their module definition
+        let instances = circuit
+            .types
+            .keys()
+            .filter(|&id| definition.is_some() || !db.is_hidden(id))
+            .collect();
+        Self {
+            db,
+            circuit,
+            definition,
+            definition_names,
+            instances,
+            nets: Netlist::default(),
+            net_names: HashMap::new(),
+            net_widths: HashMap::new(),
+            names: Names::default(),
+            ports: Vec::new(),
+            declarations: String::new(),
+            body: String::new(),
+        }
+    }
+
+    /// Render the module. `clock_ports` collects the clock ports of every definition written so
+    /// far and gains the ones of this module.
+    fn write(mut self, clock_ports: &mut HashMap<ModuleDefId, Vec<String>>) -> String {
+        self.build_nets();
+        self.collect_ports(clock_ports);
+        self.name_nets();
+
+        for id in self.instances.clone() {
+            self.write_instance(id);
+        }
+
+        let name = self
+            .definition
+            .map_or("top", |id| self.definition_names[&id].as_str());
+        let mut out = String::new();
+        let port_names: Vec<&str> = self.ports.iter().map(|p| p.name.as_str()).collect();
+        writeln!(out, "module {name}({});", port_names.join(", ")).ok();
+        for port in &self.ports {
+            let direction = match port.kind {
+                PinKind::Input => "input",
+                PinKind::Output => "output",
+            };
+            writeln!(out, "    {direction} {}{};", range(port.width), port.name).ok();
+        }
+        out.push_str(&self.declarations);
+        out.push_str(&self.body);
+        writeln!(out, "endmodule").ok();
+        out
+    }
+
+    /// Pins of an instance with their width. Module instances use the ports of their definition.
+    fn instance_pins(&self, id: InstanceId) -> Vec<(Pin, u8)> {
+        if let InstanceKind::Module(definition_id) = self.circuit.ty(id) {
+            let definition = self.db.get_module_def(definition_id);
+            return definition
+                .get_unconnected_internal_pins(self.db)
+                .into_iter()
+                .enumerate()
+                .map(|(i, internal)| {
+                    let pin = Pin::new(id, i as u32, internal.kind);
+                    (pin, definition.circuit.pin_width(internal))
+                })
+                .collect();
+        }
+        self.circuit
+            .pins_of(id, self.db)
+            .into_iter()
+            .map(|pin| (pin, self.circuit.pin_width(pin)))
+            .collect()
+    }
+
+    fn find(&self, pin: Pin) -> NetId {
+        self.nets
+            .net_of(pin)
+            .expect("pins of the exported instances are on a net")
+    }
+
+    /// A net is as wide as the widest pin on it
+    fn build_nets(&mut self) {
+        self.nets = Netlist::of_instances(self.db, self.circuit, &self.instances);
+        for id in self.instances.clone() {
+            for (pin, width) in self.instance_pins(id) {
+                let net = self.find(pin);
+                let widest = self.net_widths.entry(net).or_default();
+                *widest = (*widest).max(width);
+            }
+        }
+    }
+
+    fn collect_ports(&mut self, clock_ports: &mut HashMap<ModuleDefId, Vec<String>>) {
+        if let Some(definition_id) = self.definition {
+            let definition = self.db.get_module_def(definition_id);
+            for (i, pin) in definition
+                .get_unconnected_internal_pins(self.db)
+                .into_iter()
+                .enumerate()
+            {
+                let name = self.names.fresh(&format!("p{i}"));
+                self.ports.push(Port {
+                    name,
+                    kind: pin.kind,
+                    width: self.circuit.pin_width(pin),
+                    source: PortSource::Pin(pin),
+                });
+            }
+        }
+
+        for id in self.instances.clone() {
+            let (kind, pin, base) = match self.circuit.ty(id) {
+                InstanceKind::Power if self.definition.is_none() => {
+                    (PinKind::Input, power_output(id), "in")
+                }
+                InstanceKind::Lamp if self.definition.is_none() => {
+                    (PinKind::Output, lamp_input(id), "out")
+                }
+                InstanceKind::Clock => (PinKind::Input, clock_output(id), "clk"),
+                _ => continue,
+            };
+            let name = self.names.fresh(self.circuit.name(id).unwrap_or(base));
+            self.ports.push(Port {
+                name,
+                kind,
+                width: 1,
+                source: PortSource::Pin(pin),
+            });
+        }
+
+        // Clocks of child modules come in through clock ports of this module
+        for id in self.instances.clone() {
+            let InstanceKind::Module(definition_id) = self.circuit.ty(id) else {
+                continue;
+            };
+            for clock in clock_ports.get(&definition_id).cloned().unwrap_or_default() {
+                let name = self
+                    .names
+                    .fresh(&format!("{}_{clock}", self.instance_name(id)));
+                self.ports.push(Port {
+                    name,
+                    kind: PinKind::Input,
+                    width: 1,
+                    source: PortSource::ChildClock(id, clock),
+                });
+            }
+        }
+
+        if let Some(definition_id) = self.definition {
+            let own_clocks = self
+                .ports
+                .iter()
+                .filter(|port| match port.source {
+                    PortSource::Pin(pin) => {
+                        port.kind == PinKind::Input
+                            && matches!(self.circuit.ty(pin.ins), InstanceKind::Clock)
+                    }
+                    PortSource::ChildClock(..) => true,
+                })
+                .map(|port| port.name.clone())
+                .collect();
+            clock_ports.insert(definition_id, own_clocks);
+        }
+    }
+
+    /// Nets take the name of a port on them, other nets are called `n<k>`
+    fn name_nets(&mut self) {
+        let mut assigns = Vec::new();
+        for port in &self.ports {
+            let PortSource::Pin(pin) = port.source else {
+                continue;
+            };
+            let net = self.find(pin);
+            match self.net_names.get(&net) {
+                Some(existing) => assigns.push(match port.kind {
+                    PinKind::Input => format!("    assign {existing} = {};", port.name),
+                    PinKind::Output => format!("    assign {} = {existing};", port.name),
+                }),
+                None => {
+                    self.net_names.insert(net, port.name.clone());
+                }
+            }
+        }
+
+        let mut count = 0;
+        for net in 0..self.nets.nets.len() {
+            if self.net_names.contains_key(&net) {
+                continue;
+            }
+            let name = self.names.fresh(&format!("n{count}"));
+            count += 1;
+            writeln!(
+                self.declarations,
+                "    wire {}{name};",
+                range(self.net_widths[&net])
+            )
+            .ok();
+            self.net_names.insert(net, name);
+        }
+        for assign in assigns {
+            writeln!(self.body, "{assign}").ok();
+        }
+    }
+
+    fn net(&self, pin: Pin) -> &str {
+        &self.net_names[&self.find(pin)]
+    }
+
+    fn instance_name(&self, id: InstanceId) -> String {
+        let base = kind_name(self.db, self.circuit.ty(id));
+        match self.circuit.name(id) {
+            Some(name) => format!("{name}_{base}"),
+            None => format!("{base}_{id}"),
+        }
+    }
+
+    fn write_instance(&mut self, id: InstanceId) {
+        let name = self.names.fresh(&self.instance_name(id));
+        let body = match self.circuit.ty(id) {
+            InstanceKind::Gate(kind) => self.gate(id, kind, &name),
+            InstanceKind::Power if self.definition.is_some() => {
+                let on = u8::from(self.circuit.get_power(id).on);
+                format!("    assign {} = 1'b{on};\n", self.net(power_output(id)))
+            }
+            // Ports, or only wiring
+            InstanceKind::Power
+            | InstanceKind::Lamp
+            | InstanceKind::Clock
+            | InstanceKind::Wire
+            | InstanceKind::Tunnel => String::new(),
+            InstanceKind::Splitter(kind) => self.splitter(id, kind),
+            InstanceKind::FlipFlop(kind) => self.flip_flop(id, kind, &name),
+            InstanceKind::Memory(kind) => self.memory(id, kind, &name),
+            InstanceKind::Module(definition_id) => {
+                let mut connections: Vec<String> = self
+                    .instance_pins(id)
+                    .into_iter()
+                    .map(|(pin, _)| format!(".p{}({})", pin.index, self.net(pin)))
+                    .collect();
+                for port in &self.ports {
+                    if let PortSource::ChildClock(child, clock) = &port.source
+                        && *child == id
+                    {
+                        connections.push(format!(".{clock}({})", port.name));
+                    }
+                }
+                format!(
+                    "    {} {name}({});\n",
+                    self.definition_names[&definition_id],
+                    connections.join(", ")
+                )
+            }
+        };
+        self.body.push_str(&body);
+    }
+
+    fn gate(&self, id: InstanceId, kind: GateKind, name: &str) -> String {
+        let width = self.circuit.get_gate(id).width;
+        let array = if width > 1 {
+            format!(" [{}:0]", width - 1)
+        } else {
+            String::new()
+        };
+        let (primitive, inputs) = match kind {
+            GateKind::And => ("and", vec![gate_inp1(id), gate_inp2(id)]),
+            GateKind::Nand => ("nand", vec![gate_inp1(id), gate_inp2(id)]),
+            GateKind::Or => ("or", vec![gate_inp1(id), gate_inp2(id)]),
+            GateKind::Nor => ("nor", vec![gate_inp1(id), gate_inp2(id)]),
+            GateKind::Xor => ("xor", vec![gate_inp1(id), gate_inp2(id)]),
+            GateKind::Xnor => ("xnor", vec![gate_inp1(id), gate_inp2(id)]),
+            GateKind::TriState => ("bufif1", vec![gate_inp1(id), gate_inp2(id)]),
+            GateKind::Not => ("not", vec![gate_inp1(id)]),
+        };
+        let output = match kind {
+            GateKind::Not => Pin::new(id, 1, PinKind::Output),
+            _ => gate_output(id),
+        };
+        let terminals: Vec<&str> = std::iter::once(output)
+            .chain(inputs)
+            .map(|pin| self.net(pin))
+            .collect();
+        format!("    {primitive} {name}{array}({});\n", terminals.join(", "))
+    }
+
+    fn splitter(&self, id: InstanceId, kind: SplitterKind) -> String {
+        let width = self.circuit.get_splitter(id).width;
+        let mut out = String::new();
+        match kind {
+            SplitterKind::Split => {
+                let bus = self.net(splitter_input(id));
+                for bit in 0..width {
+                    let target = self.net(splitter_output(id, bit));
+                    writeln!(out, "    assign {target} = {bus}[{bit}];").ok();
+                }
+            }
+            SplitterKind::Join => {
+                let bits: Vec<&str> = (0..width)
+                    .rev()
+                    .map(|bit| self.net(joiner_input(id, bit)))
+                    .collect();
+                let target = self.net(joiner_output(id));
+                writeln!(out, "    assign {target} = {{{}}};", bits.join(", ")).ok();
+            }
+        }
+        out
+    }
+
+    /// Whether anything drives the net of `pin`, an input port or an output of an instance
+    fn is_driven(&self, pin: Pin) -> bool {
+        let net = self.find(pin);
+        let from_port = self.ports.iter().any(|port| {
+            port.kind == PinKind::Input
+                && matches!(port.source, PortSource::Pin(source) if self.find(source) == net)
+        });
+        from_port || !self.nets.nets[net].drivers.is_empty()
+    }
+
+    /// Set, reset and the latch enable are inactive when nothing drives them, like in the
+    /// simulator. Their branches are left out then.
+    fn flip_flop(&mut self, id: InstanceId, kind: FlipFlopKind, name: &str) -> String {
+        let q = self.names.fresh(&format!("{name}_q"));
+        let flip_flop = self.circuit.get_flip_flop(id);
+        let pin = |role| self.net(flip_flop_pin(id, kind, role));
+        let control = |role| {
+            let control = flip_flop_pin(id, kind, role);
+            self.is_driven(control).then(|| self.net(control))
+        };
+        let init = match flip_flop.state.q {
+            Value::Zero => " = 1'b0",
+            Value::One => " = 1'b1",
+            Value::X | Value::Z => "",
+        };
+
+        let latch = matches!(kind, FlipFlopKind::SrLatch | FlipFlopKind::DLatch);
+        let assign = if latch { "=" } else { "<=" };
+        let (set, reset) = match kind {
+            FlipFlopKind::DLatch => (None, None),
+            _ => (control(FlipFlopPin::Set), control(FlipFlopPin::Reset)),
+        };
+        let mut branches: Vec<String> = reset
+            .map(|reset| format!("if ({reset}) {q} {assign} 1'b0;"))
+            .into_iter()
+            .chain(set.map(|set| format!("if ({set}) {q} {assign} 1'b1;")))
+            .collect();
+        let events = match kind {
+            FlipFlopKind::SrLatch => "*".to_owned(),
+            FlipFlopKind::DLatch => {
+                if let Some(enable) = control(FlipFlopPin::Enable) {
+                    branches.push(format!("if ({enable}) {q} = {};", pin(FlipFlopPin::D)));
+                }
+                "*".to_owned()
+            }
+            FlipFlopKind::D | FlipFlopKind::Jk | FlipFlopKind::T => {
+                branches.push(match kind {
+                    FlipFlopKind::D => format!("{q} <= {};", pin(FlipFlopPin::D)),
+                    FlipFlopKind::Jk => format!(
+                        "case ({{{}, {}}}) 2'b01: {q} <= 1'b0; 2'b10: {q} <= 1'b1; \
+                         2'b11: {q} <= ~{q}; default: ; endcase",
+                        pin(FlipFlopPin::J),
+                        pin(FlipFlopPin::K)
+                    ),
+                    _ => format!("if ({}) {q} <= ~{q};", pin(FlipFlopPin::T)),
+                });
+                let edges: Vec<String> = std::iter::once(pin(FlipFlopPin::Clock))
+                    .chain(set)
+                    .chain(reset)
+                    .map(|net| format!("posedge {net}"))
+                    .collect();
+                format!("({})", edges.join(" or "))
+            }
+        };
+
+        let mut out = String::new();
+        writeln!(out, "    reg {q}{init};").ok();
+        if !branches.is_empty() {
+            writeln!(out, "    always @{events}").ok();
+            for (i, branch) in branches.iter().enumerate() {
+                let otherwise = if i == 0 { "" } else { "else " };
+                writeln!(out, "        {otherwise}{branch}").ok();
+            }
+        }
+        writeln!(out, "    assign {} = {q};", pin(FlipFlopPin::Q)).ok();
+        writeln!(out, "    assign {} = ~{q};", pin(FlipFlopPin::NotQ)).ok();
+        out
+    }
+
+    fn memory(&mut self, id: InstanceId, kind: MemoryKind, name: &str) -> String {
+        let words = self.names.fresh(&format!("{name}_words"));
+        let i = self.names.fresh(&format!("{name}_i"));
+        let memory = self.circuit.get_memory(id);
+        let (data_width, size) = (memory.data_width, memory.contents.len());
+        let digits = usize::from(data_width.div_ceil(4));
+
+        // Clear everything, then set the words that are not zero
+        let mut out = String::new();
+        writeln!(
+            out,
+            "    reg {}{words} [0:{}];",
+            range(data_width),
+            size.max(1) - 1
+        )
+        .ok();
+        writeln!(out, "    integer {i};").ok();
+        writeln!(out, "    initial begin").ok();
+        writeln!(
+            out,
+            "        for ({i} = 0; {i} < {size}; {i} = {i} + 1) {words}[{i}] = {data_width}'h0;"
+        )
+        .ok();
+        for (address, word) in memory.contents.iter().enumerate() {
+            if *word != 0 {
+                writeln!(
+                    out,
+                    "        {words}[{address}] = {data_width}'h{word:0digits$X};"
+                )
+                .ok();
+            }
+        }
+        writeln!(out, "    end").ok();
+
+        let address = self.net(memory_address(id));
+        if kind == MemoryKind::Ram {
+            writeln!(out, "    always @(posedge {})", self.net(memory_clock(id))).ok();
+            writeln!(
+                out,
+                "        if ({}) {words}[{address}] <= {};",
+                self.net(memory_write_enable(id)),
+                self.net(memory_data_in(id))
+            )
+            .ok();
+        }
+        writeln!(
+            out,
+            "    assign {} = {words}[{address}];",
+            self.net(memory_output(id, kind))
+        )
+        .ok();
+        out
+    }
+}
+
+fn range(width: u8) -> String {
+    if width > 1 {
+        format!("[{}:0] ", width - 1)
+    } else {
+        String::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::export;
+    use crate::builder::CircuitBuilder;
+    use crate::db::{FlipFlopKind, GateKind};
+
+    #[test]
+    fn half_adder_and_register() -> Result<(), String> {
+        let mut b = CircuitBuilder::new();
+        b.input("a")?;
+        b.input("b")?;
+        b.clock("clk", 10, 0)?;
+        b.gate("x", GateKind::Xor)?;
+        b.gate("c", GateKind::And)?;
+        b.flip_flop("r", FlipFlopKind::D)?;
+        b.output("sum")?;
+        b.output("carry")?;
+        b.connect("a", "x.a")?;
+        b.connect("b", "x.b")?;
+        b.connect("a", "c.a")?;
+        b.connect("b", "c.b")?;
+        b.connect("x", "sum")?;
+        b.connect("c", "r.d")?;
+        b.connect("clk", "r.clk")?;
+        b.connect("r.q", "carry")?;
+        let sim = b.build();
+
+        let verilog = export(&sim.db);
+        for line in [
+            "module top(a, b, clk, sum, carry);",
+            "    input a;",
+            "    output carry;",
+            "    xor x_xor",
+            "    assign carry = r_d_q;",
+            "endmodule",
+        ] {
+            assert!(verilog.contains(line), "missing {line:?} in\n{verilog}");
+        }
+        assert!(verilog.contains("(sum, a, b);"), "{verilog}");
+        // Set and reset are open, so the register only follows the clock
+        assert_eq!(
+            flip_flop_block(&verilog),
+            "    always @(posedge clk)\n        r_d_q <= n0;\n"
+        );
+        Ok(())
+    }
+
+    /// Lines from the `always` of the first flip flop up to its output
+    fn flip_flop_block(verilog: &str) -> String {
+        verilog
+            .lines()
+            .skip_while(|line| !line.trim_start().starts_with("always"))
+            .take_while(|line| !line.trim_start().starts_with("assign"))
+            .map(|line| format!("{line}\n"))
+            .collect()
+    }
+
+    #[test]
+    fn flip_flop_with_reset_only() -> Result<(), String> {
+        let mut b = CircuitBuilder::new();
+        b.input("clr")?;
+        b.input("t")?;
+        b.clock("clk", 10, 0)?;
+        b.flip_flop("r", FlipFlopKind::T)?;
+        b.output("q")?;
+        b.connect("clr", "r.r")?;
+        b.connect("t", "r.t")?;
+        b.connect("clk", "r.clk")?;
+        b.connect("r.q", "q")?;
+        let sim = b.build();
+
+        let verilog = export(&sim.db);
+        assert_eq!(
+            flip_flop_block(&verilog),
+            "    always @(posedge clk or posedge clr)\n        \
+             if (clr) r_t_q <= 1'b0;\n        else if (t) r_t_q <= ~r_t_q;\n",
+            "{verilog}"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,1507 @@
+//! Import of structural Verilog netlists.
+//!
+//! The gate level subset written by synthesis tools and used in textbooks is understood: module
+//! declarations with classic or ANSI port lists, `input`, `output` and `wire` declarations with an
+//! optional `[msb:lsb]` range, the primitives `and`, `nand`, `or`, `nor`, `xor`, `xnor`, `not`,
+//! `buf` and `bufif1` including arrays of instances, instances of other modules with positional
+//! or named connections and `assign` of a net or a one bit constant. Bit selects, expressions and
+//! behavioural code are rejected with the line they are on.
+//!
+//! Every module becomes a module definition with one pin per port. The module that no other
+//! module instantiates is also placed as the circuit, with a power source for every input bit and
+//! a lamp for every output bit. Instances are placed in columns by their distance from the
+//! inputs and joined with straight wires.
+
+use std::collections::{BTreeMap, HashMap};
+
+use egui::{Pos2, pos2};
+
+use crate::app::GRID_SIZE;
+use crate::assets::PinKind;
+use crate::config::CanvasConfig;
+use crate::connection_manager::Connection;
+use crate::db::{
+    Circuit, DB, Gate, GateKind, InstanceId, Lamp, ModuleDefId, Orientation, Pin, Power, Splitter,
+    SplitterKind, Wire,
+};
+use crate::module::{Module, ModuleDefinition};
+use crate::simulator::{
+    MAX_BUS_WIDTH, joiner_input, joiner_output, lamp_input, power_output, splitter_input,
+    splitter_output, wire_end, wire_start,
+};
+
+/// Horizontal distance between columns of the placement
+const COLUMN_SPACING: f32 = 200.0;
+/// Vertical distance between instances in a column
+const ROW_SPACING: f32 = 100.0;
+/// Vertical distance between the powers or lamps of the bits of a bus port
+const BIT_SPACING: f32 = 40.0;
+/// Distance of the powers and lamps of a bus port from its joiner or splitter
+const BIT_OFFSET: f32 = 100.0;
+/// Distance between the port wires of a module definition
+const PORT_SPACING: f32 = 80.0;
+const PORT_WIRE_LENGTH: f32 = 40.0;
+
+const UNSUPPORTED: &[&str] = &[
+    "always",
+    "initial",
+    "reg",
+    "integer",
+    "parameter",
+    "localparam",
+    "function",
+    "task",
+    "generate",
+    "specify",
+    "bufif0",
+    "notif0",
+    "notif1",
+];
+
+/// Build a circuit from Verilog source.
+pub fn import(text: &str) -> Result<DB, String> {
+    let modules = Parser::new(tokenize(text)?).file()?;
+    let mut by_name: HashMap<&str, usize> = HashMap::new();
+    for (index, module) in modules.iter().enumerate() {
+        if by_name.insert(&module.name, index).is_some() {
+            return Err(format!(
+                "line {}: module `{}` is defined twice",
+                module.line, module.name
+            ));
+        }
+    }
+
+    let mut instantiated = vec![false; modules.len()];
+    for module in &modules {
+        for item in &module.items {
+            if let Item::Instance {
+                module: child,
+                line,
+                ..
+            } = item
+            {
+                let &index = by_name
+                    .get(child.as_str())
+                    .ok_or_else(|| format!("line {line}: unknown module `{child}`"))?;
+                instantiated[index] = true;
+            }
+        }
+    }
+    let top = (0..modules.len())
+        .rev()
+        .find(|&i| !instantiated[i])
+        .ok_or_else(|| {
+            if modules.is_empty() {
+                "no module found".to_owned()
+            } else {
+                "every module is instantiated by another one, there is no top module".to_owned()
+            }
+        })?;
+
+    // Definitions are built children first so their pins are known to their parents
+    let mut order = Vec::new();
+    let mut state = vec![Visit::New; modules.len()];
+    for index in 0..modules.len() {
+        visit(&modules, &by_name, index, &mut state, &mut order)?;
+    }
+
+    let mut db = DB::default();
+    let mut definitions: HashMap<String, Definition> = HashMap::new();
+    for index in order {
+        let module = &modules[index];
+        let netlist = Netlist::new(module, &definitions)?;
+        if index == top {
+            netlist.define(&mut db)?;
+            netlist.place_top(&mut db)?;
+        } else {
+            let definition = netlist.define(&mut db)?;
+            definitions.insert(module.name.clone(), definition);
+        }
+    }
+    Ok(db)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Visit {
+    New,
+    Active,
+    Done,
+}
+
+fn visit(
+    modules: &[VModule],
+    by_name: &HashMap<&str, usize>,
+    index: usize,
+    state: &mut [Visit],
+    order: &mut Vec<usize>,
+) -> Result<(), String> {
+    match state[index] {
+        Visit::Done => return Ok(()),
+        Visit::Active => {
+            return Err(format!(
+                "line {}: module `{}` instantiates itself",
+                modules[index].line, modules[index].name
+            ));
+        }
+        Visit::New => {}
+    }
+    state[index] = Visit::Active;
+    for item in &modules[index].items {
+        if let Item::Instance { module, .. } = item {
+            visit(modules, by_name, by_name[module.as_str()], state, order)?;
+        }
+    }
+    state[index] = Visit::Done;
+    order.push(index);
+    Ok(())
+}
+
+// Tokens
+
+struct Token {
+    text: String,
+    line: usize,
+    /// Written as `\name`, which can be any characters
+    escaped: bool,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            c if c.is_whitespace() => {}
+            '/' if chars.peek() == Some(&'/') => while chars.next_if(|&c| c != '\n').is_some() {},
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let start = line;
+                let mut last = ' ';
+                loop {
+                    match chars.next() {
+                        Some('/') if last == '*' => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            last = c;
+                        }
+                        None => return Err(format!("line {start}: comment is not closed")),
+                    }
+                }
+            }
+            // Compiler directives such as `timescale
+            '`' => while chars.next_if(|&c| c != '\n').is_some() {},
+            // Attributes like (* keep *)
+            '(' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+                loop {
+                    match chars.next() {
+                        Some(')') if last == '*' => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            last = c;
+                        }
+                        None => return Err(format!("line {line}: attribute is not closed")),
+                    }
+                }
+            }
+            // Escaped identifiers run up to the next white space
+            '\\' => {
+                let mut text = String::new();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    text.push(c);
+                }
+                tokens.push(Token {
+                    text,
+                    line,
+                    escaped: true,
+                });
+            }
+            c if c.is_ascii_alphanumeric() || c == '_' || c == '\'' => {
+                let mut text = String::from(c);
+                while let Some(c) =
+                    chars.next_if(|&c| c.is_ascii_alphanumeric() || matches!(c, '_' | '$' | '\''))
+                {
+                    text.push(c);
+                }
+                tokens.push(Token {
+                    text,
+                    line,
+                    escaped: false,
+                });
+            }
+            c => tokens.push(Token {
+                text: c.to_string(),
+                line,
+                escaped: false,
+            }),
+        }
+    }
+    Ok(tokens)
+}
+
+// Syntax
+
+#[derive(Clone, Copy, PartialEq)]
+enum Direction {
+    Input,
+    Output,
+}
+
+struct Declaration {
+    direction: Option<Direction>,
+    width: u8,
+}
+
+#[derive(Clone)]
+enum Expr {
+    Net(String),
+    Constant(bool),
+}
+
+enum Connections {
+    Positional(Vec<Option<Expr>>),
+    Named(Vec<(String, Option<Expr>)>),
+}
+
+#[derive(Clone, Copy)]
+enum Primitive {
+    Gate(GateKind),
+    Buf,
+}
+
+enum Item {
+    Primitive {
+        primitive: Primitive,
+        name: Option<String>,
+        /// Number of instances for an array of instances
+        width: u8,
+        terminals: Vec<Expr>,
+        line: usize,
+    },
+    Instance {
+        module: String,
+        name: String,
+        connections: Connections,
+        line: usize,
+    },
+    Assign {
+        target: String,
+        value: Expr,
+        line: usize,
+    },
+}
+
+struct VModule {
+    name: String,
+    line: usize,
+    ports: Vec<String>,
+    declarations: HashMap<String, Declaration>,
+    items: Vec<Item>,
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(tokens: Vec<Token>) -> Self {
+        Self { tokens, pos: 0 }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|t| t.text.as_str())
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or_else(|| self.tokens.last())
+            .map_or(1, |t| t.line)
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("line {}: {message}", self.line())
+    }
+
+    fn next(&mut self) -> Result<&str, String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .ok_or_else(|| "unexpected end of file".to_owned())?;
+        self.pos += 1;
+        Ok(&token.text)
+    }
+
+    fn eat(&mut self, text: &str) -> bool {
+        let found = self.peek() == Some(text);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), String> {
+        if self.eat(text) {
+            Ok(())
+        } else {
+            let found = self.peek().unwrap_or("end of file").to_owned();
+            Err(self.error(&format!("expected `{text}`, found `{found}`")))
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, String> {
+        match self.tokens.get(self.pos) {
+            Some(token)
+                if token.escaped
+                    || token
+                        .text
+                        .starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') =>
+            {
+                self.pos += 1;
+                Ok(token.text.clone())
+            }
+            found => {
+                let found = found.map_or("end of file", |t| t.text.as_str()).to_owned();
+                Err(self.error(&format!("expected a name, found `{found}`")))
+            }
+        }
+    }
+
+    fn file(&mut self) -> Result<Vec<VModule>, String> {
+        let mut modules = Vec::new();
+        while self.peek().is_some() {
+            if !self.eat("module") && !self.eat("macromodule") {
+                return Err(self.error("expected `module`"));
+            }
+            modules.push(self.module()?);
+        }
+        Ok(modules)
+    }
+
+    fn module(&mut self) -> Result<VModule, String> {
+        let line = self.line();
+        let mut module = VModule {
+            name: self.identifier()?,
+            line,
+            ports: Vec::new(),
+            declarations: HashMap::new(),
+            items: Vec::new(),
+        };
+        if self.peek() == Some("#") {
+            return Err(self.error("module parameters are not supported"));
+        }
+        if self.eat("(") && !self.eat(")") {
+            // ANSI headers declare the direction in the port list
+            let mut declaration: Option<(Direction, u8)> = None;
+            loop {
+                if let Some(direction) = self.direction()? {
+                    self.eat("wire");
+                    declaration = Some((direction, self.range()?));
+                }
+                let name = self.identifier()?;
+                if let Some((direction, width)) = declaration {
+                    module.declare(&name, Some(direction), width);
+                }
+                module.ports.push(name);
+                if !self.eat(",") {
+                    break;
+                }
+            }
+            self.expect(")")?;
+        }
+        self.expect(";")?;
+
+        while !self.eat("endmodule") {
+            let line = self.line();
+            if let Some(direction) = self.direction()? {
+                self.eat("wire");
+                let width = self.range()?;
+                for name in self.names()? {
+                    module.declare(&name, Some(direction), width);
+                }
+            } else if self.eat("wire") {
+                let width = self.range()?;
+                for name in self.names()? {
+                    module.declare(&name, None, width);
+                }
+            } else if self.eat("assign") {
+                loop {
+                    let line = self.line();
+                    let target = self.identifier()?;
+                    if self.peek() == Some("[") {
+                        return Err(self.error("bit and part selects are not supported"));
+                    }
+                    self.expect("=")?;
+                    let value = self.expr()?;
+                    module.items.push(Item::Assign {
+                        target,
+                        value,
+                        line,
+                    });
+                    if !self.eat(",") {
+                        break;
+                    }
+                }
+                self.expect(";")?;
+            } else if let Some(primitive) = self.primitive() {
+                self.delay()?;
+                loop {
+                    let line = self.line();
+                    let name = if self.peek() == Some("(") {
+                        None
+                    } else {
+                        Some(self.identifier()?)
+                    };
+                    let width = self.range()?;
+                    self.expect("(")?;
+                    let mut terminals = Vec::new();
+                    loop {
+                        terminals.push(self.expr()?);
+                        if !self.eat(",") {
+                            break;
+                        }
+                    }
+                    self.expect(")")?;
+                    module.items.push(Item::Primitive {
+                        primitive,
+                        name,
+                        width,
+                        terminals,
+                        line,
+                    });
+                    if !self.eat(",") {
+                        break;
+                    }
+                }
+                self.expect(";")?;
+            } else {
+                let keyword = self.identifier()?;
+                if UNSUPPORTED.contains(&keyword.as_str()) {
+                    self.pos -= 1;
+                    return Err(self.error(&format!(
+                        "`{keyword}` is not supported, only structural netlists can be imported"
+                    )));
+                }
+                if self.peek() == Some("#") {
+                    return Err(self.error("module parameters are not supported"));
+                }
+                loop {
+                    let line = self.line();
+                    let name = self.identifier()?;
+                    if self.peek() == Some("[") {
+                        return Err(self.error("arrays of module instances are not supported"));
+                    }
+                    self.expect("(")?;
+                    let connections = self.connections()?;
+                    self.expect(")")?;
+                    module.items.push(Item::Instance {
+                        module: keyword.clone(),
+                        name,
+                        connections,
+                        line,
+                    });
+                    if !self.eat(",") {
+                        break;
+                    }
+                }
+                self.expect(";")?;
+            }
+            if self.peek().is_none() {
+                return Err(format!(
+                    "line {line}: module `{}` has no `endmodule`",
+                    module.name
+                ));
+            }
+        }
+        Ok(module)
+    }
+
+    fn direction(&mut self) -> Result<Option<Direction>, String> {
+        if self.eat("input") {
+            Ok(Some(Direction::Input))
+        } else if self.eat("output") {
+            Ok(Some(Direction::Output))
+        } else if self.peek() == Some("inout") {
+            Err(self.error("inout ports are not supported"))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn primitive(&mut self) -> Option<Primitive> {
+        let primitive = match self.peek()? {
+            "and" => Primitive::Gate(GateKind::And),
+            "nand" => Primitive::Gate(GateKind::Nand),
+            "or" => Primitive::Gate(GateKind::Or),
+            "nor" => Primitive::Gate(GateKind::Nor),
+            "xor" => Primitive::Gate(GateKind::Xor),
+            "xnor" => Primitive::Gate(GateKind::Xnor),
+            "not" => Primitive::Gate(GateKind::Not),
+            "bufif1" => Primitive::Gate(GateKind::TriState),
+            "buf" => Primitive::Buf,
+            _ => return None,
+        };
+        self.pos += 1;
+        Some(primitive)
+    }
+
+    /// Skip a `#delay`, gates use their own delays
+    fn delay(&mut self) -> Result<(), String> {
+        if !self.eat("#") {
+            return Ok(());
+        }
+        if !self.eat("(") {
+            self.next()?;
+            return Ok(());
+        }
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next()? {
+                "(" => depth += 1,
+                ")" => depth -= 1,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Width of an optional `[msb:lsb]` range
+    fn range(&mut self) -> Result<u8, String> {
+        if !self.eat("[") {
+            return Ok(1);
+        }
+        let msb = self.number()?;
+        self.expect(":")?;
+        let lsb = self.number()?;
+        self.expect("]")?;
+        let width = msb.abs_diff(lsb) + 1;
+        u8::try_from(width)
+            .ok()
+            .filter(|&width| width <= MAX_BUS_WIDTH)
+            .ok_or_else(|| self.error(&format!("buses are limited to {MAX_BUS_WIDTH} bits")))
+    }
+
+    fn number(&mut self) -> Result<u64, String> {
+        let text = self.next()?.to_owned();
+        text.parse()
+            .ok()
+            .ok_or_else(|| self.error(&format!("expected a number, found `{text}`")))
+    }
+
+    fn names(&mut self) -> Result<Vec<String>, String> {
+        let mut names = vec![self.identifier()?];
+        while self.eat(",") {
+            names.push(self.identifier()?);
+        }
+        self.expect(";")?;
+        Ok(names)
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let Some(text) = self.peek().map(str::to_owned) else {
+            return Err(self.error("unexpected end of file"));
+        };
+        if text.starts_with(|c: char| c.is_ascii_digit() || c == '\'') {
+            self.pos += 1;
+            return constant(&text)
+                .map(Expr::Constant)
+                .ok_or_else(|| self.error("only the one bit constants 0 and 1 are supported"));
+        }
+        match text.as_str() {
+            "{" => return Err(self.error("concatenations are not supported")),
+            "~" | "!" | "&" | "|" | "^" | "(" => {
+                return Err(self.error("expressions are not supported, use gates"));
+            }
+            _ => {}
+        }
+        let name = self.identifier()?;
+        if self.peek() == Some("[") {
+            return Err(self.error("bit and part selects are not supported"));
+        }
+        if matches!(self.peek(), Some("&" | "|" | "^" | "?" | "+" | "-")) {
+            return Err(self.error("expressions are not supported, use gates"));
+        }
+        Ok(Expr::Net(name))
+    }
+
+    fn connections(&mut self) -> Result<Connections, String> {
+        if self.peek() == Some(")") {
+            return Ok(Connections::Positional(Vec::new()));
+        }
+        if self.peek() == Some(".") {
+            let mut named = Vec::new();
+            loop {
+                self.expect(".")?;
+                let port = self.identifier()?;
+                self.expect("(")?;
+                let expr = if self.peek() == Some(")") {
+                    None
+                } else {
+                    Some(self.expr()?)
+                };
+                self.expect(")")?;
+                named.push((port, expr));
+                if !self.eat(",") {
+                    return Ok(Connections::Named(named));
+                }
+            }
+        }
+        let mut positional = Vec::new();
+        loop {
+            let expr = if matches!(self.peek(), Some(")" | ",")) {
+                None
+            } else {
+                Some(self.expr()?)
+            };
+            positional.push(expr);
+            if !self.eat(",") {
+                return Ok(Connections::Positional(positional));
+            }
+        }
+    }
+}
+
+impl VModule {
+    fn declare(&mut self, name: &str, direction: Option<Direction>, width: u8) {
+        let declaration = self
+            .declarations
+            .entry(name.to_owned())
+            .or_insert(Declaration { direction, width });
+        // `output y; wire y;` declares the same net twice
+        declaration.direction = declaration.direction.or(direction);
+        declaration.width = declaration.width.max(width);
+    }
+}
+
+/// Value of a constant, `None` for anything but a single 0 or 1
+fn constant(text: &str) -> Option<bool> {
+    let value = match text.split_once('\'') {
+        Some((size, value)) => {
+            if !matches!(size, "" | "1") {
+                return None;
+            }
+            let value = value.trim_start_matches(['s', 'S']);
+            value
+                .get(1..)
+                .filter(|_| value.starts_with(['b', 'B', 'd', 'D', 'h', 'H', 'o', 'O']))?
+        }
+        None => text,
+    };
+    match value.trim_start_matches('0') {
+        "" if !value.is_empty() => Some(false),
+        "1" => Some(true),
+        _ => None,
+    }
+}
+
+// Netlist
+
+/// Pins of an imported module definition, by port
+struct Definition {
+    id: ModuleDefId,
+    /// Name, direction, width and external pin index of every port
+    ports: Vec<(String, Direction, u8, u32)>,
+}
+
+enum CellKind {
+    Gate { kind: GateKind, width: u8 },
+    Input { name: String, width: u8 },
+    Output { name: String, width: u8 },
+    Constant(bool),
+    Module { definition: ModuleDefId },
+}
+
+struct Cell {
+    kind: CellKind,
+    name: Option<String>,
+    /// Pin index on the placed instance, direction and net
+    pins: Vec<(u32, PinKind, usize)>,
+}
+
+/// One Verilog module as cells joined by nets
+struct Netlist {
+    name: String,
+    cells: Vec<Cell>,
+    nets: Vec<(String, u8)>,
+}
+
+/// Union-find of the net names joined by `assign` and `buf`
+#[derive(Default)]
+struct Nets {
+    index: HashMap<String, usize>,
+    parent: Vec<usize>,
+    names: Vec<(String, u8)>,
+}
+
+impl Nets {
+    fn get(&mut self, name: &str, width: u8) -> usize {
+        if let Some(&index) = self.index.get(name) {
+            return self.find(index);
+        }
+        self.add(name, width)
+    }
+
+    fn add(&mut self, name: &str, width: u8) -> usize {
+        let index = self.parent.len();
+        self.parent.push(index);
+        self.names.push((name.to_owned(), width));
+        self.index.insert(name.to_owned(), index);
+        index
+    }
+
+    fn find(&mut self, mut index: usize) -> usize {
+        while self.parent[index] != index {
+            self.parent[index] = self.parent[self.parent[index]];
+            index = self.parent[index];
+        }
+        index
+    }
+
+    fn width(&mut self, index: usize) -> u8 {
+        let root = self.find(index);
+        self.names[root].1
+    }
+
+    fn union(&mut self, a: usize, b: usize, line: usize) -> Result<(), String> {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return Ok(());
+        }
+        let (wa, wb) = (self.names[a].1, self.names[b].1);
+        if wa != wb {
+            return Err(format!(
+                "line {line}: `{}` has {wa} bits and `{}` has {wb}",
+                self.names[a].0, self.names[b].0
+            ));
+        }
+        self.parent[b] = a;
+        Ok(())
+    }
+}
+
+impl Netlist {
+    fn new(module: &VModule, definitions: &HashMap<String, Definition>) -> Result<Self, String> {
+        let mut nets = Nets::default();
+        let mut sorted: Vec<_> = module.declarations.iter().collect();
+        sorted.sort_by_key(|(name, _)| name.as_str());
+        for (name, declaration) in sorted {
+            nets.add(name, declaration.width);
+        }
+        for port in &module.ports {
+            let declared = module
+                .declarations
+                .get(port)
+                .is_some_and(|d| d.direction.is_some());
+            if !declared {
+                return Err(format!(
+                    "line {}: port `{port}` of `{}` has no direction",
+                    module.line, module.name
+                ));
+            }
+        }
+
+        // Aliases first, so every cell sees the final nets
+        for item in &module.items {
+            let (target, source, line) = match item {
+                Item::Assign {
+                    target,
+                    value: Expr::Net(source),
+                    line,
+                } => (target, source, *line),
+                Item::Primitive {
+                    primitive: Primitive::Buf,
+                    terminals,
+                    width,
+                    line,
+                    ..
+                } => {
+                    let Some((Expr::Net(source), outputs)) = terminals.split_last() else {
+                        continue;
+                    };
+                    let source = nets.get(source, *width);
+                    for output in outputs {
+                        let Expr::Net(output) = output else {
+                            return Err(format!("line {line}: a constant cannot be driven"));
+                        };
+                        let output = nets.get(output, *width);
+                        nets.union(source, output, *line)?;
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            let (target, source) = (nets.get(target, 1), nets.get(source, 1));
+            nets.union(target, source, line)?;
+        }
+
+        let mut netlist = Self {
+            name: module.name.clone(),
+            cells: Vec::new(),
+            nets: Vec::new(),
+        };
+        for port in &module.ports {
+            let declaration = &module.declarations[port];
+            let net = nets.get(port, declaration.width);
+            let width = declaration.width;
+            let (kind, pin) = match declaration.direction {
+                Some(Direction::Input) => (
+                    CellKind::Input {
+                        name: port.clone(),
+                        width,
+                    },
+                    PinKind::Output,
+                ),
+                _ => (
+                    CellKind::Output {
+                        name: port.clone(),
+                        width,
+                    },
+                    PinKind::Input,
+                ),
+            };
+            netlist.cells.push(Cell {
+                kind,
+                name: Some(port.clone()),
+                pins: vec![(0, pin, net)],
+            });
+        }
+
+        for item in &module.items {
+            match item {
+                Item::Assign {
+                    target,
+                    value: Expr::Constant(value),
+                    line,
+                } => {
+                    let net = netlist.attach(&mut nets, &Expr::Net(target.clone()), 1, *line)?;
+                    netlist.cells.push(Cell {
+                        kind: CellKind::Constant(*value),
+                        name: None,
+                        pins: vec![(0, PinKind::Output, net)],
+                    });
+                }
+                Item::Assign { .. } => {}
+                Item::Primitive {
+                    primitive: Primitive::Buf,
+                    width,
+                    terminals,
+                    line,
+                    ..
+                } => match terminals.split_last() {
+                    Some((Expr::Constant(value), outputs)) if !outputs.is_empty() => {
+                        if *width != 1 {
+                            return Err(format!(
+                                "line {line}: constants can only drive one bit pins"
+                            ));
+                        }
+                        for output in outputs {
+                            let net = netlist.driven(&mut nets, output, 1, *line)?;
+                            netlist.cells.push(Cell {
+                                kind: CellKind::Constant(*value),
+                                name: None,
+                                pins: vec![(0, PinKind::Output, net)],
+                            });
+                        }
+                    }
+                    Some((_, outputs)) if !outputs.is_empty() => {}
+                    _ => return Err(format!("line {line}: `buf` needs an output and an input")),
+                },
+                Item::Primitive {
+                    primitive: Primitive::Gate(kind),
+                    name,
+                    width,
+                    terminals,
+                    line,
+                } => netlist.primitive(&mut nets, *kind, name, *width, terminals, *line)?,
+                Item::Instance {
+                    module: child,
+                    name,
+                    connections,
+                    line,
+                } => {
+                    let definition = &definitions[child];
+                    let by_index: Vec<Option<&Expr>> = match connections {
+                        Connections::Positional(exprs) => {
+                            if exprs.len() > definition.ports.len() {
+                                return Err(format!(
+                                    "line {line}: `{child}` has {} ports, {} are connected",
+                                    definition.ports.len(),
+                                    exprs.len()
+                                ));
+                            }
+                            (0..definition.ports.len())
+                                .map(|i| exprs.get(i).and_then(Option::as_ref))
+                                .collect()
+                        }
+                        Connections::Named(named) => {
+                            let mut by_index = vec![None; definition.ports.len()];
+                            for (port, expr) in named {
+                                let index = definition
+                                    .ports
+                                    .iter()
+                                    .position(|(name, ..)| name == port)
+                                    .ok_or_else(|| {
+                                        format!("line {line}: `{child}` has no port `{port}`")
+                                    })?;
+                                by_index[index] = expr.as_ref();
+                            }
+                            by_index
+                        }
+                    };
+                    let mut pins = Vec::new();
+                    for (expr, (_, direction, width, index)) in
+                        by_index.iter().zip(&definition.ports)
+                    {
+                        let Some(expr) = expr else {
+                            continue;
+                        };
+                        let net = netlist.attach(&mut nets, expr, *width, *line)?;
+                        let kind = match direction {
+                            Direction::Input => PinKind::Input,
+                            Direction::Output => PinKind::Output,
+                        };
+                        pins.push((*index, kind, net));
+                    }
+                    netlist.cells.push(Cell {
+                        kind: CellKind::Module {
+                            definition: definition.id,
+                        },
+                        name: Some(name.clone()),
+                        pins,
+                    });
+                }
+            }
+        }
+
+        // Number the nets that are left after joining aliases
+        let mut numbers = HashMap::new();
+        for cell in &mut netlist.cells {
+            for (_, _, net) in &mut cell.pins {
+                let root = nets.find(*net);
+                *net = *numbers.entry(root).or_insert_with(|| {
+                    netlist.nets.push(nets.names[root].clone());
+                    netlist.nets.len() - 1
+                });
+            }
+        }
+        Ok(netlist)
+    }
+
+    /// Net for a terminal, checking its width. Constants get a power source of their own.
+    fn attach(
+        &mut self,
+        nets: &mut Nets,
+        expr: &Expr,
+        width: u8,
+        line: usize,
+    ) -> Result<usize, String> {
+        match expr {
+            Expr::Net(name) => {
+                let net = nets.get(name, width);
+                let found = nets.width(net);
+                if found != width {
+                    return Err(format!(
+                        "line {line}: `{name}` has {found} bits where {width} are needed"
+                    ));
+                }
+                Ok(net)
+            }
+            Expr::Constant(value) => {
+                if width != 1 {
+                    return Err(format!(
+                        "line {line}: constants can only drive one bit pins"
+                    ));
+                }
+                let net = nets.add(&format!("const{}", self.cells.len()), 1);
+                self.cells.push(Cell {
+                    kind: CellKind::Constant(*value),
+                    name: None,
+                    pins: vec![(0, PinKind::Output, net)],
+                });
+                Ok(net)
+            }
+        }
+    }
+
+    /// Gates for a primitive. Gates with more than two inputs become a chain.
+    fn primitive(
+        &mut self,
+        nets: &mut Nets,
+        kind: GateKind,
+        name: &Option<String>,
+        width: u8,
+        terminals: &[Expr],
+        line: usize,
+    ) -> Result<(), String> {
+        let gate = |pins| Cell {
+            kind: CellKind::Gate { kind, width },
+            name: name.clone(),
+            pins,
+        };
+        match kind {
+            GateKind::Not => {
+                let Some((input, outputs)) = terminals.split_last().filter(|(_, o)| !o.is_empty())
+                else {
+                    return Err(format!("line {line}: `not` needs an output and an input"));
+                };
+                let input = self.attach(nets, input, width, line)?;
+                for output in outputs {
+                    let output = self.driven(nets, output, width, line)?;
+                    self.cells.push(gate(vec![
+                        (0, PinKind::Input, input),
+                        (1, PinKind::Output, output),
+                    ]));
+                }
+            }
+            GateKind::TriState => {
+                let [output, input, enable] = terminals else {
+                    return Err(format!(
+                        "line {line}: `bufif1` needs an output, an input and an enable"
+                    ));
+                };
+                let output = self.driven(nets, output, width, line)?;
+                let input = self.attach(nets, input, width, line)?;
+                let enable = self.attach(nets, enable, 1, line)?;
+                self.cells.push(gate(vec![
+                    (0, PinKind::Input, input),
+                    (1, PinKind::Input, enable),
+                    (2, PinKind::Output, output),
+                ]));
+            }
+            _ => {
+                let [output, first, inputs @ ..] = terminals else {
+                    return Err(format!("line {line}: gate needs an output and inputs"));
+                };
+                if inputs.is_empty() {
+                    return Err(format!("line {line}: gate needs at least two inputs"));
+                }
+                let output = self.driven(nets, output, width, line)?;
+                let mut previous = self.attach(nets, first, width, line)?;
+                // Inner gates of a chain do not invert, only the last one does
+                let inner = match kind {
+                    GateKind::Nand => GateKind::And,
+                    GateKind::Nor => GateKind::Or,
+                    GateKind::Xnor => GateKind::Xor,
+                    kind => kind,
+                };
+                for (i, input) in inputs.iter().enumerate() {
+                    let input = self.attach(nets, input, width, line)?;
+                    let last = i + 1 == inputs.len();
+                    let out = if last {
+                        output
+                    } else {
+                        nets.add(&format!("chain{}", self.cells.len()), width)
+                    };
+                    self.cells.push(Cell {
+                        kind: CellKind::Gate {
+                            kind: if last { kind } else { inner },
+                            width,
+                        },
+                        name: if last { name.clone() } else { None },
+                        pins: vec![
+                            (0, PinKind::Input, previous),
+                            (1, PinKind::Input, input),
+                            (2, PinKind::Output, out),
+                        ],
+                    });
+                    previous = out;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn driven(
+        &mut self,
+        nets: &mut Nets,
+        expr: &Expr,
+        width: u8,
+        line: usize,
+    ) -> Result<usize, String> {
+        if matches!(expr, Expr::Constant(_)) {
+            return Err(format!("line {line}: a constant cannot be driven"));
+        }
+        self.attach(nets, expr, width, line)
+    }
+
+    /// Column of every cell, the longest path from an input ignoring feedback
+    fn columns(&self) -> Vec<usize> {
+        let mut drivers: Vec<Vec<usize>> = vec![Vec::new(); self.nets.len()];
+        for (index, cell) in self.cells.iter().enumerate() {
+            for &(_, kind, net) in &cell.pins {
+                if kind == PinKind::Output {
+                    drivers[net].push(index);
+                }
+            }
+        }
+        let mut columns = vec![None; self.cells.len()];
+        let mut active = vec![false; self.cells.len()];
+        for index in 0..self.cells.len() {
+            self.column(index, &drivers, &mut columns, &mut active);
+        }
+        let mut columns: Vec<usize> = columns.into_iter().map(Option::unwrap_or_default).collect();
+
+        // Outputs line up in a column of their own
+        let last = self
+            .cells
+            .iter()
+            .zip(&columns)
+            .filter(|(cell, _)| !matches!(cell.kind, CellKind::Output { .. }))
+            .map(|(_, &column)| column + 1)
+            .max()
+            .unwrap_or(0);
+        for (cell, column) in self.cells.iter().zip(&mut columns) {
+            if matches!(cell.kind, CellKind::Output { .. }) {
+                *column = last;
+            }
+        }
+        columns
+    }
+
+    fn column(
+        &self,
+        index: usize,
+        drivers: &[Vec<usize>],
+        columns: &mut [Option<usize>],
+        active: &mut [bool],
+    ) -> usize {
+        if let Some(column) = columns[index] {
+            return column;
+        }
+        active[index] = true;
+        let mut column = 0;
+        if !matches!(
+            self.cells[index].kind,
+            CellKind::Input { .. } | CellKind::Constant(_)
+        ) {
+            for &(_, kind, net) in &self.cells[index].pins {
+                if kind != PinKind::Input {
+                    continue;
+                }
+                for &driver in &drivers[net] {
+                    if !active[driver] {
+                        column = column.max(self.column(driver, drivers, columns, active) + 1);
+                    }
+                }
+            }
+        }
+        active[index] = false;
+        columns[index] = Some(column);
+        column
+    }
+
+    /// Position of every cell, columns left to right with cells stacked from the top
+    fn positions(&self) -> Vec<Pos2> {
+        let columns = self.columns();
+        let mut next_y: HashMap<usize, f32> = HashMap::new();
+        self.cells
+            .iter()
+            .zip(columns)
+            .map(|(cell, column)| {
+                let height = match cell.kind {
+                    CellKind::Input { width, .. } | CellKind::Output { width, .. } => {
+                        (f32::from(width) * BIT_SPACING).max(ROW_SPACING)
+                    }
+                    _ => ROW_SPACING,
+                };
+                let y = next_y.entry(column).or_insert(0.0);
+                let pos = pos2(column as f32 * COLUMN_SPACING, *y + height / 2.0);
+                *y += height;
+                snap(pos)
+            })
+            .collect()
+    }
+
+    /// Pins and drivers of every net, pins are given per cell
+    fn connect(&self, pins: &[Vec<Pin>]) -> Vec<(Pin, Pin, usize)> {
+        let mut drivers: Vec<Vec<Pin>> = vec![Vec::new(); self.nets.len()];
+        let mut readers: Vec<Vec<Pin>> = vec![Vec::new(); self.nets.len()];
+        for (cell, pins) in self.cells.iter().zip(pins) {
+            for (&(_, kind, net), &pin) in cell.pins.iter().zip(pins) {
+                match kind {
+                    PinKind::Output => drivers[net].push(pin),
+                    PinKind::Input => readers[net].push(pin),
+                }
+            }
+        }
+        let mut connections = Vec::new();
+        for (net, drivers) in drivers.iter().enumerate() {
+            for &driver in drivers {
+                for &reader in &readers[net] {
+                    connections.push((driver, reader, net));
+                }
+            }
+        }
+        connections
+    }
+
+    /// Add the module as a definition, its ports are wires along the top in port order
+    fn define(&self, db: &mut DB) -> Result<Definition, String> {
+        let mut circuit = Circuit::default();
+        let positions = self.positions();
+        let mut pins = Vec::new();
+        let mut port_wires = Vec::new();
+        for (cell, &pos) in self.cells.iter().zip(&positions) {
+            let cell_pins = match cell.kind {
+                CellKind::Gate { kind, width } => {
+                    let id = circuit.new_gate(Gate {
+                        width,
+                        ..Gate::new(pos, kind)
+                    });
+                    cell_pins(cell, id)
+                }
+                CellKind::Constant(on) => {
+                    vec![power_output(circuit.new_power(Power::new(pos, on)))]
+                }
+                CellKind::Input { ref name, width } | CellKind::Output { ref name, width } => {
+                    let x = port_wires.len() as f32 * PORT_SPACING;
+                    let y = -ROW_SPACING;
+                    let id = circuit.new_wire(Wire {
+                        width,
+                        ..Wire::new(pos2(x, y), pos2(x + PORT_WIRE_LENGTH, y))
+                    });
+                    // Truth tables and the equivalence check show the port names
+                    circuit.set_name(id, name);
+                    port_wires.push(id);
+                    if matches!(cell.kind, CellKind::Input { .. }) {
+                        vec![wire_end(id)]
+                    } else {
+                        vec![wire_start(id)]
+                    }
+                }
+                CellKind::Module { definition } => {
+                    let child = db.get_module_def(definition);
+                    let external = child.get_unconnected_internal_pins(db);
+                    let id = circuit.new_module_id(Module {
+                        pos,
+                        definition_id: definition,
+                        instance_members: Vec::new(),
+                        pins: BTreeMap::new(),
+                        orientation: Orientation::default(),
+                    });
+                    circuit.get_module_mut(id).pins = external
+                        .iter()
+                        .enumerate()
+                        .map(|(i, &internal)| (Pin::new(id, i as u32, internal.kind), internal))
+                        .collect();
+                    cell_pins(cell, id)
+                }
+            };
+            pins.push(cell_pins);
+        }
+        for (driver, reader, _) in self.connect(&pins) {
+            circuit.connections.insert(Connection::new(driver, reader));
+        }
+
+        let definition = ModuleDefinition {
+            name: self.name.clone(),
+            circuit,
+        };
+        // Unused ports leave both ends of their wire open, so look up where each one ended up
+        let external = definition.get_unconnected_internal_pins(db);
+        let ports = self
+            .cells
+            .iter()
+            .filter_map(|cell| match &cell.kind {
+                CellKind::Input { name, width } => Some((name, Direction::Input, *width)),
+                CellKind::Output { name, width } => Some((name, Direction::Output, *width)),
+                _ => None,
+            })
+            .zip(port_wires)
+            .map(|((name, direction, width), wire)| {
+                let outer = match direction {
+                    Direction::Input => wire_start(wire),
+                    Direction::Output => wire_end(wire),
+                };
+                let index = external
+                    .iter()
+                    .position(|&pin| pin == outer)
+                    .ok_or_else(|| {
+                        format!(
+                            "port `{name}` of module `{}` is not on the module boundary",
+                            self.name
+                        )
+                    })?;
+                Ok((name.clone(), direction, width, index as u32))
+            })
+            .collect::<Result<_, String>>()?;
+        let id = db.module_definitions.insert(definition);
+        Ok(Definition { id, ports })
+    }
+
+    /// Place the module as the circuit itself with powers and lamps for its ports
+    fn place_top(self, db: &mut DB) -> Result<(), String> {
+        let positions = self.positions();
+        let mut pins = Vec::new();
+        for (cell, &pos) in self.cells.iter().zip(&positions) {
+            let cell_pins = match cell.kind {
+                CellKind::Gate { kind, width } => {
+                    let id = db.circuit.new_gate(Gate {
+                        width,
+                        ..Gate::new(pos, kind)
+                    });
+                    cell_pins(cell, id)
+                }
+                CellKind::Constant(on) => {
+                    vec![power_output(db.circuit.new_power(Power::new(pos, on)))]
+                }
+                CellKind::Input { width: 1, .. } => {
+                    vec![power_output(db.circuit.new_power(Power::new(pos, false)))]
+                }
+                CellKind::Output { width: 1, .. } => {
+                    vec![lamp_input(db.circuit.new_lamp(Lamp::new(pos)))]
+                }
+                CellKind::Input { ref name, width } => {
+                    let joiner = db.circuit.new_splitter(Splitter {
+                        width,
+                        ..Splitter::new(pos, SplitterKind::Join)
+                    });
+                    for bit in 0..width {
+                        let id = db.circuit.new_power(Power::new(
+                            bit_position(pos, -BIT_OFFSET, bit, width),
+                            false,
+                        ));
+                        db.circuit.set_name(id, &format!("{name}[{bit}]"));
+                        wire(db, power_output(id), joiner_input(joiner, bit), 1)?;
+                    }
+                    pins.push(vec![joiner_output(joiner)]);
+                    continue;
+                }
+                CellKind::Output { ref name, width } => {
+                    let splitter = db.circuit.new_splitter(Splitter {
+                        width,
+                        ..Splitter::new(pos, SplitterKind::Split)
+                    });
+                    for bit in 0..width {
+                        let id = db
+                            .circuit
+                            .new_lamp(Lamp::new(bit_position(pos, BIT_OFFSET, bit, width)));
+                        db.circuit.set_name(id, &format!("{name}[{bit}]"));
+                        wire(db, splitter_output(splitter, bit), lamp_input(id), 1)?;
+                    }
+                    pins.push(vec![splitter_input(splitter)]);
+                    continue;
+                }
+                CellKind::Module { definition } => {
+                    let id = db.new_module(definition, pos);
+                    cell_pins(cell, id)
+                }
+            };
+            if let (Some(name), Some(pin)) = (&cell.name, cell_pins.first()) {
+                db.circuit.set_name(pin.ins, name);
+            }
+            pins.push(cell_pins);
+        }
+        for (driver, reader, net) in self.connect(&pins) {
+            let (name, width) = &self.nets[net];
+            wire(db, driver, reader, *width).map_err(|e| format!("net `{name}`: {e}"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Pins of the instance placed for a gate or module cell
+fn cell_pins(cell: &Cell, id: InstanceId) -> Vec<Pin> {
+    cell.pins
+        .iter()
+        .map(|&(index, kind, _)| Pin::new(id, index, kind))
+        .collect()
+}
+
+/// Join two pins of the top level circuit with a straight wire
+fn wire(db: &mut DB, from: Pin, to: Pin, width: u8) -> Result<(), String> {
+    let canvas_config = CanvasConfig::default();
+    let start = db.circuit.pin_position(from, &canvas_config, db);
+    let end = db.circuit.pin_position(to, &canvas_config, db);
+    let id = db.circuit.new_wire(Wire {
+        width,
+        ..Wire::new(start, end)
+    });
+    for connection in [
+        Connection::new(from, wire_start(id)),
+        Connection::new(wire_end(id), to),
+    ] {
+        db.circuit.check_connection(connection)?;
+        db.circuit.connections.insert(connection);
+    }
+    Ok(())
+}
+
+fn bit_position(pos: Pos2, dx: f32, bit: u8, width: u8) -> Pos2 {
+    let top = pos.y - f32::from(width - 1) * BIT_SPACING / 2.0;
+    snap(pos2(pos.x + dx, top + f32::from(bit) * BIT_SPACING))
+}
+
+fn snap(pos: Pos2) -> Pos2 {
+    pos2(
+        (pos.x / GRID_SIZE).round() * GRID_SIZE,
+        (pos.y / GRID_SIZE).round() * GRID_SIZE,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::import;
+    use crate::builder::Simulation;
+    use crate::simulator::Value;
+
+    const FULL_ADDER: &str = "
+        // Full adder from two half adders
+        module half_adder(a, b, s, c);
+            input a, b;
+            output s, c;
+            xor (s, a, b);
+            and #1 g1 (c, a, b);
+        endmodule
+
+        module full_adder(input a, input b, input cin, output sum, output cout);
+            wire s1, c1, c2;
+            half_adder h1 (.a(a), .b(b), .s(s1), .c(c1));
+            half_adder h2 (s1, cin, sum, c2);
+            /* a three input OR is a chain of two gates */
+            or g2 (cout, c1, c2, 1'b0);
+        endmodule
+    ";
+
+    #[test]
+    fn full_adder_from_half_adders() -> Result<(), String> {
+        let db = import(FULL_ADDER)?;
+        let mut names: Vec<&str> = db
+            .module_definitions
+            .values()
+            .map(|definition| definition.name.as_str())
+            .collect();
+        names.sort_unstable();
+        assert_eq!(names, ["full_adder", "half_adder"]);
+        let mut sim = Simulation::new(db);
+        for bits in 0..8_u8 {
+            let (a, b, cin) = (bits & 1 != 0, bits & 2 != 0, bits & 4 != 0);
+            sim.set("a", a)?;
+            sim.set("b", b)?;
+            sim.set("cin", cin)?;
+            sim.settle()?;
+            let total = u8::from(a) + u8::from(b) + u8::from(cin);
+            let bit = |on| if on { Value::One } else { Value::Zero };
+            assert_eq!(sim.value("sum")?, bit(total & 1 != 0), "inputs {bits:03b}");
+            assert_eq!(sim.value("cout")?, bit(total > 1), "inputs {bits:03b}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn unsupported_syntax_names_the_line() {
+        let text = "module m(a, y);\n  input [1:0] a;\n  output y;\n  not (y, a[0]);\nendmodule\n";
+        assert_eq!(
+            import(text).err().as_deref(),
+            Some("line 4: bit and part selects are not supported")
+        );
+        assert!(import("module m(y); output y; always y = 1; endmodule").is_err());
+    }
+}
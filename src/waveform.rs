@@ -0,0 +1,513 @@
+//! Recording of probed pins over simulated time and the timing diagram that shows them.
+//!
+//! The simulator samples every probed pin whenever simulated time moves on and at the end of
+//! each run, so a trace holds the settled value at each point in time. Only changes are stored,
+//! each trace keeps the last `history` of them.
+
+use std::collections::VecDeque;
+
+use egui::{Color32, Rect, Sense, Stroke, Ui, pos2, vec2};
+
+use crate::App;
+use crate::db::{InstanceKind, Pin};
+use crate::simulator::{BusValue, Value, wire_start};
+
+/// Default number of value changes kept per trace
+pub const DEFAULT_HISTORY: usize = 10_000;
+
+pub const WAVEFORM_LABEL_WIDTH: f32 = 180.0;
+pub const WAVEFORM_ROW_HEIGHT: f32 = 28.0;
+pub const WAVEFORM_AXIS_HEIGHT: f32 = 18.0;
+/// Smallest distance between two time axis ticks in pixels
+pub const WAVEFORM_TICK_SPACING: f32 = 60.0;
+pub const WAVEFORM_MIN_ZOOM: f32 = 0.01;
+pub const WAVEFORM_MAX_ZOOM: f32 = 200.0;
+
+pub const COLOR_TRACE: Color32 = Color32::from_rgb(60, 180, 75);
+pub const COLOR_TRACE_Z: Color32 = Color32::from_rgb(230, 180, 40);
+pub const COLOR_TRACE_X: Color32 = Color32::RED;
+pub const COLOR_CURSOR_A: Color32 = Color32::from_rgb(80, 140, 255);
+pub const COLOR_CURSOR_B: Color32 = Color32::from_rgb(230, 100, 230);
+pub const COLOR_PROBE: Color32 = Color32::from_rgb(80, 140, 255);
+pub const PROBE_MARKER_RADIUS: f32 = 5.0;
+
+pub struct Trace {
+    pub pin: Pin,
+    /// Value changes as (time, new value), oldest first
+    pub changes: VecDeque<(u64, BusValue)>,
+}
+
+impl Trace {
+    /// Value the pin had at `time`, `None` before recording started
+    pub fn value_at(&self, time: u64) -> Option<BusValue> {
+        let after = self.changes.partition_point(|&(t, _)| t <= time);
+        after.checked_sub(1).map(|i| self.changes[i].1)
+    }
+}
+
+pub struct Recorder {
+    pub traces: Vec<Trace>,
+    /// Number of value changes kept per trace, older ones are dropped first
+    pub history: usize,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self {
+            traces: Vec::new(),
+            history: DEFAULT_HISTORY,
+        }
+    }
+}
+
+impl Recorder {
+    pub fn is_probed(&self, pin: Pin) -> bool {
+        self.traces.iter().any(|trace| trace.pin == pin)
+    }
+
+    /// Start recording `pin`. Its first value is taken on the next simulation run.
+    pub fn add(&mut self, pin: Pin) {
+        if !self.is_probed(pin) {
+            self.traces.push(Trace {
+                pin,
+                changes: VecDeque::new(),
+            });
+        }
+    }
+
+    pub fn remove(&mut self, pin: Pin) {
+        self.traces.retain(|trace| trace.pin != pin);
+    }
+
+    /// Probe or stop probing `pin`, returns whether it is probed afterwards
+    pub fn toggle(&mut self, pin: Pin) -> bool {
+        if self.is_probed(pin) {
+            self.remove(pin);
+            false
+        } else {
+            self.add(pin);
+            true
+        }
+    }
+
+    /// Forget the history, every trace keeps only its value at `time`
+    pub fn clear(&mut self, time: u64) {
+        for trace in &mut self.traces {
+            let last = trace.changes.back().copied();
+            trace.changes.clear();
+            if let Some((_, value)) = last {
+                trace.changes.push_back((time, value));
+            }
+        }
+    }
+
+    /// Time of the oldest change still recorded
+    pub fn first_time(&self) -> Option<u64> {
+        self.traces
+            .iter()
+            .filter_map(|trace| trace.changes.front())
+            .map(|&(time, _)| time)
+            .min()
+    }
+
+    /// Record the value of every trace at `time`, `values` is in the order of `traces`. A second
+    /// sample at the same time replaces the first one.
+    pub(crate) fn record(&mut self, time: u64, values: &[BusValue]) {
+        for (trace, &value) in self.traces.iter_mut().zip(values) {
+            match trace.changes.back() {
+                Some(&(_, last)) if last == value => continue,
+                Some(&(last_time, _)) if last_time == time => {
+                    trace.changes.pop_back();
+                    if trace.changes.back().is_some_and(|&(_, v)| v == value) {
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            trace.changes.push_back((time, value));
+            while trace.changes.len() > self.history {
+                trace.changes.pop_front();
+            }
+        }
+    }
+}
+
+/// Scroll position, zoom and cursors of the waveform panel
+pub struct WaveformView {
+    /// Pixels per simulated time unit
+    pub zoom: f32,
+    /// Time at the left edge of the plot
+    pub start: f32,
+    /// Keep the current time in view while the simulation runs
+    pub follow: bool,
+    /// Measurement cursors, placed with a left and a right click on the plot
+    pub cursors: [Option<u64>; 2],
+}
+
+impl Default for WaveformView {
+    fn default() -> Self {
+        Self {
+            zoom: 4.0,
+            start: 0.0,
+            follow: true,
+            cursors: [None; 2],
+        }
+    }
+}
+
+/// Text shown for a bus value, hex when every bit is known
+fn bus_text(value: BusValue) -> String {
+    match value.to_u64() {
+        Some(n) if value.width() > 1 => format!("{n:X}"),
+        _ => value.to_string(),
+    }
+}
+
+/// Distance between time axis ticks: 1, 2 or 5 times a power of ten, at least
+/// `WAVEFORM_TICK_SPACING` pixels apart
+fn tick_step(zoom: f32) -> u64 {
+    let mut step = 1;
+    loop {
+        for factor in [1, 2, 5] {
+            if (step * factor) as f32 * zoom >= WAVEFORM_TICK_SPACING {
+                return step * factor;
+            }
+        }
+        step *= 10;
+    }
+}
+
+impl App {
+    /// Pin marked by the probe shortcut: the hovered pin, or the hovered wire
+    pub(crate) fn hovered_probe_pin(&self) -> Option<Pin> {
+        match self.hovered? {
+            crate::app::Hover::Pin(pin) => Some(pin),
+            crate::app::Hover::Instance(id) => {
+                matches!(self.db.circuit.ty(id), InstanceKind::Wire).then(|| wire_start(id))
+            }
+        }
+    }
+
+    fn probe_name(&self, pin: Pin) -> String {
+        let circuit = &self.db.circuit;
+        if let Some(name) = circuit.name(pin.ins)
+            && let Some((pin_name, _)) = crate::builder::pin_names(&self.db, pin.ins)
+                .into_iter()
+                .find(|(_, p)| *p == pin)
+        {
+            return format!("{name}.{pin_name}");
+        }
+        pin.display_short(circuit, &self.db)
+    }
+
+    /// Mark probed pins on the canvas
+    pub(crate) fn draw_probes(&self, ui: &Ui) {
+        for trace in &self.simulator.recorder.traces {
+            let pin = trace.pin;
+            if !self.db.circuit.types.contains_key(pin.ins) || self.db.is_hidden(pin.ins) {
+                continue;
+            }
+            let pos = self.adjusted_pos(self.circuit().pin_position(
+                pin,
+                &self.canvas_config,
+                &self.db,
+            ));
+            ui.painter()
+                .circle_filled(pos, PROBE_MARKER_RADIUS, COLOR_PROBE);
+        }
+    }
+
+    pub fn draw_waveform_panel(&mut self, ctx: &egui::Context) {
+        egui::TopBottomPanel::bottom("waveform")
+            .resizable(true)
+            .default_height(220.0)
+            .show(ctx, |ui| {
+                let now = self.simulator.time;
+                ui.horizontal(|ui| {
+                    ui.heading("Waveforms");
+                    ui.add_space(16.0);
+                    let view = &mut self.waveform_view;
+                    ui.checkbox(&mut view.follow, "Follow");
+                    if ui.button("−").on_hover_text("Zoom out").clicked() {
+                        view.zoom = (view.zoom / 2.0).max(WAVEFORM_MIN_ZOOM);
+                    }
+                    if ui.button("+").on_hover_text("Zoom in").clicked() {
+                        view.zoom = (view.zoom * 2.0).min(WAVEFORM_MAX_ZOOM);
+                    }
+                    if ui.button("Fit").clicked() {
+                        let first = self.simulator.recorder.first_time().unwrap_or(0);
+                        let width = (ui.available_width() - WAVEFORM_LABEL_WIDTH).max(1.0);
+                        let span = now.saturating_sub(first).max(1) as f32;
+                        view.zoom = (width / span).clamp(WAVEFORM_MIN_ZOOM, WAVEFORM_MAX_ZOOM);
+                        view.start = first as f32;
+                        view.follow = false;
+                    }
+                    if ui.button("Clear").clicked() {
+                        self.simulator.recorder.clear(now);
+                        view.cursors = [None; 2];
+                    }
+                    ui.add_space(16.0);
+
+                    if let Some(a) = view.cursors[0] {
+                        ui.colored_label(COLOR_CURSOR_A, format!("A = {a}"));
+                    }
+                    if let Some(b) = view.cursors[1] {
+                        ui.colored_label(COLOR_CURSOR_B, format!("B = {b}"));
+                    }
+                    if let [Some(a), Some(b)] = view.cursors {
+                        ui.label(format!("Δt = {}", a.abs_diff(b)));
+                    }
+                });
+                ui.separator();
+
+                if self.simulator.recorder.traces.is_empty() {
+                    ui.label("Hover a pin or wire and press P to probe it");
+                    return;
+                }
+                egui::ScrollArea::vertical()
+                    .auto_shrink([false, true])
+                    .show(ui, |ui| self.draw_traces(ui));
+            });
+    }
+
+    fn draw_traces(&mut self, ui: &mut Ui) {
+        let now = self.simulator.time;
+        let rows = self.simulator.recorder.traces.len();
+        let height = WAVEFORM_AXIS_HEIGHT + rows as f32 * WAVEFORM_ROW_HEIGHT;
+        let (rect, _) = ui.allocate_exact_size(vec2(ui.available_width(), height), Sense::hover());
+        let plot_rect = Rect::from_min_max(
+            pos2(rect.left() + WAVEFORM_LABEL_WIDTH, rect.top()),
+            rect.max,
+        );
+        let plot = ui.interact(
+            plot_rect,
+            ui.id().with("waveform_plot"),
+            Sense::click_and_drag(),
+        );
+
+        // Navigation
+        let view = &mut self.waveform_view;
+        if plot.dragged() {
+            view.start -= plot.drag_delta().x / view.zoom;
+            view.follow = false;
+        }
+        if let Some(mouse) = plot.hover_pos() {
+            let zoom_delta = ui.input(|i| i.zoom_delta());
+            if zoom_delta != 1.0 {
+                let anchor = view.start + (mouse.x - plot_rect.left()) / view.zoom;
+                view.zoom = (view.zoom * zoom_delta).clamp(WAVEFORM_MIN_ZOOM, WAVEFORM_MAX_ZOOM);
+                view.start = anchor - (mouse.x - plot_rect.left()) / view.zoom;
+                view.follow = false;
+            }
+        }
+        if view.follow {
+            view.start = now as f32 - plot_rect.width() / view.zoom;
+        }
+        view.start = view.start.max(0.0);
+        let (start, zoom) = (view.start, view.zoom);
+        let time_at = |x: f32| (start + (x - plot_rect.left()) / zoom).round().max(0.0) as u64;
+        if let Some(mouse) = plot.interact_pointer_pos() {
+            if plot.clicked() {
+                view.cursors[0] = Some(time_at(mouse.x));
+            } else if plot.secondary_clicked() {
+                view.cursors[1] = Some(time_at(mouse.x));
+            }
+        }
+        let cursors = view.cursors;
+
+        let x_at = |t: u64| plot_rect.left() + (t as f32 - start) * zoom;
+        let end = start + plot_rect.width() / zoom;
+        let painter = ui.painter_at(rect);
+        let visuals = ui.visuals();
+        let text_color = visuals.text_color();
+        let grid_color = visuals.widgets.noninteractive.bg_stroke.color;
+        let font = egui::FontId::monospace(12.0);
+
+        // Time axis
+        let step = tick_step(zoom);
+        let mut tick = (start as u64).div_ceil(step) * step;
+        while (tick as f32) <= end {
+            let x = x_at(tick);
+            painter.line_segment(
+                [
+                    pos2(x, rect.top() + WAVEFORM_AXIS_HEIGHT),
+                    pos2(x, rect.bottom()),
+                ],
+                Stroke::new(1.0, grid_color),
+            );
+            painter.text(
+                pos2(x + 2.0, rect.top()),
+                egui::Align2::LEFT_TOP,
+                tick.to_string(),
+                font.clone(),
+                text_color,
+            );
+            tick += step;
+        }
+
+        let mut remove = None;
+        for (row, trace) in self.simulator.recorder.traces.iter().enumerate() {
+            let top = rect.top() + WAVEFORM_AXIS_HEIGHT + row as f32 * WAVEFORM_ROW_HEIGHT;
+            let high = top + 5.0;
+            let low = top + WAVEFORM_ROW_HEIGHT - 5.0;
+            let mid = (high + low) / 2.0;
+
+            // Label column with the value under cursor A, or the current value
+            let shown = trace.value_at(cursors[0].unwrap_or(now));
+            let button_rect = Rect::from_min_size(pos2(rect.left(), top + 4.0), vec2(20.0, 20.0));
+            if ui
+                .put(button_rect, egui::Button::new("✕").small())
+                .on_hover_text("Stop probing")
+                .clicked()
+            {
+                remove = Some(trace.pin);
+            }
+            painter.text(
+                pos2(rect.left() + 24.0, mid),
+                egui::Align2::LEFT_CENTER,
+                self.probe_name(trace.pin),
+                font.clone(),
+                text_color,
+            );
+            if let Some(value) = shown {
+                painter.text(
+                    pos2(plot_rect.left() - 6.0, mid),
+                    egui::Align2::RIGHT_CENTER,
+                    bus_text(value),
+                    font.clone(),
+                    text_color,
+                );
+            }
+            painter.line_segment(
+                [
+                    pos2(rect.left(), top + WAVEFORM_ROW_HEIGHT),
+                    pos2(rect.right(), top + WAVEFORM_ROW_HEIGHT),
+                ],
+                Stroke::new(1.0, grid_color),
+            );
+
+            // Signal, one segment per recorded change
+            let mut previous_y = None;
+            for (i, &(from, value)) in trace.changes.iter().enumerate() {
+                let to = trace.changes.get(i + 1).map_or(now, |&(t, _)| t);
+                if (to as f32) < start || from as f32 > end {
+                    continue;
+                }
+                let x0 = x_at(from).max(plot_rect.left());
+                let x1 = x_at(to).min(plot_rect.right());
+                if value.width() == 1 {
+                    let (y, color) = match value.bit(0) {
+                        Value::One => (high, COLOR_TRACE),
+                        Value::Zero => (low, COLOR_TRACE),
+                        Value::Z => (mid, COLOR_TRACE_Z),
+                        Value::X => {
+                            painter.rect_filled(
+                                Rect::from_x_y_ranges(x0..=x1, high..=low),
+                                0.0,
+                                COLOR_TRACE_X.gamma_multiply(0.4),
+                            );
+                            previous_y = None;
+                            continue;
+                        }
+                    };
+                    if let Some(previous_y) = previous_y {
+                        painter.line_segment(
+                            [pos2(x0, previous_y), pos2(x0, y)],
+                            Stroke::new(1.5, COLOR_TRACE),
+                        );
+                    }
+                    painter.line_segment([pos2(x0, y), pos2(x1, y)], Stroke::new(1.5, color));
+                    previous_y = Some(y);
+                } else {
+                    let color = if value.to_u64().is_some() {
+                        COLOR_TRACE
+                    } else {
+                        COLOR_TRACE_X
+                    };
+                    let stroke = Stroke::new(1.5, color);
+                    let slant = (x0 + 3.0).min(x1);
+                    painter.line_segment([pos2(x0, mid), pos2(slant, high)], stroke);
+                    painter.line_segment([pos2(x0, mid), pos2(slant, low)], stroke);
+                    painter.line_segment([pos2(slant, high), pos2(x1, high)], stroke);
+                    painter.line_segment([pos2(slant, low), pos2(x1, low)], stroke);
+                    let text = bus_text(value);
+                    let text_width = text.len() as f32 * 7.5;
+                    if x1 - slant > text_width + 6.0 {
+                        painter.text(
+                            pos2((slant + x1) / 2.0, mid),
+                            egui::Align2::CENTER_CENTER,
+                            text,
+                            font.clone(),
+                            text_color,
+                        );
+                    }
+                }
+            }
+        }
+
+        // Current time and cursors
+        let marker = |time: u64, color: Color32| {
+            let x = x_at(time);
+            if x >= plot_rect.left() && x <= plot_rect.right() {
+                painter.line_segment(
+                    [pos2(x, rect.top()), pos2(x, rect.bottom())],
+                    Stroke::new(1.5, color),
+                );
+            }
+        };
+        marker(now, grid_color);
+        if let Some(a) = cursors[0] {
+            marker(a, COLOR_CURSOR_A);
+        }
+        if let Some(b) = cursors[1] {
+            marker(b, COLOR_CURSOR_B);
+        }
+
+        if let Some(pin) = remove {
+            self.simulator.recorder.remove(pin);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::CircuitBuilder;
+    use crate::simulator::{BusValue, Value};
+
+    #[test]
+    fn records_probed_pins_over_time() -> Result<(), String> {
+        let mut b = CircuitBuilder::new();
+        b.clock("clk", 10, 0)?;
+        b.gate("inv", crate::db::GateKind::Not)?;
+        b.connect("clk", "inv.in")?;
+        let mut sim = b.build();
+        sim.probe("clk")?;
+        sim.probe("inv.out")?;
+        sim.probe("inv.in")?;
+        sim.run_until(20)?;
+
+        let recorder = &sim.simulator.recorder;
+        let changes = |i: usize| -> Vec<(u64, Value)> {
+            recorder.traces[i]
+                .changes
+                .iter()
+                .map(|&(t, v)| (t, v.bit(0)))
+                .collect()
+        };
+        let (one, zero) = (Value::One, Value::Zero);
+        assert_eq!(
+            changes(0),
+            [(0, one), (5, zero), (10, one), (15, zero), (20, one)]
+        );
+        assert_eq!(changes(2), changes(0));
+        assert_eq!(
+            changes(1),
+            [(0, Value::Z), (1, zero), (6, one), (11, zero), (16, one)]
+        );
+        assert_eq!(
+            recorder.traces[0].value_at(12),
+            Some(BusValue::from(Value::One))
+        );
+        Ok(())
+    }
+}
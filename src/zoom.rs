@@ -0,0 +1,190 @@
+//! Zooming the canvas.
+//!
+//! The canvas is drawn on its own layer scaled by [`App::zoom`] around the screen origin, so a
+//! world position `p` is at `p - viewport_offset` on the layer and at `(p - viewport_offset) *
+//! zoom` on the screen. Distances meant in screen pixels, like hit thresholds, are divided by the
+//! zoom before being compared with layer or world distances.
+
+use egui::{Pos2, Rect, Ui, Vec2};
+
+use crate::app::App;
+use crate::db::{InstanceId, InstanceKind, TUNNEL_SIZE};
+
+pub const MIN_ZOOM: f32 = 0.2;
+pub const MAX_ZOOM: f32 = 4.0;
+/// Zoom factor per point of mouse wheel scrolling, as an exponent
+const ZOOM_PER_SCROLL: f32 = 0.002;
+/// Part of the canvas left around fitted content
+const FIT_MARGIN: f32 = 0.9;
+
+/// Offset keeping the world position under `anchor` in place when the zoom changes
+pub fn zoom_about(offset: Vec2, zoom: f32, anchor: Pos2, new_zoom: f32) -> Vec2 {
+    offset + anchor.to_vec2() / zoom - anchor.to_vec2() / new_zoom
+}
+
+/// Zoom and offset showing `bounds`, given in world positions, in the middle of `canvas`
+pub fn fit(bounds: Rect, canvas: Rect) -> (f32, Vec2) {
+    let scale = canvas.size() / bounds.size().max(Vec2::splat(1.0));
+    let zoom = (scale.min_elem() * FIT_MARGIN).clamp(MIN_ZOOM, MAX_ZOOM);
+    let offset = bounds.center().to_vec2() - canvas.center().to_vec2() / zoom;
+    (zoom, offset)
+}
+
+impl App {
+    /// World position of a point on the screen
+    pub fn to_world(&self, screen: Pos2) -> Pos2 {
+        (screen.to_vec2() / self.zoom).to_pos2() + self.viewport_offset
+    }
+
+    pub fn set_zoom(&mut self, zoom: f32, offset: Vec2) {
+        self.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        self.viewport_offset = offset;
+    }
+
+    /// Zoom with the mouse wheel or a pinch, around the pointer
+    pub fn handle_zoom(&mut self, ui: &Ui) {
+        let (pinch, scroll, pointer) = ui.input(|i| {
+            (
+                i.zoom_delta(),
+                i.smooth_scroll_delta.y,
+                i.pointer.hover_pos(),
+            )
+        });
+        let factor = pinch * (scroll * ZOOM_PER_SCROLL).exp();
+        let Some(pointer) = pointer else {
+            return;
+        };
+        if factor == 1.0 {
+            return;
+        }
+        let zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let offset = zoom_about(self.viewport_offset, self.zoom, pointer, zoom);
+        self.set_zoom(zoom, offset);
+    }
+
+    pub fn zoom_to_fit(&mut self) {
+        let ids: Vec<InstanceId> = self
+            .db
+            .circuit
+            .types
+            .keys()
+            .filter(|&id| !self.db.is_hidden(id))
+            .collect();
+        let labels = self
+            .db
+            .circuit
+            .labels
+            .values()
+            .map(|label| Rect::from_pos(label.pos));
+        let bounds = ids
+            .iter()
+            .map(|&id| self.instance_bounds(id))
+            .chain(labels)
+            .reduce(|a, b| a.union(b));
+        if let Some(bounds) = bounds {
+            let (zoom, offset) = fit(bounds, self.canvas_rect);
+            self.set_zoom(zoom, offset);
+        }
+    }
+
+    pub fn zoom_to_selection(&mut self) {
+        let bounds = self
+            .selected
+            .iter()
+            .map(|&id| self.instance_bounds(id))
+            .reduce(|a, b| a.union(b));
+        if let Some(bounds) = bounds {
+            let (zoom, offset) = fit(bounds, self.canvas_rect);
+            self.set_zoom(zoom, offset);
+        }
+    }
+
+    /// Move the view so the selection is in the middle of the canvas, keeping the zoom
+    pub fn pan_to_selection(&mut self) {
+        let bounds = self
+            .selected
+            .iter()
+            .map(|&id| self.instance_bounds(id))
+            .reduce(|a, b| a.union(b));
+        if let Some(bounds) = bounds {
+            let offset =
+                bounds.center().to_vec2() - self.canvas_rect.center().to_vec2() / self.zoom;
+            self.set_zoom(self.zoom, offset);
+        }
+    }
+
+    /// Zoom back to 100%, keeping the middle of the canvas in place
+    pub fn reset_zoom(&mut self) {
+        let offset = zoom_about(
+            self.viewport_offset,
+            self.zoom,
+            self.canvas_rect.center(),
+            1.0,
+        );
+        self.set_zoom(1.0, offset);
+    }
+
+    /// Area an instance covers in world positions
+    pub(crate) fn instance_bounds(&self, id: InstanceId) -> Rect {
+        let circuit = &self.db.circuit;
+        let base = self.canvas_config.base_gate_size;
+        let body = match circuit.ty(id) {
+            InstanceKind::Gate(_) => {
+                let gate = circuit.get_gate(id);
+                Rect::from_center_size(gate.pos, gate.orientation.size(base))
+            }
+            InstanceKind::Power => {
+                let power = circuit.get_power(id);
+                Rect::from_center_size(power.pos, power.orientation.size(base))
+            }
+            InstanceKind::Lamp => {
+                let lamp = circuit.get_lamp(id);
+                Rect::from_center_size(lamp.pos, lamp.orientation.size(base))
+            }
+            InstanceKind::Clock => {
+                let clock = circuit.get_clock(id);
+                Rect::from_center_size(clock.pos, clock.orientation.size(base))
+            }
+            InstanceKind::Module(_) => {
+                let module = circuit.get_module(id);
+                Rect::from_center_size(module.pos, module.orientation.size(base))
+            }
+            InstanceKind::Tunnel => {
+                let tunnel = circuit.get_tunnel(id);
+                Rect::from_center_size(tunnel.pos, tunnel.orientation.size(TUNNEL_SIZE))
+            }
+            InstanceKind::Wire => Rect::from_points(&circuit.get_wire(id).points()),
+            InstanceKind::Splitter(_) => Rect::from_pos(circuit.get_splitter(id).pos),
+            InstanceKind::FlipFlop(_) => Rect::from_pos(circuit.get_flip_flop(id).pos),
+            InstanceKind::Memory(_) => Rect::from_pos(circuit.get_memory(id).pos),
+        };
+        circuit
+            .pins_of(id, &self.db)
+            .into_iter()
+            .map(|pin| circuit.pin_position(pin, &self.canvas_config, &self.db))
+            .fold(body, |rect, pos| rect.union(Rect::from_pos(pos)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::{Rect, pos2, vec2};
+
+    use super::{fit, zoom_about};
+
+    #[test]
+    fn zoom_keeps_anchor_and_fits_bounds() {
+        let offset = vec2(30.0, -10.0);
+        let anchor = pos2(200.0, 120.0);
+        let world = anchor.to_vec2() + offset;
+        let offset = zoom_about(offset, 1.0, anchor, 2.5);
+        assert!((anchor.to_vec2() / 2.5 + offset - world).length() < 1e-3);
+
+        let canvas = Rect::from_min_size(pos2(100.0, 50.0), vec2(400.0, 300.0));
+        let bounds = Rect::from_min_size(pos2(-500.0, 0.0), vec2(200.0, 300.0));
+        let (zoom, offset) = fit(bounds, canvas);
+        assert!((zoom - 0.9).abs() < 1e-6);
+        let center = canvas.center().to_vec2() / zoom + offset;
+        assert!((center - bounds.center().to_vec2()).length() < 1e-3);
+    }
+}